@@ -1,4 +1,4 @@
-use crate::commands::agent::run::helpers::{system_message, user_message};
+use crate::commands::agent::run::helpers::{system_message, user_message_with_attachments};
 use crate::utils::network;
 use crate::{commands::agent::run::helpers::convert_tools_map_with_filter, config::AppConfig};
 use agent_client_protocol::{self as acp, Client as AcpClient, SessionNotification};
@@ -7,13 +7,17 @@ use stakpak_api::models::ApiStreamError;
 use stakpak_api::{Client, ClientConfig};
 use stakpak_mcp_client::ClientManager;
 use stakpak_mcp_server::{EnabledToolsConfig, MCPServerConfig, ToolMode, start_server};
+use stakpak_shared::attachments::{Attachment, AttachmentError, AttachmentKind, pasted_file_path};
 use stakpak_shared::cert_utils::CertificateChain;
 use stakpak_shared::models::integrations::mcp::CallToolResultExt;
 use stakpak_shared::models::integrations::openai::{
-    ChatCompletionResponse, ChatCompletionStreamResponse, Role, ToolCall, ToolCallResultProgress,
+    ChatCompletionResponse, ChatCompletionStreamResponse, ChatMessage, Role, ToolCall,
+    ToolCallResultProgress,
 };
+use stakpak_shared::secret_manager::SecretManager;
 use std::cell::Cell;
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot};
 use tokio_util::compat::{TokioAsyncReadCompatExt as _, TokioAsyncWriteCompatExt as _};
@@ -60,8 +64,9 @@ impl StakpakAcpAgent {
         system_prompt: Option<String>,
     ) -> Result<Self, String> {
         let api_config: ClientConfig = config.clone().into();
-        let client =
-            Client::new(&api_config).map_err(|e| format!("Failed to create client: {}", e))?;
+        let client = Client::new(&api_config)
+            .map_err(|e| format!("Failed to create client: {}", e))?
+            .with_model(config.model.clone());
 
         // Initialize MCP server and tools (optional for ACP)
         let (mcp_server_host, clients, tools) =
@@ -1027,6 +1032,57 @@ impl StakpakAcpAgent {
         Ok(results)
     }
 
+    /// Turn the blocks of a prompt into a user message, with images, embedded resources and
+    /// links to local files attached
+    fn prompt_message(&self, prompt: &[acp::ContentBlock]) -> Result<ChatMessage, AttachmentError> {
+        let secret_manager = SecretManager::new(true, false);
+        let mut text = Vec::new();
+        let mut attachments = Vec::new();
+
+        for block in prompt {
+            match block {
+                acp::ContentBlock::Text(text_content) => text.push(text_content.text.clone()),
+                acp::ContentBlock::Image(image) => {
+                    let name = image.uri.clone().unwrap_or_else(|| "image".to_string());
+                    attachments.push(Attachment::from_base64(
+                        &name,
+                        AttachmentKind::from_mime_type(&image.mime_type),
+                        &image.data,
+                        &secret_manager,
+                    )?);
+                }
+                acp::ContentBlock::Resource(resource) => match &resource.resource {
+                    acp::EmbeddedResourceResource::TextResourceContents(contents) => {
+                        attachments.push(Attachment::from_text(
+                            &contents.uri,
+                            &contents.text,
+                            &secret_manager,
+                        ));
+                    }
+                    acp::EmbeddedResourceResource::BlobResourceContents(contents) => {
+                        let kind = match &contents.mime_type {
+                            Some(mime_type) => AttachmentKind::from_mime_type(mime_type),
+                            None => AttachmentKind::from_path(Path::new(&contents.uri)),
+                        };
+                        attachments.push(Attachment::from_base64(
+                            &contents.uri,
+                            kind,
+                            &contents.blob,
+                            &secret_manager,
+                        )?);
+                    }
+                },
+                acp::ContentBlock::ResourceLink(link) => match pasted_file_path(&link.uri) {
+                    Some(path) => attachments.push(Attachment::load(&path, &secret_manager)?),
+                    None => text.push(link.uri.clone()),
+                },
+                acp::ContentBlock::Audio(_) => {}
+            }
+        }
+
+        Ok(user_message_with_attachments(text.join(" "), &attachments))
+    }
+
     async fn initialize_mcp_server_and_tools(
         config: &AppConfig,
         progress_tx: Option<mpsc::Sender<ToolCallResultProgress>>,
@@ -1619,17 +1675,18 @@ impl acp::Agent for StakpakAcpAgent {
         log::info!("Received prompt request {arguments:?}");
 
         // Convert prompt to your ChatMessage format
-        let prompt_text = arguments
-            .prompt
-            .iter()
-            .map(|block| match block {
-                acp::ContentBlock::Text(text_content) => text_content.text.clone(),
-                _ => String::new(),
-            })
-            .collect::<Vec<_>>()
-            .join(" ");
-        log::info!("Processed prompt text: {}", prompt_text);
-        let user_msg = user_message(prompt_text);
+        let user_msg = self.prompt_message(&arguments.prompt).map_err(|e| {
+            log::error!("Failed to attach prompt content: {}", e);
+            acp::Error::invalid_params().with_data(e.to_string())
+        })?;
+        log::info!(
+            "Processed prompt text: {}",
+            user_msg
+                .content
+                .as_ref()
+                .map(|c| c.to_string())
+                .unwrap_or_default()
+        );
 
         // Add user message to conversation history
         {
@@ -1,3 +1,4 @@
+use crate::commands::agent::run::helpers::client_error_event_message;
 use crate::commands::agent::run::tui::send_input_event;
 use rmcp::model::CallToolResult;
 use stakpak_api::Client;
@@ -6,6 +7,7 @@ use stakpak_shared::models::integrations::{
     mcp::CallToolResultExt,
     openai::{ChatMessage, MessageContent, Role, ToolCall, ToolCallResult},
 };
+use stakpak_shared::session_store::LocalSession;
 use stakpak_tui::{InputEvent, LoadingOperation};
 use uuid::Uuid;
 
@@ -13,6 +15,14 @@ pub async fn get_checkpoint_messages(
     client: &Client,
     checkpoint_id: &String,
 ) -> Result<Vec<ChatMessage>, String> {
+    let checkpoint_output = get_checkpoint_output(client, checkpoint_id).await?;
+    Ok(get_messages_from_checkpoint_output(&checkpoint_output))
+}
+
+pub async fn get_checkpoint_output(
+    client: &Client,
+    checkpoint_id: &String,
+) -> Result<AgentOutput, String> {
     let checkpoint_uuid = Uuid::parse_str(checkpoint_id).map_err(|_| {
         format!(
             "Invalid checkpoint ID '{}' - must be a valid UUID",
@@ -24,9 +34,7 @@ pub async fn get_checkpoint_messages(
         .get_agent_checkpoint(checkpoint_uuid)
         .await
         .map_err(|e| e.to_string())?;
-    let checkpoint_output: AgentOutput = checkpoint.output;
-
-    Ok(get_messages_from_checkpoint_output(&checkpoint_output))
+    Ok(checkpoint.output)
 }
 
 pub fn get_messages_from_checkpoint_output(output: &AgentOutput) -> Vec<ChatMessage> {
@@ -59,7 +67,32 @@ pub async fn extract_checkpoint_messages_and_tool_calls(
         )));
     }
 
-    for message in &*checkpoint_messages {
+    let tool_calls = replay_messages(&checkpoint_messages, input_tx).await;
+    Ok((checkpoint_messages, tool_calls))
+}
+
+/// Resume a session from the local session store, without its stored system prompt
+pub async fn resume_local_session(
+    session: &LocalSession,
+    input_tx: &tokio::sync::mpsc::Sender<InputEvent>,
+) -> (Vec<ChatMessage>, Vec<ToolCall>) {
+    let messages: Vec<ChatMessage> = session
+        .messages
+        .iter()
+        .filter(|message| message.role != Role::System)
+        .cloned()
+        .collect();
+    let tool_calls = replay_messages(&messages, input_tx).await;
+    (messages, tool_calls)
+}
+
+/// Show `messages` in the TUI, returning the tool calls of the last one if they are
+/// still to be run
+async fn replay_messages(
+    messages: &[ChatMessage],
+    input_tx: &tokio::sync::mpsc::Sender<InputEvent>,
+) -> Vec<ToolCall> {
+    for message in messages {
         match message.role {
             Role::Assistant => {
                 if let Some(content) = &message.content {
@@ -79,7 +112,7 @@ pub async fn extract_checkpoint_messages_and_tool_calls(
                 }
             }
             Role::Tool => {
-                let tool_call = checkpoint_messages
+                let tool_call = messages
                     .iter()
                     .find(|checkpoint_message| {
                         checkpoint_message
@@ -123,15 +156,11 @@ pub async fn extract_checkpoint_messages_and_tool_calls(
         }
     }
 
-    let tool_calls = checkpoint_messages
+    messages
         .last()
         .filter(|msg| msg.role == Role::Assistant)
-        .and_then(|msg| msg.tool_calls.as_ref());
-
-    Ok((
-        checkpoint_messages.clone(),
-        tool_calls.map(|t| t.to_vec()).unwrap_or_default(),
-    ))
+        .and_then(|msg| msg.tool_calls.clone())
+        .unwrap_or_default()
 }
 
 pub fn extract_checkpoint_id_from_messages(messages: &[ChatMessage]) -> Option<String> {
@@ -167,12 +196,13 @@ pub fn extract_checkpoint_id_from_messages(messages: &[ChatMessage]) -> Option<S
         })
 }
 
-/// Resumes a session from a checkpoint, loading messages and tool calls
+/// Resumes a session from a checkpoint, loading messages, tool calls and the model the
+/// checkpoint records, if any
 pub async fn resume_session_from_checkpoint(
     client: &Client,
     session_id: &str,
     input_tx: &tokio::sync::mpsc::Sender<InputEvent>,
-) -> Result<(Vec<ChatMessage>, Vec<ToolCall>, Uuid), String> {
+) -> Result<(Vec<ChatMessage>, Vec<ToolCall>, Uuid, Option<String>), String> {
     let session_uuid = Uuid::parse_str(session_id).map_err(|e| e.to_string())?;
 
     match client
@@ -187,7 +217,12 @@ pub async fn resume_session_from_checkpoint(
             )
             .await?;
 
-            Ok((chat_messages, tool_calls, checkpoint.session.id))
+            Ok((
+                chat_messages,
+                tool_calls,
+                checkpoint.session.id,
+                checkpoint.output.model().map(str::to_string),
+            ))
         }
         Err(e) => {
             send_input_event(
@@ -195,7 +230,7 @@ pub async fn resume_session_from_checkpoint(
                 InputEvent::EndLoadingOperation(LoadingOperation::CheckpointResume),
             )
             .await?;
-            send_input_event(input_tx, InputEvent::Error(e)).await?;
+            send_input_event(input_tx, InputEvent::Error(client_error_event_message(&e))).await?;
             Err("Failed to get session checkpoint".to_string())
         }
     }
@@ -0,0 +1,521 @@
+use crate::commands::agent::run::helpers::user_message;
+use crate::commands::agent::run::tui::send_input_event;
+use stakpak_api::models::AgentOutput;
+use stakpak_api::{Client, ClientError};
+use stakpak_shared::models::integrations::openai::{ChatMessage, MessageContent, Role};
+use stakpak_shared::models::usage::{PriceTable, SessionUsage};
+use stakpak_tui::{InputEvent, LoadingOperation};
+use uuid::Uuid;
+
+/// Longest a single message may be in the transcript sent to be summarised
+const MAX_TRANSCRIPT_MESSAGE_CHARS: usize = 8_000;
+/// Tool output is mostly noise once acted on, so it is cut shorter
+const MAX_TRANSCRIPT_TOOL_RESULT_CHARS: usize = 2_000;
+
+const SUMMARY_INSTRUCTIONS: &str = "Summarize the conversation below so that the summary can \
+replace it in an ongoing session. Keep the user's goals and requirements, decisions that were \
+made, what was done and its outcome (files changed, commands run, resources created), errors and \
+how they were resolved, and what is left to do. Be concise and factual, and do not add anything \
+that is not in the conversation. Reply with the summary only.";
+
+/// Tags added to the context once at the start of a session, which are carried over
+/// verbatim since the agent relies on them for the rest of it
+const PRESERVED_TAGS: [&str; 2] = ["rulebooks", "subagents"];
+
+/// Rough token count of a conversation, at about four characters per token
+pub fn estimate_tokens(messages: &[ChatMessage]) -> u64 {
+    messages
+        .iter()
+        .map(|message| {
+            let content = message
+                .content
+                .as_ref()
+                .map(|content| content.to_string().len())
+                .unwrap_or(0);
+            let tool_calls = message
+                .tool_calls
+                .iter()
+                .flatten()
+                .map(|tool_call| tool_call.function.name.len() + tool_call.function.arguments.len())
+                .sum::<usize>();
+            // Each message also costs a few tokens of framing
+            (content + tool_calls) as u64 / 4 + 4
+        })
+        .sum()
+}
+
+/// How a conversation is split to be compacted: system messages stay first, the most
+/// recent messages are kept as they are and everything in between is summarised
+#[derive(Debug)]
+pub struct Compaction {
+    system: Vec<ChatMessage>,
+    older: Vec<ChatMessage>,
+    recent: Vec<ChatMessage>,
+}
+
+impl Compaction {
+    /// Split `messages`, keeping at least `keep_recent` of the latest ones. Returns `None`
+    /// when there is nothing old enough to summarise.
+    pub fn plan(messages: &[ChatMessage], keep_recent: usize) -> Option<Self> {
+        let (system, conversation): (Vec<_>, Vec<_>) = messages
+            .iter()
+            .cloned()
+            .partition(|message| message.role == Role::System);
+
+        // Tool results have to follow the assistant message that called them
+        let mut split = conversation.len().saturating_sub(keep_recent);
+        while split > 0
+            && conversation
+                .get(split)
+                .is_some_and(|message| message.role == Role::Tool)
+        {
+            split -= 1;
+        }
+        if split == 0 {
+            return None;
+        }
+
+        let mut older = conversation;
+        let recent = older.split_off(split);
+        Some(Self {
+            system,
+            older,
+            recent,
+        })
+    }
+
+    /// Messages asking for a summary of the older part of the conversation
+    pub fn summary_request(&self) -> Vec<ChatMessage> {
+        vec![user_message(format!(
+            "{}\n\n<conversation>\n{}\n</conversation>",
+            SUMMARY_INSTRUCTIONS,
+            transcript(&self.older)
+        ))]
+    }
+
+    /// The compacted conversation, with `summary` in place of the older messages. Rulebooks
+    /// and the todo list are carried over when they are only in the summarised part.
+    pub fn apply(self, summary: &str) -> Vec<ChatMessage> {
+        let mut context = format!(
+            "<conversation_summary>\n{}\n</conversation_summary>",
+            strip_checkpoint_ids(summary).trim()
+        );
+        for tag in PRESERVED_TAGS {
+            if let Some(block) = last_tag_block(&self.older, tag) {
+                context.push_str(&format!("\n\n{}", block));
+            }
+        }
+        if last_tag_block(&self.recent, "todo").is_none()
+            && let Some(todo) = last_tag_block(&self.older, "todo")
+        {
+            context.push_str(&format!("\n\n{}", todo));
+        }
+
+        let mut messages = self.system;
+        messages.push(user_message(context));
+        messages.extend(self.recent);
+        messages
+    }
+}
+
+/// Summarise the older part of `messages`, recording the summary's usage in `usage`.
+/// Returns `None` when the conversation is too short to compact.
+///
+/// Once the conversation has a checkpoint, the compacted one is saved as a new checkpoint
+/// so that resuming the session carries on from the summary rather than the full history.
+pub async fn compact_messages(
+    client: &Client,
+    messages: &[ChatMessage],
+    keep_recent: usize,
+    usage: &mut SessionUsage,
+    prices: Option<&PriceTable>,
+) -> Result<Option<Vec<ChatMessage>>, ClientError> {
+    let Some(compaction) = Compaction::plan(messages, keep_recent) else {
+        return Ok(None);
+    };
+
+    let response = client
+        .chat_completion(compaction.summary_request(), None)
+        .await?;
+    usage.record_extra(&response.usage, &response.model, prices);
+    let summary = response
+        .choices
+        .first()
+        .and_then(|choice| choice.message.content.as_ref())
+        .map(|content| content.to_string())
+        .unwrap_or_default();
+    if summary.trim().is_empty() {
+        return Err(ClientError::Decode(
+            "The conversation summary came back empty".to_string(),
+        ));
+    }
+
+    let compacted = compaction.apply(&summary);
+    let Some(parent_checkpoint_id) = latest_checkpoint_id(messages) else {
+        return Ok(Some(compacted));
+    };
+    let checkpoint = client
+        .create_agent_checkpoint(
+            parent_checkpoint_id,
+            &checkpoint_output(&compacted, client.model()),
+        )
+        .await?;
+    Ok(Some(with_checkpoint_id(
+        compacted,
+        checkpoint.checkpoint.id,
+    )))
+}
+
+fn latest_checkpoint_id(messages: &[ChatMessage]) -> Option<Uuid> {
+    messages
+        .iter()
+        .rev()
+        .filter(|message| message.role == Role::Assistant)
+        .find_map(|message| message.content.as_ref()?.extract_checkpoint_id())
+}
+
+fn checkpoint_output(messages: &[ChatMessage], model: &str) -> AgentOutput {
+    AgentOutput::PabloV1 {
+        messages: messages.to_vec(),
+        node_states: serde_json::json!({}),
+        model: Some(model.to_string()),
+    }
+}
+
+/// Point the last assistant message at `checkpoint_id`, so the next turn follows it
+fn with_checkpoint_id(mut messages: Vec<ChatMessage>, checkpoint_id: Uuid) -> Vec<ChatMessage> {
+    if let Some(message) = messages
+        .iter_mut()
+        .rev()
+        .find(|message| message.role == Role::Assistant)
+        && let Some(MessageContent::String(content)) = &message.content
+    {
+        message.content = Some(MessageContent::String(format!(
+            "{}\n<checkpoint_id>{}</checkpoint_id>",
+            strip_checkpoint_ids(content).trim_end(),
+            checkpoint_id
+        )));
+    }
+    messages
+}
+
+/// Compact `messages` in place while the TUI shows progress. A failed compaction is
+/// reported to the TUI and leaves the conversation as it was.
+pub async fn compact_conversation(
+    client: &Client,
+    messages: &mut Vec<ChatMessage>,
+    keep_recent: usize,
+    usage: &mut SessionUsage,
+    prices: Option<&PriceTable>,
+    input_tx: &tokio::sync::mpsc::Sender<InputEvent>,
+) -> Result<(), String> {
+    send_input_event(
+        input_tx,
+        InputEvent::StartLoadingOperation(LoadingOperation::Compacting),
+    )
+    .await?;
+    let result = compact_messages(client, messages, keep_recent, usage, prices).await;
+    send_input_event(
+        input_tx,
+        InputEvent::EndLoadingOperation(LoadingOperation::Compacting),
+    )
+    .await?;
+    send_input_event(input_tx, InputEvent::UsageUpdated(usage.clone())).await?;
+
+    match result {
+        Ok(Some(compacted)) => {
+            let before = estimate_tokens(messages);
+            *messages = compacted;
+            send_input_event(
+                input_tx,
+                InputEvent::ConversationCompacted(before, estimate_tokens(messages)),
+            )
+            .await?;
+        }
+        Ok(None) => {}
+        Err(e) => {
+            send_input_event(
+                input_tx,
+                InputEvent::Error(format!("Failed to compact the conversation: {}", e)),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+fn transcript(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let content = message
+                .content
+                .as_ref()
+                .map(|content| strip_checkpoint_ids(&content.to_string()))
+                .unwrap_or_default();
+            let mut entry = match message.role {
+                Role::Tool => format!(
+                    "[tool result]\n{}",
+                    truncate(&content, MAX_TRANSCRIPT_TOOL_RESULT_CHARS)
+                ),
+                _ => format!(
+                    "[{}]\n{}",
+                    message.role,
+                    truncate(&content, MAX_TRANSCRIPT_MESSAGE_CHARS)
+                ),
+            };
+            for tool_call in message.tool_calls.iter().flatten() {
+                entry.push_str(&format!(
+                    "\n[tool call] {}({})",
+                    tool_call.function.name,
+                    truncate(
+                        &tool_call.function.arguments,
+                        MAX_TRANSCRIPT_TOOL_RESULT_CHARS
+                    )
+                ));
+            }
+            entry
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}... [truncated]", &text[..index]),
+        None => text.to_string(),
+    }
+}
+
+/// Checkpoint ids are only meaningful on the message the API attached them to
+fn strip_checkpoint_ids(text: &str) -> String {
+    let mut text = text.to_string();
+    while let Some(start) = text.find("<checkpoint_id>") {
+        match text[start..].find("</checkpoint_id>") {
+            Some(end) => text.replace_range(start..start + end + "</checkpoint_id>".len(), ""),
+            None => break,
+        }
+    }
+    text
+}
+
+/// The last `<tag>...</tag>` block in `messages`, tags included
+fn last_tag_block(messages: &[ChatMessage], tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    messages.iter().rev().find_map(|message| {
+        let content = match message.content.as_ref()? {
+            MessageContent::String(content) => content.clone(),
+            content => content.to_string(),
+        };
+        let start = content.rfind(&open)?;
+        let end = content[start..].find(&close)? + start + close.len();
+        Some(content[start..end].to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::agent::run::checkpoint::extract_checkpoint_id_from_messages;
+    use crate::commands::agent::run::helpers::{system_message, tool_result};
+    use stakpak_api::ClientConfig;
+    use stakpak_api::cassette::{Cassette, Interaction, RecordedRequest, RecordedResponse};
+    use stakpak_shared::models::integrations::openai::{
+        ChatCompletionChoice, ChatCompletionResponse, FinishReason, FunctionCall, ToolCall, Usage,
+    };
+    use std::collections::BTreeMap;
+
+    fn assistant(content: &str, tool_call_id: Option<&str>) -> ChatMessage {
+        ChatMessage {
+            role: Role::Assistant,
+            content: Some(MessageContent::String(content.to_string())),
+            name: None,
+            tool_calls: tool_call_id.map(|id| {
+                vec![ToolCall {
+                    id: id.to_string(),
+                    r#type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "run_command".to_string(),
+                        arguments: "{\"command\":\"ls\"}".to_string(),
+                    },
+                }]
+            }),
+            tool_call_id: None,
+        }
+    }
+
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            system_message("You are a DevOps agent".to_string()),
+            user_message(
+                "Deploy the app\n\n<rulebooks>\n- deploy with helm\n</rulebooks>".to_string(),
+            ),
+            assistant(
+                "<scratchpad><todo>\n- [ ] build\n- [ ] deploy\n</todo></scratchpad>",
+                Some("call_1"),
+            ),
+            tool_result("call_1".to_string(), "Dockerfile chart".to_string()),
+            assistant(
+                "Building the image\n<checkpoint_id>abc</checkpoint_id>",
+                Some("call_2"),
+            ),
+            tool_result("call_2".to_string(), "built".to_string()),
+            assistant("The image is built", None),
+        ]
+    }
+
+    #[test]
+    fn test_keeps_system_prompt_rulebooks_todos_and_recent_tool_results() {
+        // Keeping two messages would start on a tool result, so its call is kept too
+        let compaction = Compaction::plan(&conversation(), 2).expect("something to compact");
+        assert_eq!(compaction.older.len(), 3);
+        assert_eq!(compaction.recent.len(), 3);
+
+        let request = compaction.summary_request();
+        let request = request[0].content.as_ref().map(|c| c.to_string());
+        assert!(
+            request
+                .as_ref()
+                .is_some_and(|r| r.contains("Dockerfile chart"))
+        );
+
+        let messages = compaction.apply("Asked to deploy the app.<checkpoint_id>x</checkpoint_id>");
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0].role, Role::System);
+        let context = messages[1].content.as_ref().map(|c| c.to_string());
+        let context = context.unwrap_or_default();
+        assert!(context.contains("Asked to deploy the app."));
+        assert!(!context.contains("checkpoint_id"));
+        assert!(context.contains("<rulebooks>\n- deploy with helm\n</rulebooks>"));
+        assert!(context.contains("- [ ] deploy"));
+        assert_eq!(
+            messages[2].tool_calls.as_ref().map(|t| t[0].id.as_str()),
+            Some("call_2")
+        );
+        assert_eq!(messages[3].role, Role::Tool);
+    }
+
+    #[test]
+    fn test_keeping_no_recent_messages_summarises_everything() {
+        let compaction = Compaction::plan(&conversation(), 0).expect("something to compact");
+        assert_eq!(compaction.older.len(), 6);
+        assert!(compaction.recent.is_empty());
+
+        let messages = compaction.apply("Asked to deploy the app.");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].role, Role::User);
+    }
+
+    #[tokio::test]
+    async fn test_compacted_conversation_is_saved_as_checkpoint() {
+        let parent_id = Uuid::new_v4();
+        let checkpoint_id = Uuid::new_v4();
+        let mut messages = conversation();
+        messages[4] = assistant(
+            &format!("Building the image\n<checkpoint_id>{parent_id}</checkpoint_id>"),
+            Some("call_2"),
+        );
+
+        let summary = ChatCompletionResponse {
+            id: "chatcmpl-1".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "pablo-v1".to_string(),
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                message: assistant("Asked to deploy the app.", None),
+                logprobs: None,
+                finish_reason: FinishReason::Stop,
+            }],
+            usage: Usage {
+                prompt_tokens: 1_000,
+                completion_tokens: 100,
+                total_tokens: 1_100,
+            },
+            system_fingerprint: None,
+        };
+        let checkpoint = serde_json::json!({
+            "checkpoint": {
+                "id": checkpoint_id,
+                "status": "COMPLETE",
+                "execution_depth": 1,
+                "parent": null,
+                "created_at": "2025-01-01T00:00:00Z",
+                "updated_at": "2025-01-01T00:00:00Z",
+            },
+            "session": {
+                "id": Uuid::new_v4(),
+                "agent_id": "pablo:v1",
+                "flow_ref": null,
+                "visibility": "PRIVATE",
+                "created_at": "2025-01-01T00:00:00Z",
+                "updated_at": "2025-01-01T00:00:00Z",
+            },
+            "output": {
+                "agent_id": "pablo:v1",
+                "messages": [],
+                "node_states": {},
+            },
+        });
+        let interaction = |path: &str, body: String| Interaction {
+            request: RecordedRequest {
+                method: "POST".to_string(),
+                path: path.to_string(),
+                body: None,
+            },
+            response: RecordedResponse {
+                status: 200,
+                headers: BTreeMap::from([(
+                    "content-type".to_string(),
+                    "application/json".to_string(),
+                )]),
+                body,
+            },
+        };
+        let cassette = Cassette {
+            interactions: vec![
+                interaction(
+                    "/agents/openai/v1/chat/completions",
+                    serde_json::to_string(&summary).unwrap(),
+                ),
+                interaction("/agents/checkpoints", checkpoint.to_string()),
+            ],
+        };
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("cassette.json");
+        std::fs::write(&path, serde_json::to_string(&cassette).unwrap()).unwrap();
+
+        let client = Client::new(&ClientConfig {
+            api_key: Some("test".to_string()),
+            api_endpoint: "http://127.0.0.1:1".to_string(),
+        })
+        .unwrap()
+        .with_cassette(&path)
+        .unwrap();
+        let mut usage = SessionUsage::default();
+        let compacted = compact_messages(&client, &messages, 2, &mut usage, None)
+            .await
+            .unwrap()
+            .expect("something to compact");
+
+        // The summary counts towards the session's usage, but isn't a step
+        assert_eq!(usage.total.total_tokens(), 1_100);
+        assert_eq!(usage.steps, 0);
+
+        // The next turn and a resumed session both follow the saved checkpoint
+        assert_eq!(latest_checkpoint_id(&compacted), Some(checkpoint_id));
+        assert_eq!(
+            extract_checkpoint_id_from_messages(&compacted),
+            Some(checkpoint_id.to_string())
+        );
+        let output = checkpoint_output(&compacted, client.model());
+        assert_eq!(output.model(), Some(client.model()));
+    }
+
+    #[test]
+    fn test_short_conversations_are_not_compacted() {
+        assert!(Compaction::plan(&conversation(), 10).is_none());
+        assert!(estimate_tokens(&conversation()) > estimate_tokens(&conversation()[..2]));
+    }
+}
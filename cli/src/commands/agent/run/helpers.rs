@@ -1,17 +1,22 @@
 use crate::utils::local_context::LocalContext;
-use stakpak_api::ListRuleBook;
+use stakpak_api::{ClientError, ListRuleBook};
+use stakpak_shared::attachments::{Attachment, message_content};
 use stakpak_shared::models::integrations::openai::{
     ChatMessage, FunctionDefinition, MessageContent, Role, Tool, ToolCallResult,
 };
 use stakpak_shared::models::subagent::SubagentConfigs;
+use stakpak_shared::secret_manager::SecretManager;
+use stakpak_shared::session_store::SessionRecorder;
+use std::path::PathBuf;
+use uuid::Uuid;
 
 pub fn convert_tools_map_with_filter(
     tools_map: &std::collections::HashMap<String, Vec<rmcp::model::Tool>>,
     allowed_tools: Option<&Vec<String>>,
 ) -> Vec<Tool> {
     tools_map
-        .iter()
-        .flat_map(|(_name, tools)| {
+        .values()
+        .flat_map(|tools| {
             tools.iter().filter_map(|tool| {
                 let tool_name = tool.name.as_ref();
 
@@ -46,6 +51,45 @@ pub fn user_message(user_input: String) -> ChatMessage {
     }
 }
 
+/// A user message with `attachments` sent as content parts after the text
+pub fn user_message_with_attachments(
+    user_input: String,
+    attachments: &[Attachment],
+) -> ChatMessage {
+    ChatMessage {
+        content: Some(message_content(user_input, attachments)),
+        ..user_message(String::new())
+    }
+}
+
+/// Load the files attached to a message, failing on the first one that cannot be sent
+pub fn load_attachments(
+    paths: &[PathBuf],
+    secret_manager: &SecretManager,
+) -> Result<Vec<Attachment>, String> {
+    paths
+        .iter()
+        .map(|path| Attachment::load(path, secret_manager).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Mirror the conversation to the local session store, linking it to its backend session
+/// once that is known
+pub fn record_session(
+    recorder: &mut SessionRecorder,
+    messages: &[ChatMessage],
+    model: &str,
+    session_id: Option<Uuid>,
+) -> Result<(), String> {
+    recorder
+        .record(messages, model)
+        .and_then(|_| match session_id {
+            Some(session_id) => recorder.set_remote_session(session_id),
+            None => Ok(()),
+        })
+        .map_err(|e| format!("Failed to save the session locally: {}", e))
+}
+
 pub fn system_message(system_prompt: String) -> ChatMessage {
     ChatMessage {
         role: Role::System,
@@ -189,3 +233,19 @@ pub fn tool_call_history_string(tool_calls: &[ToolCallResult]) -> Option<String>
         .join("\n");
     Some(format!("Here's my shell history:\n{}", history))
 }
+
+/// Error event for the TUI when an API request fails. Running out of credits is sent as
+/// `EXCEEDED_API_LIMIT` so the TUI can ask for a top-up, and transient failures, which the
+/// client has already retried, ask the user to check their connection and try again.
+pub fn client_error_event_message(error: &ClientError) -> String {
+    if error.is_out_of_credits() {
+        "EXCEEDED_API_LIMIT".to_string()
+    } else if error.is_transient() {
+        format!(
+            "Could not reach Stakpak, please check your connection and try again. ({})",
+            error
+        )
+    } else {
+        error.to_string()
+    }
+}
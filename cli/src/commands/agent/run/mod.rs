@@ -1,4 +1,5 @@
 pub mod checkpoint;
+pub mod compaction;
 pub mod helpers;
 pub mod mode_async;
 pub mod mode_interactive;
@@ -1,10 +1,11 @@
 use crate::agent::run::helpers::system_message;
 use crate::commands::agent::run::checkpoint::{
-    extract_checkpoint_id_from_messages, get_checkpoint_messages,
+    extract_checkpoint_id_from_messages, get_checkpoint_output, get_messages_from_checkpoint_output,
 };
+use crate::commands::agent::run::compaction::{compact_messages, estimate_tokens};
 use crate::commands::agent::run::helpers::{
-    add_local_context, add_rulebooks, add_subagents, convert_tools_map_with_filter, tool_result,
-    user_message,
+    add_local_context, add_rulebooks, add_subagents, convert_tools_map_with_filter,
+    load_attachments, record_session, tool_result, user_message_with_attachments,
 };
 use crate::commands::agent::run::renderer::{OutputFormat, OutputRenderer};
 use crate::commands::agent::run::tooling::run_tool_call;
@@ -16,8 +17,12 @@ use stakpak_mcp_client::ClientManager;
 use stakpak_mcp_server::{EnabledToolsConfig, MCPServerConfig, ToolMode, start_server};
 use stakpak_shared::cert_utils::CertificateChain;
 use stakpak_shared::local_store::LocalStore;
-use stakpak_shared::models::integrations::openai::ChatMessage;
+use stakpak_shared::models::integrations::openai::{ChatMessage, Role};
 use stakpak_shared::models::subagent::SubagentConfigs;
+use stakpak_shared::models::usage::{SessionUsage, format_tokens};
+use stakpak_shared::secret_manager::SecretManager;
+use stakpak_shared::session_store::{LocalSession, SessionStore};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
 use uuid::Uuid;
@@ -37,6 +42,12 @@ pub struct RunAsyncConfig {
     pub enable_mtls: bool,
     pub system_prompt: Option<String>,
     pub enabled_tools: EnabledToolsConfig,
+    /// Model to use instead of the profile's or the checkpoint's
+    pub model: Option<String>,
+    /// Files sent along with the prompt
+    pub attachments: Vec<PathBuf>,
+    /// Session from the local session store to carry on with
+    pub local_session: Option<LocalSession>,
 }
 
 // All print functions have been moved to the renderer module and are no longer needed here
@@ -106,12 +117,22 @@ pub async fn run_async(ctx: AppConfig, config: RunAsyncConfig) -> Result<(), Str
     })
     .map_err(|e| e.to_string())?;
 
+    let mut model = config.model.clone().or(ctx.model.clone());
+
     // Load checkpoint messages if provided
     if let Some(checkpoint_id) = config.checkpoint_id {
         let checkpoint_start = Instant::now();
-        let mut checkpoint_messages = get_checkpoint_messages(&client, &checkpoint_id).await?;
+        let checkpoint_output = get_checkpoint_output(&client, &checkpoint_id).await?;
+        let mut checkpoint_messages = get_messages_from_checkpoint_output(&checkpoint_output);
         llm_response_time += checkpoint_start.elapsed();
 
+        // Carry on with the checkpoint's model unless one was asked for
+        if config.model.is_none()
+            && let Some(checkpoint_model) = checkpoint_output.model()
+        {
+            model = Some(checkpoint_model.to_string());
+        }
+
         // Append checkpoint_id to the last assistant message if present
         if let Some(last_message) = checkpoint_messages.iter_mut().rev().find(|message| {
             message.role != stakpak_shared::models::integrations::openai::Role::User
@@ -137,13 +158,52 @@ pub async fn run_async(ctx: AppConfig, config: RunAsyncConfig) -> Result<(), Str
         );
     }
 
+    let session_store = SessionStore::default_store();
+    let mut session_recorder = session_store.recorder(&ctx.profile_name);
+    let mut current_session_id: Option<Uuid> = None;
+    if let Some(session) = &config.local_session {
+        if config.model.is_none()
+            && let Some(session_model) = &session.summary.model
+        {
+            model = Some(session_model.clone());
+        }
+        current_session_id = session.summary.remote_session_id;
+        chat_messages.extend(
+            session
+                .messages
+                .iter()
+                .filter(|message| message.role != Role::System)
+                .cloned(),
+        );
+        session_recorder = session_store.resume_recorder(session, &ctx.profile_name);
+        print!(
+            "{}",
+            renderer.render_info(&format!("Resuming local session ({})", session.summary.id))
+        );
+    }
+
+    let client = client.with_model(model);
+    print!(
+        "{}",
+        renderer.render_info(&format!("Using model {}", client.model()))
+    );
+
     if let Some(system_prompt) = config.system_prompt {
         chat_messages.insert(0, system_message(system_prompt));
         print!("{}", renderer.render_info("System prompt loaded"));
     }
 
     // Add user prompt if provided
-    if !config.prompt.is_empty() {
+    if !config.prompt.is_empty() || !config.attachments.is_empty() {
+        let secret_manager = SecretManager::new(config.redact_secrets, config.privacy_mode);
+        let attachments = load_attachments(&config.attachments, &secret_manager)?;
+        for attachment in &attachments {
+            print!(
+                "{}",
+                renderer.render_info(&format!("Attached {}", attachment.name))
+            );
+        }
+
         let (user_input, _local_context) =
             add_local_context(&chat_messages, &config.prompt, &config.local_context)
                 .await
@@ -152,7 +212,7 @@ pub async fn run_async(ctx: AppConfig, config: RunAsyncConfig) -> Result<(), Str
             add_rulebooks(&chat_messages, &user_input, &config.rulebooks);
         let (user_input, _subagents_text) =
             add_subagents(&chat_messages, &user_input, &config.subagent_configs);
-        chat_messages.push(user_message(user_input));
+        chat_messages.push(user_message_with_attachments(user_input, &attachments));
     }
 
     let mut step = 0;
@@ -161,7 +221,9 @@ pub async fn run_async(ctx: AppConfig, config: RunAsyncConfig) -> Result<(), Str
     print!("{}", renderer.render_info("Starting execution..."));
     print!("{}", renderer.render_section_break());
 
-    let mut current_session_id: Option<Uuid> = None;
+    let mut session_usage = SessionUsage::default();
+    let mut budget_exceeded = None;
+    let compaction = ctx.compaction.clone().unwrap_or_default();
 
     loop {
         step += 1;
@@ -176,6 +238,52 @@ pub async fn run_async(ctx: AppConfig, config: RunAsyncConfig) -> Result<(), Str
             break;
         }
 
+        if let Some(budget) = &ctx.budget
+            && let Some(exceeded) = session_usage.exceeded(budget)
+        {
+            print!(
+                "{}",
+                renderer.render_warning(&format!("{}, stopping execution", exceeded))
+            );
+            budget_exceeded = Some(exceeded);
+            break;
+        }
+
+        let estimated_tokens = estimate_tokens(&chat_messages);
+        if compaction.is_enabled() && estimated_tokens > compaction.threshold_tokens() {
+            let llm_start = Instant::now();
+            let compacted = compact_messages(
+                &client,
+                &chat_messages,
+                compaction.keep_recent_messages(),
+                &mut session_usage,
+                ctx.pricing.as_ref(),
+            )
+            .await;
+            llm_response_time += llm_start.elapsed();
+            match compacted {
+                Ok(Some(compacted)) => {
+                    chat_messages = compacted;
+                    print!(
+                        "{}",
+                        renderer.render_info(&format!(
+                            "Compacted conversation from ~{} to ~{} tokens",
+                            format_tokens(estimated_tokens),
+                            format_tokens(estimate_tokens(&chat_messages))
+                        ))
+                    );
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    print!(
+                        "{}",
+                        renderer
+                            .render_warning(&format!("Failed to compact the conversation: {}", e))
+                    );
+                }
+            }
+        }
+
         // Make chat completion request
         let llm_start = Instant::now();
         let response = client
@@ -183,6 +291,7 @@ pub async fn run_async(ctx: AppConfig, config: RunAsyncConfig) -> Result<(), Str
             .await
             .map_err(|e| e.to_string())?;
         llm_response_time += llm_start.elapsed();
+        session_usage.record_turn(&response.usage, &response.model, ctx.pricing.as_ref());
 
         chat_messages.push(response.choices[0].message.clone());
 
@@ -193,6 +302,14 @@ pub async fn run_async(ctx: AppConfig, config: RunAsyncConfig) -> Result<(), Str
         {
             current_session_id = Some(checkpoint_with_session.session.id);
         }
+        if let Err(e) = record_session(
+            &mut session_recorder,
+            &chat_messages,
+            client.model(),
+            current_session_id,
+        ) {
+            print!("{}", renderer.render_warning(&e));
+        }
 
         let tool_calls = response.choices[0].message.tool_calls.as_ref();
         let tool_count = tool_calls.map(|t| t.len()).unwrap_or(0);
@@ -300,6 +417,16 @@ pub async fn run_async(ctx: AppConfig, config: RunAsyncConfig) -> Result<(), Str
         }
     }
 
+    // Tool results of the last step
+    if let Err(e) = record_session(
+        &mut session_recorder,
+        &chat_messages,
+        client.model(),
+        current_session_id,
+    ) {
+        print!("{}", renderer.render_warning(&e));
+    }
+
     // Extract final checkpoint if available
     let latest_checkpoint = chat_messages
         .iter()
@@ -345,7 +472,29 @@ pub async fn run_async(ctx: AppConfig, config: RunAsyncConfig) -> Result<(), Str
         )
     );
 
-    print!("{}", renderer.render_final_completion(&chat_messages));
+    print!(
+        "{}",
+        renderer.render_stat_line(
+            "Tokens used",
+            &format!(
+                "{} ({} prompt, {} completion)",
+                format_tokens(session_usage.total.total_tokens()),
+                format_tokens(session_usage.total.prompt_tokens),
+                format_tokens(session_usage.total.completion_tokens)
+            )
+        )
+    );
+    if let Some(cost) = session_usage.total.cost {
+        print!(
+            "{}",
+            renderer.render_stat_line("Estimated cost", &format!("${:.4}", cost))
+        );
+    }
+
+    print!(
+        "{}",
+        renderer.render_final_completion(&chat_messages, &session_usage, budget_exceeded.as_ref())
+    );
 
     // Save conversation to file
     let conversation_json = serde_json::to_string_pretty(&chat_messages).unwrap_or_default();
@@ -1,15 +1,19 @@
 use crate::agent::run::helpers::system_message;
 use crate::commands::agent::run::checkpoint::{
     extract_checkpoint_id_from_messages, extract_checkpoint_messages_and_tool_calls,
-    get_checkpoint_messages, resume_session_from_checkpoint,
+    get_checkpoint_messages, resume_local_session, resume_session_from_checkpoint,
 };
+use crate::commands::agent::run::compaction::{Compaction, compact_conversation, estimate_tokens};
 use crate::commands::agent::run::helpers::{
-    add_local_context, add_rulebooks, add_subagents, convert_tools_map_with_filter,
-    tool_call_history_string, tool_result, user_message,
+    add_local_context, add_rulebooks, add_subagents, client_error_event_message,
+    convert_tools_map_with_filter, load_attachments, record_session, tool_call_history_string,
+    tool_result, user_message_with_attachments,
 };
 use crate::commands::agent::run::renderer::{OutputFormat, OutputRenderer};
-use crate::commands::agent::run::stream::process_responses_stream;
-use crate::commands::agent::run::tooling::{list_sessions, run_tool_call};
+use crate::commands::agent::run::stream::{
+    MAX_STREAM_RESUME_ATTEMPTS, StreamedTurn, process_responses_stream,
+};
+use crate::commands::agent::run::tooling::{list_local_sessions, list_sessions, run_tool_call};
 use crate::commands::agent::run::tui::{send_input_event, send_tool_call};
 use crate::config::AppConfig;
 use crate::utils::check_update::get_latest_cli_version;
@@ -24,6 +28,9 @@ use stakpak_shared::cert_utils::CertificateChain;
 use stakpak_shared::models::integrations::mcp::CallToolResultExt;
 use stakpak_shared::models::integrations::openai::{ChatMessage, ToolCall, ToolCallResultStatus};
 use stakpak_shared::models::subagent::SubagentConfigs;
+use stakpak_shared::models::usage::SessionUsage;
+use stakpak_shared::secret_manager::SecretManager;
+use stakpak_shared::session_store::{LocalSession, SessionStore};
 use stakpak_tui::{InputEvent, LoadingOperation, OutputEvent};
 use std::sync::Arc;
 use uuid::Uuid;
@@ -44,6 +51,10 @@ pub struct RunInteractiveConfig {
     pub allowed_tools: Option<Vec<String>>,
     pub auto_approve: Option<Vec<String>>,
     pub enabled_tools: EnabledToolsConfig,
+    /// Model to use instead of the profile's or a resumed checkpoint's
+    pub model: Option<String>,
+    /// Session from the local session store to carry on with
+    pub local_session: Option<LocalSession>,
 }
 
 pub async fn run_interactive(
@@ -71,6 +82,8 @@ pub async fn run_interactive(
         let auto_approve = config.auto_approve.clone();
         let enabled_tools = config.enabled_tools.clone();
         let redact_secrets = config.redact_secrets;
+        // Resumed once, not again after a profile switch
+        let local_session = config.local_session.take();
         let privacy_mode = config.privacy_mode;
         let enable_mtls = config.enable_mtls;
         let is_git_repo = config.is_git_repo;
@@ -167,19 +180,30 @@ pub async fn run_interactive(
         });
 
         // Spawn client task
+        let budget = ctx.budget.clone();
+        let compaction = ctx.compaction.clone().unwrap_or_default();
+        let pricing = ctx.pricing.clone();
+        let requested_model = config.model.clone();
+        let default_model = ctx.model.clone();
+        let models_config = ctx.clone();
         let api_key_for_client = api_key.clone();
         let api_endpoint_for_client = api_endpoint.clone();
         let shutdown_tx_for_client = shutdown_tx.clone();
         let client_handle: tokio::task::JoinHandle<ClientTaskResult> = tokio::spawn(async move {
             let mut current_session_id: Option<Uuid> = None;
-            let client = Client::new(&ClientConfig {
+            let mut client = Client::new(&ClientConfig {
                 api_key: api_key_for_client.clone(),
                 api_endpoint: api_endpoint_for_client.clone(),
             })
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| e.to_string())?
+            .with_model(requested_model.clone().or(default_model));
 
             let data = client.get_my_account().await?;
             send_input_event(&input_tx, InputEvent::GetStatus(data.to_text())).await?;
+            let session_store = SessionStore::default_store();
+            let mut session_recorder = session_store.recorder(&ctx.profile_name);
+            // Whether the sessions dialog lists sessions from the local store
+            let mut sessions_from_local_store = false;
             // Load available profiles and send to TUI
             let profiles_config_path = ctx.config_path.clone();
             let current_profile_name = ctx.profile_name.clone();
@@ -205,6 +229,12 @@ pub async fn run_interactive(
                     client.get_agent_checkpoint(checkpoint_uuid).await
                 {
                     current_session_id = Some(checkpoint_with_session.session.id);
+                    // Carry on with the checkpoint's model unless one was asked for
+                    if requested_model.is_none()
+                        && let Some(model) = checkpoint_with_session.output.model()
+                    {
+                        client = client.with_model(Some(model.to_string()));
+                    }
                 }
 
                 let checkpoint_messages =
@@ -229,16 +259,58 @@ pub async fn run_interactive(
                 messages.extend(chat_messages);
             }
 
+            if let Some(session) = local_session {
+                current_session_id = session.summary.remote_session_id;
+                if requested_model.is_none()
+                    && let Some(model) = &session.summary.model
+                {
+                    client = client.with_model(Some(model.clone()));
+                }
+
+                let (chat_messages, tool_calls) = resume_local_session(&session, &input_tx).await;
+                tools_queue.extend(tool_calls);
+                if !tools_queue.is_empty() {
+                    send_input_event(&input_tx, InputEvent::MessageToolCalls(tools_queue.clone()))
+                        .await?;
+                    let initial_tool_call = tools_queue.remove(0);
+                    send_tool_call(&input_tx, &initial_tool_call).await?;
+                }
+                messages.extend(chat_messages);
+                session_recorder = session_store.resume_recorder(&session, &ctx.profile_name);
+            }
+
+            send_input_event(
+                &input_tx,
+                InputEvent::ModelsLoaded(models_config.model_names(), client.model().to_string()),
+            )
+            .await?;
+
             if let Some(system_prompt_text) = system_prompt {
                 messages.insert(0, system_message(system_prompt_text));
             }
 
             let mut retry_attempts = 0;
             const MAX_RETRY_ATTEMPTS: u32 = 2;
+            let mut session_usage = SessionUsage::default();
+            // Shares its redaction map with the local tools, which can restore the secrets
+            let secret_manager = SecretManager::new(redact_secrets, privacy_mode);
 
             while let Some(output_event) = output_rx.recv().await {
                 match output_event {
-                    OutputEvent::UserMessage(user_input, tool_calls_results) => {
+                    OutputEvent::UserMessage(user_input, tool_calls_results, attachment_paths) => {
+                        let attachments = match load_attachments(&attachment_paths, &secret_manager)
+                        {
+                            Ok(attachments) => attachments,
+                            Err(e) => {
+                                send_input_event(
+                                    &input_tx,
+                                    InputEvent::Error(format!("{}. The message was not sent.", e)),
+                                )
+                                .await?;
+                                continue;
+                            }
+                        };
+
                         // Loading will be managed by stream processing
                         let mut user_input = user_input.clone();
 
@@ -272,7 +344,7 @@ pub async fn run_interactive(
                         send_input_event(&input_tx, InputEvent::HasUserMessage).await?;
                         send_input_event(&input_tx, InputEvent::ResetAutoApproveMessage).await?;
                         tools_queue.clear();
-                        messages.push(user_message(user_input));
+                        messages.push(user_message_with_attachments(user_input, &attachments));
                     }
                     OutputEvent::AcceptTool(tool_call) => {
                         send_input_event(
@@ -375,7 +447,21 @@ pub async fn run_interactive(
                             InputEvent::StartLoadingOperation(LoadingOperation::SessionsList),
                         )
                         .await?;
-                        match list_sessions(&client).await {
+                        let sessions = match list_sessions(&client).await {
+                            Ok(sessions) => {
+                                sessions_from_local_store = false;
+                                Ok(sessions)
+                            }
+                            // Offline, fall back to the sessions stored on this machine
+                            Err(e) => match list_local_sessions(&session_store) {
+                                Ok(local_sessions) if !local_sessions.is_empty() => {
+                                    sessions_from_local_store = true;
+                                    Ok(local_sessions)
+                                }
+                                _ => Err(e),
+                            },
+                        };
+                        match sessions {
                             Ok(sessions) => {
                                 send_input_event(&input_tx, InputEvent::SetSessions(sessions))
                                     .await?;
@@ -417,9 +503,27 @@ pub async fn run_interactive(
                             match resume_session_from_checkpoint(&client, session_id, &input_tx)
                                 .await
                             {
-                                Ok((chat_messages, tool_calls, session_id_uuid)) => {
+                                Ok((chat_messages, tool_calls, session_id_uuid, model)) => {
                                     // Track the current session ID
                                     current_session_id = Some(session_id_uuid);
+                                    // Keep recording into the local copy of the session if there is one
+                                    session_recorder =
+                                        match session_store.load(&session_id_uuid.to_string()) {
+                                            Ok(local_session) => session_store
+                                                .resume_recorder(&local_session, &ctx.profile_name),
+                                            Err(_) => session_store.recorder(&ctx.profile_name),
+                                        };
+
+                                    if requested_model.is_none()
+                                        && let Some(model) = model
+                                    {
+                                        client = client.with_model(Some(model.clone()));
+                                        send_input_event(
+                                            &input_tx,
+                                            InputEvent::ModelSwitched(model),
+                                        )
+                                        .await?;
+                                    }
 
                                     // Mark that we need to update rulebooks on the next user message
                                     should_update_rulebooks_on_next_message = true;
@@ -471,11 +575,69 @@ pub async fn run_interactive(
                             InputEvent::StartLoadingOperation(LoadingOperation::CheckpointResume),
                         )
                         .await?;
+                        if sessions_from_local_store {
+                            match session_store.load(&session_id) {
+                                Ok(session) => {
+                                    current_session_id = session.summary.remote_session_id;
+                                    if requested_model.is_none()
+                                        && let Some(model) = session.summary.model.clone()
+                                    {
+                                        client = client.with_model(Some(model.clone()));
+                                        send_input_event(
+                                            &input_tx,
+                                            InputEvent::ModelSwitched(model),
+                                        )
+                                        .await?;
+                                    }
+
+                                    let (chat_messages, tool_calls) =
+                                        resume_local_session(&session, &input_tx).await;
+                                    messages.extend(chat_messages);
+                                    tools_queue.extend(tool_calls);
+                                    session_recorder =
+                                        session_store.resume_recorder(&session, &ctx.profile_name);
+
+                                    if !tools_queue.is_empty() {
+                                        send_input_event(
+                                            &input_tx,
+                                            InputEvent::MessageToolCalls(tools_queue.clone()),
+                                        )
+                                        .await?;
+                                        let initial_tool_call = tools_queue.remove(0);
+                                        send_tool_call(&input_tx, &initial_tool_call).await?;
+                                    }
+                                }
+                                Err(e) => {
+                                    send_input_event(&input_tx, InputEvent::Error(e)).await?;
+                                }
+                            }
+                            send_input_event(
+                                &input_tx,
+                                InputEvent::EndLoadingOperation(LoadingOperation::CheckpointResume),
+                            )
+                            .await?;
+                            continue;
+                        }
                         match resume_session_from_checkpoint(&client, &session_id, &input_tx).await
                         {
-                            Ok((chat_messages, tool_calls, session_id_uuid)) => {
+                            Ok((chat_messages, tool_calls, session_id_uuid, model)) => {
                                 // Track the current session ID
                                 current_session_id = Some(session_id_uuid);
+                                // Keep recording into the local copy of the session if there is one
+                                session_recorder =
+                                    match session_store.load(&session_id_uuid.to_string()) {
+                                        Ok(local_session) => session_store
+                                            .resume_recorder(&local_session, &ctx.profile_name),
+                                        Err(_) => session_store.recorder(&ctx.profile_name),
+                                    };
+
+                                if requested_model.is_none()
+                                    && let Some(model) = model
+                                {
+                                    client = client.with_model(Some(model.clone()));
+                                    send_input_event(&input_tx, InputEvent::ModelSwitched(model))
+                                        .await?;
+                                }
 
                                 // Mark that we need to update rulebooks on the next user message
                                 should_update_rulebooks_on_next_message = true;
@@ -545,6 +707,35 @@ pub async fn run_interactive(
                             continue;
                         }
                     }
+                    OutputEvent::SwitchModel(name) => {
+                        let model = models_config.resolve_model(&name);
+                        client = client.with_model(Some(model.clone()));
+                        send_input_event(&input_tx, InputEvent::ModelSwitched(model)).await?;
+                        continue;
+                    }
+                    OutputEvent::Compact => {
+                        if Compaction::plan(&messages, compaction.keep_recent_messages()).is_none()
+                        {
+                            send_input_event(
+                                &input_tx,
+                                InputEvent::Error(
+                                    "The conversation is too short to compact yet.".to_string(),
+                                ),
+                            )
+                            .await?;
+                        } else {
+                            compact_conversation(
+                                &client,
+                                &mut messages,
+                                compaction.keep_recent_messages(),
+                                &mut session_usage,
+                                pricing.as_ref(),
+                                &input_tx,
+                            )
+                            .await?;
+                        }
+                        continue;
+                    }
                     OutputEvent::Memorize => {
                         let checkpoint_id = extract_checkpoint_id_from_messages(&messages);
                         if let Some(checkpoint_id) = checkpoint_id {
@@ -616,10 +807,53 @@ pub async fn run_interactive(
                         let _ = shutdown_tx_for_client.send(());
 
                         // Return new config to trigger outer loop restart
+                        let _ = record_session(
+                            &mut session_recorder,
+                            &messages,
+                            client.model(),
+                            current_session_id,
+                        );
                         return Ok((messages, current_session_id, Some(new_config)));
                     }
                 }
 
+                if let Some(budget) = &budget
+                    && let Some(exceeded) = session_usage.exceeded(budget)
+                {
+                    send_input_event(
+                        &input_tx,
+                        InputEvent::Error(format!(
+                            "{}. Raise the budget of profile '{}' or start a new session to continue.",
+                            exceeded, ctx.profile_name
+                        )),
+                    )
+                    .await?;
+                    continue;
+                }
+
+                if compaction.is_enabled()
+                    && estimate_tokens(&messages) > compaction.threshold_tokens()
+                {
+                    compact_conversation(
+                        &client,
+                        &mut messages,
+                        compaction.keep_recent_messages(),
+                        &mut session_usage,
+                        pricing.as_ref(),
+                        &input_tx,
+                    )
+                    .await?;
+                }
+
+                if let Err(e) = record_session(
+                    &mut session_recorder,
+                    &messages,
+                    client.model(),
+                    current_session_id,
+                ) {
+                    send_input_event(&input_tx, InputEvent::Error(e)).await?;
+                }
+
                 let headers = if study_mode {
                     let mut headers = HeaderMap::new();
                     #[allow(clippy::unwrap_used)]
@@ -628,10 +862,12 @@ pub async fn run_interactive(
                 } else {
                     None
                 };
+                let mut turn = StreamedTurn::new();
+                let mut resume_attempts = 0;
                 let response_result = loop {
                     let stream_result = client
                         .chat_completion_stream(
-                            messages.clone(),
+                            turn.request_messages(&messages),
                             Some(tools.clone()),
                             headers.clone(),
                         )
@@ -640,8 +876,19 @@ pub async fn run_interactive(
                     let (mut stream, current_request_id) = match stream_result {
                         Ok(result) => result,
                         Err(e) => {
-                            send_input_event(&input_tx, InputEvent::Error(e.clone())).await?;
-                            break Err(ApiStreamError::Unknown(e));
+                            send_input_event(
+                                &input_tx,
+                                InputEvent::EndLoadingOperation(
+                                    LoadingOperation::StreamReconnecting,
+                                ),
+                            )
+                            .await?;
+                            send_input_event(
+                                &input_tx,
+                                InputEvent::Error(client_error_event_message(&e)),
+                            )
+                            .await?;
+                            break Err(ApiStreamError::Unknown(e.to_string()));
                         }
                     };
 
@@ -650,23 +897,46 @@ pub async fn run_interactive(
 
                     // Race between stream processing and cancellation
                     match tokio::select! {
-                        result = process_responses_stream(&mut stream, &input_tx) => result,
+                        result = process_responses_stream(&mut stream, &mut turn, &input_tx) => result,
                         _ = cancel_rx_iter.recv() => {
                             // Stream was cancelled
                             if let Some(request_id) = &current_request_id {
                                 client.cancel_stream(request_id.clone()).await?;
                             }
                             // End any ongoing loading operation
+                            send_input_event(&input_tx, InputEvent::EndLoadingOperation(LoadingOperation::StreamReconnecting)).await?;
                             send_input_event(&input_tx, InputEvent::EndLoadingOperation(LoadingOperation::StreamProcessing)).await?;
                             send_input_event(&input_tx, InputEvent::Error("STREAM_CANCELLED".to_string())).await?;
                             break Err(ApiStreamError::Unknown("Stream cancelled by user".to_string()));
                         }
                     } {
-                        Ok(response) => {
+                        Ok(()) => {
                             retry_attempts = 0;
-                            break Ok(response);
+                            break Ok(std::mem::take(&mut turn).into_response());
+                        }
+                        Err(ApiStreamError::ConnectionLost(_))
+                            if resume_attempts < MAX_STREAM_RESUME_ATTEMPTS =>
+                        {
+                            // Keep what was streamed and ask for the rest of the turn
+                            resume_attempts += 1;
+                            turn.prepare_resume();
+                            send_input_event(
+                                &input_tx,
+                                InputEvent::StartLoadingOperation(
+                                    LoadingOperation::StreamReconnecting,
+                                ),
+                            )
+                            .await?;
+                            continue;
                         }
                         Err(e) => {
+                            send_input_event(
+                                &input_tx,
+                                InputEvent::EndLoadingOperation(
+                                    LoadingOperation::StreamReconnecting,
+                                ),
+                            )
+                            .await?;
                             if matches!(e, ApiStreamError::AgentInvalidResponseStream) {
                                 if retry_attempts < MAX_RETRY_ATTEMPTS {
                                     retry_attempts += 1;
@@ -679,6 +949,8 @@ pub async fn run_interactive(
                                     )
                                     .await?;
 
+                                    // The TUI drops the partial message, so start the turn over
+                                    turn = StreamedTurn::new();
                                     // Loading will be managed by stream processing on retry
                                     continue;
                                 } else {
@@ -700,6 +972,16 @@ pub async fn run_interactive(
 
                 match response_result {
                     Ok(response) => {
+                        session_usage.record_turn(
+                            &response.usage,
+                            &response.model,
+                            pricing.as_ref(),
+                        );
+                        send_input_event(
+                            &input_tx,
+                            InputEvent::UsageUpdated(session_usage.clone()),
+                        )
+                        .await?;
                         messages.push(response.choices[0].message.clone());
 
                         if current_session_id.is_none()
@@ -711,6 +993,14 @@ pub async fn run_interactive(
                         {
                             current_session_id = Some(checkpoint_with_session.session.id);
                         }
+                        if let Err(e) = record_session(
+                            &mut session_recorder,
+                            &messages,
+                            client.model(),
+                            current_session_id,
+                        ) {
+                            send_input_event(&input_tx, InputEvent::Error(e)).await?;
+                        }
 
                         // Send tool calls to TUI if present
                         if let Some(tool_calls) = &response.choices[0].message.tool_calls {
@@ -740,6 +1030,13 @@ pub async fn run_interactive(
                 }
             }
 
+            // Tool results that came in after the last response
+            let _ = record_session(
+                &mut session_recorder,
+                &messages,
+                client.model(),
+                current_session_id,
+            );
             Ok((messages, current_session_id, None))
         });
 
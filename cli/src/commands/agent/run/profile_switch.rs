@@ -1,13 +1,10 @@
 use crate::config::AppConfig;
 use stakpak_api::Client;
-use tokio::time::Duration;
-
-const MAX_RETRIES: u32 = 2;
 
 /// Validate profile switch before committing to it
 /// - Loads the new profile configuration
 /// - Inherits API key from default if new profile doesn't have one
-/// - Validates API key with retry logic
+/// - Validates API key, retrying transient failures
 pub async fn validate_profile_switch(
     new_profile: &str,
     config_path: Option<&str>,
@@ -29,29 +26,14 @@ pub async fn validate_profile_switch(
         }
     }
 
-    // 3. Test API key with retry logic
+    // 3. Test API key, the client retries transient failures
     let client = Client::new(&new_config.clone().into())
         .map_err(|e| format!("Failed to create API client: {}", e))?;
 
-    let mut last_error = String::new();
-    for attempt in 1..=MAX_RETRIES {
-        match client.get_my_account().await {
-            Ok(_) => {
-                // Success!
-                return Ok(new_config);
-            }
-            Err(e) => {
-                last_error = e;
-                if attempt < MAX_RETRIES {
-                    // Wait before retry (exponential backoff)
-                    tokio::time::sleep(Duration::from_secs(attempt as u64)).await;
-                }
-            }
-        }
-    }
+    client
+        .get_my_account()
+        .await
+        .map_err(|e| format!("API validation failed: {}", e))?;
 
-    Err(format!(
-        "API validation failed after {} attempts: {}",
-        MAX_RETRIES, last_error
-    ))
+    Ok(new_config)
 }
@@ -2,6 +2,7 @@ use crossterm::style::{Color, Stylize};
 use serde_json::Value;
 use stakpak_api::models::{AgentSessionStats, ToolUsageStats};
 use stakpak_shared::models::integrations::openai::ChatMessage;
+use stakpak_shared::models::usage::{BudgetExceeded, SessionUsage};
 use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -235,23 +236,41 @@ impl OutputRenderer {
         }
     }
 
-    pub fn render_final_completion(&self, messages: &[ChatMessage]) -> String {
+    pub fn render_final_completion(
+        &self,
+        messages: &[ChatMessage],
+        usage: &SessionUsage,
+        budget_exceeded: Option<&BudgetExceeded>,
+    ) -> String {
         match self.format {
             OutputFormat::Json => {
-                if self.verbose {
-                    serde_json::to_string_pretty(messages).unwrap_or_default()
+                let mut usage = serde_json::to_value(usage).unwrap_or_default();
+                if let (Some(usage), Some(budget_exceeded)) =
+                    (usage.as_object_mut(), budget_exceeded)
+                {
+                    usage.insert(
+                        "budget_exceeded".to_string(),
+                        Value::String(budget_exceeded.to_string()),
+                    );
+                }
+
+                let output = if self.verbose {
+                    serde_json::json!({ "messages": messages, "usage": usage })
                 } else {
                     // Find the last assistant message
                     let final_message = messages.iter().rev().find(|m| {
                         m.role == stakpak_shared::models::integrations::openai::Role::Assistant
                     });
 
-                    if let Some(message) = final_message {
-                        serde_json::to_string_pretty(message).unwrap_or_default()
-                    } else {
-                        "{}".to_string()
+                    let mut output = final_message
+                        .and_then(|message| serde_json::to_value(message).ok())
+                        .unwrap_or_else(|| serde_json::json!({}));
+                    if let Some(output) = output.as_object_mut() {
+                        output.insert("usage".to_string(), usage);
                     }
-                }
+                    output
+                };
+                serde_json::to_string_pretty(&output).unwrap_or_default()
             }
             OutputFormat::Text => {
                 // if self.verbose {
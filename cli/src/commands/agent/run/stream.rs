@@ -8,35 +8,236 @@ use stakpak_shared::models::integrations::openai::{
 use stakpak_tui::{InputEvent, LoadingOperation};
 use uuid::Uuid;
 
-pub async fn process_responses_stream(
-    stream: impl Stream<Item = Result<ChatCompletionStreamResponse, ApiStreamError>>,
-    input_tx: &tokio::sync::mpsc::Sender<InputEvent>,
-) -> Result<ChatCompletionResponse, ApiStreamError> {
-    let mut stream = Box::pin(stream);
+/// How many times a turn is resumed after its stream is cut off mid-response
+pub const MAX_STREAM_RESUME_ATTEMPTS: u32 = 3;
 
-    let mut chat_completion_response = ChatCompletionResponse {
-        id: "".to_string(),
-        object: "".to_string(),
-        created: 0,
-        model: "".to_string(),
-        choices: vec![],
-        usage: Usage {
-            prompt_tokens: 0,
-            completion_tokens: 0,
-            total_tokens: 0,
-        },
-        system_fingerprint: None,
-    };
+/// The assistant turn streamed so far. It outlives a single stream so that a response cut
+/// off by a dropped connection can be continued by a new request instead of being lost.
+pub struct StreamedTurn {
+    message_id: Uuid,
+    response: ChatCompletionResponse,
+    message: ChatMessage,
+    /// Usage last reported by the current stream, which is cumulative for the stream and
+    /// so replaces rather than adds to what it reported before
+    stream_usage: Usage,
+    /// Tool calls kept from before the last reconnect, which the resumed stream's tool
+    /// call indexes are offset by
+    tool_call_offset: usize,
+    /// Set from a reconnect until the resumed stream delivers its first chunk
+    reconnecting: bool,
+}
 
-    let mut chat_message = ChatMessage {
-        role: Role::Assistant,
-        content: None,
-        name: None,
-        tool_calls: None,
-        tool_call_id: None,
-    };
-    let message_id = Uuid::new_v4();
+impl Default for StreamedTurn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamedTurn {
+    pub fn new() -> Self {
+        Self {
+            message_id: Uuid::new_v4(),
+            response: ChatCompletionResponse {
+                id: "".to_string(),
+                object: "".to_string(),
+                created: 0,
+                model: "".to_string(),
+                choices: vec![],
+                usage: Usage::default(),
+                system_fingerprint: None,
+            },
+            message: ChatMessage {
+                role: Role::Assistant,
+                content: None,
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            },
+            stream_usage: Usage::default(),
+            tool_call_offset: 0,
+            reconnecting: false,
+        }
+    }
 
+    fn content(&self) -> &str {
+        match &self.message.content {
+            Some(MessageContent::String(content)) => content,
+            _ => "",
+        }
+    }
+
+    fn tool_calls(&self) -> &[ToolCall] {
+        self.message.tool_calls.as_deref().unwrap_or_default()
+    }
+
+    /// Prepare to resume the turn on a new stream. Tool calls that were cut off are
+    /// dropped, since the model has to send them again in full.
+    pub fn prepare_resume(&mut self) {
+        if let Some(tool_calls) = &mut self.message.tool_calls {
+            tool_calls.retain(|tool_call| !tool_call.id.is_empty() && is_complete(tool_call));
+            self.tool_call_offset = tool_calls.len();
+        }
+        self.end_stream_usage();
+        self.reconnecting = true;
+    }
+
+    /// Messages to request the rest of the turn with. Once something has been streamed,
+    /// the partial response is sent back with a request to continue it, so the model
+    /// neither repeats it nor calls the same tools twice.
+    pub fn request_messages(&self, messages: &[ChatMessage]) -> Vec<ChatMessage> {
+        let content = self.content();
+        let tool_calls = self.tool_calls();
+        let mut request = messages.to_vec();
+        if content.is_empty() && tool_calls.is_empty() {
+            return request;
+        }
+
+        if !content.is_empty() {
+            request.push(ChatMessage {
+                role: Role::Assistant,
+                content: Some(MessageContent::String(content.to_string())),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            });
+        }
+        let mut instruction = "Your previous response was cut off by a connection error. \
+            Continue it exactly where it stopped, without repeating anything you already sent."
+            .to_string();
+        if !tool_calls.is_empty() {
+            instruction
+                .push_str("\n\nThese tool calls were already received and must not be sent again:");
+            for tool_call in tool_calls {
+                instruction.push_str(&format!(
+                    "\n- {}({})",
+                    tool_call.function.name, tool_call.function.arguments
+                ));
+            }
+        }
+        request.push(ChatMessage {
+            role: Role::User,
+            content: Some(MessageContent::String(instruction)),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+        request
+    }
+
+    fn apply(&mut self, response: &ChatCompletionStreamResponse) -> Option<String> {
+        self.response.id = response.id.clone();
+        self.response.object = response.object.clone();
+        self.response.created = response.created;
+        self.response.model = response.model.clone();
+        if let Some(usage) = &response.usage {
+            self.stream_usage = usage.clone();
+        }
+
+        let delta = &response.choices.first()?.delta;
+
+        if let Some(tool_calls) = &delta.tool_calls {
+            let tool_calls_vec = self.message.tool_calls.get_or_insert_with(Vec::new);
+            for delta_tool_call in tool_calls {
+                let index = self.tool_call_offset + delta_tool_call.index;
+                match tool_calls_vec.get_mut(index) {
+                    Some(tool_call) => {
+                        let delta_func =
+                            delta_tool_call
+                                .function
+                                .as_ref()
+                                .unwrap_or(&FunctionCallDelta {
+                                    name: None,
+                                    arguments: None,
+                                });
+                        tool_call.function.arguments = tool_call.function.arguments.clone()
+                            + delta_func.arguments.as_deref().unwrap_or("");
+                    }
+                    None => {
+                        // push empty tool calls until the index is reached
+                        tool_calls_vec.extend((tool_calls_vec.len()..index).map(|_| ToolCall {
+                            id: "".to_string(),
+                            r#type: "function".to_string(),
+                            function: FunctionCall {
+                                name: "".to_string(),
+                                arguments: "".to_string(),
+                            },
+                        }));
+
+                        tool_calls_vec.push(ToolCall {
+                            id: delta_tool_call.id.clone().unwrap_or_default(),
+                            r#type: "function".to_string(),
+                            function: FunctionCall {
+                                name: delta_tool_call
+                                    .function
+                                    .as_ref()
+                                    .unwrap_or(&FunctionCallDelta {
+                                        name: None,
+                                        arguments: None,
+                                    })
+                                    .name
+                                    .as_deref()
+                                    .unwrap_or("")
+                                    .to_string(),
+                                arguments: "".to_string(),
+                            },
+                        });
+                    }
+                }
+            }
+        }
+
+        let content = delta.content.as_ref()?;
+        self.message.content = Some(MessageContent::String(match self.message.content.take() {
+            Some(MessageContent::String(old_content)) => old_content + content,
+            _ => content.clone(),
+        }));
+        Some(content.clone())
+    }
+
+    /// Add the usage of the stream that ended to the turn's, so that a resumed turn
+    /// accounts for every request it took
+    fn end_stream_usage(&mut self) {
+        let usage = std::mem::take(&mut self.stream_usage);
+        self.response.usage.prompt_tokens += usage.prompt_tokens;
+        self.response.usage.completion_tokens += usage.completion_tokens;
+        self.response.usage.total_tokens += usage.total_tokens;
+    }
+
+    pub fn into_response(mut self) -> ChatCompletionResponse {
+        self.end_stream_usage();
+        // filter out empty tool calls
+        self.message.tool_calls = Some(
+            self.tool_calls()
+                .iter()
+                .filter(|tool_call| !tool_call.id.is_empty())
+                .cloned()
+                .collect::<Vec<ToolCall>>(),
+        );
+
+        self.response.choices.push(ChatCompletionChoice {
+            index: 0,
+            message: self.message,
+            finish_reason: FinishReason::Stop,
+            logprobs: None,
+        });
+        self.response
+    }
+}
+
+/// Whether a tool call was received in full. Calls without arguments may stream none.
+fn is_complete(tool_call: &ToolCall) -> bool {
+    let arguments = tool_call.function.arguments.trim();
+    arguments.is_empty() || serde_json::from_str::<serde_json::Value>(arguments).is_ok()
+}
+
+/// Stream the response into `turn`. A dropped connection or a response that ends before it
+/// is complete is reported as [`ApiStreamError::ConnectionLost`], leaving what was received
+/// in `turn` so it can be resumed.
+pub async fn process_responses_stream(
+    stream: impl Stream<Item = Result<ChatCompletionStreamResponse, ApiStreamError>>,
+    turn: &mut StreamedTurn,
+    input_tx: &tokio::sync::mpsc::Sender<InputEvent>,
+) -> Result<(), ApiStreamError> {
     // Start stream processing loading at the beginning
     send_input_event(
         input_tx,
@@ -44,129 +245,218 @@ pub async fn process_responses_stream(
     )
     .await?;
 
+    let result = stream_into_turn(stream, turn, input_tx).await;
+
+    // End stream processing loading however the stream ended
+    send_input_event(
+        input_tx,
+        InputEvent::EndLoadingOperation(LoadingOperation::StreamProcessing),
+    )
+    .await?;
+
+    result
+}
+
+async fn stream_into_turn(
+    stream: impl Stream<Item = Result<ChatCompletionStreamResponse, ApiStreamError>>,
+    turn: &mut StreamedTurn,
+    input_tx: &tokio::sync::mpsc::Sender<InputEvent>,
+) -> Result<(), ApiStreamError> {
+    let mut stream = Box::pin(stream);
     while let Some(response) = stream.next().await {
-        match &response {
-            Ok(response) => {
-                let delta = &response.choices[0].delta;
-
-                chat_completion_response = ChatCompletionResponse {
-                    id: response.id.clone(),
-                    object: response.object.clone(),
-                    created: response.created,
-                    model: response.model.clone(),
-                    choices: vec![],
-                    usage: Usage {
-                        prompt_tokens: 0,
-                        completion_tokens: 0,
-                        total_tokens: 0,
-                    },
-                    system_fingerprint: None,
-                };
-
-                if let Some(content) = &delta.content {
-                    chat_message.content =
-                        Some(MessageContent::String(match chat_message.content {
-                            Some(MessageContent::String(old_content)) => old_content + content,
-                            _ => content.clone(),
-                        }));
+        let response = response?;
+        if turn.reconnecting {
+            turn.reconnecting = false;
+            send_input_event(
+                input_tx,
+                InputEvent::EndLoadingOperation(LoadingOperation::StreamReconnecting),
+            )
+            .await?;
+        }
 
-                    send_input_event(
-                        input_tx,
-                        InputEvent::StreamAssistantMessage(message_id, content.clone()),
-                    )
-                    .await?;
-                }
+        if let Some(content) = turn.apply(&response) {
+            send_input_event(
+                input_tx,
+                InputEvent::StreamAssistantMessage(turn.message_id, content),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
 
-                if let Some(tool_calls) = &delta.tool_calls {
-                    for delta_tool_call in tool_calls {
-                        if chat_message.tool_calls.is_none() {
-                            chat_message.tool_calls = Some(vec![]);
-                        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stakpak_shared::models::integrations::openai::{
+        ChatCompletionStreamChoice, ChatMessageDelta, ToolCallDelta,
+    };
 
-                        let tool_calls_vec = chat_message.tool_calls.as_mut();
-                        if let Some(tool_calls_vec) = tool_calls_vec {
-                            match tool_calls_vec.get_mut(delta_tool_call.index) {
-                                Some(tool_call) => {
-                                    let delta_func = delta_tool_call.function.as_ref().unwrap_or(
-                                        &FunctionCallDelta {
-                                            name: None,
-                                            arguments: None,
-                                        },
-                                    );
-                                    tool_call.function.arguments =
-                                        tool_call.function.arguments.clone()
-                                            + delta_func.arguments.as_deref().unwrap_or("");
-                                }
-                                None => {
-                                    // push empty tool calls until the index is reached
-                                    tool_calls_vec.extend(
-                                        (tool_calls_vec.len()..delta_tool_call.index).map(|_| {
-                                            ToolCall {
-                                                id: "".to_string(),
-                                                r#type: "function".to_string(),
-                                                function: FunctionCall {
-                                                    name: "".to_string(),
-                                                    arguments: "".to_string(),
-                                                },
-                                            }
-                                        }),
-                                    );
-
-                                    tool_calls_vec.push(ToolCall {
-                                        id: delta_tool_call.id.clone().unwrap_or_default(),
-                                        r#type: "function".to_string(),
-                                        function: FunctionCall {
-                                            name: delta_tool_call
-                                                .function
-                                                .as_ref()
-                                                .unwrap_or(&FunctionCallDelta {
-                                                    name: None,
-                                                    arguments: None,
-                                                })
-                                                .name
-                                                .as_deref()
-                                                .unwrap_or("")
-                                                .to_string(),
-                                            arguments: "".to_string(),
-                                        },
-                                    });
-                                }
-                            }
+    fn finish_chunk() -> ChatCompletionStreamResponse {
+        let mut response = chunk(None, None);
+        response.choices[0].finish_reason = Some(FinishReason::Stop);
+        response
+    }
+
+    fn chunk(
+        content: Option<&str>,
+        tool_call: Option<(usize, Option<&str>, &str)>,
+    ) -> ChatCompletionStreamResponse {
+        ChatCompletionStreamResponse {
+            id: "chatcmpl-1".to_string(),
+            object: "chat.completion.chunk".to_string(),
+            created: 0,
+            model: "model".to_string(),
+            choices: vec![ChatCompletionStreamChoice {
+                index: 0,
+                delta: ChatMessageDelta {
+                    role: None,
+                    content: content.map(str::to_string),
+                    // The first delta of a tool call carries its id and name, the
+                    // following ones its arguments
+                    tool_calls: tool_call.map(|(index, id, arguments)| {
+                        let delta = |id: Option<&str>, arguments: &str| ToolCallDelta {
+                            index,
+                            id: id.map(str::to_string),
+                            r#type: None,
+                            function: Some(FunctionCallDelta {
+                                name: id.map(|_| "run_command".to_string()),
+                                arguments: Some(arguments.to_string()),
+                            }),
+                        };
+                        match id {
+                            Some(id) => vec![delta(Some(id), ""), delta(None, arguments)],
+                            None => vec![delta(None, arguments)],
                         }
-                    }
-                }
-            }
-            Err(e) => {
-                return Err(e.clone());
-            }
+                    }),
+                },
+                finish_reason: None,
+            }],
+            usage: None,
         }
     }
 
-    // filter out empty tool calls
-    chat_message.tool_calls = Some(
-        chat_message
+    #[test]
+    fn test_resumed_turn_keeps_partial_content_and_complete_tool_calls() {
+        let mut turn = StreamedTurn::new();
+        turn.apply(&chunk(Some("Checking the "), None));
+        turn.apply(&chunk(
+            None,
+            Some((0, Some("call_1"), "{\"command\":\"ls\"}")),
+        ));
+        turn.apply(&chunk(None, Some((1, Some("call_2"), "{\"comm"))));
+
+        turn.prepare_resume();
+        let messages = vec![ChatMessage {
+            role: Role::User,
+            content: Some(MessageContent::String("list files".to_string())),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }];
+        let request = turn.request_messages(&messages);
+        assert_eq!(request.len(), 3);
+        assert_eq!(request[1].role, Role::Assistant);
+        let instruction = request[2].content.as_ref().map(|c| c.to_string());
+        assert!(instruction.is_some_and(|i| i.contains("run_command({\"command\":\"ls\"})")));
+
+        // The resumed stream numbers its tool calls from zero again
+        turn.apply(&chunk(Some("files"), None));
+        turn.apply(&chunk(
+            None,
+            Some((0, Some("call_3"), "{\"command\":\"pwd\"}")),
+        ));
+        turn.apply(&finish_chunk());
+
+        let response = turn.into_response();
+        let message = &response.choices[0].message;
+        assert_eq!(
+            message.content.as_ref().map(|c| c.to_string()),
+            Some("Checking the files".to_string())
+        );
+        let ids = message
             .tool_calls
-            .as_ref()
-            .unwrap_or(&vec![])
             .iter()
-            .filter(|tool_call| !tool_call.id.is_empty())
-            .cloned()
-            .collect::<Vec<ToolCall>>(),
-    );
-
-    chat_completion_response.choices.push(ChatCompletionChoice {
-        index: 0,
-        message: chat_message.clone(),
-        finish_reason: FinishReason::Stop,
-        logprobs: None,
-    });
-
-    // End stream processing loading when stream completes
-    send_input_event(
-        input_tx,
-        InputEvent::EndLoadingOperation(LoadingOperation::StreamProcessing),
-    )
-    .await?;
+            .flatten()
+            .map(|tool_call| tool_call.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["call_1", "call_3"]);
+    }
 
-    Ok(chat_completion_response)
+    #[test]
+    fn test_usage_is_summed_across_resumed_streams() {
+        let usage_chunk = |prompt_tokens, completion_tokens| ChatCompletionStreamResponse {
+            choices: vec![],
+            usage: Some(Usage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            }),
+            ..chunk(None, None)
+        };
+        let mut turn = StreamedTurn::new();
+        turn.apply(&chunk(Some("Checking"), None));
+        turn.apply(&usage_chunk(100, 5));
+        // Streams that report usage on every chunk report it cumulatively
+        turn.apply(&usage_chunk(100, 10));
+        turn.prepare_resume();
+        turn.apply(&chunk(Some(" files"), None));
+        turn.apply(&usage_chunk(120, 20));
+
+        let usage = turn.into_response().usage;
+        assert_eq!(usage.prompt_tokens, 220);
+        assert_eq!(usage.completion_tokens, 30);
+        assert_eq!(usage.total_tokens, 250);
+    }
+
+    #[test]
+    fn test_fresh_turn_requests_original_messages() {
+        let turn = StreamedTurn::new();
+        assert!(turn.request_messages(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_loading_ends_when_the_stream_is_cut_off() {
+        let (input_tx, mut input_rx) = tokio::sync::mpsc::channel(16);
+        let mut turn = StreamedTurn::new();
+        let stream = futures_util::stream::iter(vec![
+            Ok(chunk(Some("The pod is crashing because"), None)),
+            Err(ApiStreamError::ConnectionLost("reset".to_string())),
+        ]);
+
+        let result = process_responses_stream(stream, &mut turn, &input_tx).await;
+        assert!(matches!(result, Err(ApiStreamError::ConnectionLost(_))));
+        assert_eq!(
+            turn.request_messages(&[])[0]
+                .content
+                .as_ref()
+                .map(|c| c.to_string()),
+            Some("The pod is crashing because".to_string())
+        );
+
+        drop(input_tx);
+        let mut events = Vec::new();
+        while let Some(event) = input_rx.recv().await {
+            events.push(event);
+        }
+        assert!(matches!(
+            events.last(),
+            Some(InputEvent::EndLoadingOperation(
+                LoadingOperation::StreamProcessing
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stream_without_finish_reason_is_complete() {
+        let (input_tx, _input_rx) = tokio::sync::mpsc::channel(16);
+        let mut turn = StreamedTurn::new();
+        let stream = futures_util::stream::iter(vec![Ok(chunk(Some("Done"), None))]);
+
+        assert!(
+            process_responses_stream(stream, &mut turn, &input_tx)
+                .await
+                .is_ok()
+        );
+    }
 }
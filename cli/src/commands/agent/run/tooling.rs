@@ -7,6 +7,7 @@ use stakpak_api::models::AgentSession;
 use stakpak_mcp_client::ClientManager;
 use stakpak_shared::models::integrations::mcp::CallToolResultExt;
 use stakpak_shared::models::integrations::openai::ToolCall;
+use stakpak_shared::session_store::SessionStore;
 use stakpak_tui::SessionInfo;
 use uuid::Uuid;
 
@@ -49,6 +50,24 @@ pub async fn list_sessions(client: &Client) -> Result<Vec<SessionInfo>, String>
     Ok(session_infos)
 }
 
+/// Sessions from the local session store, for when the backend cannot be reached
+pub fn list_local_sessions(store: &SessionStore) -> Result<Vec<SessionInfo>, String> {
+    Ok(store
+        .list()?
+        .into_iter()
+        .map(|session| SessionInfo {
+            id: session.id,
+            title: format!("[local] {}", session.title),
+            updated_at: session.updated_at.to_string(),
+            checkpoints: session
+                .checkpoints
+                .iter()
+                .map(|checkpoint_id| checkpoint_id.to_string())
+                .collect(),
+        })
+        .collect())
+}
+
 pub async fn run_tool_call(
     client_manager: &ClientManager,
     tools_map: &std::collections::HashMap<String, Vec<rmcp::model::Tool>>,
@@ -6,13 +6,17 @@ use crate::{
     utils::network,
 };
 use agent::AgentCommands;
-use clap::Subcommand;
+use clap::{Subcommand, ValueEnum};
 use flow::{clone, get_flow_ref, push};
+use sessions::SessionsCommands;
 use stakpak_api::{
     Client, ClientConfig,
     models::{Document, ProvisionerType, TranspileTargetProvisionerType},
 };
 use stakpak_mcp_server::{EnabledToolsConfig, MCPServerConfig, ToolMode, start_server};
+use stakpak_shared::secret_store::SecretStore;
+use stakpak_shared::secrets::audit::{self, ScanReport};
+use stakpak_shared::secrets::gitleaks::{initialize_gitleaks_config, validate_rule_files};
 use termimad::MadSkin;
 use walkdir::WalkDir;
 
@@ -20,6 +24,7 @@ pub mod acp;
 pub mod agent;
 pub mod auto_update;
 pub mod flow;
+pub mod sessions;
 pub mod warden;
 
 #[derive(Subcommand, PartialEq)]
@@ -28,6 +33,38 @@ pub enum ConfigCommands {
     Show,
     /// Print a complete sample configuration file
     Sample,
+    /// Check user and project secret detection rule files for errors
+    Validate,
+}
+
+#[derive(Subcommand, PartialEq)]
+pub enum SecretsCommands {
+    /// Wipe the encrypted secret redaction map of the current session
+    Purge {
+        /// Also delete the secrets key, making every map encrypted with it unreadable
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Scan files or git history for secrets with the rules used for redaction, exiting
+    /// with status 1 when any are found
+    Scan {
+        /// File or directory to scan, or the repository for --git-range
+        #[arg(default_value = ".")]
+        path: String,
+        /// Scan the lines added by the commits in a git revision range, e.g. main..HEAD
+        #[arg(long = "git-range")]
+        git_range: Option<String>,
+        /// Output format
+        #[arg(long, short, value_enum, default_value_t = ScanFormat::Text)]
+        format: ScanFormat,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum ScanFormat {
+    Text,
+    Json,
+    Sarif,
 }
 
 #[derive(Subcommand, PartialEq)]
@@ -66,6 +103,14 @@ pub enum Commands {
     #[command(subcommand)]
     Config(ConfigCommands),
 
+    /// Manage secrets stored by the agent during sessions
+    #[command(subcommand)]
+    Secrets(SecretsCommands),
+
+    /// Browse, resume and delete the agent sessions stored on this machine, which works offline
+    #[command(subcommand)]
+    Sessions(SessionsCommands),
+
     /// Get current account
     Account,
 
@@ -143,7 +188,7 @@ pub enum Commands {
         #[arg(long = "disable-secret-redaction", default_value_t = false)]
         disable_secret_redaction: bool,
 
-        /// Enable privacy mode to redact private data like IP addresses and AWS account IDs
+        /// Enable privacy mode to replace private data like IP addresses, emails and AWS account IDs with consistent pseudonyms
         #[arg(long = "privacy-mode", default_value_t = false)]
         privacy_mode: bool,
 
@@ -191,6 +236,8 @@ impl Commands {
                 | Commands::Logout
                 | Commands::Set { .. }
                 | Commands::Config(_)
+                | Commands::Secrets(_)
+                | Commands::Sessions(_)
                 | Commands::Version
                 | Commands::Update
                 | Commands::Acp { .. }
@@ -354,7 +401,37 @@ impl Commands {
                 ConfigCommands::Sample => {
                     print_sample_config();
                 }
+                ConfigCommands::Validate => {
+                    validate_secret_rules()?;
+                }
+            },
+            Commands::Secrets(secrets_command) => match secrets_command {
+                SecretsCommands::Purge { all } => {
+                    let removed = SecretStore::for_current_session().purge(all)?;
+                    if removed.is_empty() {
+                        println!("No stored secrets found");
+                    }
+                    for path in removed {
+                        println!("Removed {}", path.display());
+                    }
+                }
+                SecretsCommands::Scan {
+                    path,
+                    git_range,
+                    format,
+                } => {
+                    let path = std::path::Path::new(&path);
+                    let report = match git_range {
+                        Some(range) => audit::scan_git_range(path, &range)?,
+                        None => audit::scan_path(path)?,
+                    };
+                    print_scan_report(&report, format)?;
+                    if !report.findings.is_empty() {
+                        std::process::exit(1);
+                    }
+                }
             },
+            Commands::Sessions(sessions_command) => sessions_command.run()?,
             Commands::Account => {
                 let client = Client::new(&(config.into())).map_err(|e| e.to_string())?;
                 let data = client.get_my_account().await?;
@@ -609,6 +686,71 @@ impl Commands {
     }
 }
 
+fn print_scan_report(report: &ScanReport, format: ScanFormat) -> Result<(), String> {
+    match format {
+        ScanFormat::Text => print!("{}", audit::to_text(report)),
+        ScanFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report.findings).map_err(|e| e.to_string())?
+        ),
+        ScanFormat::Sarif => println!(
+            "{}",
+            serde_json::to_string_pretty(&audit::to_sarif(report, env!("CARGO_PKG_VERSION")))
+                .map_err(|e| e.to_string())?
+        ),
+    }
+    Ok(())
+}
+
+fn validate_secret_rules() -> Result<(), String> {
+    let reports = validate_rule_files();
+    if reports.is_empty() {
+        println!("No custom secret rule files found");
+        println!("  User rules:    ~/.stakpak/secrets/*.toml");
+        println!("  Project rules: .stakpak/secrets.toml");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for report in &reports {
+        let status = if report.errors.has_errors() {
+            failed += 1;
+            "ERROR"
+        } else {
+            "OK"
+        };
+        println!(
+            "{} {} ({} rules)",
+            status,
+            report.path.display(),
+            report.rule_count
+        );
+        for (path, error) in &report.errors.file_errors {
+            println!("  failed to load {}: {}", path, error);
+        }
+        for (rule_id, error) in &report.errors.regex_errors {
+            println!("  rule '{}': {}", rule_id, error);
+        }
+        for warning in &report.errors.warnings {
+            println!("  warning: {}", warning);
+        }
+    }
+
+    println!(
+        "Secret detection uses {} rules",
+        initialize_gitleaks_config(false)
+    );
+
+    if failed > 0 {
+        return Err(format!(
+            "{} of {} secret rule files have errors",
+            failed,
+            reports.len()
+        ));
+    }
+    Ok(())
+}
+
 fn print_sample_config() {
     println!(
         r#"# Stakpak Configuration File
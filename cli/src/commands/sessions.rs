@@ -0,0 +1,138 @@
+use clap::{Subcommand, ValueEnum};
+use stakpak_shared::models::integrations::openai::Role;
+use stakpak_shared::session_store::{LocalSession, SessionStore};
+
+#[derive(Subcommand, PartialEq)]
+pub enum SessionsCommands {
+    /// List the sessions stored on this machine
+    List,
+    /// Print a stored session
+    Show {
+        /// Session ID, a unique prefix of it, or the ID of its backend session
+        id: String,
+        /// Output format, json exports the full conversation
+        #[arg(long, short, value_enum, default_value_t = SessionFormat::Text)]
+        format: SessionFormat,
+    },
+    /// Continue a stored session in the agent, even when the backend has no copy of it
+    Resume {
+        /// Session ID, a unique prefix of it, or the ID of its backend session
+        id: String,
+    },
+    /// Delete a stored session
+    Rm {
+        /// Session ID, a unique prefix of it, or the ID of its backend session
+        id: String,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum SessionFormat {
+    Text,
+    Json,
+}
+
+impl SessionsCommands {
+    pub fn run(self) -> Result<(), String> {
+        let store = SessionStore::default_store();
+        match self {
+            SessionsCommands::List => {
+                let sessions = store.list()?;
+                if sessions.is_empty() {
+                    println!("No sessions stored in {}", store.dir().display());
+                }
+                for session in sessions {
+                    println!(
+                        "{}  {}  {:>4} messages  {}",
+                        &session.id[..8.min(session.id.len())],
+                        session
+                            .updated_at
+                            .with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M"),
+                        session.messages,
+                        session.title
+                    );
+                }
+            }
+            SessionsCommands::Show { id, format } => {
+                let session = store.load(&id)?;
+                match format {
+                    SessionFormat::Text => print_session(&session),
+                    SessionFormat::Json => {
+                        let json = serde_json::json!({
+                            "id": session.summary.id,
+                            "title": session.summary.title,
+                            "created_at": session.summary.created_at,
+                            "updated_at": session.summary.updated_at,
+                            "cwd": session.summary.cwd,
+                            "model": session.summary.model,
+                            "remote_session_id": session.summary.remote_session_id,
+                            "checkpoints": session.summary.checkpoints,
+                            "messages": session.messages,
+                        });
+                        let json = serde_json::to_string_pretty(&json)
+                            .map_err(|e| format!("Failed to serialize session: {}", e))?;
+                        println!("{}", json);
+                    }
+                }
+            }
+            SessionsCommands::Resume { .. } => {
+                // Resuming starts the agent, so it is handled where the agent is started
+                return Err("Sessions can only be resumed when starting the agent".to_string());
+            }
+            SessionsCommands::Rm { id } => {
+                let path = store.remove(&id)?;
+                println!("Removed {}", path.display());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_session(session: &LocalSession) {
+    let summary = &session.summary;
+    println!("Session {}", summary.id);
+    println!("Title: {}", summary.title);
+    println!(
+        "Updated: {}",
+        summary
+            .updated_at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+    );
+    if let Some(cwd) = &summary.cwd {
+        println!("Directory: {}", cwd);
+    }
+    if let Some(model) = &summary.model {
+        println!("Model: {}", model);
+    }
+    if let Some(remote_session_id) = &summary.remote_session_id {
+        println!("Backend session: {}", remote_session_id);
+    }
+    if let Some(checkpoint_id) = summary.checkpoints.last() {
+        println!("Latest checkpoint: {}", checkpoint_id);
+    }
+
+    for message in &session.messages {
+        if message.role == Role::System {
+            continue;
+        }
+        println!();
+        match message.role {
+            Role::Tool => println!("[tool result]"),
+            _ => println!("[{}]", message.role),
+        }
+        if let Some(content) = &message.content {
+            let content = content.to_string();
+            if !content.trim().is_empty() {
+                println!("{}", content.trim_end());
+            }
+        }
+        for tool_call in message.tool_calls.iter().flatten() {
+            println!(
+                "[tool call] {}({})",
+                tool_call.function.name, tool_call.function.arguments
+            );
+        }
+    }
+}
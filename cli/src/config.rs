@@ -1,6 +1,7 @@
 use config::ConfigError;
 use serde::{Deserialize, Serialize};
 use stakpak_api::{ClientConfig, ListRuleBook};
+use stakpak_shared::models::usage::{PriceTable, UsageBudget};
 use std::collections::HashMap;
 use std::fs::{create_dir_all, write};
 use std::path::Path;
@@ -17,6 +18,16 @@ pub struct RulebookConfig {
     pub exclude_tags: Option<Vec<String>>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CompactionConfig {
+    /// Compact the conversation automatically when it nears the context limit (default: true)
+    pub enabled: Option<bool>,
+    /// Estimated conversation size in tokens above which it is compacted
+    pub threshold_tokens: Option<u64>,
+    /// Number of most recent messages kept as they are when compacting
+    pub keep_recent_messages: Option<usize>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ProfileConfig {
     pub api_endpoint: Option<String>,
@@ -27,6 +38,21 @@ pub struct ProfileConfig {
     pub auto_approve: Option<Vec<String>>,
     /// Rulebook filtering configuration
     pub rulebooks: Option<RulebookConfig>,
+    /// Limits after which the agent stops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<UsageBudget>,
+    /// Model prices in USD per million tokens, used to track cost
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<PriceTable>,
+    /// Conversation compaction settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compaction: Option<CompactionConfig>,
+    /// Model used by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Alternative models by name, to switch to with `--model` or `/model`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub models: Option<HashMap<String, String>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -57,6 +83,16 @@ pub struct AppConfig {
     pub auto_approve: Option<Vec<String>>,
     /// Rulebook filtering configuration
     pub rulebooks: Option<RulebookConfig>,
+    /// Limits after which the agent stops
+    pub budget: Option<UsageBudget>,
+    /// Model prices in USD per million tokens, used to track cost
+    pub pricing: Option<PriceTable>,
+    /// Conversation compaction settings
+    pub compaction: Option<CompactionConfig>,
+    /// Model used by default
+    pub model: Option<String>,
+    /// Alternative models by name, to switch to with `--model` or `/model`
+    pub models: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -119,6 +155,11 @@ impl AppConfig {
                             allowed_tools: None,
                             auto_approve: None,
                             rulebooks: None,
+                            budget: None,
+                            pricing: None,
+                            compaction: None,
+                            model: None,
+                            models: None,
                         },
                     );
 
@@ -156,6 +197,11 @@ impl AppConfig {
                     allowed_tools: None,
                     auto_approve: None,
                     rulebooks: None,
+                    budget: None,
+                    pricing: None,
+                    compaction: None,
+                    model: None,
+                    models: None,
                 },
             );
 
@@ -211,6 +257,35 @@ impl AppConfig {
             .rulebooks
             .or_else(|| all_profile.and_then(|all| all.rulebooks.clone()));
 
+        let budget = profile
+            .budget
+            .or_else(|| all_profile.and_then(|all| all.budget.clone()));
+
+        let pricing = profile
+            .pricing
+            .or_else(|| all_profile.and_then(|all| all.pricing.clone()));
+
+        let compaction = profile
+            .compaction
+            .or_else(|| all_profile.and_then(|all| all.compaction.clone()));
+
+        let model = profile
+            .model
+            .or_else(|| all_profile.and_then(|all| all.model.clone()));
+
+        let models = profile
+            .models
+            .or_else(|| all_profile.and_then(|all| all.models.clone()));
+
+        // The default model may be given by one of the named models
+        let model = model.map(|model| {
+            models
+                .as_ref()
+                .and_then(|models| models.get(&model))
+                .cloned()
+                .unwrap_or(model)
+        });
+
         // Override with environment variables if present
         let api_key = std::env::var("STAKPAK_API_KEY").ok().or(api_key);
         let api_endpoint = std::env::var("STAKPAK_API_ENDPOINT").unwrap_or(api_endpoint);
@@ -226,6 +301,11 @@ impl AppConfig {
             allowed_tools,
             auto_approve,
             rulebooks,
+            budget,
+            pricing,
+            compaction,
+            model,
+            models,
         })
     }
 
@@ -280,6 +360,26 @@ impl AppConfig {
         })
     }
 
+    /// The model a name refers to: one of the profile's named models, or a model as is
+    pub fn resolve_model(&self, name: &str) -> String {
+        self.models
+            .as_ref()
+            .and_then(|models| models.get(name))
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// Names of the profile's alternative models, sorted
+    pub fn model_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .models
+            .as_ref()
+            .map(|models| models.keys().cloned().collect())
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
     pub fn save(&self) -> Result<(), String> {
         // Load existing config or create new one
         let mut config_file = if Path::new(&self.config_path).exists() {
@@ -306,6 +406,11 @@ impl AppConfig {
                 allowed_tools: self.allowed_tools.clone(),
                 auto_approve: self.auto_approve.clone(),
                 rulebooks: self.rulebooks.clone(),
+                budget: self.budget.clone(),
+                pricing: self.pricing.clone(),
+                compaction: self.compaction.clone(),
+                model: self.model.clone(),
+                models: self.models.clone(),
             },
         );
 
@@ -325,6 +430,25 @@ impl AppConfig {
     }
 }
 
+impl CompactionConfig {
+    const DEFAULT_THRESHOLD_TOKENS: u64 = 150_000;
+    const DEFAULT_KEEP_RECENT_MESSAGES: usize = 10;
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    pub fn threshold_tokens(&self) -> u64 {
+        self.threshold_tokens
+            .unwrap_or(Self::DEFAULT_THRESHOLD_TOKENS)
+    }
+
+    pub fn keep_recent_messages(&self) -> usize {
+        self.keep_recent_messages
+            .unwrap_or(Self::DEFAULT_KEEP_RECENT_MESSAGES)
+    }
+}
+
 impl RulebookConfig {
     /// Filter rulebooks based on the configuration rules
     pub fn filter_rulebooks(&self, rulebooks: Vec<ListRuleBook>) -> Vec<ListRuleBook> {
@@ -740,4 +864,36 @@ auto_append_gitignore = true
         let filtered = config.filter_rulebooks(rulebooks);
         assert_eq!(filtered.len(), original_count);
     }
+
+    #[test]
+    fn test_profile_models_inherit_from_all() {
+        let config_path =
+            std::env::temp_dir().join(format!("stakpak-config-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &config_path,
+            r#"
+[profiles.all]
+model = "pablo-v1"
+
+[profiles.all.models]
+fast = "claude-haiku-4-5"
+strong = "claude-opus-4-1"
+
+[profiles.ci]
+api_key = "key"
+model = "fast"
+
+[settings]
+"#,
+        )
+        .unwrap();
+
+        let config = AppConfig::load("ci", config_path.to_str()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+
+        assert_eq!(config.model.as_deref(), Some("claude-haiku-4-5"));
+        assert_eq!(config.model_names(), ["fast", "strong"]);
+        assert_eq!(config.resolve_model("strong"), "claude-opus-4-1");
+        assert_eq!(config.resolve_model("gpt-5"), "gpt-5");
+    }
 }
@@ -1,10 +1,14 @@
 use clap::Parser;
 use names::{self, Name};
 use rustls::crypto::CryptoProvider;
-use stakpak_api::{Client, ClientConfig};
+use stakpak_api::{Client, ClientConfig, cassette};
 use stakpak_mcp_server::EnabledToolsConfig;
 use stakpak_shared::models::subagent::SubagentConfigs;
-use std::{env, path::Path};
+use stakpak_shared::session_store::SessionStore;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
 
 mod apkey_auth;
 mod code_index;
@@ -18,6 +22,7 @@ use commands::{
         self,
         run::{OutputFormat, RunAsyncConfig, RunInteractiveConfig},
     },
+    sessions::SessionsCommands,
 };
 use config::AppConfig;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -68,7 +73,7 @@ struct Cli {
     #[arg(long = "disable-secret-redaction", default_value_t = false)]
     disable_secret_redaction: bool,
 
-    /// Enable privacy mode to redact private data like IP addresses and AWS account IDs
+    /// Enable privacy mode to replace private data like IP addresses, emails and AWS account IDs with consistent pseudonyms
     #[arg(long = "privacy-mode", default_value_t = false)]
     privacy_mode: bool,
 
@@ -108,6 +113,10 @@ struct Cli {
     #[arg(long = "prompt-file")]
     prompt_file: Option<String>,
 
+    /// Attach an image, PDF or text file to the prompt, can be repeated (runs in async mode only)
+    #[arg(long = "attach", action = clap::ArgAction::Append)]
+    attachments: Vec<PathBuf>,
+
     /// Configuration profile to use (can also be set with STAKPAK_PROFILE env var)
     #[arg(long = "profile")]
     profile: Option<String>,
@@ -116,6 +125,14 @@ struct Cli {
     #[arg(long = "config")]
     config_path: Option<String>,
 
+    /// Model to use, either one of the profile's named models or a model name (overrides the profile's model)
+    #[arg(long = "model")]
+    model: Option<String>,
+
+    /// Record API requests to a cassette file, or replay them from it if it exists (can also be set with STAKPAK_CASSETTE env var)
+    #[arg(long = "cassette")]
+    cassette: Option<String>,
+
     /// Prompt to run the agent
     prompt: Option<String>,
 
@@ -140,6 +157,17 @@ async fn main() {
         eprintln!("Auto-update failed: {}", e);
     }
 
+    // Resolved before --workdir changes the current directory
+    let cassette = cli.cassette.as_ref().map(|cassette| {
+        env::current_dir()
+            .map(|dir| dir.join(cassette))
+            .unwrap_or_else(|_| cassette.into())
+    });
+    if let Some((_, mode)) = cassette::init(cassette) {
+        // SAFETY: set at startup, before anything else reads or writes the environment
+        unsafe { env::set_var(cassette::CASSETTE_MODE_ENV, mode.as_str()) };
+    }
+
     if let Some(workdir) = cli.workdir {
         let workdir = Path::new(&workdir);
         if let Err(e) = env::set_current_dir(workdir) {
@@ -179,14 +207,23 @@ async fn main() {
                 }
             }
 
-            match cli.command {
+            // Resuming a stored session starts the agent rather than running a command
+            let resume_session_id = match &cli.command {
+                Some(Commands::Sessions(SessionsCommands::Resume { id })) => Some(id.clone()),
+                _ => None,
+            };
+
+            match cli.command.filter(|_| resume_session_id.is_none()) {
                 Some(command) => {
                     // Skip check_update for ACP command
                     if !matches!(command, Commands::Acp { .. }) {
                         let _ =
                             check_update(format!("v{}", env!("CARGO_PKG_VERSION")).as_str()).await;
                     }
-                    if config.api_key.is_none() && command.requires_auth() {
+                    if config.api_key.is_none()
+                        && command.requires_auth()
+                        && !cassette::is_replaying()
+                    {
                         prompt_for_api_key(&mut config).await;
                     }
 
@@ -202,7 +239,17 @@ async fn main() {
                     }
                 }
                 None => {
-                    if config.api_key.is_none() {
+                    let local_session = match resume_session_id {
+                        Some(id) => match SessionStore::default_store().load(&id) {
+                            Ok(session) => Some(session),
+                            Err(e) => {
+                                eprintln!("{}", e);
+                                std::process::exit(1);
+                            }
+                        },
+                        None => None,
+                    };
+                    if config.api_key.is_none() && !cassette::is_replaying() {
                         prompt_for_api_key(&mut config).await;
                     }
                     let local_context = analyze_local_context(&config).await.ok();
@@ -333,6 +380,7 @@ async fn main() {
 
                     let allowed_tools = cli.allowed_tools.or_else(|| config.allowed_tools.clone());
                     let auto_approve = config.auto_approve.clone();
+                    let model = cli.model.map(|model| config.resolve_model(&model));
 
                     match use_async_mode {
                         // Async mode: run continuously until no more tool calls (or max_steps=1 for single-step)
@@ -355,6 +403,9 @@ async fn main() {
                                 enabled_tools: EnabledToolsConfig {
                                     slack: cli.enable_slack_tools,
                                 },
+                                model,
+                                attachments: cli.attachments,
+                                local_session,
                             },
                         )
                         .await
@@ -384,8 +435,9 @@ async fn main() {
                                 auto_approve,
                                 enabled_tools: EnabledToolsConfig {
                                     slack: cli.enable_slack_tools,
-                                    ..EnabledToolsConfig::default()
                                 },
+                                model,
+                                local_session,
                             },
                         )
                         .await
@@ -0,0 +1,549 @@
+// Record and replay of Stakpak API interactions
+//
+// A cassette is a JSON file of request/response pairs. When `STAKPAK_CASSETTE` (or the CLI's
+// `--cassette` flag) names a file that does not exist yet, every request made through
+// `Client` is sent to the API as usual and recorded to it, SSE streams included. When the
+// file exists, a local stand-in server is started that answers requests from the cassette
+// instead, so agent loops can be tested deterministically without the live backend. The
+// mode is decided once when the process starts and passed on to subprocesses through
+// `STAKPAK_CASSETTE_MODE`.
+use crate::error::ClientError;
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response as AxumResponse};
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub const CASSETTE_ENV: &str = "STAKPAK_CASSETTE";
+/// The [`Mode`] decided by a parent process, so subprocesses like subagents don't replay a
+/// cassette that is still being recorded
+pub const CASSETTE_MODE_ENV: &str = "STAKPAK_CASSETTE_MODE";
+
+/// Response headers worth keeping; the rest describe the live connection
+const RECORDED_HEADERS: &[&str] = &["content-type", "retry-after", "x-request-id"];
+
+static CASSETTE: OnceLock<Option<(PathBuf, Mode)>> = OnceLock::new();
+
+static OPEN_CASSETTES: Lazy<Mutex<HashMap<PathBuf, OpenCassette>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static VOLATILE_FIELDS: Lazy<Vec<(Regex, &'static str)>> = Lazy::new(|| {
+    [
+        (
+            r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+            "<uuid>",
+        ),
+        (
+            r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:?\d{2})?",
+            "<timestamp>",
+        ),
+        (
+            r"\[REDACTED_SECRET:([^:\]]+):[^\]]+\]",
+            "[REDACTED_SECRET:$1:<id>]",
+        ),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| {
+        (
+            Regex::new(pattern).expect("valid volatile field regex"),
+            replacement,
+        )
+    })
+    .collect()
+});
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path and query, relative to the API's `/v1` base URL
+    pub path: String,
+    /// The request body, with volatile fields normalised
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// The raw body; for SSE streams, the full event stream
+    pub body: String,
+}
+
+/// Whether the cassette in use is recorded to or replayed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Record,
+    Replay,
+}
+
+impl Mode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Record => "record",
+            Mode::Replay => "replay",
+        }
+    }
+
+    /// The mode for the cassette at `path`: `inherited` from a parent process if it set one,
+    /// otherwise replay if the cassette exists and record if it doesn't
+    fn for_cassette(path: &Path, inherited: Option<&str>) -> Self {
+        match inherited {
+            Some("record") => Mode::Record,
+            Some("replay") => Mode::Replay,
+            _ if path.exists() => Mode::Replay,
+            _ => Mode::Record,
+        }
+    }
+}
+
+/// Use the cassette at `path`, or the one named by `STAKPAK_CASSETTE` if `None`, and decide
+/// whether it is recorded or replayed. Meant to be called when the process starts, so the
+/// mode doesn't change once a recording creates the file; only the first call has an effect.
+pub fn init(path: Option<PathBuf>) -> Option<(&'static Path, Mode)> {
+    CASSETTE
+        .get_or_init(|| {
+            let path = path.or_else(|| {
+                std::env::var(CASSETTE_ENV)
+                    .ok()
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+            })?;
+            let inherited = std::env::var(CASSETTE_MODE_ENV).ok();
+            let mode = Mode::for_cassette(&path, inherited.as_deref());
+            Some((path, mode))
+        })
+        .as_ref()
+        .map(|(path, mode)| (path.as_path(), *mode))
+}
+
+/// The cassette set by [`init`] or `STAKPAK_CASSETTE`, if any
+pub fn cassette_path() -> Option<PathBuf> {
+    init(None).map(|(path, _)| path.to_path_buf())
+}
+
+/// Whether API requests are answered from an existing cassette
+pub fn is_replaying() -> bool {
+    init(None).is_some_and(|(_, mode)| mode == Mode::Replay)
+}
+
+/// Replace the parts of a request body that change between runs, like ids and
+/// timestamps, so recorded requests can be matched and diffed
+pub fn normalize(text: &str) -> String {
+    VOLATILE_FIELDS
+        .iter()
+        .fold(text.to_string(), |text, (regex, replacement)| {
+            regex.replace_all(&text, *replacement).into_owned()
+        })
+}
+
+fn normalize_body(body: Option<&[u8]>) -> Option<Value> {
+    let body = std::str::from_utf8(body?).ok()?;
+    if body.is_empty() {
+        return None;
+    }
+    let body = normalize(body);
+    Some(serde_json::from_str(&body).unwrap_or(Value::String(body)))
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self, ClientError> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            ClientError::Config(format!("Failed to read cassette {}: {}", path.display(), e))
+        })?;
+        serde_json::from_str(&content)
+            .map_err(|e| ClientError::Config(format!("Invalid cassette {}: {}", path.display(), e)))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, content).map_err(|e| e.to_string())
+    }
+}
+
+/// How a client uses its cassette
+#[derive(Debug, Clone)]
+pub(crate) enum OpenCassette {
+    Record(Arc<Recorder>),
+    /// Base URL of the stand-in server
+    Replay(String),
+}
+
+/// Open the cassette at `path`, once per process so that every client shares the same
+/// recording or replay position. The cassette in use keeps the mode decided by [`init`].
+pub(crate) fn open(path: &Path) -> Result<OpenCassette, ClientError> {
+    let mut open_cassettes = OPEN_CASSETTES
+        .lock()
+        .map_err(|_| ClientError::Config("Cassette registry is poisoned".to_string()))?;
+    if let Some(open) = open_cassettes.get(path) {
+        return Ok(open.clone());
+    }
+    let mode = match init(None) {
+        Some((active, mode)) if active == path => mode,
+        _ => Mode::for_cassette(path, None),
+    };
+    let open = if mode == Mode::Replay {
+        OpenCassette::Replay(start_replay_server(Cassette::load(path)?)?)
+    } else {
+        OpenCassette::Record(Arc::new(Recorder {
+            path: path.to_path_buf(),
+            cassette: Mutex::new(Cassette::default()),
+        }))
+    };
+    open_cassettes.insert(path.to_path_buf(), open.clone());
+    Ok(open)
+}
+
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+}
+
+impl Recorder {
+    pub(crate) fn request(
+        method: &reqwest::Method,
+        path: &str,
+        body: Option<&[u8]>,
+    ) -> RecordedRequest {
+        RecordedRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: normalize_body(body),
+        }
+    }
+
+    fn push(&self, interaction: Interaction) {
+        if let Ok(mut cassette) = self.cassette.lock() {
+            cassette.interactions.push(interaction);
+            // Saved after every interaction, so a run that is interrupted keeps its recording
+            if let Err(e) = cassette.save(&self.path) {
+                tracing::warn!("Failed to save cassette {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    /// Record `response` to `request` and hand back an equivalent response. Event streams
+    /// are recorded as they are consumed, once they end.
+    pub(crate) async fn record(
+        self: &Arc<Self>,
+        request: RecordedRequest,
+        response: Response,
+    ) -> Result<Response, ClientError> {
+        let status = response.status();
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| RECORDED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect::<BTreeMap<_, _>>();
+        let is_event_stream = headers
+            .get("content-type")
+            .is_some_and(|content_type| content_type.starts_with("text/event-stream"));
+
+        let mut builder = axum::http::Response::builder().status(status.as_u16());
+        for (name, value) in &headers {
+            builder = builder.header(name, value);
+        }
+
+        let body = if is_event_stream {
+            let captured = Arc::new(Mutex::new(Vec::new()));
+            let tee = {
+                let captured = captured.clone();
+                response.bytes_stream().inspect(move |chunk| {
+                    if let (Ok(bytes), Ok(mut captured)) = (chunk, captured.lock()) {
+                        captured.extend_from_slice(bytes);
+                    }
+                })
+            };
+            let recorder = self.clone();
+            let finish = futures_util::stream::once(async move {
+                let body = captured
+                    .lock()
+                    .map(|captured| String::from_utf8_lossy(&captured).into_owned())
+                    .unwrap_or_default();
+                recorder.push(Interaction {
+                    request,
+                    response: RecordedResponse {
+                        status: status.as_u16(),
+                        headers,
+                        body,
+                    },
+                });
+            })
+            .filter_map(|_| async { None });
+            reqwest::Body::wrap_stream(tee.chain(finish))
+        } else {
+            let body = response.text().await?;
+            self.push(Interaction {
+                request,
+                response: RecordedResponse {
+                    status: status.as_u16(),
+                    headers,
+                    body: body.clone(),
+                },
+            });
+            reqwest::Body::from(body)
+        };
+
+        builder
+            .body(body)
+            .map(Response::from)
+            .map_err(|e| ClientError::Decode(e.to_string()))
+    }
+}
+
+struct Replayer {
+    interactions: Vec<Interaction>,
+    used: Mutex<Vec<bool>>,
+}
+
+impl Replayer {
+    /// The first unused interaction for the request, preferring one whose normalised
+    /// body matches exactly over the next one for the same method and path
+    fn take(&self, method: &str, path: &str, body: Option<&Value>) -> Option<&Interaction> {
+        let mut used = self.used.lock().ok()?;
+        let candidates = self
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(index, interaction)| {
+                !used[*index]
+                    && interaction.request.method == method
+                    && interaction.request.path == path
+            })
+            .collect::<Vec<_>>();
+        let (index, interaction) = candidates
+            .iter()
+            .find(|(_, interaction)| interaction.request.body.as_ref() == body)
+            .or_else(|| candidates.first())
+            .copied()?;
+        used[index] = true;
+        Some(interaction)
+    }
+}
+
+async fn replay(
+    axum::extract::State(replayer): axum::extract::State<Arc<Replayer>>,
+    method: Method,
+    uri: Uri,
+    body: Bytes,
+) -> AxumResponse {
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let path = path.strip_prefix("/v1").unwrap_or(path);
+    let body = normalize_body(Some(&body));
+
+    let Some(interaction) = replayer.take(method.as_str(), path, body.as_ref()) else {
+        let error = serde_json::json!({
+            "error": {
+                "key": "CASSETTE_MISS",
+                "message": format!("No recorded response for {} {}", method, path),
+            }
+        });
+        return (StatusCode::NOT_FOUND, axum::Json(error)).into_response();
+    };
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &interaction.response.headers {
+        if let (Ok(name), Ok(value)) = (name.parse::<axum::http::HeaderName>(), value.parse()) {
+            headers.insert(name, value);
+        }
+    }
+    let status = StatusCode::from_u16(interaction.response.status)
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (
+        status,
+        headers,
+        Body::from(interaction.response.body.clone()),
+    )
+        .into_response()
+}
+
+/// Serve `cassette` from a local server on its own thread, so it works whichever runtime
+/// the client runs on, and return its base URL
+fn start_replay_server(cassette: Cassette) -> Result<String, ClientError> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+        .map_err(|e| ClientError::Config(format!("Failed to start replay server: {}", e)))?;
+    let address = listener
+        .local_addr()
+        .map_err(|e| ClientError::Config(format!("Failed to start replay server: {}", e)))?;
+
+    let replayer = Arc::new(Replayer {
+        used: Mutex::new(vec![false; cassette.interactions.len()]),
+        interactions: cassette.interactions,
+    });
+    let router = axum::Router::new().fallback(replay).with_state(replayer);
+
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                tracing::error!("Failed to start replay server: {}", e);
+                return;
+            }
+        };
+        runtime.block_on(async move {
+            match tokio::net::TcpListener::from_std(listener) {
+                Ok(listener) => {
+                    if let Err(e) = axum::serve(listener, router).await {
+                        tracing::error!("Replay server failed: {}", e);
+                    }
+                }
+                Err(e) => tracing::error!("Failed to start replay server: {}", e),
+            }
+        });
+    });
+
+    Ok(format!("http://{}/v1", address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Client, ClientConfig};
+    use futures_util::StreamExt;
+    use stakpak_shared::models::integrations::openai::{ChatMessage, MessageContent, Role};
+
+    #[test]
+    fn test_normalize_volatile_fields() {
+        let body = r#"{"id":"7c9e6679-7425-40de-944b-e07fc1f90ae7","at":"2025-07-01T10:20:30.123Z","content":"key [REDACTED_SECRET:github-pat:a1b2c3]"}"#;
+        assert_eq!(
+            normalize(body),
+            r#"{"id":"<uuid>","at":"<timestamp>","content":"key [REDACTED_SECRET:github-pat:<id>]"}"#
+        );
+    }
+
+    fn interaction(method: &str, path: &str, content_type: &str, body: &str) -> Interaction {
+        Interaction {
+            request: RecordedRequest {
+                method: method.to_string(),
+                path: path.to_string(),
+                body: None,
+            },
+            response: RecordedResponse {
+                status: 200,
+                headers: BTreeMap::from([("content-type".to_string(), content_type.to_string())]),
+                body: body.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_mode_is_inherited_from_parent_process() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("cassette.json");
+        assert_eq!(Mode::for_cassette(&path, None), Mode::Record);
+
+        Cassette::default().save(&path).unwrap();
+        assert_eq!(Mode::for_cassette(&path, None), Mode::Replay);
+        // A subagent started after its parent began recording keeps recording
+        assert_eq!(Mode::for_cassette(&path, Some("record")), Mode::Record);
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let directory = tempfile::tempdir().unwrap();
+        let upstream_path = directory.path().join("upstream.json");
+        let recorded_path = directory.path().join("recorded.json");
+
+        let chunk = r#"{"id":"1","object":"chat.completion.chunk","created":0,"model":"m","choices":[{"index":0,"delta":{"content":"hi"},"finish_reason":null}]}"#;
+        Cassette {
+            interactions: vec![
+                interaction(
+                    "GET",
+                    "/account",
+                    "application/json",
+                    r#"{"username":"ada","id":"1","first_name":"Ada","last_name":"Lovelace"}"#,
+                ),
+                interaction(
+                    "POST",
+                    "/agents/openai/v1/chat/completions",
+                    "text/event-stream",
+                    &format!("data: {}\n\ndata: [DONE]\n\n", chunk),
+                ),
+            ],
+        }
+        .save(&upstream_path)
+        .unwrap();
+
+        // Record against a replayed upstream standing in for the live API
+        let OpenCassette::Replay(upstream) = open(&upstream_path).unwrap() else {
+            panic!("existing cassette should be replayed");
+        };
+        let config = ClientConfig {
+            api_key: Some("test".to_string()),
+            api_endpoint: upstream.trim_end_matches("/v1").to_string(),
+        };
+        let messages = vec![ChatMessage {
+            role: Role::User,
+            content: Some(MessageContent::String("hello".to_string())),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }];
+
+        let recording = Client::new(&config)
+            .unwrap()
+            .with_cassette(&recorded_path)
+            .unwrap();
+        assert_eq!(recording.get_my_account().await.unwrap().username, "ada");
+        let (stream, _) = recording
+            .chat_completion_stream(messages.clone(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(stream.collect::<Vec<_>>().await.len(), 1);
+
+        let recorded = Cassette::load(&recorded_path).unwrap();
+        assert_eq!(recorded.interactions.len(), 2);
+        assert!(
+            recorded.interactions[1]
+                .response
+                .body
+                .contains("chat.completion.chunk")
+        );
+
+        // Replay the recording without any upstream
+        OPEN_CASSETTES.lock().unwrap().remove(&recorded_path);
+        let replaying = Client::new(&config)
+            .unwrap()
+            .with_cassette(&recorded_path)
+            .unwrap();
+        assert_eq!(replaying.get_my_account().await.unwrap().username, "ada");
+        let (stream, _) = replaying
+            .chat_completion_stream(messages, None, None)
+            .await
+            .unwrap();
+        let chunks = stream.collect::<Vec<_>>().await;
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].is_ok());
+
+        let error = replaying.list_rulebooks().await.unwrap_err();
+        assert_eq!(error.key(), Some("CASSETTE_MISS"));
+    }
+}
@@ -0,0 +1,282 @@
+use rand::Rng;
+use serde::Deserialize;
+use std::time::Duration;
+
+const EXCEEDED_API_LIMIT: &str = "EXCEEDED_API_LIMIT";
+const TOP_UP_MESSAGE: &str =
+    "Please top up your account at https://stakpak.dev/settings/billing to keep Stakpaking.";
+
+/// Errors returned by [`crate::Client`]
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ClientError {
+    /// The client could not be configured, e.g. a missing API key
+    #[error("{0}")]
+    Config(String),
+    /// The request never got an HTTP response. `connect` is set when the connection
+    /// could not be established, so the request is known not to have reached the API.
+    #[error("Failed to reach the Stakpak API: {message}")]
+    Transport { message: String, connect: bool },
+    #[error("Request to the Stakpak API timed out")]
+    Timeout,
+    /// 429, with the delay the API asked for in its `Retry-After` header
+    #[error("Too many requests to the Stakpak API: {message}")]
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// Any other 4xx, with the error key of the API's error body when it has one
+    #[error("{}", api_error_message(.key.as_deref(), .message))]
+    Api {
+        status: u16,
+        key: Option<String>,
+        message: String,
+    },
+    #[error("Stakpak API error ({status}): {message}")]
+    Server { status: u16, message: String },
+    #[error("Failed to deserialize response: {0}")]
+    Decode(String),
+}
+
+fn api_error_message(key: Option<&str>, message: &str) -> String {
+    if key == Some(EXCEEDED_API_LIMIT) {
+        format!("{}.\n\n{}", message, TOP_UP_MESSAGE)
+    } else {
+        message.to_string()
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    error: ApiErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorDetail {
+    key: String,
+    message: String,
+}
+
+impl ClientError {
+    /// Classify a non-success response from its status, `Retry-After` header and body
+    pub fn from_response(status: u16, retry_after: Option<&str>, body: &str) -> Self {
+        let (key, message) = match serde_json::from_str::<ApiErrorBody>(body) {
+            Ok(body) => (Some(body.error.key), body.error.message),
+            Err(_) if body.trim().is_empty() => (None, format!("HTTP {}", status)),
+            Err(_) => (None, body.trim().to_string()),
+        };
+        match status {
+            429 => ClientError::RateLimited {
+                message,
+                retry_after: retry_after
+                    .and_then(|value| value.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs),
+            },
+            500..=599 => ClientError::Server { status, message },
+            _ => ClientError::Api {
+                status,
+                key,
+                message,
+            },
+        }
+    }
+
+    /// HTTP status of the response, if there was one
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ClientError::RateLimited { .. } => Some(429),
+            ClientError::Api { status, .. } | ClientError::Server { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Error key of the API's error body, like `EXCEEDED_API_LIMIT`
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            ClientError::Api { key, .. } => key.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the account has run out of credits and needs a top-up
+    pub fn is_out_of_credits(&self) -> bool {
+        self.key() == Some(EXCEEDED_API_LIMIT)
+    }
+
+    /// Whether the failure is likely to go away on its own, like a network blip, an
+    /// overloaded API or rate limiting, rather than the request being rejected
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ClientError::Transport { .. }
+                | ClientError::Timeout
+                | ClientError::RateLimited { .. }
+                | ClientError::Server { .. }
+        )
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            ClientError::Timeout
+        } else if error.is_decode() {
+            ClientError::Decode(error.to_string())
+        } else {
+            ClientError::Transport {
+                message: error.to_string(),
+                connect: error.is_connect(),
+            }
+        }
+    }
+}
+
+impl From<ClientError> for String {
+    fn from(error: ClientError) -> Self {
+        error.to_string()
+    }
+}
+
+/// When and how long [`crate::Client`] waits before retrying a failed request.
+///
+/// Idempotent requests are retried on any transient failure. Other requests are only
+/// retried when they are known not to have been processed: a refused connection or a 429.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Whether to retry after `error` on the zero-based `attempt`
+    pub fn should_retry(&self, attempt: u32, error: &ClientError, idempotent: bool) -> bool {
+        if attempt >= self.max_retries {
+            return false;
+        }
+        match error {
+            // Waiting longer than the policy allows would look like a hang
+            ClientError::RateLimited { retry_after, .. } => {
+                retry_after.is_none_or(|delay| delay <= self.max_backoff)
+            }
+            ClientError::Transport { connect: true, .. } => true,
+            ClientError::Transport { .. } | ClientError::Timeout | ClientError::Server { .. } => {
+                idempotent
+            }
+            ClientError::Config(_) | ClientError::Api { .. } | ClientError::Decode(_) => false,
+        }
+    }
+
+    /// Delay before retrying after `error` on the zero-based `attempt`: the server's
+    /// `Retry-After` when given, otherwise exponential backoff with jitter
+    pub fn backoff(&self, attempt: u32, error: &ClientError) -> Duration {
+        if let ClientError::RateLimited {
+            retry_after: Some(delay),
+            ..
+        } = error
+        {
+            return *delay;
+        }
+        let ceiling = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        let half = ceiling / 2;
+        half + half.mul_f64(rand::rng().random::<f64>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classifies_error_responses() {
+        let body = r#"{"error":{"key":"EXCEEDED_API_LIMIT","message":"Exceeded API limit"}}"#;
+        let error = ClientError::from_response(402, None, body);
+        assert!(error.is_out_of_credits());
+        assert!(!error.is_transient());
+        assert!(error.to_string().contains("top up your account"));
+
+        let error = ClientError::from_response(429, Some("7"), "");
+        assert!(matches!(
+            error,
+            ClientError::RateLimited {
+                retry_after: Some(delay),
+                ..
+            } if delay == Duration::from_secs(7)
+        ));
+
+        let error = ClientError::from_response(503, None, "upstream unavailable");
+        assert_eq!(error.status(), Some(503));
+        assert!(error.is_transient());
+        assert!(error.to_string().contains("upstream unavailable"));
+    }
+
+    #[test]
+    fn test_retries_depend_on_idempotency() {
+        let policy = RetryPolicy::default();
+        let server = ClientError::Server {
+            status: 502,
+            message: "Bad gateway".to_string(),
+        };
+        let refused = ClientError::Transport {
+            message: "connection refused".to_string(),
+            connect: true,
+        };
+        let client = ClientError::Api {
+            status: 400,
+            key: None,
+            message: "Bad request".to_string(),
+        };
+
+        assert!(policy.should_retry(0, &server, true));
+        assert!(!policy.should_retry(0, &server, false));
+        assert!(policy.should_retry(0, &refused, false));
+        assert!(!policy.should_retry(0, &client, true));
+        assert!(!policy.should_retry(policy.max_retries, &server, true));
+        assert!(!RetryPolicy::none().should_retry(0, &refused, true));
+
+        let slow = ClientError::RateLimited {
+            message: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(60)),
+        };
+        assert!(!policy.should_retry(0, &slow, true));
+    }
+
+    #[test]
+    fn test_backoff_is_bounded() {
+        let policy = RetryPolicy::default();
+        for attempt in 0..10 {
+            let ceiling = policy
+                .initial_backoff
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(policy.max_backoff);
+            let delay = policy.backoff(attempt, &ClientError::Timeout);
+            assert!(delay >= ceiling / 2 && delay <= ceiling);
+        }
+
+        let limited = ClientError::RateLimited {
+            message: String::new(),
+            retry_after: Some(Duration::from_secs(3)),
+        };
+        assert_eq!(policy.backoff(0, &limited), Duration::from_secs(3));
+    }
+}
@@ -1,13 +1,21 @@
 use chrono::{DateTime, Utc};
 use eventsource_stream::Eventsource;
 use reqwest::header::HeaderMap;
-use reqwest::{Client as ReqwestClient, Error as ReqwestError, Response, header};
+use reqwest::{Client as ReqwestClient, RequestBuilder, Response, header};
 use rmcp::model::Content;
 use rmcp::model::JsonRpcResponse;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use stakpak_shared::tls_client::TlsClientConfig;
 use stakpak_shared::tls_client::create_tls_client;
+#[cfg(feature = "cassette")]
+use std::path::Path;
+#[cfg(feature = "cassette")]
+use std::sync::Arc;
 use url::Url;
+#[cfg(feature = "cassette")]
+pub mod cassette;
+mod error;
 pub mod models;
 use futures_util::Stream;
 use futures_util::StreamExt;
@@ -15,13 +23,17 @@ use models::*;
 use serde_json::Value;
 use serde_json::json;
 use stakpak_shared::models::integrations::openai::{
-    ChatCompletionRequest, ChatCompletionResponse, ChatCompletionStreamResponse, ChatMessage, Tool,
+    ChatCompletionRequest, ChatCompletionResponse, ChatCompletionStreamResponse, ChatMessage,
+    DEFAULT_MODEL, Tool,
 };
 use uuid::Uuid;
 pub mod dave_v1;
 pub mod kevin_v1;
 pub mod norbert_v1;
 pub mod stuart_v1;
+#[cfg(feature = "cassette")]
+use cassette::{OpenCassette, Recorder};
+pub use error::{ClientError, RetryPolicy};
 pub use models::Block;
 
 #[derive(Clone, Debug)]
@@ -29,6 +41,10 @@ pub use models::Block;
 pub struct Client {
     client: ReqwestClient,
     base_url: String,
+    retry_policy: RetryPolicy,
+    #[cfg(feature = "cassette")]
+    recorder: Option<Arc<Recorder>>,
+    model: Option<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -38,48 +54,25 @@ pub struct ClientConfig {
     pub api_endpoint: String,
 }
 
-#[derive(Deserialize)]
-struct ApiError {
-    error: ApiErrorDetail,
-}
-
-#[derive(Deserialize)]
-struct ApiErrorDetail {
-    key: String,
-    message: String,
-}
-
 impl Client {
-    async fn handle_response_error(&self, response: Response) -> Result<Response, String> {
-        if response.status().is_success() {
-            Ok(response)
-        } else {
-            match response.json::<ApiError>().await {
-                Ok(response) => {
-                    if response.error.key == "EXCEEDED_API_LIMIT" {
-                        Err(format!(
-                            "{}.\n\nPlease top up your account at https://stakpak.dev/settings/billing to keep Stakpaking.",
-                            response.error.message
-                        ))
-                    } else {
-                        Err(response.error.message)
-                    }
-                }
-                Err(e) => Err(e.to_string()),
+    pub fn new(config: &ClientConfig) -> Result<Self, ClientError> {
+        let api_key = match &config.api_key {
+            Some(api_key) => api_key.as_str(),
+            // Replayed responses don't need credentials
+            #[cfg(feature = "cassette")]
+            None if cassette::is_replaying() => "",
+            None => {
+                return Err(ClientError::Config(
+                    "API Key not found, please login".to_string(),
+                ));
             }
-        }
-    }
-
-    pub fn new(config: &ClientConfig) -> Result<Self, String> {
-        if config.api_key.is_none() {
-            return Err("API Key not found, please login".into());
-        }
+        };
 
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::AUTHORIZATION,
-            header::HeaderValue::from_str(&format!("Bearer {}", config.api_key.clone().unwrap()))
-                .expect("Invalid API key format"),
+            header::HeaderValue::from_str(&format!("Bearer {}", api_key))
+                .map_err(|_| ClientError::Config("Invalid API key format".to_string()))?,
         );
         headers.insert(
             header::USER_AGENT,
@@ -91,143 +84,184 @@ impl Client {
             TlsClientConfig::default()
                 .with_headers(headers)
                 .with_timeout(std::time::Duration::from_secs(300)),
-        )?;
+        )
+        .map_err(ClientError::Config)?;
 
-        Ok(Self {
+        let client = Self {
             client,
             base_url: config.api_endpoint.clone() + "/v1",
-        })
+            retry_policy: RetryPolicy::default(),
+            #[cfg(feature = "cassette")]
+            recorder: None,
+            model: None,
+        };
+        #[cfg(feature = "cassette")]
+        if let Some(path) = cassette::cassette_path() {
+            return client.with_cassette(&path);
+        }
+        Ok(client)
     }
 
-    pub async fn get_my_account(&self) -> Result<GetMyAccountResponse, String> {
-        let url = format!("{}/account", self.base_url);
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
+    /// Model to request chat completions from, instead of [`DEFAULT_MODEL`]
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Model that chat completions are requested from
+    pub fn model(&self) -> &str {
+        self.model.as_deref().unwrap_or(DEFAULT_MODEL)
+    }
 
-        let response = self.handle_response_error(response).await?;
+    /// Record requests to the cassette at `path`, or replay them from it if it exists.
+    /// See [`cassette`].
+    #[cfg(feature = "cassette")]
+    pub fn with_cassette(mut self, path: &Path) -> Result<Self, ClientError> {
+        match cassette::open(path)? {
+            OpenCassette::Record(recorder) => self.recorder = Some(recorder),
+            OpenCassette::Replay(base_url) => self.base_url = base_url,
+        }
+        Ok(self)
+    }
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<GetMyAccountResponse>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
+    /// Send `request`, retrying transient failures according to the retry policy.
+    /// Requests that are not `idempotent` are only retried when the API is known not
+    /// to have processed them.
+    async fn send(
+        &self,
+        request: RequestBuilder,
+        idempotent: bool,
+    ) -> Result<Response, ClientError> {
+        let mut attempt = 0;
+        loop {
+            let Some(attempt_request) = request.try_clone() else {
+                // Streaming bodies can't be replayed
+                return Self::check_status(self.execute(request).await?).await;
+            };
+            let error = match self.execute(attempt_request).await {
+                Ok(response) => match Self::check_status(response).await {
+                    Ok(response) => return Ok(response),
+                    Err(error) => error,
+                },
+                Err(error) => error,
+            };
+            if !self.retry_policy.should_retry(attempt, &error, idempotent) {
+                return Err(error);
             }
+            tokio::time::sleep(self.retry_policy.backoff(attempt, &error)).await;
+            attempt += 1;
         }
     }
 
-    pub async fn list_rulebooks(&self) -> Result<Vec<ListRuleBook>, String> {
-        let url = format!("{}/rules", self.base_url);
+    async fn execute(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+        let request = request.build()?;
+        #[cfg(feature = "cassette")]
+        if let Some(recorder) = &self.recorder {
+            let path = request
+                .url()
+                .as_str()
+                .strip_prefix(&self.base_url)
+                .unwrap_or(request.url().path())
+                .to_string();
+            let recorded = Recorder::request(
+                request.method(),
+                &path,
+                request.body().and_then(|body| body.as_bytes()),
+            );
+            let response = self.client.execute(request).await?;
+            return recorder.record(recorded, response).await;
+        }
+        Ok(self.client.execute(request).await?)
+    }
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
+    async fn check_status(response: Response) -> Result<Response, ClientError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response.text().await.unwrap_or_default();
+        Err(ClientError::from_response(
+            status.as_u16(),
+            retry_after.as_deref(),
+            &body,
+        ))
+    }
 
-        let response = self.handle_response_error(response).await?;
+    async fn decode<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
+        let body = response.bytes().await?;
+        serde_json::from_slice(&body).map_err(|e| ClientError::Decode(e.to_string()))
+    }
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<ListRulebooksResponse>(value.clone()) {
-            Ok(response) => Ok(response.results),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+    pub async fn get_my_account(&self) -> Result<GetMyAccountResponse, ClientError> {
+        let url = format!("{}/account", self.base_url);
+
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
+
+        Self::decode(response).await
+    }
+
+    pub async fn list_rulebooks(&self) -> Result<Vec<ListRuleBook>, ClientError> {
+        let url = format!("{}/rules", self.base_url);
+
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
+
+        let response: ListRulebooksResponse = Self::decode(response).await?;
+        Ok(response.results)
     }
 
-    pub async fn get_rulebook_by_uri(&self, uri: &str) -> Result<RuleBook, String> {
+    pub async fn get_rulebook_by_uri(&self, uri: &str) -> Result<RuleBook, ClientError> {
         // URL encode the URI to handle special characters
         let base_url = Url::parse(&format!("{}/rules/", self.base_url))
-            .map_err(|e| format!("Invalid base URL: {}", e))?;
+            .map_err(|e| ClientError::Config(format!("Invalid base URL: {}", e)))?;
         let url = base_url
             .join(uri)
-            .map_err(|e| format!("Failed to construct URL: {}", e))?;
+            .map_err(|e| ClientError::Config(format!("Failed to construct URL: {}", e)))?;
 
-        let response = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.get(url);
+        let response = self.send(request, true).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<RuleBook>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
-    pub async fn list_flows(&self, owner_name: &str) -> Result<GetFlowsResponse, String> {
+    pub async fn list_flows(&self, owner_name: &str) -> Result<GetFlowsResponse, ClientError> {
         let url = format!("{}/flows/{}", self.base_url, owner_name);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<GetFlowsResponse>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn get_flow(
         &self,
         owner_name: &str,
         flow_name: &str,
-    ) -> Result<GetFlowResponse, String> {
+    ) -> Result<GetFlowResponse, ClientError> {
         let url = format!("{}/flows/{}/{}", self.base_url, owner_name, flow_name);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<GetFlowResponse>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn create_flow(
         &self,
         flow_name: &str,
         visibility: Option<FlowVisibility>,
-    ) -> Result<CreateFlowResponse, String> {
+    ) -> Result<CreateFlowResponse, ClientError> {
         let url = format!("{}/flows", self.base_url);
 
         let input = CreateFlowInput {
@@ -235,81 +269,37 @@ impl Client {
             visibility,
         };
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<CreateFlowResponse>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn save_edits(
         &self,
         flow_ref: &FlowRef,
         edits: Vec<Edit>,
-    ) -> Result<SaveEditsResponse, String> {
+    ) -> Result<SaveEditsResponse, ClientError> {
         let url = format!("{}/flows/{}/save", self.base_url, flow_ref);
 
         let input = SaveEditsInput { edits };
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<SaveEditsResponse>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn get_flow_documents(
         &self,
         flow_ref: &FlowRef,
-    ) -> Result<GetFlowDocumentsResponse, String> {
+    ) -> Result<GetFlowDocumentsResponse, ClientError> {
         let url = format!("{}/flows/{}/documents", self.base_url, flow_ref);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<GetFlowDocumentsResponse>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn query_blocks(
@@ -318,11 +308,12 @@ impl Client {
         generate_query: bool,
         synthesize_output: bool,
         flow_ref: Option<&str>,
-    ) -> Result<QueryBlocksResponse, String> {
+    ) -> Result<QueryBlocksResponse, ClientError> {
         let url = format!("{}/commands/query", self.base_url);
 
         let flow_ref = if let Some(flow_ref) = flow_ref {
-            let flow_ref: FlowRef = FlowRef::new(flow_ref.to_string())?;
+            let flow_ref: FlowRef =
+                FlowRef::new(flow_ref.to_string()).map_err(ClientError::Config)?;
             Some(flow_ref)
         } else {
             None
@@ -335,99 +326,40 @@ impl Client {
             flow_ref,
         };
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<QueryBlocksResponse>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
-    pub async fn list_agent_sessions(&self) -> Result<Vec<AgentSession>, String> {
+    pub async fn list_agent_sessions(&self) -> Result<Vec<AgentSession>, ClientError> {
         let url = format!("{}/agents/sessions", self.base_url);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<Vec<AgentSession>>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
-    pub async fn get_agent_session(&self, session_id: Uuid) -> Result<AgentSession, String> {
+    pub async fn get_agent_session(&self, session_id: Uuid) -> Result<AgentSession, ClientError> {
         let url = format!("{}/agents/sessions/{}", self.base_url, session_id);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
-
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
 
-        match serde_json::from_value::<AgentSession>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn get_agent_session_stats(
         &self,
         session_id: Uuid,
-    ) -> Result<AgentSessionStats, String> {
+    ) -> Result<AgentSessionStats, ClientError> {
         let url = format!("{}/agents/sessions/{}/stats", self.base_url, session_id);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-
-        match serde_json::from_value::<AgentSessionStats>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn create_agent_session(
@@ -435,7 +367,7 @@ impl Client {
         agent_id: AgentID,
         visibility: AgentSessionVisibility,
         input: Option<AgentInput>,
-    ) -> Result<AgentSession, String> {
+    ) -> Result<AgentSession, ClientError> {
         let url = format!("{}/agents/sessions", self.base_url);
 
         let input = serde_json::json!({
@@ -444,104 +376,66 @@ impl Client {
             "input": input,
         });
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<AgentSession>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
-    pub async fn run_agent(&self, input: &RunAgentInput) -> Result<RunAgentOutput, String> {
+    pub async fn run_agent(&self, input: &RunAgentInput) -> Result<RunAgentOutput, ClientError> {
         let url = format!("{}/agents/run", self.base_url);
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<RunAgentOutput>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn get_agent_checkpoint(
         &self,
         checkpoint_id: Uuid,
-    ) -> Result<RunAgentOutput, String> {
+    ) -> Result<RunAgentOutput, ClientError> {
         let url = format!("{}/agents/checkpoints/{}", self.base_url, checkpoint_id);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
 
-        let response = self.handle_response_error(response).await?;
+        Self::decode(response).await
+    }
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<RunAgentOutput>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+    /// Save `output` as a new checkpoint following `parent_checkpoint_id`, for a
+    /// conversation that was rewritten on this side, e.g. compacted
+    pub async fn create_agent_checkpoint(
+        &self,
+        parent_checkpoint_id: Uuid,
+        output: &AgentOutput,
+    ) -> Result<RunAgentOutput, ClientError> {
+        let url = format!("{}/agents/checkpoints", self.base_url);
+
+        let input = serde_json::json!({
+            "parent_id": parent_checkpoint_id,
+            "output": output,
+        });
+
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
+
+        Self::decode(response).await
     }
 
     pub async fn get_agent_session_latest_checkpoint(
         &self,
         session_id: Uuid,
-    ) -> Result<RunAgentOutput, String> {
+    ) -> Result<RunAgentOutput, ClientError> {
         let url = format!(
             "{}/agents/sessions/{}/checkpoints/latest",
             self.base_url, session_id
         );
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<RunAgentOutput>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn transpile(
@@ -549,7 +443,7 @@ impl Client {
         content: Vec<Document>,
         source_provisioner: ProvisionerType,
         target_provisioner: TranspileTargetProvisionerType,
-    ) -> Result<TranspileOutput, String> {
+    ) -> Result<TranspileOutput, ClientError> {
         let url = format!(
             "{}/commands/{}/transpile",
             self.base_url,
@@ -564,32 +458,17 @@ impl Client {
             output: target_provisioner.to_string(),
         };
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<TranspileOutput>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn get_agent_tasks(
         &self,
         provisioner: &ProvisionerType,
         dir: Option<String>,
-    ) -> Result<Vec<AgentTask>, String> {
+    ) -> Result<Vec<AgentTask>, ClientError> {
         let url = format!(
             "{}/agents/tasks?provisioner={}{}",
             self.base_url,
@@ -597,55 +476,29 @@ impl Client {
             dir.map(|d| format!("&dir={}", d)).unwrap_or_default(),
         );
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
+        let request = self.client.get(&url);
+        let response = self.send(request, true).await?;
 
-        let response = self.handle_response_error(response).await?;
-
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<AgentTaskOutput>(value.clone()) {
-            Ok(response) => Ok(response.results),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        let response: AgentTaskOutput = Self::decode(response).await?;
+        Ok(response.results)
     }
 
     pub async fn chat_completion(
         &self,
         messages: Vec<ChatMessage>,
         tools: Option<Vec<Tool>>,
-    ) -> Result<ChatCompletionResponse, String> {
+    ) -> Result<ChatCompletionResponse, ClientError> {
         let url = format!("{}/agents/openai/v1/chat/completions", self.base_url);
 
-        let input = ChatCompletionRequest::new(messages, tools, None);
-
-        let response = self
-            .client
-            .post(&url)
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let input = ChatCompletionRequest {
+            model: self.model().to_string(),
+            ..ChatCompletionRequest::new(messages, tools, None)
+        };
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
 
-        match serde_json::from_value::<ChatCompletionResponse>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn chat_completion_stream(
@@ -658,20 +511,22 @@ impl Client {
             impl Stream<Item = Result<ChatCompletionStreamResponse, ApiStreamError>>,
             Option<String>,
         ),
-        String,
+        ClientError,
     > {
         let url = format!("{}/agents/openai/v1/chat/completions", self.base_url);
 
-        let input = ChatCompletionRequest::new(messages, tools, Some(true));
+        let input = ChatCompletionRequest {
+            model: self.model().to_string(),
+            ..ChatCompletionRequest::new(messages, tools, Some(true))
+        };
 
-        let response = self
+        let request = self
             .client
             .post(&url)
             .headers(headers.unwrap_or_default())
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
+            .json(&input);
+        // Nothing has been streamed yet, so setting up the stream is safe to retry
+        let response = self.send(request, true).await?;
 
         // Extract x-request-id from headers
         let request_id = response
@@ -680,32 +535,14 @@ impl Client {
             .and_then(|v| v.to_str().ok())
             .map(|s| s.to_string());
 
-        let response = self.handle_response_error(response).await?;
-        let stream = response.bytes_stream().eventsource().map(|event| {
-            event
-                .map_err(|_| ApiStreamError::Unknown("Failed to read response".to_string()))
-                .and_then(|event| match event.event.as_str() {
-                    "error" => Err(ApiStreamError::from(event.data)),
-                    _ => serde_json::from_str::<ChatCompletionStreamResponse>(&event.data).map_err(
-                        |_| {
-                            ApiStreamError::Unknown(
-                                "Failed to parse JSON from Anthropic response".to_string(),
-                            )
-                        },
-                    ),
-                })
-        });
+        let stream = chat_completion_events(response.bytes_stream().eventsource());
 
         Ok((stream, request_id))
     }
 
-    pub async fn cancel_stream(&self, request_id: String) -> Result<(), String> {
+    pub async fn cancel_stream(&self, request_id: String) -> Result<(), ClientError> {
         let url = format!("{}/agents/requests/{}/cancel", self.base_url, request_id);
-        self.client
-            .post(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
+        self.send(self.client.post(&url), true).await?;
 
         Ok(())
     }
@@ -713,58 +550,31 @@ impl Client {
     pub async fn generate_code(
         &self,
         input: &GenerateCodeInput,
-    ) -> Result<GenerateCodeOutput, String> {
+    ) -> Result<GenerateCodeOutput, ClientError> {
         let url = format!("{}/commands/{}/generate", self.base_url, input.provisioner);
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<GenerateCodeOutput>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
     pub async fn build_code_index(
         &self,
         input: &BuildCodeIndexInput,
-    ) -> Result<BuildCodeIndexOutput, String> {
+    ) -> Result<BuildCodeIndexOutput, ClientError> {
         let url = format!("{}/commands/build_code_index", self.base_url,);
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&input)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.post(&url).json(&input);
+        let response = self.send(request, false).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-        match serde_json::from_value::<BuildCodeIndexOutput>(value.clone()) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        Self::decode(response).await
     }
 
-    pub async fn call_mcp_tool(&self, input: &ToolsCallParams) -> Result<Vec<Content>, String> {
+    pub async fn call_mcp_tool(
+        &self,
+        input: &ToolsCallParams,
+    ) -> Result<Vec<Content>, ClientError> {
         let url = format!("{}/mcp", self.base_url);
 
         let payload = json!({
@@ -777,42 +587,20 @@ impl Client {
             "id": Uuid::new_v4().to_string(),
         });
 
-        let response = self
-            .client
-            .post(&url)
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let response = self.handle_response_error(response).await?;
+        let request = self.client.post(&url).json(&payload);
+        let response = self.send(request, false).await?;
 
-        let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-
-        match serde_json::from_value::<JsonRpcResponse<ToolsCallResponse>>(value.clone()) {
-            Ok(response) => Ok(response.result.content),
-            Err(e) => {
-                eprintln!("Failed to deserialize response: {}", e);
-                eprintln!("Raw response: {}", value);
-                Err("Failed to deserialize response:".into())
-            }
-        }
+        let response: JsonRpcResponse<ToolsCallResponse> = Self::decode(response).await?;
+        Ok(response.result.content)
     }
 
-    pub async fn memorize_session(&self, checkpoint_id: Uuid) -> Result<(), String> {
+    pub async fn memorize_session(&self, checkpoint_id: Uuid) -> Result<(), ClientError> {
         let url = format!(
             "{}/agents/sessions/checkpoints/{}/extract-memory",
             self.base_url, checkpoint_id
         );
 
-        let response = self
-            .client
-            .post(&url)
-            .send()
-            .await
-            .map_err(|e: ReqwestError| e.to_string())?;
-
-        let _ = self.handle_response_error(response).await?;
+        self.send(self.client.post(&url), false).await?;
         Ok(())
     }
 }
@@ -932,7 +720,7 @@ impl GetFlowResponse {
         ));
 
         let mut versions = self.resource.versions.clone();
-        versions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        versions.sort_by_key(|v| std::cmp::Reverse(v.created_at));
 
         for version in versions {
             let tags = version
@@ -1200,3 +988,109 @@ pub struct ToolsCallParams {
 pub struct ToolsCallResponse {
     pub content: Vec<Content>,
 }
+
+/// Parse the events of a chat completion stream, which is complete once the `[DONE]`
+/// terminator arrives. The body is still read to its end, so recordings are saved. A stream
+/// that closes before the terminator, without a chunk carrying a finish reason either, was
+/// cut off and ends with [`ApiStreamError::ConnectionLost`].
+fn chat_completion_events<E: std::fmt::Display>(
+    events: impl Stream<Item = Result<eventsource_stream::Event, E>>,
+) -> impl Stream<Item = Result<ChatCompletionStreamResponse, ApiStreamError>> {
+    events
+        .map(Some)
+        .chain(futures_util::stream::once(futures_util::future::ready(
+            None,
+        )))
+        .scan(false, |complete, event| {
+            let item = match event {
+                Some(Ok(event)) if event.data.trim() == "[DONE]" => {
+                    *complete = true;
+                    None
+                }
+                Some(Ok(event)) if event.event == "error" => {
+                    Some(Err(ApiStreamError::from(event.data)))
+                }
+                Some(Ok(event)) => Some(
+                    serde_json::from_str::<ChatCompletionStreamResponse>(&event.data)
+                        .inspect(|response| {
+                            if response
+                                .choices
+                                .iter()
+                                .any(|choice| choice.finish_reason.is_some())
+                            {
+                                *complete = true;
+                            }
+                        })
+                        .map_err(|_| {
+                            ApiStreamError::Unknown(
+                                "Failed to parse JSON from Anthropic response".to_string(),
+                            )
+                        }),
+                ),
+                Some(Err(e)) => {
+                    // Reported here, not again once the stream closes
+                    *complete = true;
+                    Some(Err(ApiStreamError::ConnectionLost(e.to_string())))
+                }
+                None if *complete => None,
+                None => Some(Err(ApiStreamError::ConnectionLost(
+                    "Response ended before it was complete".to_string(),
+                ))),
+            };
+            futures_util::future::ready(Some(item))
+        })
+        .filter_map(futures_util::future::ready)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eventsource_stream::Event;
+
+    fn event(data: &str) -> Result<Event, String> {
+        Ok(Event {
+            data: data.to_string(),
+            ..Event::default()
+        })
+    }
+
+    fn chunk(finish_reason: &str) -> String {
+        format!(
+            r#"{{"id":"1","object":"chat.completion.chunk","created":0,"model":"m","choices":[{{"index":0,"delta":{{"content":"hi"}},"finish_reason":{}}}]}}"#,
+            finish_reason
+        )
+    }
+
+    async fn collect(
+        events: Vec<Result<Event, String>>,
+    ) -> Vec<Result<ChatCompletionStreamResponse, ApiStreamError>> {
+        chat_completion_events(futures_util::stream::iter(events))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_stream_ending_at_done_is_complete() {
+        let items = collect(vec![event(&chunk("null")), event("[DONE]")]).await;
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stream_with_finish_reason_is_complete() {
+        let items = collect(vec![event(&chunk("null")), event(&chunk(r#""stop""#))]).await;
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn test_stream_closed_early_is_cut_off() {
+        let items = collect(vec![event(&chunk("null"))]).await;
+        assert_eq!(items.len(), 2);
+        assert!(matches!(items[1], Err(ApiStreamError::ConnectionLost(_))));
+
+        let items = collect(vec![event(&chunk("null")), Err("reset".to_string())]).await;
+        assert_eq!(items.len(), 2);
+        assert!(matches!(&items[1], Err(ApiStreamError::ConnectionLost(e)) if e == "reset"));
+    }
+}
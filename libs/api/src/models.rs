@@ -21,6 +21,8 @@ pub enum ApiStreamError {
     InvalidGeneratedCode,
     CopilotError,
     SaveError,
+    /// The connection dropped, or the stream ended, before the response was complete
+    ConnectionLost(String),
     Unknown(String),
 }
 
@@ -936,6 +938,10 @@ pub enum AgentOutput {
     PabloV1 {
         messages: Vec<ChatMessage>,
         node_states: serde_json::Value,
+        /// Model the conversation was carried on with. Only checkpoints saved by the
+        /// client, such as compacted conversations, are known to record it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
     },
 }
 
@@ -950,6 +956,14 @@ impl AgentOutput {
             AgentOutput::PabloV1 { .. } => AgentID::PabloV1,
         }
     }
+
+    /// Model recorded with the output, if any
+    pub fn model(&self) -> Option<&str> {
+        match self {
+            AgentOutput::PabloV1 { model, .. } => model.as_deref(),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
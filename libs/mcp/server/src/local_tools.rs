@@ -4,9 +4,25 @@ use rmcp::service::RequestContext;
 use rmcp::{Error as McpError, handler::server::tool::Parameters, model::*, schemars, tool};
 use rmcp::{RoleServer, tool_router};
 use serde::Deserialize;
+use stakpak_shared::dir_sync::{SyncOptions, apply_sync_plan, plan_sync};
+use stakpak_shared::exec_target::{
+    ExecOptions, ExecTarget, ExecTargetFileSystemProvider, SUDO_PASSWORD_MASK, SudoOptions,
+    SudoTarget, TargetKind, TargetLocation,
+};
 use stakpak_shared::file_backup_manager::FileBackupManager;
+use stakpak_shared::file_transfer::{
+    DEFAULT_MAX_TRANSFER_SIZE, TransferEndpoint, TransferOptions, TransferProgress,
+};
+use stakpak_shared::fleet::{
+    DEFAULT_FLEET_CONCURRENCY, DEFAULT_FLEET_HOST_TIMEOUT, FleetOptions, run_fleet_command,
+};
+use stakpak_shared::inventory::Inventory;
+use stakpak_shared::log_tail::{
+    DEFAULT_TAIL_LINES, DEFAULT_TAIL_POLL_INTERVAL, LogTailer, TailOptions, TailTarget,
+};
+use stakpak_shared::port_forward::{ForwardDirection, PortForward, PortForwardSpec};
 use stakpak_shared::remote_connection::{
-    PathLocation, RemoteConnection, RemoteConnectionInfo, RemoteFileSystemProvider,
+    RemoteConnection, RemoteConnectionInfo, RemoteFileSystemProvider,
 };
 
 use html2md;
@@ -16,19 +32,30 @@ use similar::TextDiff;
 use stakpak_shared::local_store::LocalStore;
 use stakpak_shared::models::integrations::mcp::CallToolResultExt;
 use stakpak_shared::models::integrations::openai::ToolCallResultProgress;
+use stakpak_shared::secrets::stream::StreamingRedactor;
 use stakpak_shared::task_manager::TaskInfo;
 use stakpak_shared::tls_client::{TlsClientConfig, create_tls_client};
-use stakpak_shared::utils::{LocalFileSystemProvider, generate_directory_tree};
-use std::fs::{self};
+use stakpak_shared::utils::generate_directory_tree;
 use std::path::Path;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
 use tokio::time::{Duration, sleep, timeout as tokio_timeout};
 use tracing::error;
 use url;
 use uuid::Uuid;
 
+/// Privilege escalation settings shared by the command and file tools
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct SudoParams {
+    #[schemars(description = "Run with root privileges through sudo (default: false)")]
+    pub sudo: Option<bool>,
+    #[schemars(description = "Run as this user through sudo instead of root; implies sudo")]
+    pub become_user: Option<String>,
+    #[schemars(
+        description = "Password for sudo, given as a secret placeholder like [REDACTED_SECRET:rule-id:hash]. Without it sudo must not require a password"
+    )]
+    pub sudo_password: Option<String>,
+}
+
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct RunCommandRequest {
     #[schemars(description = "The shell command to execute")]
@@ -38,13 +65,163 @@ pub struct RunCommandRequest {
     #[schemars(description = "Optional timeout for the command execution in seconds")]
     pub timeout: Option<u64>,
     #[schemars(
-        description = "Optional remote connection string (format: user@host or user@host:port)"
+        description = "Optional remote target: an SSH connection string (user@host or user@host:port), a container (container://name) or a Kubernetes pod (pod://namespace/name)"
     )]
     pub remote: Option<String>,
     #[schemars(description = "Optional password for remote connection")]
     pub password: Option<String>,
     #[schemars(description = "Optional path to private key for remote connection")]
     pub private_key_path: Option<String>,
+    #[serde(flatten)]
+    pub sudo_params: SudoParams,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RunCommandFleetRequest {
+    #[schemars(description = "The shell command to execute on every selected host")]
+    pub command: String,
+    #[schemars(
+        description = "Hosts to target: a group name, host name or pattern, e.g. 'web', 'web:!web7', 'prod:&canary', 'db1,db2', 'web*' or 'all'"
+    )]
+    pub target: String,
+    #[schemars(
+        description = "Optional path to an inventory file (YAML or INI). Defaults to .stakpak/inventory.yml, .stakpak/inventory.yaml or .stakpak/inventory.ini"
+    )]
+    pub inventory: Option<String>,
+    #[schemars(
+        description = "Optional maximum number of hosts to run on in parallel (default: 10)"
+    )]
+    pub concurrency: Option<usize>,
+    #[schemars(
+        description = "Optional per-host timeout in seconds, covering connection and execution (default: 60)"
+    )]
+    pub timeout: Option<u64>,
+    #[schemars(
+        description = "Run the fan-out as a background task and return a task ID immediately (default: false)"
+    )]
+    pub background: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct PortForwardRequest {
+    #[schemars(description = "Remote connection string (format: user@host or user@host:port)")]
+    pub remote: String,
+    #[schemars(description = "Optional password for remote connection")]
+    pub password: Option<String>,
+    #[schemars(description = "Optional path to private key for remote connection")]
+    pub private_key_path: Option<String>,
+    #[schemars(
+        description = "Forward direction: 'local' listens locally and forwards to remote_host:remote_port as seen from the server (ssh -L), 'remote' listens on the server and forwards to local_host:local_port (ssh -R). Default: 'local'"
+    )]
+    pub direction: Option<String>,
+    #[schemars(
+        description = "Local address to listen on ('local') or connect to ('remote') (default: 127.0.0.1)"
+    )]
+    pub local_host: Option<String>,
+    #[schemars(
+        description = "Local port to listen on ('local', 0 or omitted picks a free port) or connect to ('remote', required)"
+    )]
+    pub local_port: Option<u16>,
+    #[schemars(
+        description = "Remote address to connect to from the server ('local') or listen on ('remote') (default: localhost)"
+    )]
+    pub remote_host: Option<String>,
+    #[schemars(
+        description = "Remote port to connect to ('local') or listen on ('remote', 0 lets the server pick a port)"
+    )]
+    pub remote_port: u16,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct CopyRequest {
+    #[schemars(
+        description = "Source path. Local path, or remote path in format user@host:/path or user@host#port:/path (use ABSOLUTE paths for remote files). Container and pod paths are not supported"
+    )]
+    pub source: String,
+    #[schemars(
+        description = "Destination path, local or remote in the same format as source. Copying into an existing directory places the source inside it"
+    )]
+    pub destination: String,
+    #[schemars(description = "Copy directories and their contents (default: false)")]
+    pub recursive: Option<bool>,
+    #[schemars(
+        description = "Continue partially copied files from where they stopped instead of starting over (default: false)"
+    )]
+    pub resume: Option<bool>,
+    #[schemars(description = "Preserve file and directory permissions (default: true)")]
+    pub preserve_permissions: Option<bool>,
+    #[schemars(
+        description = "Verify each file with a SHA-256 checksum after copying (default: true)"
+    )]
+    pub verify_checksum: Option<bool>,
+    #[schemars(
+        description = "Maximum total size to copy in megabytes; larger transfers are refused (default: 1024)"
+    )]
+    pub max_size_mb: Option<u64>,
+    #[schemars(description = "Optional password for remote connections")]
+    pub password: Option<String>,
+    #[schemars(description = "Optional path to private key for remote connections")]
+    pub private_key_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SyncRequest {
+    #[schemars(description = "Local directory to sync from")]
+    pub local_path: String,
+    #[schemars(
+        description = "Remote directory to sync to, in format user@host:/path or user@host#port:/path (use ABSOLUTE paths)"
+    )]
+    pub remote_path: String,
+    #[schemars(
+        description = "The exact plan returned by a previous sync call with the same arguments. Omit it to only compute and return the plan"
+    )]
+    pub plan: Option<String>,
+    #[schemars(description = "Delete remote files that don't exist locally (default: false)")]
+    pub delete: Option<bool>,
+    #[schemars(
+        description = "Glob patterns for paths to leave untouched on both sides, e.g. ['.git', '*.swp']"
+    )]
+    pub exclude: Option<Vec<String>>,
+    #[schemars(
+        description = "Compare file hashes even when size and modification time match (default: false)"
+    )]
+    pub checksum: Option<bool>,
+    #[schemars(description = "Optional password for remote connection")]
+    pub password: Option<String>,
+    #[schemars(description = "Optional path to private key for remote connection")]
+    pub private_key_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct TailRequest {
+    #[schemars(
+        description = "Log files to follow. Local paths or remote paths in format user@host:/path or user@host#port:/path. Lines from several files are merged by timestamp. Container and pod paths are not supported"
+    )]
+    pub paths: Vec<String>,
+    #[schemars(
+        description = "Number of existing lines to show from the end of each file (default: 10)"
+    )]
+    pub lines: Option<usize>,
+    #[schemars(
+        description = "How long to follow the files in seconds (default: 30, max: 600). Background tails run until cancelled when omitted"
+    )]
+    pub duration: Option<u64>,
+    #[schemars(description = "Only show lines matching at least one of these regexes")]
+    pub include: Option<Vec<String>>,
+    #[schemars(description = "Hide lines matching any of these regexes")]
+    pub exclude: Option<Vec<String>>,
+    #[schemars(
+        description = "Maximum lines per second to show; excess lines are counted and dropped (default: 50)"
+    )]
+    pub max_lines_per_second: Option<u32>,
+    #[schemars(
+        description = "Run as a background task that keeps the most recent lines; read it with get_task_details (default: false)"
+    )]
+    pub background: Option<bool>,
+    #[schemars(description = "Optional password for remote connections")]
+    pub password: Option<String>,
+    #[schemars(description = "Optional path to private key for remote connections")]
+    pub private_key_path: Option<String>,
 }
 
 #[derive(Debug)]
@@ -82,7 +259,7 @@ pub struct AwaitTasksRequest {
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct ViewRequest {
     #[schemars(
-        description = "The path to the file or directory to view. For remote files, use format: user@host:/path or user@host#port:/path (use ABSOLUTE paths for remote files)"
+        description = "The path to the file or directory to view. For remote files, use format: user@host:/path or user@host#port:/path; for containers and pods, container://name:/path or pod://namespace/name:/path (use ABSOLUTE paths for remote files)"
     )]
     pub path: String,
     #[schemars(
@@ -95,6 +272,8 @@ pub struct ViewRequest {
         description = "Optional path to private key for remote connection (if path is remote)"
     )]
     pub private_key_path: Option<String>,
+    #[serde(flatten)]
+    pub sudo_params: SudoParams,
     #[schemars(description = "Display directory as a nested tree structure (default: false)")]
     pub tree: Option<bool>,
 }
@@ -102,7 +281,7 @@ pub struct ViewRequest {
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct StrReplaceRequest {
     #[schemars(
-        description = "The path to the file to modify. For remote files, use format: user@host:/path or user@host#port:/path (use ABSOLUTE paths for remote files)"
+        description = "The path to the file to modify. For remote files, use format: user@host:/path or user@host#port:/path; for containers and pods, container://name:/path or pod://namespace/name:/path (use ABSOLUTE paths for remote files)"
     )]
     pub path: String,
     #[schemars(
@@ -123,12 +302,14 @@ pub struct StrReplaceRequest {
         description = "Optional path to private key for remote connection (if path is remote)"
     )]
     pub private_key_path: Option<String>,
+    #[serde(flatten)]
+    pub sudo_params: SudoParams,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct CreateRequest {
     #[schemars(
-        description = "The path where the new file should be created. For remote files, use format: user@host:/path or user@host#port:/path (use ABSOLUTE paths for remote files)"
+        description = "The path where the new file should be created. For remote files, use format: user@host:/path or user@host#port:/path; for containers and pods, container://name:/path or pod://namespace/name:/path (use ABSOLUTE paths for remote files)"
     )]
     pub path: String,
     #[schemars(
@@ -141,6 +322,8 @@ pub struct CreateRequest {
         description = "Optional path to private key for remote connection (if path is remote)"
     )]
     pub private_key_path: Option<String>,
+    #[serde(flatten)]
+    pub sudo_params: SudoParams,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
@@ -154,7 +337,7 @@ pub struct GeneratePasswordRequest {
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct RemoveRequest {
     #[schemars(
-        description = "The path to the file or directory to remove. For remote files, use format: user@host:/path or user@host#port:/path (use ABSOLUTE paths for remote files)"
+        description = "The path to the file or directory to remove. For remote files, use format: user@host:/path or user@host#port:/path; for containers and pods, container://name:/path or pod://namespace/name:/path (use ABSOLUTE paths for remote files)"
     )]
     pub path: String,
     #[schemars(
@@ -167,6 +350,8 @@ pub struct RemoveRequest {
         description = "Optional path to private key for remote connection (if path is remote)"
     )]
     pub private_key_path: Option<String>,
+    #[serde(flatten)]
+    pub sudo_params: SudoParams,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
@@ -188,11 +373,18 @@ REMOTE EXECUTION:
   * 'user@server.com' (uses default port 22 and auto-discovered keys)
   * 'user@server.com:2222' with password authentication
   * Remote paths: 'ssh://user@host/path' or 'user@host:/path'
+- Set 'remote' to 'container://name' (docker, or podman if docker is not installed) or 'pod://namespace/name' (kubectl, append '#container' to pick a container) to run inside a container or pod
+
+PRIVILEGE ESCALATION:
+- Set 'sudo=true' to run as root, or 'become_user' to run as another user
+- Pass the sudo password as a secret placeholder in 'sudo_password'; it is fed to 'sudo -S' on stdin and never appears in output
+- Without 'sudo_password', sudo runs non-interactively and fails instead of hanging on a password prompt
 
 SECRET HANDLING: 
 - Output containing secrets will be redacted and shown as placeholders like [REDACTED_SECRET:rule-id:hash]
 - You can use these placeholders in subsequent commands - they will be automatically restored to actual values before execution
 - Example: If you see 'export API_KEY=[REDACTED_SECRET:api-key:abc123]', you can use '[REDACTED_SECRET:api-key:abc123]' in later commands
+- Secret references like {{secret:env:NAME}}, {{secret:file:~/path}} or {{secret:cmd:pass show name}} are resolved just before execution; their values are never shown
 
 If the command's output exceeds 300 lines the result will be truncated and the full output will be saved to a file in the current directory"
     )]
@@ -206,11 +398,26 @@ If the command's output exceeds 300 lines the result will be truncated and the f
             remote,
             password,
             private_key_path,
+            sudo_params,
         }): Parameters<RunCommandRequest>,
     ) -> Result<CallToolResult, McpError> {
+        // Secrets in remote output are attributed to the remote host
+        let host = remote.clone();
+        let sudo = match self.sudo_options("run_command", sudo_params) {
+            Ok(sudo) => sudo,
+            Err(error_result) => return Ok(error_result),
+        };
         // Use unified command execution helper
         match self
-            .execute_command_unified(&command, timeout, remote, password, private_key_path, &ctx)
+            .execute_command_unified(
+                &command,
+                timeout,
+                remote,
+                password,
+                private_key_path,
+                sudo,
+                &ctx,
+            )
             .await
         {
             Ok(mut command_result) => {
@@ -229,114 +436,937 @@ If the command's output exceeds 300 lines the result will be truncated and the f
                     return Ok(CallToolResult::success(vec![Content::text("No output")]));
                 }
 
-                let redacted_output = self
-                    .get_secret_manager()
-                    .redact_and_store_secrets(&command_result.output, None);
+                let secret_manager = self.get_secret_manager();
+                let redacted_output = match &host {
+                    Some(host) => secret_manager.redact_and_store_secrets_from_host(
+                        &command_result.output,
+                        None,
+                        host,
+                    ),
+                    None => secret_manager.redact_and_store_secrets(&command_result.output, None),
+                };
+
+                if command_result.exit_code != 0 {
+                    return Ok(CallToolResult::error(vec![
+                        Content::text("COMMAND_FAILED"),
+                        Content::text(redacted_output),
+                    ]));
+                }
+                Ok(CallToolResult::success(vec![Content::text(
+                    &redacted_output,
+                )]))
+            }
+            Err(error_result) => Ok(error_result),
+        }
+    }
+
+    #[tool(
+        description = "Execute a shell command asynchronously in the background on LOCAL OR REMOTE systems and return immediately with task information without waiting for completion.
+
+REMOTE EXECUTION SUPPORT:
+- Set 'remote' parameter to 'user@host' or 'user@host:port' for SSH background execution
+- Use 'password' for password authentication or 'private_key_path' for key-based auth  
+- Automatic SSH key discovery from ~/.ssh/ if no credentials provided
+- Examples:
+  * 'user@server.com' - Remote background task with auto-discovered keys
+  * 'user@server.com:2222' - Remote background task with custom port
+
+Use this for starting servers, tailing logs, or other long-running commands that you want to monitor separately, or whenever the user wants to run a command in the background. For SSH tunnels, use the port_forward tool instead.
+
+PARAMETERS:
+- command: The shell command to execute (locally or remotely)
+- description: Optional description of the command (not used in execution)  
+- timeout: Optional timeout in seconds after which the task will be terminated
+- remote: Optional remote connection string for SSH execution
+- password: Optional password for remote authentication
+- private_key_path: Optional path to private key for remote authentication
+
+RETURNS:
+- task_id: Unique identifier for the background task
+- status: Current task status (will be 'Running' initially)
+- start_time: When the task was started
+
+SECRET HANDLING:
+- Commands containing secrets will have them restored before execution
+- Task output will be redacted when retrieved
+- Use secret placeholders like [REDACTED_SECRET:rule-id:hash] or references like {{secret:env:NAME}} in commands
+
+Use the get_all_tasks tool to monitor task progress, or the cancel_task tool to cancel a task."
+    )]
+    pub async fn run_command_task(
+        &self,
+        _ctx: RequestContext<RoleServer>,
+        Parameters(RunCommandRequest {
+            command,
+            description: _,
+            timeout,
+            remote,
+            password,
+            private_key_path,
+            sudo_params,
+        }): Parameters<RunCommandRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        // Restore secrets in the command before execution
+        let mut actual_command = match self.restore_tool_input("run_command_task", &command) {
+            Ok(command) => command,
+            Err(error_result) => return Ok(error_result),
+        };
+
+        // The sudo password reaches `sudo -S` on stdin, so it never becomes part of the task's command
+        let mut stdin = None;
+        let sudo = match self.sudo_options("run_command_task", sudo_params) {
+            Ok(sudo) => sudo,
+            Err(error_result) => return Ok(error_result),
+        };
+        if let Some(sudo) = sudo {
+            // Task output is redacted when it is retrieved, which then masks the password too
+            if let Some(password) = &sudo.password {
+                self.get_secret_manager()
+                    .redact_and_store_password(password, password);
+            }
+            actual_command = sudo.wrap(&actual_command);
+            stdin = sudo.password_input();
+        }
+
+        let timeout_duration = timeout.map(std::time::Duration::from_secs);
+
+        // Handle both local and remote async commands using TaskManager
+        let remote_connection = remote.map(|remote_str| RemoteConnectionInfo {
+            connection_string: remote_str,
+            password,
+            private_key_path,
+        });
+        let result = self
+            .get_task_manager()
+            .start_task_with_input(actual_command, timeout_duration, remote_connection, stdin)
+            .await;
+
+        match result {
+            Ok(task_info) => {
+                let output = serde_json::to_string_pretty(&task_info)
+                    .unwrap_or_else(|_| format!("Task started: {}", task_info.id));
+
+                Ok(CallToolResult::success(vec![Content::text(format!(
+                    "Background task started:\n{}",
+                    output
+                ))]))
+            }
+            Err(e) => {
+                error!("Failed to start background task: {}", e);
+
+                Ok(CallToolResult::error(vec![
+                    Content::text("RUN_COMMAND_TASK_ERROR"),
+                    Content::text(format!("Failed to start background task: {}", e)),
+                ]))
+            }
+        }
+    }
+
+    #[tool(
+        description = "Open a native SSH port forward (tunnel) to a remote host and keep it running as a background task.
+
+DIRECTIONS:
+- 'local' (default, like ssh -L): listen on local_host:local_port and forward each connection through the SSH server to remote_host:remote_port
+  * Example: reach a database only visible from a bastion with remote='user@bastion', remote_host='db.internal', remote_port=5432
+- 'remote' (like ssh -R): listen on the server at remote_host:remote_port and forward each connection back to local_host:local_port
+  * Example: expose a local dev server on port 3000 to the server with direction='remote', local_port=3000, remote_port=8080
+
+The forward is bound before this tool returns, so the effective ports in the result are ready to use. Set local_port to 0 (or omit it) for 'local' forwards to pick a free port.
+
+MANAGEMENT:
+- The forward runs as a task: use get_task_details to see active connections and bytes transferred
+- Use cancel_task to close the tunnel
+- Prefer this over running 'ssh -L'/'ssh -R' with run_command_task"
+    )]
+    pub async fn port_forward(
+        &self,
+        Parameters(PortForwardRequest {
+            remote,
+            password,
+            private_key_path,
+            direction,
+            local_host,
+            local_port,
+            remote_host,
+            remote_port,
+        }): Parameters<PortForwardRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let direction = match direction
+            .as_deref()
+            .unwrap_or("local")
+            .parse::<ForwardDirection>()
+        {
+            Ok(direction) => direction,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("INVALID_DIRECTION"),
+                    Content::text(e),
+                ]));
+            }
+        };
+
+        if direction == ForwardDirection::RemoteToLocal && local_port.unwrap_or(0) == 0 {
+            return Ok(CallToolResult::error(vec![
+                Content::text("INVALID_PORT"),
+                Content::text("local_port is required for remote forwards"),
+            ]));
+        }
+
+        let spec = PortForwardSpec {
+            direction,
+            local_host: local_host.unwrap_or_else(|| "127.0.0.1".to_string()),
+            local_port: local_port.unwrap_or(0),
+            remote_host: remote_host.unwrap_or_else(|| "localhost".to_string()),
+            remote_port,
+        };
+
+        let connection_info = RemoteConnectionInfo {
+            connection_string: remote.clone(),
+            password,
+            private_key_path,
+        };
+        let connection = match self
+            .get_remote_connection_manager()
+            .get_connection(&connection_info)
+            .await
+        {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("Failed to establish remote connection: {}", e);
+                return Ok(CallToolResult::error(vec![
+                    Content::text("REMOTE_CONNECTION_ERROR"),
+                    Content::text(format!("Failed to connect to remote host: {}", e)),
+                ]));
+            }
+        };
+
+        let forward = match PortForward::bind(connection, spec).await {
+            Ok(forward) => forward,
+            Err(e) => {
+                error!("Failed to set up port forward: {}", e);
+                return Ok(CallToolResult::error(vec![
+                    Content::text("PORT_FORWARD_ERROR"),
+                    Content::text(format!("Failed to set up port forward: {}", e)),
+                ]));
+            }
+        };
+
+        let label = format!("port_forward {} via {}", forward.spec(), remote);
+        match self
+            .get_task_manager()
+            .start_port_forward_task(forward, label)
+            .await
+        {
+            Ok(task_info) => {
+                let output = serde_json::to_string_pretty(&task_info)
+                    .unwrap_or_else(|_| format!("Task started: {}", task_info.id));
+
+                Ok(CallToolResult::success(vec![Content::text(format!(
+                    "Port forward started:\n{}\n\nUse get_task_details to monitor connections or cancel_task to close the tunnel.",
+                    output
+                ))]))
+            }
+            Err(e) => {
+                error!("Failed to start port forward task: {}", e);
+
+                Ok(CallToolResult::error(vec![
+                    Content::text("PORT_FORWARD_ERROR"),
+                    Content::text(format!("Failed to start port forward task: {}", e)),
+                ]))
+            }
+        }
+    }
+
+    #[tool(
+        description = "Run a shell command across a group of hosts from an Ansible-style inventory over SSH, in parallel, and aggregate the results.
+
+INVENTORY:
+- YAML or INI inventories with Ansible-style groups, children and vars
+- Loaded from .stakpak/inventory.yml, .stakpak/inventory.yaml or .stakpak/inventory.ini unless 'inventory' is set
+- Connection details come from host/group vars: ansible_host, ansible_user, ansible_port, ansible_ssh_private_key_file, ansible_password
+
+TARGETING:
+- 'target' is a group name, host name or wildcard pattern ('web', 'db1', 'web*', 'all')
+- Combine with ',' or ':', exclude with '!' and intersect with '&' (e.g. 'web:!web7', 'prod:&canary')
+
+EXECUTION:
+- 'concurrency' bounds how many hosts run at once (default: 10)
+- 'timeout' applies per host in seconds (default: 60)
+- Set 'background=true' for large fan-outs to run as a background task; monitor it with get_task_details or wait_for_tasks
+
+RESULTS:
+- Hosts with identical output are grouped together
+- Failures (connection errors, timeouts, non-zero exit codes) are listed first, followed by the most common output and any outliers
+
+SECRET HANDLING:
+- Secret placeholders like [REDACTED_SECRET:rule-id:hash] and references like {{secret:env:NAME}} in the command are restored before execution
+- Output containing secrets will be redacted"
+    )]
+    pub async fn run_command_fleet(
+        &self,
+        ctx: RequestContext<RoleServer>,
+        Parameters(RunCommandFleetRequest {
+            command,
+            target,
+            inventory,
+            concurrency,
+            timeout,
+            background,
+        }): Parameters<RunCommandFleetRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let inventory = match Inventory::load(inventory.as_deref()) {
+            Ok(inventory) => inventory,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("INVENTORY_ERROR"),
+                    Content::text(format!("Failed to load inventory: {}", e)),
+                ]));
+            }
+        };
+
+        let hosts = match inventory.select(&target) {
+            Ok(hosts) if hosts.is_empty() => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("NO_HOSTS_MATCHED"),
+                    Content::text(format!("No hosts matched target '{}'", target)),
+                ]));
+            }
+            Ok(hosts) => hosts,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("INVALID_TARGET"),
+                    Content::text(format!("Failed to resolve target: {}", e)),
+                ]));
+            }
+        };
+
+        let options = FleetOptions {
+            concurrency: concurrency.unwrap_or(DEFAULT_FLEET_CONCURRENCY),
+            host_timeout: timeout
+                .map(std::time::Duration::from_secs)
+                .unwrap_or(DEFAULT_FLEET_HOST_TIMEOUT),
+        };
+
+        let actual_command = match self.restore_tool_input("run_command_fleet", &command) {
+            Ok(command) => command,
+            Err(error_result) => return Ok(error_result),
+        };
+
+        if background.unwrap_or(false) {
+            return match self
+                .get_task_manager()
+                .start_fleet_task(
+                    actual_command,
+                    target,
+                    hosts,
+                    options,
+                    self.get_remote_connection_manager().clone(),
+                )
+                .await
+            {
+                Ok(task_info) => {
+                    let mut task_info = task_info;
+                    task_info.command = self
+                        .get_secret_manager()
+                        .redact_and_store_secrets(&task_info.command, None);
+                    let output = serde_json::to_string_pretty(&task_info)
+                        .unwrap_or_else(|_| format!("Task started: {}", task_info.id));
+
+                    Ok(CallToolResult::success(vec![Content::text(format!(
+                        "Background fleet task started:\n{}",
+                        output
+                    ))]))
+                }
+                Err(e) => {
+                    error!("Failed to start background fleet task: {}", e);
+
+                    Ok(CallToolResult::error(vec![
+                        Content::text("RUN_COMMAND_FLEET_ERROR"),
+                        Content::text(format!("Failed to start background fleet task: {}", e)),
+                    ]))
+                }
+            };
+        }
+
+        let progress_id = Uuid::new_v4();
+        let total_hosts = hosts.len();
+        let completed_hosts = std::sync::atomic::AtomicUsize::new(0);
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+
+        let fleet_run = run_fleet_command(
+            self.get_remote_connection_manager(),
+            &hosts,
+            &actual_command,
+            &options,
+            |result| {
+                let done = completed_hosts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                let status = match (&result.error, result.exit_code) {
+                    (Some(error), _) => format!("failed: {}", error),
+                    (None, Some(0)) => "ok".to_string(),
+                    (None, code) => format!("exited with code {:?}", code),
+                };
+                let _ = progress_tx.send(format!(
+                    "[{}/{}] {} {}\n",
+                    done, total_hosts, result.host, status
+                ));
+            },
+        );
+        tokio::pin!(fleet_run);
+
+        // Forward per-host progress while the fan-out runs
+        let mut redactor = StreamingRedactor::new(self.get_secret_manager().clone());
+        let report = loop {
+            tokio::select! {
+                report = &mut fleet_run => break report,
+                Some(line) = progress_rx.recv() => {
+                    let _ = ctx.peer.notify_progress(ProgressNotificationParam {
+                        progress_token: ProgressToken(NumberOrString::Number(0)),
+                        progress: 50,
+                        total: Some(100),
+                        message: Some(serde_json::to_string(&ToolCallResultProgress {
+                            id: progress_id,
+                            message: redactor.push(&line),
+                        }).unwrap_or_default()),
+                    }).await;
+                }
+                _ = ctx.ct.cancelled() => {
+                    return Ok(CallToolResult::cancel(Some(&vec![
+                        Content::text("COMMAND_CANCELLED"),
+                        Content::text("Fleet command execution was cancelled"),
+                    ])));
+                }
+            }
+        };
+
+        let output = match handle_large_output(&report.to_markdown(), "fleet.output") {
+            Ok(result) => result,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("OUTPUT_HANDLING_ERROR"),
+                    Content::text(format!("Failed to handle fleet output: {}", e)),
+                ]));
+            }
+        };
+
+        let redacted_output = self
+            .get_secret_manager()
+            .redact_and_store_secrets(&output, None);
+
+        if report.succeeded_count() == 0 {
+            return Ok(CallToolResult::error(vec![
+                Content::text("FLEET_COMMAND_FAILED"),
+                Content::text(redacted_output),
+            ]));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(
+            redacted_output,
+        )]))
+    }
+
+    #[tool(
+        description = "Copy files or directories between the local machine and remote hosts over SFTP, without shelling out to scp.
+
+PATHS:
+- Local paths as usual; remote paths as user@host:/path or user@host#port:/path (ABSOLUTE paths)
+- Supports local to remote, remote to local, and remote to remote (streamed through this machine)
+- Copying into an existing directory places the source inside it, like cp
+
+OPTIONS:
+- recursive: copy directories and their contents (default: false)
+- resume: continue partially copied files instead of starting over (default: false)
+- preserve_permissions: keep file and directory modes (default: true)
+- verify_checksum: compare SHA-256 of source and destination after each file (default: true)
+- max_size_mb: refuse transfers larger than this (default: 1024)
+
+Progress is streamed while the copy runs. Use password or private_key_path for remote authentication; they apply to every remote path."
+    )]
+    pub async fn copy(
+        &self,
+        ctx: RequestContext<RoleServer>,
+        Parameters(CopyRequest {
+            source,
+            destination,
+            recursive,
+            resume,
+            preserve_permissions,
+            verify_checksum,
+            max_size_mb,
+            password,
+            private_key_path,
+        }): Parameters<CopyRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let source_endpoint = match self
+            .get_transfer_endpoint(&source, password.clone(), private_key_path.clone())
+            .await
+        {
+            Ok(endpoint) => endpoint,
+            Err(error_result) => return Ok(error_result),
+        };
+        let destination_endpoint = match self
+            .get_transfer_endpoint(&destination, password, private_key_path)
+            .await
+        {
+            Ok(endpoint) => endpoint,
+            Err(error_result) => return Ok(error_result),
+        };
+
+        let options = TransferOptions {
+            recursive: recursive.unwrap_or(false),
+            resume: resume.unwrap_or(false),
+            preserve_permissions: preserve_permissions.unwrap_or(true),
+            verify_checksum: verify_checksum.unwrap_or(true),
+            max_size: Some(
+                max_size_mb
+                    .map(|mb| mb.saturating_mul(1024 * 1024))
+                    .unwrap_or(DEFAULT_MAX_TRANSFER_SIZE),
+            ),
+        };
+
+        let progress_id = Uuid::new_v4();
+        let last_percent = std::sync::atomic::AtomicU64::new(u64::MAX);
+        let (progress_tx, mut progress_rx) =
+            tokio::sync::mpsc::unbounded_channel::<TransferProgress>();
+
+        let transfer = stakpak_shared::file_transfer::copy(
+            &source_endpoint,
+            &destination_endpoint,
+            &options,
+            |progress| {
+                // Only report whole-percent changes to avoid flooding the client
+                let percent = progress.percent();
+                if last_percent.swap(percent, std::sync::atomic::Ordering::Relaxed) != percent {
+                    let _ = progress_tx.send(progress.clone());
+                }
+            },
+        );
+        tokio::pin!(transfer);
+
+        let mut redactor = StreamingRedactor::new(self.get_secret_manager().clone());
+        let result = loop {
+            tokio::select! {
+                result = &mut transfer => break result,
+                Some(progress) = progress_rx.recv() => {
+                    let _ = ctx.peer.notify_progress(ProgressNotificationParam {
+                        progress_token: ProgressToken(NumberOrString::Number(0)),
+                        progress: progress.percent() as u32,
+                        total: Some(100),
+                        message: Some(serde_json::to_string(&ToolCallResultProgress {
+                            id: progress_id,
+                            message: redactor.push(&format!(
+                                "[{}/{} files] {}% {}\n",
+                                progress.files_completed + 1,
+                                progress.files_total,
+                                progress.percent(),
+                                progress.file
+                            )),
+                        }).unwrap_or_default()),
+                    }).await;
+                }
+                _ = ctx.ct.cancelled() => {
+                    return Ok(CallToolResult::cancel(Some(&vec![
+                        Content::text("COPY_CANCELLED"),
+                        Content::text("Copy was cancelled; re-run with resume=true to continue"),
+                    ])));
+                }
+            }
+        };
+
+        match result {
+            Ok(report) => Ok(CallToolResult::success(vec![Content::text(
+                report.summary(),
+            )])),
+            Err(e) => {
+                error!("Copy failed: {}", e);
+                Ok(CallToolResult::error(vec![
+                    Content::text("COPY_FAILED"),
+                    Content::text(format!(
+                        "Failed to copy {} to {}: {}",
+                        source, destination, e
+                    )),
+                ]))
+            }
+        }
+    }
+
+    #[tool(
+        description = "Sync a local directory to a remote directory over SFTP, transferring only the differences (rsync-lite).
+
+TWO STEPS:
+1. Call without 'plan' to compare both sides (size, modification time, then SHA-256 when needed) and get the plan. Nothing is changed.
+2. Call again with the same arguments and 'plan' set to the exact plan lines returned in step 1 to apply it. If the remote changed since the plan was made, nothing is applied and the new plan is returned.
+
+PLAN LINES:
+- '+ path (size)' file will be added
+- '~ path (size)' file will be updated
+- '- path (size)' remote file will be deleted (only with delete=true)
+
+SAFETY:
+- Remote files are moved to a backup location before being overwritten or deleted; backup paths are listed in the result
+- Uploaded files keep their local permissions and modification time, and are verified with SHA-256
+- Use 'exclude' to leave paths such as '.git' or '*.swp' untouched on both sides"
+    )]
+    pub async fn sync(
+        &self,
+        ctx: RequestContext<RoleServer>,
+        Parameters(SyncRequest {
+            local_path,
+            remote_path,
+            plan,
+            delete,
+            exclude,
+            checksum,
+            password,
+            private_key_path,
+        }): Parameters<SyncRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if !TargetLocation::parse_path(&local_path).is_ok_and(|(location, _)| location.is_local()) {
+            return Ok(CallToolResult::error(vec![
+                Content::text("INVALID_PATH"),
+                Content::text("local_path must be a local directory"),
+            ]));
+        }
+        if TargetLocation::parse_path(&remote_path).is_ok_and(|(location, _)| location.is_local()) {
+            return Ok(CallToolResult::error(vec![
+                Content::text("INVALID_PATH"),
+                Content::text("remote_path must be in format user@host:/path"),
+            ]));
+        }
+
+        let (conn, remote_root) = match self
+            .get_remote_connection(&remote_path, password, private_key_path)
+            .await
+        {
+            Ok(result) => result,
+            Err(error_result) => return Ok(error_result),
+        };
+        let provider = RemoteFileSystemProvider::new(conn.clone());
+
+        let options = SyncOptions {
+            delete: delete.unwrap_or(false),
+            exclude: exclude.unwrap_or_default(),
+            checksum: checksum.unwrap_or(false),
+        };
+
+        let sync_plan = match plan_sync(&provider, &local_path, &remote_root, &options).await {
+            Ok(sync_plan) => sync_plan,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("SYNC_PLAN_ERROR"),
+                    Content::text(format!("Failed to compute sync plan: {}", e)),
+                ]));
+            }
+        };
+
+        let Some(expected_plan) = plan else {
+            let next_step = if sync_plan.is_empty() {
+                "Nothing to apply."
+            } else {
+                "To apply, call sync again with the same arguments and 'plan' set to the plan lines above."
+            };
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "Sync plan for {} -> {} ({}):\n\n{}\n\n{}",
+                local_path,
+                remote_path,
+                sync_plan.summary(),
+                sync_plan.to_text(),
+                next_step
+            ))]));
+        };
+
+        if !sync_plan.matches_text(&expected_plan) {
+            return Ok(CallToolResult::error(vec![
+                Content::text("SYNC_PLAN_CHANGED"),
+                Content::text(format!(
+                    "The directories changed since the plan was made; nothing was applied. Current plan ({}):\n\n{}",
+                    sync_plan.summary(),
+                    sync_plan.to_text()
+                )),
+            ]));
+        }
+
+        if sync_plan.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "{} is already in sync with {}",
+                remote_path, local_path
+            ))]));
+        }
+
+        let progress_id = Uuid::new_v4();
+        let total_changes = sync_plan.changes.len();
+        let applied_changes = std::sync::atomic::AtomicUsize::new(0);
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+
+        let apply = apply_sync_plan(&provider, &sync_plan, |change| {
+            let done = applied_changes.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            let _ = progress_tx.send(format!(
+                "[{}/{}] {}\n",
+                done,
+                total_changes,
+                change.to_line()
+            ));
+        });
+        tokio::pin!(apply);
 
-                if command_result.exit_code != 0 {
-                    return Ok(CallToolResult::error(vec![
-                        Content::text("COMMAND_FAILED"),
-                        Content::text(redacted_output),
-                    ]));
+        let mut redactor = StreamingRedactor::new(self.get_secret_manager().clone());
+        let result = loop {
+            tokio::select! {
+                result = &mut apply => break result,
+                Some(line) = progress_rx.recv() => {
+                    let _ = ctx.peer.notify_progress(ProgressNotificationParam {
+                        progress_token: ProgressToken(NumberOrString::Number(0)),
+                        progress: 50,
+                        total: Some(100),
+                        message: Some(serde_json::to_string(&ToolCallResultProgress {
+                            id: progress_id,
+                            message: redactor.push(&line),
+                        }).unwrap_or_default()),
+                    }).await;
+                }
+                _ = ctx.ct.cancelled() => {
+                    return Ok(CallToolResult::cancel(Some(&vec![
+                        Content::text("SYNC_CANCELLED"),
+                        Content::text("Sync was cancelled; run sync again without 'plan' to see what is left"),
+                    ])));
                 }
-                Ok(CallToolResult::success(vec![Content::text(
-                    &redacted_output,
-                )]))
             }
-            Err(error_result) => Ok(error_result),
+        };
+
+        let report = match result {
+            Ok(report) => report,
+            Err(e) => {
+                error!("Sync failed: {}", e);
+                return Ok(CallToolResult::error(vec![
+                    Content::text("SYNC_FAILED"),
+                    Content::text(format!("{}", e)),
+                ]));
+            }
+        };
+
+        let mut output = format!(
+            "Synced {} -> {} ({})",
+            local_path,
+            remote_path,
+            sync_plan.summary()
+        );
+
+        if !report.backups.is_empty() {
+            let ssh_prefix = conn.get_ssh_prefix().unwrap_or_default();
+            let backup_mapping: std::collections::HashMap<String, String> = report
+                .backups
+                .iter()
+                .map(|(original, backup)| {
+                    (
+                        format!("{}{}", ssh_prefix, original),
+                        format!("{}{}", ssh_prefix, backup),
+                    )
+                })
+                .collect();
+            output.push_str("\n\n");
+            output.push_str(&FileBackupManager::format_backup_xml(
+                &backup_mapping,
+                "remote",
+            ));
         }
+
+        Ok(CallToolResult::success(vec![Content::text(output)]))
     }
 
     #[tool(
-        description = "Execute a shell command asynchronously in the background on LOCAL OR REMOTE systems and return immediately with task information without waiting for completion.
-
-REMOTE EXECUTION SUPPORT:
-- Set 'remote' parameter to 'user@host' or 'user@host:port' for SSH background execution
-- Use 'password' for password authentication or 'private_key_path' for key-based auth  
-- Automatic SSH key discovery from ~/.ssh/ if no credentials provided
-- Examples:
-  * 'user@server.com' - Remote background task with auto-discovered keys
-  * 'user@server.com:2222' - Remote background task with custom port
+        description = "Follow log files on the local machine or remote hosts (like tail -f) and stream new lines as they are written.
 
-Use this for port-forwarding, starting servers, tailing logs, or other long-running commands that you want to monitor separately, or whenever the user wants to run a command in the background.
+FEATURES:
+- Local paths or remote paths (user@host:/var/log/app.log), several at once; lines from multiple files are merged by timestamp and prefixed with their source
+- Rotation aware: truncated or replaced files are detected and followed from the start
+- 'include'/'exclude' regexes filter lines, e.g. include=['ERROR|WARN'], exclude=['healthcheck']
+- 'max_lines_per_second' rate-limits noisy logs (default: 50); dropped lines are counted
 
-PARAMETERS:
-- command: The shell command to execute (locally or remotely)
-- description: Optional description of the command (not used in execution)  
-- timeout: Optional timeout in seconds after which the task will be terminated
-- remote: Optional remote connection string for SSH execution
-- password: Optional password for remote authentication
-- private_key_path: Optional path to private key for remote authentication
+MODES:
+- Default: follow for 'duration' seconds (default: 30, max: 600), streaming lines as they arrive, then return the collected lines
+- background=true: run as a background task that keeps the most recent lines; read it incrementally with get_task_details and stop it with cancel_task
 
-RETURNS:
-- task_id: Unique identifier for the background task
-- status: Current task status (will be 'Running' initially)
-- start_time: When the task was started
+For journald, tail the underlying files or use run_command_task with 'journalctl -f'.
 
 SECRET HANDLING:
-- Commands containing secrets will have them restored before execution
-- Task output will be redacted when retrieved
-- Use secret placeholders like [REDACTED_SECRET:rule-id:hash] in commands
-
-Use the get_all_tasks tool to monitor task progress, or the cancel_task tool to cancel a task."
+- Lines are redacted before being shown"
     )]
-    pub async fn run_command_task(
+    pub async fn tail(
         &self,
-        _ctx: RequestContext<RoleServer>,
-        Parameters(RunCommandRequest {
-            command,
-            description: _,
-            timeout,
-            remote,
+        ctx: RequestContext<RoleServer>,
+        Parameters(TailRequest {
+            paths,
+            lines,
+            duration,
+            include,
+            exclude,
+            max_lines_per_second,
+            background,
             password,
             private_key_path,
-        }): Parameters<RunCommandRequest>,
+        }): Parameters<TailRequest>,
     ) -> Result<CallToolResult, McpError> {
-        // Restore secrets in the command before execution
-        let actual_command = self
-            .get_secret_manager()
-            .restore_secrets_in_string(&command);
+        const MAX_TAIL_DURATION_SECS: u64 = 600;
+        const DEFAULT_TAIL_DURATION_SECS: u64 = 30;
+        const DEFAULT_MAX_LINES_PER_SECOND: u32 = 50;
+        const MAX_RESULT_LINES: usize = 300;
+
+        let options = match TailOptions::new(
+            &include.unwrap_or_default(),
+            &exclude.unwrap_or_default(),
+            Some(max_lines_per_second.unwrap_or(DEFAULT_MAX_LINES_PER_SECOND)),
+            lines.unwrap_or(DEFAULT_TAIL_LINES),
+        ) {
+            Ok(options) => options,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("INVALID_FILTER"),
+                    Content::text(e.to_string()),
+                ]));
+            }
+        };
 
-        let timeout_duration = timeout.map(std::time::Duration::from_secs);
+        let mut targets = Vec::with_capacity(paths.len());
+        for path in &paths {
+            match self
+                .get_transfer_endpoint(path, password.clone(), private_key_path.clone())
+                .await
+            {
+                Ok(TransferEndpoint::Local(local_path)) => {
+                    targets.push(TailTarget::Local(local_path))
+                }
+                Ok(TransferEndpoint::Remote { connection, path }) => {
+                    targets.push(TailTarget::Remote { connection, path })
+                }
+                Err(error_result) => return Ok(error_result),
+            }
+        }
 
-        // Handle both local and remote async commands using TaskManager
-        let result = if let Some(remote_str) = remote {
-            // Remote async command
-            let remote_connection = RemoteConnectionInfo {
-                connection_string: remote_str,
-                password,
-                private_key_path,
-            };
+        let mut tailer = match LogTailer::new(targets, options).await {
+            Ok(tailer) => tailer,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![
+                    Content::text("TAIL_ERROR"),
+                    Content::text(format!("Failed to open log files: {}", e)),
+                ]));
+            }
+        };
 
-            self.get_task_manager()
-                .start_task(actual_command, timeout_duration, Some(remote_connection))
-                .await
-        } else {
-            // Local async command (existing logic)
-            self.get_task_manager()
-                .start_task(actual_command, timeout_duration, None)
+        if background.unwrap_or(false) {
+            let label = format!("tail {}", paths.join(" "));
+            let task_duration = duration.map(std::time::Duration::from_secs);
+            return match self
+                .get_task_manager()
+                .start_tail_task(tailer, task_duration, label)
                 .await
-        };
+            {
+                Ok(task_info) => {
+                    let output = serde_json::to_string_pretty(&task_info)
+                        .unwrap_or_else(|_| format!("Task started: {}", task_info.id));
+                    Ok(CallToolResult::success(vec![Content::text(format!(
+                        "Background tail started:\n{}\n\nUse get_task_details to read the latest lines or cancel_task to stop it.",
+                        output
+                    ))]))
+                }
+                Err(e) => {
+                    error!("Failed to start background tail: {}", e);
+                    Ok(CallToolResult::error(vec![
+                        Content::text("TAIL_ERROR"),
+                        Content::text(format!("Failed to start background tail: {}", e)),
+                    ]))
+                }
+            };
+        }
 
-        match result {
-            Ok(task_info) => {
-                let output = serde_json::to_string_pretty(&task_info)
-                    .unwrap_or_else(|_| format!("Task started: {}", task_info.id));
+        let follow_for = std::time::Duration::from_secs(
+            duration
+                .unwrap_or(DEFAULT_TAIL_DURATION_SECS)
+                .min(MAX_TAIL_DURATION_SECS),
+        );
+        let deadline = tokio::time::Instant::now() + follow_for;
+        let with_source = tailer.follows_multiple_files();
+        let progress_id = Uuid::new_v4();
+        let mut collected: std::collections::VecDeque<String> =
+            std::collections::VecDeque::with_capacity(MAX_RESULT_LINES);
+        let mut total_lines = 0usize;
+        let mut poll_interval = tokio::time::interval(DEFAULT_TAIL_POLL_INTERVAL);
+        let mut events = tailer.initial_events().await;
+        let mut redactor = StreamingRedactor::new(self.get_secret_manager().clone());
+
+        loop {
+            if !events.is_empty() {
+                let batch = events
+                    .iter()
+                    .map(|event| format!("{}\n", event.format(with_source)))
+                    .collect::<String>();
+                let redacted_batch = redactor.push(&batch);
+
+                if !redacted_batch.is_empty() {
+                    let _ = ctx
+                        .peer
+                        .notify_progress(ProgressNotificationParam {
+                            progress_token: ProgressToken(NumberOrString::Number(0)),
+                            progress: 50,
+                            total: Some(100),
+                            message: Some(
+                                serde_json::to_string(&ToolCallResultProgress {
+                                    id: progress_id,
+                                    message: redacted_batch.clone(),
+                                })
+                                .unwrap_or_default(),
+                            ),
+                        })
+                        .await;
+                }
 
-                Ok(CallToolResult::success(vec![Content::text(format!(
-                    "Background task started:\n{}",
-                    output
-                ))]))
+                for line in redacted_batch.lines() {
+                    total_lines += 1;
+                    if collected.len() == MAX_RESULT_LINES {
+                        collected.pop_front();
+                    }
+                    collected.push_back(line.to_string());
+                }
             }
-            Err(e) => {
-                error!("Failed to start background task: {}", e);
 
-                Ok(CallToolResult::error(vec![
-                    Content::text("RUN_COMMAND_TASK_ERROR"),
-                    Content::text(format!("Failed to start background task: {}", e)),
-                ]))
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => break,
+                _ = poll_interval.tick() => events = tailer.poll().await,
+                _ = ctx.ct.cancelled() => {
+                    return Ok(CallToolResult::cancel(Some(&vec![
+                        Content::text("TAIL_CANCELLED"),
+                        Content::text("Log tailing was cancelled"),
+                    ])));
+                }
+            }
+        }
+
+        // Lines held back by the redactor (an unterminated key block) still belong in the result
+        for line in redactor.finish().lines() {
+            total_lines += 1;
+            if collected.len() == MAX_RESULT_LINES {
+                collected.pop_front();
             }
+            collected.push_back(line.to_string());
         }
+
+        let mut output = format!(
+            "Followed {} for {}s: {} lines",
+            paths.join(", "),
+            follow_for.as_secs(),
+            total_lines
+        );
+        if total_lines > collected.len() {
+            output.push_str(&format!(" (showing last {})", collected.len()));
+        }
+        output.push_str("\n\n");
+        output.push_str(
+            &collected
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+
+        Ok(CallToolResult::success(vec![Content::text(output)]))
     }
 
     #[tool(
@@ -645,6 +1675,13 @@ REMOTE FILE ACCESS:
   * 'user@server.com:/home/user/documents' - Remote directory listing
   * '/local/path/file.txt' - Local file (default behavior)
 
+CONTAINERS AND PODS:
+- 'container://name:/path' runs through docker (or podman if docker is not installed); force one with 'docker://' or 'podman://'
+- 'pod://namespace/name:/path' runs through kubectl; append '#container' to the pod name to pick a container
+
+PRIVILEGED FILES:
+- Set 'sudo=true' (or 'become_user') with 'sudo_password' as a secret placeholder to work with root-owned files
+
 For directories:
 - Default behavior: Lists immediate directory contents
 - With tree=true: Displays nested directory structure as a tree (limited to 3 levels deep)
@@ -664,27 +1701,31 @@ A maximum of 300 lines will be shown at a time, the rest will be truncated."
             password,
             private_key_path,
             tree,
+            sudo_params,
         }): Parameters<ViewRequest>,
     ) -> Result<CallToolResult, McpError> {
         const MAX_LINES: usize = 300;
 
-        // Check if this is a remote path
-        if Self::is_remote_path(&path) {
-            // Handle remote file/directory viewing
-            match self
-                .get_remote_connection(&path, password, private_key_path)
+        match self
+            .get_exec_target(&path, password, private_key_path)
+            .await
+        {
+            Ok((target, target_path)) => {
+                let target = match self.with_sudo("view", target, sudo_params) {
+                    Ok(target) => target,
+                    Err(error_result) => return Ok(error_result),
+                };
+                self.view_target_path(
+                    target.as_ref(),
+                    &target_path,
+                    &path,
+                    view_range,
+                    MAX_LINES,
+                    tree,
+                )
                 .await
-            {
-                Ok((conn, remote_path)) => {
-                    self.view_remote_path(&conn, &remote_path, &path, view_range, MAX_LINES, tree)
-                        .await
-                }
-                Err(error_result) => Ok(error_result),
             }
-        } else {
-            // Handle local file/directory viewing
-            self.view_local_path(&path, view_range, MAX_LINES, tree)
-                .await
+            Err(error_result) => Ok(error_result),
         }
     }
 
@@ -701,8 +1742,15 @@ REMOTE FILE EDITING:
   * 'ssh://user@server.com/var/www/app/config.php' - Edit remote application config
   * '/local/path/file.txt' - Edit local file (default behavior)
 
+CONTAINERS AND PODS:
+- 'container://name:/path' runs through docker (or podman if docker is not installed); force one with 'docker://' or 'podman://'
+- 'pod://namespace/name:/path' runs through kubectl; append '#container' to the pod name to pick a container
+
+PRIVILEGED FILES:
+- Set 'sudo=true' (or 'become_user') with 'sudo_password' as a secret placeholder to work with root-owned files
+
 SECRET HANDLING:
-- You can use secret placeholders like [REDACTED_SECRET:rule-id:hash] in both old_str and new_str parameters
+- You can use secret placeholders like [REDACTED_SECRET:rule-id:hash] and references like {{secret:env:NAME}} in both old_str and new_str parameters
 - These placeholders will be automatically restored to actual secret values before performing the replacement
 - This allows you to safely work with secret values without exposing them
 
@@ -717,32 +1765,29 @@ When replacing code, ensure the new text maintains proper syntax, indentation, a
             replace_all,
             password,
             private_key_path,
+            sudo_params,
         }): Parameters<StrReplaceRequest>,
     ) -> Result<CallToolResult, McpError> {
-        // Check if this is a remote path
-        if Self::is_remote_path(&path) {
-            // Handle remote file replacement
-            match self
-                .get_remote_connection(&path, password, private_key_path)
+        match self
+            .get_exec_target(&path, password, private_key_path)
+            .await
+        {
+            Ok((target, target_path)) => {
+                let target = match self.with_sudo("str_replace", target, sudo_params) {
+                    Ok(target) => target,
+                    Err(error_result) => return Ok(error_result),
+                };
+                self.str_replace_target(
+                    target.as_ref(),
+                    &target_path,
+                    &path,
+                    &old_str,
+                    &new_str,
+                    replace_all,
+                )
                 .await
-            {
-                Ok((conn, remote_path)) => {
-                    self.str_replace_remote(
-                        &conn,
-                        &remote_path,
-                        &path,
-                        &old_str,
-                        &new_str,
-                        replace_all,
-                    )
-                    .await
-                }
-                Err(error_result) => Ok(error_result),
             }
-        } else {
-            // Handle local file replacement
-            self.str_replace_local(&path, &old_str, &new_str, replace_all)
-                .await
+            Err(error_result) => Ok(error_result),
         }
     }
 
@@ -760,9 +1805,16 @@ REMOTE FILE CREATION:
   * 'ssh://user@server.com/var/www/new-config.json' - Create remote config
   * '/local/path/file.txt' - Create local file (default behavior)
 
+CONTAINERS AND PODS:
+- 'container://name:/path' runs through docker (or podman if docker is not installed); force one with 'docker://' or 'podman://'
+- 'pod://namespace/name:/path' runs through kubectl; append '#container' to the pod name to pick a container
+
+PRIVILEGED FILES:
+- Set 'sudo=true' (or 'become_user') with 'sudo_password' as a secret placeholder to work with root-owned files
+
 SECRET HANDLING:
 - File content containing secrets will have them restored before writing to ensure functionality
-- Use secret placeholders like [REDACTED_SECRET:rule-id:hash] in file_text parameter"
+- Use secret placeholders like [REDACTED_SECRET:rule-id:hash] or references like {{secret:env:NAME}} in file_text parameter"
     )]
     pub async fn create(
         &self,
@@ -771,24 +1823,22 @@ SECRET HANDLING:
             file_text,
             password,
             private_key_path,
+            sudo_params,
         }): Parameters<CreateRequest>,
     ) -> Result<CallToolResult, McpError> {
-        // Check if this is a remote path
-        if Self::is_remote_path(&path) {
-            // Handle remote file creation
-            match self
-                .get_remote_connection(&path, password, private_key_path)
-                .await
-            {
-                Ok((conn, remote_path)) => {
-                    self.create_remote(&conn, &remote_path, &path, &file_text)
-                        .await
-                }
-                Err(error_result) => Ok(error_result),
+        match self
+            .get_exec_target(&path, password, private_key_path)
+            .await
+        {
+            Ok((target, target_path)) => {
+                let target = match self.with_sudo("create", target, sudo_params) {
+                    Ok(target) => target,
+                    Err(error_result) => return Ok(error_result),
+                };
+                self.create_target_file(target.as_ref(), &target_path, &path, &file_text)
+                    .await
             }
-        } else {
-            // Handle local file creation
-            self.create_local(&path, &file_text)
+            Err(error_result) => Ok(error_result),
         }
     }
 
@@ -946,6 +1996,13 @@ REMOTE FILE REMOVAL:
   * 'user@server.com#2222:/var/log/old-logs/' - Remove remote directory (with recursive=true)
   * '/local/path/file.txt' - Remove local file (default behavior)
 
+CONTAINERS AND PODS:
+- 'container://name:/path' runs through docker (or podman if docker is not installed); force one with 'docker://' or 'podman://'
+- 'pod://namespace/name:/path' runs through kubectl; append '#container' to the pod name to pick a container
+
+PRIVILEGED FILES:
+- Set 'sudo=true' (or 'become_user') with 'sudo_password' as a secret placeholder to work with root-owned files
+
 DIRECTORY REMOVAL:
 - Use 'recursive=true' to remove directories and their contents
 - Files can be removed without the recursive flag
@@ -953,7 +2010,7 @@ DIRECTORY REMOVAL:
 BACKUP & RECOVERY:
 - ALL removed files and directories are automatically backed up before deletion
 - Local files: Moved to '.stakpak/session/backups/{uuid}/' on the local machine
-- Remote files: Moved to '.stakpak/session/backups/{uuid}/' on the remote machine
+- Remote files: Moved to '.stakpak/session/backups/{uuid}/' on the remote machine, container or pod
 - Backup paths are returned in XML format showing original and backup locations
 - Files are moved (not copied) to backup location, making removal efficient
 - Both files and entire directories can be recovered from backup locations
@@ -971,23 +2028,45 @@ SAFETY NOTES:
             recursive,
             password,
             private_key_path,
+            sudo_params,
         }): Parameters<RemoveRequest>,
     ) -> Result<CallToolResult, McpError> {
         let recursive = recursive.unwrap_or(false);
 
-        if Self::is_remote_path(&path) {
-            match self
-                .get_remote_connection(&path, password, private_key_path)
-                .await
-            {
-                Ok((conn, remote_path)) => {
-                    self.remove_remote_path(&conn, &remote_path, &path, recursive)
-                        .await
-                }
-                Err(error_result) => Ok(error_result),
+        match self
+            .get_exec_target(&path, password, private_key_path)
+            .await
+        {
+            Ok((target, target_path)) => {
+                let target = match self.with_sudo("remove", target, sudo_params) {
+                    Ok(target) => target,
+                    Err(error_result) => return Ok(error_result),
+                };
+                self.remove_target_path(target.as_ref(), &target_path, &path, recursive)
+                    .await
+            }
+            Err(error_result) => Ok(error_result),
+        }
+    }
+
+    /// Resolve a local or remote path into a transfer endpoint, connecting if remote
+    async fn get_transfer_endpoint(
+        &self,
+        path: &str,
+        password: Option<String>,
+        private_key_path: Option<String>,
+    ) -> Result<TransferEndpoint, CallToolResult> {
+        match parse_ssh_path(path)? {
+            None => Ok(TransferEndpoint::Local(path.to_string())),
+            Some(_) => {
+                let (connection, remote_path) = self
+                    .get_remote_connection(path, password, private_key_path)
+                    .await?;
+                Ok(TransferEndpoint::Remote {
+                    connection,
+                    path: remote_path,
+                })
             }
-        } else {
-            self.remove_local_path(&path, recursive).await
         }
     }
 
@@ -998,231 +2077,223 @@ SAFETY NOTES:
         password: Option<String>,
         private_key_path: Option<String>,
     ) -> Result<(Arc<RemoteConnection>, String), CallToolResult> {
-        let path_location = PathLocation::parse(path).map_err(|e| {
-            CallToolResult::error(vec![
-                Content::text("INVALID_PATH"),
-                Content::text(format!("Failed to parse path: {}", e)),
-            ])
-        })?;
+        let Some((mut connection, remote_path)) = parse_ssh_path(path)? else {
+            return Err(CallToolResult::error(vec![
+                Content::text("NOT_REMOTE"),
+                Content::text("This helper is for remote connections only"),
+            ]));
+        };
+
+        // Override connection details if provided
+        if let Some(pwd) = password {
+            connection.password = Some(pwd);
+        }
+        if let Some(key_path) = private_key_path {
+            connection.private_key_path = Some(key_path);
+        }
+
+        let connection_manager = self.get_remote_connection_manager();
+        let conn = connection_manager
+            .get_connection(&connection)
+            .await
+            .map_err(|e| {
+                error!("Failed to establish remote connection: {}", e);
+                CallToolResult::error(vec![
+                    Content::text("REMOTE_CONNECTION_ERROR"),
+                    Content::text(format!("Failed to connect to remote host: {}", e)),
+                ])
+            })?;
+
+        Ok((conn, remote_path))
+    }
 
-        match path_location {
-            PathLocation::Remote {
-                mut connection,
-                path: remote_path,
-            } => {
-                // Override connection details if provided
+    /// Connect to the exec target for a location, applying SSH credentials when given
+    async fn connect_target(
+        &self,
+        location: TargetLocation,
+        password: Option<String>,
+        private_key_path: Option<String>,
+    ) -> Result<Arc<dyn ExecTarget>, CallToolResult> {
+        let location = match location {
+            TargetLocation::Ssh(mut connection) => {
                 if let Some(pwd) = password {
                     connection.password = Some(pwd);
                 }
                 if let Some(key_path) = private_key_path {
                     connection.private_key_path = Some(key_path);
                 }
-
-                let connection_manager = self.get_remote_connection_manager();
-                let conn = connection_manager
-                    .get_connection(&connection)
-                    .await
-                    .map_err(|e| {
-                        error!("Failed to establish remote connection: {}", e);
-                        CallToolResult::error(vec![
-                            Content::text("REMOTE_CONNECTION_ERROR"),
-                            Content::text(format!("Failed to connect to remote host: {}", e)),
-                        ])
-                    })?;
-
-                Ok((conn, remote_path))
+                TargetLocation::Ssh(connection)
             }
-            PathLocation::Local(_) => Err(CallToolResult::error(vec![
-                Content::text("NOT_REMOTE"),
-                Content::text("This helper is for remote connections only"),
-            ])),
+            location => location,
+        };
+
+        location
+            .connect(self.get_remote_connection_manager())
+            .await
+            .map_err(|e| {
+                error!("Failed to establish remote connection: {}", e);
+                CallToolResult::error(vec![
+                    Content::text("REMOTE_CONNECTION_ERROR"),
+                    Content::text(format!("Failed to connect to remote host: {}", e)),
+                ])
+            })
+    }
+
+    /// Restore placeholders and resolve secret references in input for `tool_name`,
+    /// reporting egress policy violations and references that cannot be resolved as a
+    /// tool error
+    fn restore_tool_input(&self, tool_name: &str, input: &str) -> Result<String, CallToolResult> {
+        let secret_manager = self.get_secret_manager();
+        let violations = secret_manager.check_egress(tool_name, input);
+        if !violations.is_empty() {
+            let reasons: Vec<String> = violations.iter().map(ToString::to_string).collect();
+            return Err(CallToolResult::error(vec![
+                Content::text("SECRET_EGRESS_BLOCKED"),
+                Content::text(reasons.join("\n")),
+            ]));
         }
+        secret_manager
+            .try_restore_secrets_in_string(input)
+            .map_err(|e| {
+                CallToolResult::error(vec![
+                    Content::text("SECRET_REFERENCE_ERROR"),
+                    Content::text(e.to_string()),
+                ])
+            })
     }
 
-    /// Check if a path is remote
-    fn is_remote_path(path: &str) -> bool {
-        PathLocation::parse(path)
-            .map(|loc| loc.is_remote())
-            .unwrap_or(false)
+    /// Sudo settings for a tool call, with the password restored from its secret placeholder
+    /// under the same egress checks as the rest of the tool input
+    fn sudo_options(
+        &self,
+        tool_name: &str,
+        SudoParams {
+            sudo,
+            become_user,
+            sudo_password,
+        }: SudoParams,
+    ) -> Result<Option<SudoOptions>, CallToolResult> {
+        if !sudo.unwrap_or(false) && become_user.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(SudoOptions {
+            user: become_user,
+            password: sudo_password
+                .map(|password| self.restore_tool_input(tool_name, &password))
+                .transpose()?,
+        }))
     }
 
-    /// Execute command either locally or remotely based on parameters
-    async fn execute_command_unified(
+    /// Wrap a target in sudo when the tool call asks for privilege escalation
+    fn with_sudo(
         &self,
-        command: &str,
-        timeout: Option<u64>,
-        remote: Option<String>,
+        tool_name: &str,
+        target: Arc<dyn ExecTarget>,
+        sudo_params: SudoParams,
+    ) -> Result<Arc<dyn ExecTarget>, CallToolResult> {
+        Ok(match self.sudo_options(tool_name, sudo_params)? {
+            Some(options) => Arc::new(SudoTarget::new(target, options)),
+            None => target,
+        })
+    }
+
+    /// Resolve a tool path into the target it lives on and the path on that target
+    async fn get_exec_target(
+        &self,
+        path: &str,
         password: Option<String>,
         private_key_path: Option<String>,
-        ctx: &RequestContext<RoleServer>,
-    ) -> Result<CommandResult, CallToolResult> {
-        let actual_command = self.get_secret_manager().restore_secrets_in_string(command);
-
-        if let Some(remote_str) = &remote {
-            // Remote execution
-            let connection_info = RemoteConnectionInfo {
-                connection_string: remote_str.clone(),
-                password: password.clone(),
-                private_key_path: private_key_path.clone(),
-            };
-
-            let connection_manager = self.get_remote_connection_manager();
-            let connection = connection_manager
-                .get_connection(&connection_info)
-                .await
-                .map_err(|e| {
-                    error!("Failed to establish remote connection: {}", e);
-                    CallToolResult::error(vec![
-                        Content::text("REMOTE_CONNECTION_ERROR"),
-                        Content::text(format!("Failed to connect to remote host: {}", e)),
-                    ])
-                })?;
-
-            let timeout_duration = timeout.map(std::time::Duration::from_secs);
-            let (output, exit_code) = connection
-                .execute_command(&actual_command, timeout_duration, Some(ctx))
-                .await
-                .map_err(|e| {
-                    error!("Failed to execute remote command: {}", e);
-                    CallToolResult::error(vec![
-                        Content::text("REMOTE_COMMAND_ERROR"),
-                        Content::text(format!("Failed to execute remote command: {}", e)),
-                    ])
-                })?;
-
-            let mut result = output;
-            if exit_code != 0 {
-                result.push_str(&format!("\nCommand exited with code {}", exit_code));
-            }
-
-            Ok(CommandResult {
-                output: result,
-                exit_code,
-            })
-        } else {
-            // Local execution - existing logic
-            self.execute_local_command(&actual_command, timeout, ctx)
-                .await
-        }
+    ) -> Result<(Arc<dyn ExecTarget>, String), CallToolResult> {
+        let (location, target_path) = TargetLocation::parse_path(path).map_err(|e| {
+            CallToolResult::error(vec![
+                Content::text("INVALID_PATH"),
+                Content::text(format!("Failed to parse path: {}", e)),
+            ])
+        })?;
+        let target = self
+            .connect_target(location, password, private_key_path)
+            .await?;
+        Ok((target, target_path))
     }
 
-    /// Execute local command with existing logic extracted to avoid duplication
-    async fn execute_local_command(
+    /// Execute command locally, over SSH or inside a container or pod, streaming its output
+    #[allow(clippy::too_many_arguments)]
+    async fn execute_command_unified(
         &self,
-        actual_command: &str,
+        command: &str,
         timeout: Option<u64>,
+        remote: Option<String>,
+        password: Option<String>,
+        private_key_path: Option<String>,
+        sudo: Option<SudoOptions>,
         ctx: &RequestContext<RoleServer>,
     ) -> Result<CommandResult, CallToolResult> {
-        let mut cmd = Command::new("sh");
-        cmd.arg("-c")
-            .arg(actual_command)
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped());
-        #[cfg(unix)]
-        {
-            cmd.env("DEBIAN_FRONTEND", "noninteractive")
-                .env("SUDO_ASKPASS", "/bin/false")
-                .process_group(0);
-        }
-        #[cfg(windows)]
-        {
-            // On Windows, create a new process group
-            cmd.creation_flags(0x00000200); // CREATE_NEW_PROCESS_GROUP
+        let actual_command = self.restore_tool_input("run_command", command)?;
+
+        let location = match &remote {
+            Some(remote_str) => TargetLocation::parse_target(remote_str).map_err(|e| {
+                CallToolResult::error(vec![
+                    Content::text("INVALID_REMOTE"),
+                    Content::text(format!("Failed to parse remote target: {}", e)),
+                ])
+            })?,
+            None => TargetLocation::Local,
+        };
+        let mut target = self
+            .connect_target(location, password, private_key_path)
+            .await?;
+        // Masked by the redactor, which sees the password whole even when it is split
+        // across chunks
+        let sudo_password = sudo.as_ref().and_then(|sudo| sudo.password.clone());
+        if let Some(sudo) = sudo {
+            target = Arc::new(SudoTarget::new(target, sudo));
         }
 
-        let mut child = cmd.spawn().map_err(|e| {
-            error!("Failed to run command: {}", e);
-            CallToolResult::error(vec![
-                Content::text("COMMAND_ERROR"),
-                Content::text(format!("Failed to run command: {}", e)),
-            ])
-        })?;
-
-        #[allow(clippy::unwrap_used)]
-        let stdout = child.stdout.take().unwrap();
-        #[allow(clippy::unwrap_used)]
-        let stderr = child.stderr.take().unwrap();
-        let mut stdout_reader = BufReader::new(stdout);
-        let mut stderr_reader = BufReader::new(stderr);
-        let mut stdout_buf = String::new();
-        let mut stderr_buf = String::new();
-        let mut result = String::new();
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
+        let mut cancel_tx = Some(cancel_tx);
         let progress_id = Uuid::new_v4();
 
-        // Helper function to stream output and wait for process completion
-        let stream_and_wait = async {
-            // Read from both streams concurrently
-            loop {
-                tokio::select! {
-                    Ok(n) = stderr_reader.read_line(&mut stderr_buf) => {
-                        if n == 0 {
-                            break;
-                        }
-                        let line = stderr_buf.trim_end_matches('\n').to_string();
-                        stderr_buf.clear();
-                        result.push_str(&format!("{}\n", line));
-                        // Send notification but continue processing
-                        let _ = ctx.peer.notify_progress(ProgressNotificationParam {
-                            progress_token: ProgressToken(NumberOrString::Number(0)),
-                            progress: 50,
-                            total: Some(100),
-                            message: Some(serde_json::to_string(&ToolCallResultProgress {
-                                id: progress_id,
-                                message: line,
-                            }).unwrap_or_default()),
-                        }).await;
-                    }
-                    Ok(n) = stdout_reader.read_line(&mut stdout_buf) => {
-                        if n == 0 {
-                            break;
-                        }
-                        let line = stdout_buf.trim_end_matches('\n').to_string();
-                        stdout_buf.clear();
-                        result.push_str(&format!("{}\n", line));
-                        // Send notification but continue processing
-                        // skip if message is empty
-                        if line.is_empty() {
-                            continue;
-                        }
-                        let _ = ctx.peer.notify_progress(ProgressNotificationParam {
-                            progress_token: ProgressToken(NumberOrString::Number(0)),
-                            progress: 50,
-                            total: Some(100),
-                            message: Some(serde_json::to_string(&ToolCallResultProgress {
-                                id: progress_id,
-                                message: format!("{}\n", line),
-                            }).unwrap_or_default()),
-                        }).await;
-                    }
-                    else => break,
-                }
-            }
-
-            // Wait for the process to complete
-            child.wait().await
-        };
-
-        // Execute with timeout and cancellation support
-        let execution_result = if let Some(timeout_secs) = timeout {
-            let timeout_duration = std::time::Duration::from_secs(timeout_secs);
+        let execution = target.exec(
+            &actual_command,
+            ExecOptions {
+                timeout: timeout.map(std::time::Duration::from_secs),
+                output_tx: Some(output_tx),
+                cancel_rx: Some(cancel_rx),
+                stdin: None,
+            },
+        );
+        tokio::pin!(execution);
 
+        // Output is redacted before it is streamed, holding back partial lines so a
+        // secret split across chunks is still caught
+        let mut redactor = StreamingRedactor::new(self.get_secret_manager().clone())
+            .masking(sudo_password, SUDO_PASSWORD_MASK);
+        let result = loop {
             tokio::select! {
-                result = tokio::time::timeout(timeout_duration, stream_and_wait) => result,
-                _ = ctx.ct.cancelled() => {
-                    // Cancellation occurred, kill the process
-                    let _ = child.kill().await;
-                    return Err(CallToolResult::cancel(Some(&vec![
-                        Content::text("COMMAND_CANCELLED"),
-                        Content::text("Command execution was cancelled"),
-                    ])));
+                result = &mut execution => break result,
+                Some(chunk) = output_rx.recv() => {
+                    let redacted = redactor.push(&chunk);
+                    // skip if message is empty
+                    if redacted.trim().is_empty() {
+                        continue;
+                    }
+                    let _ = ctx.peer.notify_progress(ProgressNotificationParam {
+                        progress_token: ProgressToken(NumberOrString::Number(0)),
+                        progress: 50,
+                        total: Some(100),
+                        message: Some(serde_json::to_string(&ToolCallResultProgress {
+                            id: progress_id,
+                            message: format!("{}\n", redacted.trim_end_matches('\n')),
+                        }).unwrap_or_default()),
+                    }).await;
                 }
-            }
-        } else {
-            tokio::select! {
-                result = stream_and_wait => Ok(result),
                 _ = ctx.ct.cancelled() => {
-                    let _ = child.kill().await;
+                    // Let the target kill the process before reporting the cancellation
+                    if let Some(cancel_tx) = cancel_tx.take() {
+                        let _ = cancel_tx.send(());
+                    }
+                    let _ = (&mut execution).await;
                     return Err(CallToolResult::cancel(Some(&vec![
                         Content::text("COMMAND_CANCELLED"),
                         Content::text("Command execution was cancelled"),
@@ -1231,164 +2302,120 @@ SAFETY NOTES:
             }
         };
 
-        let exit_code = match execution_result {
-            Ok(Ok(exit_status)) => exit_status.code().unwrap_or(-1),
-            Ok(Err(e)) => {
-                return Err(CallToolResult::error(vec![
-                    Content::text("COMMAND_ERROR"),
-                    Content::text(format!("Failed to wait for command: {}", e)),
-                ]));
-            }
-            Err(_) => {
-                // Timeout occurred, kill the process
-                let _ = child.kill().await;
-                result.push_str(&format!(
-                    "Command timed out after {} seconds\n",
-                    timeout.unwrap_or_default()
-                ));
-                -1
-            }
-        };
+        let remaining = redactor.finish();
+        if !remaining.trim().is_empty() {
+            let _ = ctx
+                .peer
+                .notify_progress(ProgressNotificationParam {
+                    progress_token: ProgressToken(NumberOrString::Number(0)),
+                    progress: 50,
+                    total: Some(100),
+                    message: Some(
+                        serde_json::to_string(&ToolCallResultProgress {
+                            id: progress_id,
+                            message: format!("{}\n", remaining.trim_end_matches('\n')),
+                        })
+                        .unwrap_or_default(),
+                    ),
+                })
+                .await;
+        }
+
+        let exec_output = result.map_err(|e| {
+            error!("Failed to execute command: {}", e);
+            let code = if target.kind() == TargetKind::Local {
+                "COMMAND_ERROR"
+            } else {
+                "REMOTE_COMMAND_ERROR"
+            };
+            CallToolResult::error(vec![
+                Content::text(code),
+                Content::text(format!("Failed to execute command: {}", e)),
+            ])
+        })?;
 
-        if exit_code != 0 {
-            result.push_str(&format!("Command exited with code {}\n", exit_code));
+        let mut output = exec_output.output;
+        if exec_output.exit_code != 0 {
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            output.push_str(&format!(
+                "Command exited with code {}\n",
+                exec_output.exit_code
+            ));
         }
 
         Ok(CommandResult {
-            output: result,
-            exit_code,
+            output,
+            exit_code: exec_output.exit_code,
         })
     }
 
-    /// View the contents of a local file or directory
-    async fn view_local_path(
+    /// View the contents of a file or directory on any exec target
+    async fn view_target_path(
         &self,
-        path: &str,
+        target: &dyn ExecTarget,
+        target_path: &str,
+        original_path: &str,
         view_range: Option<[i32; 2]>,
         max_lines: usize,
         tree: Option<bool>,
     ) -> Result<CallToolResult, McpError> {
-        let path_obj = Path::new(path);
-
-        if !path_obj.exists() {
-            return Ok(CallToolResult::error(vec![
-                Content::text("FILE_NOT_FOUND"),
-                Content::text(format!("File or directory not found: {}", path)),
-            ]));
-        }
-
-        if path_obj.is_dir() {
-            let depth = if tree.unwrap_or(false) { 3 } else { 1 };
-            let provider = LocalFileSystemProvider;
-            let path_str = path_obj.to_string_lossy();
-
-            match generate_directory_tree(&provider, &path_str, "", depth, 0).await {
-                Ok(tree_content) => {
-                    let result = format!(
-                        "Directory tree for \"{}\":\n{}\n{}",
-                        path,
-                        path_obj
-                            .file_name()
-                            .map(|name| name.to_string_lossy().to_string())
-                            .unwrap_or_else(|| path.to_string()),
-                        tree_content
-                    );
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                }
-                Err(e) => Ok(CallToolResult::error(vec![
-                    Content::text("READ_ERROR"),
-                    Content::text(format!("Cannot read directory: {}", e)),
-                ])),
-            }
+        let (file_label, tree_label) = if target.kind() == TargetKind::Local {
+            ("File", "Directory tree")
         } else {
-            // Read file contents
-            match fs::read_to_string(path) {
-                Ok(content) => {
-                    let result = match self
-                        .format_file_content(&content, path, view_range, max_lines, "File")
-                    {
-                        Ok(result) => result,
-                        Err(e) => {
-                            return Ok(CallToolResult::error(vec![
-                                Content::text("FORMAT_ERROR"),
-                                Content::text(format!("Failed to format file content: {}", e)),
-                            ]));
-                        }
-                    };
-
-                    let redacted_result = self
-                        .get_secret_manager()
-                        .redact_and_store_secrets(&result, Some(path));
-                    Ok(CallToolResult::success(vec![Content::text(
-                        &redacted_result,
-                    )]))
-                }
-                Err(e) => Ok(CallToolResult::error(vec![
-                    Content::text("READ_ERROR"),
-                    Content::text(format!("Cannot read file: {}", e)),
-                ])),
-            }
-        }
-    }
+            ("Remote file", "Remote directory tree")
+        };
 
-    /// View the contents of a remote file or directory
-    async fn view_remote_path(
-        &self,
-        conn: &Arc<RemoteConnection>,
-        remote_path: &str,
-        original_path: &str,
-        view_range: Option<[i32; 2]>,
-        max_lines: usize,
-        tree: Option<bool>,
-    ) -> Result<CallToolResult, McpError> {
-        if !conn.exists(remote_path).await {
+        if !target.exists(target_path).await {
             return Ok(CallToolResult::error(vec![
                 Content::text("FILE_NOT_FOUND"),
                 Content::text(format!(
-                    "Remote file or directory not found: {}",
-                    original_path
+                    "{} or directory not found: {}",
+                    file_label, original_path
                 )),
             ]));
         }
 
-        if conn.is_directory(remote_path).await {
+        if target.is_directory(target_path).await {
             let depth = if tree.unwrap_or(false) { 3 } else { 1 };
-            let provider = RemoteFileSystemProvider::new(conn.clone());
+            let provider = ExecTargetFileSystemProvider::new(target);
 
-            match generate_directory_tree(&provider, remote_path, "", depth, 0).await {
+            match generate_directory_tree(&provider, target_path, "", depth, 0).await {
                 Ok(tree_content) => {
                     let result = format!(
-                        "Remote directory tree for \"{}\":\n{}\n{}",
+                        "{} for \"{}\":\n{}\n{}",
+                        tree_label,
                         original_path,
-                        remote_path.split('/').next_back().unwrap_or(remote_path),
+                        Path::new(target_path)
+                            .file_name()
+                            .map(|name| name.to_string_lossy().to_string())
+                            .unwrap_or_else(|| target_path.to_string()),
                         tree_content
                     );
                     Ok(CallToolResult::success(vec![Content::text(result)]))
                 }
                 Err(e) => Ok(CallToolResult::error(vec![
                     Content::text("READ_ERROR"),
-                    Content::text(format!("Cannot read remote directory: {}", e)),
+                    Content::text(format!("Cannot read directory: {}", e)),
                 ])),
             }
         } else {
-            // Read remote file contents
-            match conn.read_file_to_string(remote_path).await {
+            // Read file contents
+            match target.read_file_to_string(target_path).await {
                 Ok(content) => {
                     let result = match self.format_file_content(
                         &content,
                         original_path,
                         view_range,
                         max_lines,
-                        "Remote file",
+                        file_label,
                     ) {
                         Ok(result) => result,
                         Err(e) => {
                             return Ok(CallToolResult::error(vec![
                                 Content::text("FORMAT_ERROR"),
-                                Content::text(format!(
-                                    "Failed to format remote file content: {}",
-                                    e
-                                )),
+                                Content::text(format!("Failed to format file content: {}", e)),
                             ]));
                         }
                     };
@@ -1402,7 +2429,7 @@ SAFETY NOTES:
                 }
                 Err(e) => Ok(CallToolResult::error(vec![
                     Content::text("READ_ERROR"),
-                    Content::text(format!("Cannot read remote file: {}", e)),
+                    Content::text(format!("Cannot read file: {}", e)),
                 ])),
             }
         }
@@ -1520,96 +2547,24 @@ SAFETY NOTES:
         )
     }
 
-    /// Replace a specific string in a remote file
-    async fn str_replace_remote(
+    /// Replace a specific string in a file on any exec target
+    async fn str_replace_target(
         &self,
-        conn: &Arc<RemoteConnection>,
-        remote_path: &str,
+        target: &dyn ExecTarget,
+        target_path: &str,
         original_path: &str,
         old_str: &str,
         new_str: &str,
         replace_all: Option<bool>,
     ) -> Result<CallToolResult, McpError> {
-        let actual_old_str = self.get_secret_manager().restore_secrets_in_string(old_str);
-        let actual_new_str = self.get_secret_manager().restore_secrets_in_string(new_str);
-
-        if actual_old_str == actual_new_str {
-            return Ok(CallToolResult::error(vec![
-                Content::text("OLD_STR_NEW_STR_IDENTICAL"),
-                Content::text(
-                    "The old string and new string are identical - no replacement needed",
-                ),
-            ]));
-        }
-
-        let content = match conn.read_file_to_string(remote_path).await {
-            Ok(content) => content,
-            Err(e) => {
-                error!("Failed to read remote file for str_replace: {}", e);
-                return Ok(CallToolResult::error(vec![
-                    Content::text("REMOTE_FILE_READ_ERROR"),
-                    Content::text(format!("Failed to read remote file: {}", e)),
-                ]));
-            }
-        };
-
-        if !content.contains(&actual_old_str) {
-            return Ok(CallToolResult::error(vec![
-                Content::text("STRING_NOT_FOUND"),
-                Content::text("The string old_str was not found in the file"),
-            ]));
-        }
-
-        let new_content = if replace_all.unwrap_or(false) {
-            content.replace(&actual_old_str, &actual_new_str)
-        } else {
-            content.replacen(&actual_old_str, &actual_new_str, 1)
-        };
-
-        let replaced_count = if replace_all.unwrap_or(false) {
-            content.matches(&actual_old_str).count()
-        } else if content.contains(&actual_old_str) {
-            1
-        } else {
-            0
+        let (actual_old_str, actual_new_str) = match (
+            self.restore_tool_input("str_replace", old_str),
+            self.restore_tool_input("str_replace", new_str),
+        ) {
+            (Ok(old_str), Ok(new_str)) => (old_str, new_str),
+            (Err(error_result), _) | (_, Err(error_result)) => return Ok(error_result),
         };
 
-        if let Err(e) = conn.write_file(remote_path, new_content.as_bytes()).await {
-            error!("Failed to write remote file for str_replace: {}", e);
-            return Ok(CallToolResult::error(vec![
-                Content::text("REMOTE_FILE_WRITE_ERROR"),
-                Content::text(format!("Failed to write remote file: {}", e)),
-            ]));
-        }
-
-        let unified_diff =
-            self.create_unified_diff(&content, &new_content, original_path, original_path);
-
-        let output = format!(
-            "Successfully replaced {} occurrences of text (remote)\n\n```diff\n{}\n```",
-            replaced_count, unified_diff
-        );
-
-        let redacted_output = self
-            .get_secret_manager()
-            .redact_and_store_secrets(&output, Some(original_path));
-
-        Ok(CallToolResult::success(vec![Content::text(
-            redacted_output,
-        )]))
-    }
-
-    /// Replace a specific string in a local file
-    async fn str_replace_local(
-        &self,
-        path: &str,
-        old_str: &str,
-        new_str: &str,
-        replace_all: Option<bool>,
-    ) -> Result<CallToolResult, McpError> {
-        let actual_old_str = self.get_secret_manager().restore_secrets_in_string(old_str);
-        let actual_new_str = self.get_secret_manager().restore_secrets_in_string(new_str);
-
         if actual_old_str == actual_new_str {
             return Ok(CallToolResult::error(vec![
                 Content::text("OLD_STR_NEW_STR_IDENTICAL"),
@@ -1619,13 +2574,13 @@ SAFETY NOTES:
             ]));
         }
 
-        let original_content = match fs::read_to_string(path) {
+        let original_content = match target.read_file_to_string(target_path).await {
             Ok(content) => content,
             Err(e) => {
-                error!("Failed to read local file for str_replace: {}", e);
+                error!("Failed to read file for str_replace: {}", e);
                 return Ok(CallToolResult::error(vec![
                     Content::text("FILE_READ_ERROR"),
-                    Content::text(format!("Failed to read local file: {}", e)),
+                    Content::text(format!("Failed to read file: {}", e)),
                 ]));
             }
         };
@@ -1637,74 +2592,79 @@ SAFETY NOTES:
             ]));
         }
 
-        let new_content = if replace_all.unwrap_or(false) {
-            original_content.replace(&actual_old_str, &actual_new_str)
-        } else {
-            original_content.replacen(&actual_old_str, &actual_new_str, 1)
-        };
-
-        let replaced_count = if replace_all.unwrap_or(false) {
-            original_content.matches(&actual_old_str).count()
-        } else if original_content.contains(&actual_old_str) {
-            1
+        let (new_content, replaced_count) = if replace_all.unwrap_or(false) {
+            (
+                original_content.replace(&actual_old_str, &actual_new_str),
+                original_content.matches(&actual_old_str).count(),
+            )
         } else {
-            0
+            (
+                original_content.replacen(&actual_old_str, &actual_new_str, 1),
+                1,
+            )
         };
 
-        let unified_diff = self.create_unified_diff(&original_content, &new_content, path, path);
-
-        if let Err(e) = fs::write(path, &new_content) {
-            error!("Failed to write local file for str_replace: {}", e);
+        if let Err(e) = target.write_file(target_path, new_content.as_bytes()).await {
+            error!("Failed to write file for str_replace: {}", e);
             return Ok(CallToolResult::error(vec![
                 Content::text("FILE_WRITE_ERROR"),
-                Content::text(format!("Failed to write local file: {}", e)),
+                Content::text(format!("Failed to write file: {}", e)),
             ]));
         }
 
+        let unified_diff = self.create_unified_diff(
+            &original_content,
+            &new_content,
+            original_path,
+            original_path,
+        );
+
         let output = format!(
-            "Successfully replaced {} occurrences of text (local)\n\n```diff\n{}\n```",
-            replaced_count, unified_diff
+            "Successfully replaced {} occurrences of text ({})\n\n```diff\n{}\n```",
+            replaced_count,
+            target.kind().as_str(),
+            unified_diff
         );
 
         let redacted_output = self
             .get_secret_manager()
-            .redact_and_store_secrets(&output, Some(path));
+            .redact_and_store_secrets(&output, Some(original_path));
 
         Ok(CallToolResult::success(vec![Content::text(
             redacted_output,
         )]))
     }
 
-    /// Create a remote file with the specified content
-    async fn create_remote(
+    /// Create a file with the specified content on any exec target
+    async fn create_target_file(
         &self,
-        conn: &Arc<RemoteConnection>,
-        remote_path: &str,
+        target: &dyn ExecTarget,
+        target_path: &str,
         original_path: &str,
         file_text: &str,
     ) -> Result<CallToolResult, McpError> {
-        if conn.exists(remote_path).await {
+        if target.exists(target_path).await {
             return Ok(CallToolResult::error(vec![
                 Content::text("FILE_EXISTS"),
-                Content::text(format!("Remote file already exists: {}", original_path)),
+                Content::text(format!("File already exists: {}", original_path)),
             ]));
         }
 
-        // Create parent directories if needed
-        if let Some(parent) = std::path::Path::new(remote_path).parent() {
+        // Create parent directories if they don't exist
+        if let Some(parent) = Path::new(target_path).parent() {
             let parent_str = parent.to_string_lossy().to_string();
             if !parent_str.is_empty()
-                && !conn.exists(&parent_str).await
-                && let Err(e) = conn.create_directories(&parent_str).await
+                && !target.exists(&parent_str).await
+                && let Err(e) = target.create_directories(&parent_str).await
             {
                 error!(
-                    "Failed to create remote parent directories '{}': {}",
+                    "Failed to create parent directories '{}': {}",
                     parent_str, e
                 );
                 return Ok(CallToolResult::error(vec![
                     Content::text("CREATE_DIR_ERROR"),
                     Content::text(format!(
-                        "Failed to create remote parent directories '{}': {}",
+                        "Cannot create parent directories '{}': {}",
                         parent_str, e
                     )),
                 ]));
@@ -1712,125 +2672,69 @@ SAFETY NOTES:
         }
 
         // Restore secrets in the file content before writing
-        let actual_file_text = self
-            .get_secret_manager()
-            .restore_secrets_in_string(file_text);
+        let actual_file_text = match self.restore_tool_input("create", file_text) {
+            Ok(file_text) => file_text,
+            Err(error_result) => return Ok(error_result),
+        };
 
-        // Create the file using the correct SFTP method
-        if let Err(e) = conn
-            .create_file(remote_path, actual_file_text.as_bytes())
+        if let Err(e) = target
+            .write_file(target_path, actual_file_text.as_bytes())
             .await
         {
-            error!("Failed to create remote file '{}': {}", remote_path, e);
+            error!("Failed to create file '{}': {}", original_path, e);
             return Ok(CallToolResult::error(vec![
-                Content::text("CREATE_ERROR"),
-                Content::text(format!(
-                    "Failed to create remote file '{}': {}",
-                    remote_path, e
-                )),
+                Content::text("WRITE_ERROR"),
+                Content::text(format!("Cannot create file: {}", e)),
             ]));
         }
 
         let lines = actual_file_text.lines().count();
         Ok(CallToolResult::success(vec![Content::text(format!(
-            "Successfully created remote file {} with {} lines",
+            "Successfully created file {} with {} lines",
             original_path, lines
         ))]))
     }
 
-    /// Create a local file with the specified content  
-    fn create_local(&self, path: &str, file_text: &str) -> Result<CallToolResult, McpError> {
-        let path_obj = Path::new(&path);
-
-        if path_obj.exists() {
-            return Ok(CallToolResult::error(vec![
-                Content::text("FILE_EXISTS"),
-                Content::text(format!("File already exists: {}", path)),
-            ]));
-        }
-
-        // Create parent directories if they don't exist
-        if let Some(parent) = path_obj.parent()
-            && !parent.exists()
-            && let Err(e) = fs::create_dir_all(parent)
-        {
-            return Ok(CallToolResult::error(vec![
-                Content::text("CREATE_DIR_ERROR"),
-                Content::text(format!("Cannot create parent directories: {}", e)),
-            ]));
-        }
-
-        // Restore secrets in the file content before writing
-        let actual_file_text = self
-            .get_secret_manager()
-            .restore_secrets_in_string(file_text);
-
-        match fs::write(path, actual_file_text) {
-            Ok(_) => {
-                let lines = fs::read_to_string(path)
-                    .map(|content| content.lines().count())
-                    .unwrap_or(0);
-                Ok(CallToolResult::success(vec![Content::text(format!(
-                    "Successfully created file {} with {} lines",
-                    path, lines
-                ))]))
-            }
-            Err(e) => Ok(CallToolResult::error(vec![
-                Content::text("WRITE_ERROR"),
-                Content::text(format!("Cannot create file: {}", e)),
-            ])),
-        }
-    }
-
-    /// Remove a remote file or directory using native SFTP operations where possible
-    async fn remove_remote_path(
+    /// Remove a file or directory on any exec target by moving it to the target's backup location
+    async fn remove_target_path(
         &self,
-        conn: &Arc<RemoteConnection>,
-        remote_path: &str,
+        target: &dyn ExecTarget,
+        target_path: &str,
         original_path: &str,
         recursive: bool,
     ) -> Result<CallToolResult, McpError> {
-        if !conn.exists(remote_path).await {
+        if !target.exists(target_path).await {
             return Ok(CallToolResult::error(vec![
                 Content::text("PATH_NOT_FOUND"),
                 Content::text(format!("Path does not exist: {}", original_path)),
             ]));
         }
 
-        let is_directory = conn.is_directory(remote_path).await;
+        let is_directory = target.is_directory(target_path).await;
+        let path_prefix = target.path_prefix();
 
-        let ssh_prefix = match conn.get_ssh_prefix() {
-            Ok(prefix) => prefix,
-            Err(e) => {
-                return Ok(CallToolResult::error(vec![
-                    Content::text("CONNECTION_ERROR"),
-                    Content::text(format!("Failed to get SSH connection info: {}", e)),
-                ]));
-            }
-        };
-
-        let canonical_original_path = match conn.canonicalize(remote_path).await {
-            Ok(abs_path) => abs_path,
-            Err(_) => remote_path.to_string(),
-        };
-        let ssh_prefixed_original_path = format!("{}{}", ssh_prefix, canonical_original_path);
+        let canonical_original_path = target
+            .canonicalize(target_path)
+            .await
+            .unwrap_or_else(|_| target_path.to_string());
 
         // Move the entire path (file or directory) to backup location - this IS the removal
         let backup_path =
-            match FileBackupManager::move_remote_path_to_backup(conn, remote_path).await {
+            match FileBackupManager::move_target_path_to_backup(target, target_path).await {
                 Ok(backup_path) => backup_path,
                 Err(e) => {
                     return Ok(CallToolResult::error(vec![
                         Content::text("BACKUP_ERROR"),
-                        Content::text(format!("Failed to move remote path to backup: {}", e)),
+                        Content::text(format!("Failed to move path to backup: {}", e)),
                     ]));
                 }
             };
 
-        let ssh_prefixed_backup_path = format!("{}{}", ssh_prefix, backup_path);
-
         let mut backup_mapping = std::collections::HashMap::new();
-        backup_mapping.insert(ssh_prefixed_original_path, ssh_prefixed_backup_path);
+        backup_mapping.insert(
+            format!("{}{}", path_prefix, canonical_original_path),
+            format!("{}{}", path_prefix, backup_path),
+        );
 
         let item_type = if is_directory { "directory" } else { "file" };
         let recursive_note = if is_directory && recursive {
@@ -1839,7 +2743,8 @@ SAFETY NOTES:
             ""
         };
 
-        let backup_xml = FileBackupManager::format_backup_xml(&backup_mapping, "remote");
+        let backup_xml =
+            FileBackupManager::format_backup_xml(&backup_mapping, target.kind().as_str());
         let output = format!(
             "Successfully removed {} '{}'{}\n\n{}",
             item_type, original_path, recursive_note, backup_xml
@@ -1848,57 +2753,6 @@ SAFETY NOTES:
         Ok(CallToolResult::success(vec![Content::text(output)]))
     }
 
-    /// Remove a local file or directory
-    async fn remove_local_path(
-        &self,
-        path: &str,
-        recursive: bool,
-    ) -> Result<CallToolResult, McpError> {
-        let local_path = Path::new(path);
-
-        if !local_path.exists() {
-            return Ok(CallToolResult::error(vec![
-                Content::text("PATH_NOT_FOUND"),
-                Content::text(format!("Path does not exist: {}", path)),
-            ]));
-        }
-
-        let is_directory = local_path.is_dir();
-
-        let absolute_original_path = match local_path.canonicalize() {
-            Ok(abs_path) => abs_path.to_string_lossy().to_string(),
-            Err(_) => path.to_string(),
-        };
-
-        // Move the entire path (file or directory) to backup location - this IS the removal
-        let backup_path = match FileBackupManager::move_local_path_to_backup(path) {
-            Ok(backup_path) => backup_path,
-            Err(e) => {
-                return Ok(CallToolResult::error(vec![
-                    Content::text("BACKUP_ERROR"),
-                    Content::text(format!("Failed to move local path to backup: {}", e)),
-                ]));
-            }
-        };
-
-        let mut backup_mapping = std::collections::HashMap::new();
-        backup_mapping.insert(absolute_original_path, backup_path);
-
-        let item_type = if is_directory { "directory" } else { "file" };
-        let recursive_note = if is_directory && recursive {
-            " (recursively)"
-        } else {
-            ""
-        };
-
-        let backup_xml = FileBackupManager::format_backup_xml(&backup_mapping, "local");
-        let output = format!(
-            "Successfully removed {} '{}'{}\n\n{}",
-            item_type, path, recursive_note, backup_xml
-        );
-
-        Ok(CallToolResult::success(vec![Content::text(output)]))
-    }
     async fn wait_for_tasks_with_streaming(
         &self,
         task_ids: &[String],
@@ -1942,7 +2796,9 @@ SAFETY NOTES:
                     }
                 }
 
-                let progress_table = self.format_tasks_table(&all_tasks, task_ids);
+                let progress_table = self
+                    .get_secret_manager()
+                    .redact_and_store_secrets(&self.format_tasks_table(&all_tasks, task_ids), None);
 
                 let _ = ctx
                     .peer
@@ -2004,7 +2860,7 @@ SAFETY NOTES:
 
         // Sort tasks by start time (newest first)
         let mut sorted_tasks = tasks.to_vec();
-        sorted_tasks.sort_by(|a, b| b.start_time.cmp(&a.start_time));
+        sorted_tasks.sort_by_key(|t| std::cmp::Reverse(t.start_time));
 
         // Compact format for streaming - one line per task
         for task in &sorted_tasks {
@@ -2057,6 +2913,16 @@ SAFETY NOTES:
 }
 
 /// Helper method to handle large output by truncating and saving to file
+/// Parse a copy, sync or tail path, which may only be local or over SSH
+fn parse_ssh_path(path: &str) -> Result<Option<(RemoteConnectionInfo, String)>, CallToolResult> {
+    TargetLocation::parse_ssh_path(path).map_err(|e| {
+        CallToolResult::error(vec![
+            Content::text("INVALID_PATH"),
+            Content::text(format!("Failed to parse path: {}", e)),
+        ])
+    })
+}
+
 fn handle_large_output(output: &str, file_prefix: &str) -> Result<String, McpError> {
     const MAX_LINES: usize = 300;
 
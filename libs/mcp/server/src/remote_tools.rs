@@ -626,7 +626,7 @@ This tool searches through the locally indexed code blocks using text matching a
         }
 
         // Sort by score descending
-        matching_blocks.sort_by(|a, b| b.1.cmp(&a.1));
+        matching_blocks.sort_by_key(|b| std::cmp::Reverse(b.1));
 
         // Limit results
         matching_blocks.truncate(search_limit);
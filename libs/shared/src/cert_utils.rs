@@ -297,7 +297,7 @@ mod tests {
         let result = timeout(
             Duration::from_secs(5),
             client
-                .get(&format!("https://127.0.0.1:{}/test", server_addr.port()))
+                .get(format!("https://127.0.0.1:{}/test", server_addr.port()))
                 .send(),
         )
         .await;
@@ -363,7 +363,7 @@ mod tests {
         let result = timeout(
             Duration::from_secs(5),
             client
-                .get(&format!("https://127.0.0.1:{}/test", server_addr.port()))
+                .get(format!("https://127.0.0.1:{}/test", server_addr.port()))
                 .send(),
         )
         .await;
@@ -421,9 +421,7 @@ mod tests {
             .create_server_config()
             .expect("Failed to create server config");
 
-        // Verify server config is created successfully
         // The fact that it doesn't panic/error is the main test
-        assert!(true, "Server config created successfully");
     }
 
     #[tokio::test]
@@ -435,9 +433,7 @@ mod tests {
             .create_client_config()
             .expect("Failed to create client config");
 
-        // Verify client config is created successfully
         // The fact that it doesn't panic/error is the main test
-        assert!(true, "Client config created successfully");
     }
 
     #[tokio::test]
@@ -497,7 +493,7 @@ mod tests {
             let response = timeout(
                 Duration::from_secs(10),
                 client
-                    .get(&format!("https://127.0.0.1:{}/{}", test_port, endpoint))
+                    .get(format!("https://127.0.0.1:{}/{}", test_port, endpoint))
                     .send(),
             )
             .await
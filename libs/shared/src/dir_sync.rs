@@ -0,0 +1,559 @@
+use crate::file_backup_manager::FileBackupManager;
+use crate::file_transfer::{TransferEndpoint, TransferOptions};
+use crate::remote_connection::RemoteFileSystemProvider;
+use crate::utils::{format_bytes, pattern_matches_glob};
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    /// Delete remote files that no longer exist locally
+    pub delete: bool,
+    /// Glob patterns matched against relative paths and file names; matches are left untouched
+    pub exclude: Vec<String>,
+    /// Compare content hashes even when size and modification time match
+    pub checksum: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncAction {
+    Add,
+    Update,
+    Delete,
+}
+
+impl SyncAction {
+    fn symbol(&self) -> char {
+        match self {
+            SyncAction::Add => '+',
+            SyncAction::Update => '~',
+            SyncAction::Delete => '-',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncChange {
+    pub action: SyncAction,
+    /// Path relative to the sync roots, `/` separated
+    pub path: String,
+    pub size: u64,
+}
+
+impl SyncChange {
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {} ({})",
+            self.action.symbol(),
+            self.path,
+            format_bytes(self.size)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncPlan {
+    pub local_root: String,
+    pub remote_root: String,
+    pub changes: Vec<SyncChange>,
+    pub unchanged: usize,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn count(&self, action: SyncAction) -> usize {
+        self.changes.iter().filter(|c| c.action == action).count()
+    }
+
+    /// One line per change: `+` add, `~` update, `-` delete
+    pub fn to_text(&self) -> String {
+        if self.changes.is_empty() {
+            return "No changes".to_string();
+        }
+        self.changes
+            .iter()
+            .map(SyncChange::to_line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} to add, {} to update, {} to delete, {} unchanged",
+            self.count(SyncAction::Add),
+            self.count(SyncAction::Update),
+            self.count(SyncAction::Delete),
+            self.unchanged
+        )
+    }
+
+    /// Whether a previously shown plan (as produced by `to_text`) still describes this plan
+    pub fn matches_text(&self, expected: &str) -> bool {
+        let normalize = |text: &str| {
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        normalize(expected) == normalize(&self.to_text())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncReport {
+    pub applied: Vec<SyncChange>,
+    /// Remote path -> backup path for every overwritten or deleted remote file
+    pub backups: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+struct FileSnapshot {
+    relative_path: String,
+    size: u64,
+    mtime: Option<u64>,
+}
+
+/// Outcome of comparing metadata alone; `Compare` entries need a content hash to decide
+#[derive(Debug, PartialEq)]
+enum Candidate {
+    Change(SyncChange),
+    Unchanged,
+    Compare { path: String, size: u64 },
+}
+
+fn is_excluded(relative_path: &str, exclude: &[String]) -> bool {
+    exclude.iter().any(|pattern| {
+        pattern_matches_glob(pattern, relative_path)
+            || relative_path
+                .split('/')
+                .any(|component| pattern_matches_glob(pattern, component))
+    })
+}
+
+fn walk_local_files(root: &str, exclude: &[String]) -> Result<Vec<FileSnapshot>> {
+    let root_path = Path::new(root);
+    if !root_path.is_dir() {
+        return Err(anyhow!("Local path {} is not a directory", root));
+    }
+
+    let relative = |path: &Path| {
+        path.strip_prefix(root_path)
+            .unwrap_or(path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+
+    let mut files = Vec::new();
+    let walker = WalkDir::new(root_path)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| !is_excluded(&relative(entry.path()), exclude));
+
+    for entry in walker {
+        let entry = entry.map_err(|e| anyhow!("Failed to walk {}: {}", root, e))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let metadata = entry.metadata().map_err(|e| {
+            anyhow!(
+                "Failed to read metadata for {}: {}",
+                entry.path().display(),
+                e
+            )
+        })?;
+        files.push(FileSnapshot {
+            relative_path: relative(entry.path()),
+            size: metadata.len(),
+            mtime: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs()),
+        });
+    }
+
+    files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    Ok(files)
+}
+
+fn compare_snapshots(
+    local: &[FileSnapshot],
+    remote: &[FileSnapshot],
+    options: &SyncOptions,
+) -> Vec<Candidate> {
+    let remote_by_path: BTreeMap<&str, &FileSnapshot> = remote
+        .iter()
+        .map(|file| (file.relative_path.as_str(), file))
+        .collect();
+
+    let mut candidates: Vec<Candidate> = local
+        .iter()
+        .map(
+            |file| match remote_by_path.get(file.relative_path.as_str()) {
+                None => Candidate::Change(SyncChange {
+                    action: SyncAction::Add,
+                    path: file.relative_path.clone(),
+                    size: file.size,
+                }),
+                Some(existing) if existing.size != file.size => Candidate::Change(SyncChange {
+                    action: SyncAction::Update,
+                    path: file.relative_path.clone(),
+                    size: file.size,
+                }),
+                Some(existing)
+                    if !options.checksum
+                        && existing.mtime.is_some()
+                        && existing.mtime == file.mtime =>
+                {
+                    Candidate::Unchanged
+                }
+                Some(_) => Candidate::Compare {
+                    path: file.relative_path.clone(),
+                    size: file.size,
+                },
+            },
+        )
+        .collect();
+
+    if options.delete {
+        let local_paths: BTreeSet<&str> = local
+            .iter()
+            .map(|file| file.relative_path.as_str())
+            .collect();
+        candidates.extend(
+            remote
+                .iter()
+                .filter(|file| !local_paths.contains(file.relative_path.as_str()))
+                .map(|file| {
+                    Candidate::Change(SyncChange {
+                        action: SyncAction::Delete,
+                        path: file.relative_path.clone(),
+                        size: file.size,
+                    })
+                }),
+        );
+    }
+
+    candidates
+}
+
+fn local_sha256(path: &Path) -> Result<String> {
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn remote_join(root: &str, relative_path: &str) -> String {
+    format!("{}/{}", root.trim_end_matches('/'), relative_path)
+}
+
+/// Compare a local directory with a remote one by size and modification time, falling back
+/// to SHA-256 when metadata alone can't tell whether a file changed
+pub async fn plan_sync(
+    provider: &RemoteFileSystemProvider,
+    local_root: &str,
+    remote_root: &str,
+    options: &SyncOptions,
+) -> Result<SyncPlan> {
+    let local = walk_local_files(local_root, &options.exclude)?;
+
+    let remote = if provider.connection().exists(remote_root).await {
+        if !provider.connection().is_directory(remote_root).await {
+            return Err(anyhow!("Remote path {} is not a directory", remote_root));
+        }
+        provider
+            .walk_files(remote_root, |path| is_excluded(path, &options.exclude))
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_iter()
+            .filter(|file| !is_excluded(&file.relative_path, &options.exclude))
+            .map(|file| FileSnapshot {
+                relative_path: file.relative_path,
+                size: file.size,
+                mtime: file.mtime.map(u64::from),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+    for candidate in compare_snapshots(&local, &remote, options) {
+        match candidate {
+            Candidate::Change(change) => changes.push(change),
+            Candidate::Unchanged => unchanged += 1,
+            Candidate::Compare { path, size } => {
+                let local_hash = local_sha256(&Path::new(local_root).join(&path))?;
+                let remote_hash = provider
+                    .file_sha256(&remote_join(remote_root, &path))
+                    .await
+                    .map_err(|e| anyhow!(e))?;
+                if local_hash == remote_hash {
+                    unchanged += 1;
+                } else {
+                    changes.push(SyncChange {
+                        action: SyncAction::Update,
+                        path,
+                        size,
+                    });
+                }
+            }
+        }
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(SyncPlan {
+        local_root: local_root.to_string(),
+        remote_root: remote_root.to_string(),
+        changes,
+        unchanged,
+    })
+}
+
+/// Apply a plan over SFTP. Remote files are moved to the remote backup location before they
+/// are overwritten or deleted, and uploaded files keep the local modification time so the
+/// next plan can skip them without hashing.
+pub async fn apply_sync_plan<F>(
+    provider: &RemoteFileSystemProvider,
+    plan: &SyncPlan,
+    on_change: F,
+) -> Result<SyncReport>
+where
+    F: Fn(&SyncChange),
+{
+    let connection = provider.connection();
+    let mut report = SyncReport {
+        applied: Vec::with_capacity(plan.changes.len()),
+        backups: HashMap::new(),
+    };
+
+    let transfer_options = TransferOptions {
+        max_size: None,
+        ..Default::default()
+    };
+
+    for change in &plan.changes {
+        let remote_path = remote_join(&plan.remote_root, &change.path);
+        let local_path = Path::new(&plan.local_root).join(&change.path);
+
+        let result: Result<()> = async {
+            if matches!(change.action, SyncAction::Update | SyncAction::Delete) {
+                let backup_path =
+                    FileBackupManager::move_remote_path_to_backup(connection, &remote_path)
+                        .await
+                        .map_err(|e| anyhow!(e))?;
+                report.backups.insert(remote_path.clone(), backup_path);
+            }
+
+            if matches!(change.action, SyncAction::Add | SyncAction::Update) {
+                if let Some((parent, _)) = remote_path.rsplit_once('/')
+                    && !parent.is_empty()
+                    && !connection.exists(parent).await
+                {
+                    connection.create_directories(parent).await?;
+                }
+
+                crate::file_transfer::copy(
+                    &TransferEndpoint::Local(local_path.to_string_lossy().to_string()),
+                    &TransferEndpoint::Remote {
+                        connection: connection.clone(),
+                        path: remote_path.clone(),
+                    },
+                    &transfer_options,
+                    |_| {},
+                )
+                .await?;
+
+                if let Some(mtime) = std::fs::metadata(&local_path)
+                    .ok()
+                    .and_then(|metadata| metadata.modified().ok())
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .and_then(|duration| u32::try_from(duration.as_secs()).ok())
+                {
+                    connection.set_modified_time(&remote_path, mtime).await?;
+                }
+            }
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            return Err(anyhow!(
+                "Failed to apply '{}' after {} of {} changes: {}",
+                change.to_line(),
+                report.applied.len(),
+                plan.changes.len(),
+                e
+            ));
+        }
+
+        on_change(change);
+        report.applied.push(change.clone());
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn snapshot(path: &str, size: u64, mtime: Option<u64>) -> FileSnapshot {
+        FileSnapshot {
+            relative_path: path.to_string(),
+            size,
+            mtime,
+        }
+    }
+
+    fn change(action: SyncAction, path: &str, size: u64) -> Candidate {
+        Candidate::Change(SyncChange {
+            action,
+            path: path.to_string(),
+            size,
+        })
+    }
+
+    #[test]
+    fn test_compare_snapshots_detects_adds_updates_and_deletes() {
+        let local = vec![
+            snapshot("app.yml", 10, Some(100)),
+            snapshot("nginx.conf", 20, Some(100)),
+            snapshot("same.conf", 5, Some(100)),
+            snapshot("touched.conf", 5, Some(200)),
+        ];
+        let remote = vec![
+            snapshot("nginx.conf", 25, Some(100)),
+            snapshot("old.conf", 7, Some(100)),
+            snapshot("same.conf", 5, Some(100)),
+            snapshot("touched.conf", 5, Some(100)),
+        ];
+        let options = SyncOptions {
+            delete: true,
+            ..Default::default()
+        };
+
+        let candidates = compare_snapshots(&local, &remote, &options);
+
+        assert_eq!(
+            candidates,
+            vec![
+                change(SyncAction::Add, "app.yml", 10),
+                change(SyncAction::Update, "nginx.conf", 20),
+                Candidate::Unchanged,
+                Candidate::Compare {
+                    path: "touched.conf".to_string(),
+                    size: 5
+                },
+                change(SyncAction::Delete, "old.conf", 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_snapshots_keeps_remote_only_files_without_delete() {
+        let candidates = compare_snapshots(
+            &[],
+            &[snapshot("old.conf", 7, Some(100))],
+            &SyncOptions::default(),
+        );
+        assert!(candidates.is_empty());
+    }
+
+    #[test]
+    fn test_checksum_option_forces_hash_comparison() {
+        let options = SyncOptions {
+            checksum: true,
+            ..Default::default()
+        };
+        let candidates = compare_snapshots(
+            &[snapshot("a", 1, Some(100))],
+            &[snapshot("a", 1, Some(100))],
+            &options,
+        );
+        assert_eq!(
+            candidates,
+            vec![Candidate::Compare {
+                path: "a".to_string(),
+                size: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plan_text_round_trip() {
+        let plan = SyncPlan {
+            local_root: "./config".to_string(),
+            remote_root: "/etc/app".to_string(),
+            changes: vec![
+                SyncChange {
+                    action: SyncAction::Add,
+                    path: "app.yml".to_string(),
+                    size: 2048,
+                },
+                SyncChange {
+                    action: SyncAction::Delete,
+                    path: "old.conf".to_string(),
+                    size: 10,
+                },
+            ],
+            unchanged: 3,
+        };
+
+        assert_eq!(plan.to_text(), "+ app.yml (2.0 KB)\n- old.conf (10 B)");
+        assert!(plan.matches_text("  + app.yml (2.0 KB)\n\n- old.conf (10 B)\n"));
+        assert!(!plan.matches_text("+ app.yml (2.0 KB)"));
+        assert_eq!(
+            plan.summary(),
+            "1 to add, 0 to update, 1 to delete, 3 unchanged"
+        );
+    }
+
+    #[test]
+    fn test_walk_local_files_applies_excludes() {
+        let temp = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp.path().join("conf.d")).unwrap();
+        std::fs::create_dir_all(temp.path().join(".git")).unwrap();
+        std::fs::write(temp.path().join("app.yml"), b"a: 1").unwrap();
+        std::fs::write(temp.path().join("conf.d/site.conf"), b"server {}").unwrap();
+        std::fs::write(temp.path().join("conf.d/site.conf.swp"), b"").unwrap();
+        std::fs::write(temp.path().join(".git/HEAD"), b"ref").unwrap();
+
+        let files = walk_local_files(
+            &temp.path().to_string_lossy(),
+            &[".git".to_string(), "*.swp".to_string()],
+        )
+        .unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f.relative_path.as_str()).collect();
+
+        assert_eq!(paths, vec!["app.yml", "conf.d/site.conf"]);
+        assert_eq!(files[1].size, 9);
+    }
+}
@@ -0,0 +1,1486 @@
+use crate::local_store::LocalStore;
+use crate::remote_connection::{
+    CommandOptions, PathLocation, RemoteConnection, RemoteConnectionInfo, RemoteConnectionManager,
+    RemoteFileSystemProvider,
+};
+use crate::remote_store::RemoteStore;
+use crate::utils::{DirectoryEntry, FileSystemProvider, LocalFileSystemProvider};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Local,
+    Ssh,
+    Container,
+    Pod,
+    Memory,
+}
+
+impl TargetKind {
+    /// Location name used in tool output and backup records
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TargetKind::Local => "local",
+            TargetKind::Ssh => "remote",
+            TargetKind::Container => "container",
+            TargetKind::Pod => "pod",
+            TargetKind::Memory => "memory",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecOutput {
+    pub output: String,
+    pub exit_code: i32,
+}
+
+#[derive(Debug, Default)]
+pub struct ExecOptions {
+    pub timeout: Option<Duration>,
+    /// Receives output as it is produced, for streaming to the caller. Chunks keep
+    /// their newlines, so joining them gives back the output.
+    pub output_tx: Option<mpsc::UnboundedSender<String>>,
+    /// Stops the command (and kills the process where possible) when signalled
+    pub cancel_rx: Option<oneshot::Receiver<()>>,
+    /// Data written to the command's stdin before it is closed
+    pub stdin: Option<Vec<u8>>,
+}
+
+/// A place where tools can run commands and work with files: the local machine,
+/// an SSH host, a container or a Kubernetes pod.
+///
+/// Only `exec`, `read_file`, `write_file`, `list_directory` and `rename` are required;
+/// the remaining operations default to shell commands run through `exec`.
+#[async_trait]
+pub trait ExecTarget: Send + Sync {
+    fn kind(&self) -> TargetKind;
+
+    /// Prefix that turns a path on this target back into a tool path, e.g. `user@host:`
+    fn path_prefix(&self) -> String;
+
+    async fn exec(&self, command: &str, options: ExecOptions) -> Result<ExecOutput>;
+
+    async fn read_file(&self, path: &str) -> Result<Vec<u8>>;
+
+    async fn write_file(&self, path: &str, data: &[u8]) -> Result<()>;
+
+    async fn list_directory(&self, path: &str) -> Result<Vec<DirectoryEntry>>;
+
+    async fn rename(&self, old_path: &str, new_path: &str) -> Result<()>;
+
+    async fn read_file_to_string(&self, path: &str) -> Result<String> {
+        let content = self.read_file(path).await?;
+        String::from_utf8(content)
+            .map_err(|e| anyhow!("File {} contains invalid UTF-8: {}", path, e))
+    }
+
+    async fn exists(&self, path: &str) -> bool {
+        exec_succeeds(self, &format!("test -e {}", shell_quote(path))).await
+    }
+
+    async fn is_directory(&self, path: &str) -> bool {
+        exec_succeeds(self, &format!("test -d {}", shell_quote(path))).await
+    }
+
+    async fn create_directories(&self, path: &str) -> Result<()> {
+        exec_checked(self, &format!("mkdir -p {}", shell_quote(path))).await?;
+        Ok(())
+    }
+
+    async fn canonicalize(&self, path: &str) -> Result<String> {
+        let quoted = shell_quote(path);
+        let output = exec_checked(
+            self,
+            &format!("realpath -- {0} 2>/dev/null || readlink -f -- {0}", quoted),
+        )
+        .await?;
+        Ok(output.trim_end().to_string())
+    }
+
+    /// Create and return the absolute directory that backups for a session are moved into
+    async fn backup_directory(&self, session_id: &str) -> Result<String> {
+        let backup_path = RemoteStore::get_backup_session_string(session_id);
+        self.create_directories(&backup_path).await?;
+        self.canonicalize(&backup_path).await
+    }
+}
+
+async fn exec_succeeds<T: ExecTarget + ?Sized>(target: &T, command: &str) -> bool {
+    target
+        .exec(command, ExecOptions::default())
+        .await
+        .map(|result| result.exit_code == 0)
+        .unwrap_or(false)
+}
+
+async fn exec_checked<T: ExecTarget + ?Sized>(target: &T, command: &str) -> Result<String> {
+    let result = target.exec(command, ExecOptions::default()).await?;
+    if result.exit_code != 0 {
+        return Err(anyhow!(
+            "`{}` exited with code {}: {}",
+            command,
+            result.exit_code,
+            result.output.trim()
+        ));
+    }
+    Ok(result.output)
+}
+
+/// Quote a string for safe use as a single POSIX shell word
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn join_path(directory: &str, name: &str) -> String {
+    if directory.ends_with('/') {
+        format!("{}{}", directory, name)
+    } else {
+        format!("{}/{}", directory, name)
+    }
+}
+
+/// Parse `ls -1Ap` output, where directories carry a trailing `/`
+fn parse_listing(path: &str, listing: &str) -> Vec<DirectoryEntry> {
+    listing
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (name, is_directory) = match line.strip_suffix('/') {
+                Some(name) => (name, true),
+                None => (line, false),
+            };
+            DirectoryEntry {
+                name: name.to_string(),
+                path: join_path(path, name),
+                is_directory,
+            }
+        })
+        .collect()
+}
+
+/// Run a process, streaming its stdout/stderr lines until it exits, times out or is cancelled
+async fn run_process(mut cmd: Command, options: ExecOptions) -> Result<ExecOutput> {
+    let ExecOptions {
+        timeout,
+        output_tx,
+        cancel_rx,
+        stdin,
+    } = options;
+    cmd.stdin(if stdin.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    })
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .kill_on_drop(true);
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run command: {}", e))?;
+
+    if let Some(input) = stdin
+        && let Some(mut child_stdin) = child.stdin.take()
+    {
+        // Write from a separate task so a command that doesn't read its input can't block us
+        tokio::spawn(async move {
+            let _ = child_stdin.write_all(&input).await;
+            let _ = child_stdin.shutdown().await;
+        });
+    }
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Failed to capture command stdout"))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("Failed to capture command stderr"))?;
+    let mut stdout_lines = BufReader::new(stdout).lines();
+    let mut stderr_lines = BufReader::new(stderr).lines();
+    let mut output = String::new();
+
+    let stream_and_wait = async {
+        let mut stdout_done = false;
+        let mut stderr_done = false;
+        while !stdout_done || !stderr_done {
+            let (line, from_stdout) = tokio::select! {
+                line = stdout_lines.next_line(), if !stdout_done => (line, true),
+                line = stderr_lines.next_line(), if !stderr_done => (line, false),
+            };
+            match line {
+                Ok(Some(mut line)) => {
+                    line.push('\n');
+                    output.push_str(&line);
+                    if let Some(tx) = &output_tx {
+                        let _ = tx.send(line);
+                    }
+                }
+                _ if from_stdout => stdout_done = true,
+                _ => stderr_done = true,
+            }
+        }
+        child.wait().await
+    };
+
+    let cancelled = async {
+        match cancel_rx {
+            Some(cancel_rx) => {
+                let _ = cancel_rx.await;
+            }
+            None => std::future::pending::<()>().await,
+        }
+    };
+
+    let result = match timeout {
+        Some(timeout) => tokio::select! {
+            result = tokio::time::timeout(timeout, stream_and_wait) => result,
+            _ = cancelled => return Err(anyhow!("Command was cancelled")),
+        },
+        None => tokio::select! {
+            result = stream_and_wait => Ok(result),
+            _ = cancelled => return Err(anyhow!("Command was cancelled")),
+        },
+    };
+
+    let exit_code = match result {
+        Ok(Ok(status)) => status.code().unwrap_or(-1),
+        Ok(Err(e)) => return Err(anyhow!("Failed to wait for command: {}", e)),
+        Err(_) => {
+            output.push_str(&format!(
+                "Command timed out after {} seconds\n",
+                timeout.unwrap_or_default().as_secs()
+            ));
+            -1
+        }
+    };
+
+    Ok(ExecOutput { output, exit_code })
+}
+
+/// The machine the agent runs on
+pub struct LocalTarget;
+
+#[async_trait]
+impl ExecTarget for LocalTarget {
+    fn kind(&self) -> TargetKind {
+        TargetKind::Local
+    }
+
+    fn path_prefix(&self) -> String {
+        String::new()
+    }
+
+    async fn exec(&self, command: &str, options: ExecOptions) -> Result<ExecOutput> {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        #[cfg(unix)]
+        {
+            cmd.env("DEBIAN_FRONTEND", "noninteractive")
+                .env("SUDO_ASKPASS", "/bin/false")
+                .process_group(0);
+        }
+        #[cfg(windows)]
+        {
+            // On Windows, create a new process group
+            cmd.creation_flags(0x00000200); // CREATE_NEW_PROCESS_GROUP
+        }
+        run_process(cmd, options).await
+    }
+
+    async fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        tokio::fs::read(path)
+            .await
+            .map_err(|e| anyhow!("Failed to read file {}: {}", path, e))
+    }
+
+    async fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        tokio::fs::write(path, data)
+            .await
+            .map_err(|e| anyhow!("Failed to write file {}: {}", path, e))
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<Vec<DirectoryEntry>> {
+        LocalFileSystemProvider
+            .list_directory(path)
+            .await
+            .map_err(|e| anyhow!("Failed to read directory {}: {}", path, e))
+    }
+
+    async fn rename(&self, old_path: &str, new_path: &str) -> Result<()> {
+        tokio::fs::rename(old_path, new_path)
+            .await
+            .map_err(|e| anyhow!("Failed to rename '{}' to '{}': {}", old_path, new_path, e))
+    }
+
+    async fn exists(&self, path: &str) -> bool {
+        Path::new(path).exists()
+    }
+
+    async fn is_directory(&self, path: &str) -> bool {
+        Path::new(path).is_dir()
+    }
+
+    async fn create_directories(&self, path: &str) -> Result<()> {
+        tokio::fs::create_dir_all(path)
+            .await
+            .map_err(|e| anyhow!("Failed to create directory {}: {}", path, e))
+    }
+
+    async fn canonicalize(&self, path: &str) -> Result<String> {
+        tokio::fs::canonicalize(path)
+            .await
+            .map(|path| path.to_string_lossy().to_string())
+            .map_err(|e| anyhow!("Failed to canonicalize path {}: {}", path, e))
+    }
+
+    async fn backup_directory(&self, session_id: &str) -> Result<String> {
+        let backup_dir = LocalStore::get_local_session_store_path()
+            .join(LocalStore::get_backup_session_path(session_id));
+        self.create_directories(&backup_dir.to_string_lossy())
+            .await?;
+        Ok(backup_dir.to_string_lossy().to_string())
+    }
+}
+
+/// A host reached over an existing SSH connection, with file operations over SFTP
+pub struct SshTarget {
+    connection: Arc<RemoteConnection>,
+}
+
+impl SshTarget {
+    pub fn new(connection: Arc<RemoteConnection>) -> Self {
+        Self { connection }
+    }
+
+    pub fn connection(&self) -> &Arc<RemoteConnection> {
+        &self.connection
+    }
+}
+
+#[async_trait]
+impl ExecTarget for SshTarget {
+    fn kind(&self) -> TargetKind {
+        TargetKind::Ssh
+    }
+
+    fn path_prefix(&self) -> String {
+        self.connection
+            .get_ssh_prefix()
+            .unwrap_or_else(|_| format!("{}:", self.connection.connection_string()))
+    }
+
+    async fn exec(&self, command: &str, options: ExecOptions) -> Result<ExecOutput> {
+        let ExecOptions {
+            timeout,
+            output_tx,
+            cancel_rx,
+            stdin,
+        } = options;
+        // Keep the sender alive so the command is only cancelled on request
+        let (_cancel_tx, default_cancel_rx) = oneshot::channel();
+        let mut cancel_rx = cancel_rx.unwrap_or(default_cancel_rx);
+        let command_options = CommandOptions {
+            timeout,
+            simple: false,
+            stdin,
+        };
+
+        let (output, exit_code) = self
+            .connection
+            .execute_command_unified(
+                command,
+                command_options,
+                &mut cancel_rx,
+                Some(move |chunk: String| {
+                    if let Some(tx) = &output_tx {
+                        let _ = tx.send(chunk);
+                    }
+                }),
+                None,
+            )
+            .await?;
+        Ok(ExecOutput { output, exit_code })
+    }
+
+    async fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.connection.read_file(path).await
+    }
+
+    async fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.connection.create_file(path, data).await
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<Vec<DirectoryEntry>> {
+        RemoteFileSystemProvider::new(self.connection.clone())
+            .list_directory(path)
+            .await
+            .map_err(|e| anyhow!(e))
+    }
+
+    async fn rename(&self, old_path: &str, new_path: &str) -> Result<()> {
+        self.connection.rename(old_path, new_path).await
+    }
+
+    async fn read_file_to_string(&self, path: &str) -> Result<String> {
+        self.connection.read_file_to_string(path).await
+    }
+
+    async fn exists(&self, path: &str) -> bool {
+        self.connection.exists(path).await
+    }
+
+    async fn is_directory(&self, path: &str) -> bool {
+        self.connection.is_directory(path).await
+    }
+
+    async fn create_directories(&self, path: &str) -> Result<()> {
+        self.connection.create_directories(path).await
+    }
+
+    async fn canonicalize(&self, path: &str) -> Result<String> {
+        self.connection.canonicalize(path).await
+    }
+
+    async fn backup_directory(&self, session_id: &str) -> Result<String> {
+        RemoteStore::get_absolute_backup_session_path(&self.connection, session_id)
+            .await
+            .map_err(|e| anyhow!(e))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+}
+
+impl ContainerRuntime {
+    pub fn binary(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+        }
+    }
+
+    /// Pick docker if it is installed, otherwise podman
+    pub fn detect() -> Self {
+        let on_path = |binary: &str| {
+            std::env::var_os("PATH")
+                .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(binary).is_file()))
+                .unwrap_or(false)
+        };
+        if !on_path("docker") && on_path("podman") {
+            ContainerRuntime::Podman
+        } else {
+            ContainerRuntime::Docker
+        }
+    }
+}
+
+/// A container or pod reached through its runtime CLI (`docker exec`, `podman exec`, `kubectl exec`)
+pub struct CliExecTarget {
+    kind: TargetKind,
+    program: String,
+    args: Vec<String>,
+    prefix: String,
+}
+
+impl CliExecTarget {
+    pub fn container(runtime: ContainerRuntime, name: &str) -> Self {
+        Self {
+            kind: TargetKind::Container,
+            program: runtime.binary().to_string(),
+            args: vec!["exec".to_string(), "-i".to_string(), name.to_string()],
+            prefix: format!("{}://{}:", runtime.binary(), name),
+        }
+    }
+
+    pub fn pod(namespace: Option<&str>, name: &str, container: Option<&str>) -> Self {
+        let mut args = vec!["exec".to_string(), "-i".to_string()];
+        if let Some(namespace) = namespace {
+            args.extend(["-n".to_string(), namespace.to_string()]);
+        }
+        args.push(name.to_string());
+        if let Some(container) = container {
+            args.extend(["-c".to_string(), container.to_string()]);
+        }
+        args.push("--".to_string());
+
+        let mut prefix = String::from("pod://");
+        if let Some(namespace) = namespace {
+            prefix.push_str(&format!("{}/", namespace));
+        }
+        prefix.push_str(name);
+        if let Some(container) = container {
+            prefix.push_str(&format!("#{}", container));
+        }
+        prefix.push(':');
+
+        Self {
+            kind: TargetKind::Pod,
+            program: "kubectl".to_string(),
+            args,
+            prefix,
+        }
+    }
+
+    fn command(&self, script: &str) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args).arg("sh").arg("-c").arg(script);
+        cmd
+    }
+
+    /// Run a script to completion, feeding it `input` and returning raw stdout
+    async fn run_script(&self, script: &str, input: Option<&[u8]>) -> Result<Vec<u8>> {
+        let mut cmd = self.command(script);
+        cmd.stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run {}: {}", self.program, e))?;
+        if let Some(input) = input
+            && let Some(mut stdin) = child.stdin.take()
+        {
+            stdin.write_all(input).await?;
+            stdin.shutdown().await?;
+        }
+
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{}",
+                String::from_utf8_lossy(&output.stderr).trim().to_string()
+            ));
+        }
+        Ok(output.stdout)
+    }
+}
+
+#[async_trait]
+impl ExecTarget for CliExecTarget {
+    fn kind(&self) -> TargetKind {
+        self.kind
+    }
+
+    fn path_prefix(&self) -> String {
+        self.prefix.clone()
+    }
+
+    async fn exec(&self, command: &str, options: ExecOptions) -> Result<ExecOutput> {
+        run_process(self.command(command), options).await
+    }
+
+    async fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.run_script(&format!("cat -- {}", shell_quote(path)), None)
+            .await
+            .map_err(|e| anyhow!("Failed to read file {}: {}", path, e))
+    }
+
+    async fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.run_script(&format!("cat > {}", shell_quote(path)), Some(data))
+            .await
+            .map_err(|e| anyhow!("Failed to write file {}: {}", path, e))?;
+        Ok(())
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<Vec<DirectoryEntry>> {
+        let listing = self
+            .run_script(&format!("ls -1Ap -- {}", shell_quote(path)), None)
+            .await
+            .map_err(|e| anyhow!("Failed to read directory {}: {}", path, e))?;
+
+        Ok(parse_listing(path, &String::from_utf8_lossy(&listing)))
+    }
+
+    async fn rename(&self, old_path: &str, new_path: &str) -> Result<()> {
+        self.run_script(
+            &format!("mv -- {} {}", shell_quote(old_path), shell_quote(new_path)),
+            None,
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to rename '{}' to '{}': {}", old_path, new_path, e))?;
+        Ok(())
+    }
+}
+
+/// Shown in place of the sudo password if it ever shows up in command output
+pub const SUDO_PASSWORD_MASK: &str = "[REDACTED_SUDO_PASSWORD]";
+
+#[derive(Debug, Clone, Default)]
+pub struct SudoOptions {
+    /// User to run as instead of root
+    pub user: Option<String>,
+    /// Password fed to `sudo -S` on stdin; without one sudo runs with `-n` and fails
+    /// instead of waiting for a prompt nobody can answer
+    pub password: Option<String>,
+}
+
+impl SudoOptions {
+    /// Wrap a shell command so it runs under sudo.
+    ///
+    /// With a password, `-k` makes sudo ask for it even when credentials are cached, and
+    /// the command reads from /dev/null so a password line sudo didn't need (a NOPASSWD
+    /// rule) can't reach it.
+    pub fn wrap(&self, command: &str) -> String {
+        let mut wrapped = String::from("sudo ");
+        wrapped.push_str(if self.password.is_some() {
+            "-k -S -p '' "
+        } else {
+            "-n "
+        });
+        if let Some(user) = &self.user {
+            wrapped.push_str(&format!("-u {} ", shell_quote(user)));
+        }
+        let command = if self.password.is_some() {
+            format!("exec < /dev/null; {}", command)
+        } else {
+            command.to_string()
+        };
+        wrapped.push_str(&format!("-- sh -c {}", shell_quote(&command)));
+        wrapped
+    }
+
+    /// The stdin line `sudo -S` reads the password from
+    pub fn password_input(&self) -> Option<Vec<u8>> {
+        self.password
+            .as_ref()
+            .map(|password| format!("{}\n", password).into_bytes())
+    }
+}
+
+/// Runs commands and file operations on another target through `sudo`.
+///
+/// Files are read and written through a temp file only the connecting user can access;
+/// writes are copied into place as the privileged user so existing ownership and
+/// permissions are kept.
+pub struct SudoTarget {
+    inner: Arc<dyn ExecTarget>,
+    options: SudoOptions,
+}
+
+impl SudoTarget {
+    pub fn new(inner: Arc<dyn ExecTarget>, options: SudoOptions) -> Self {
+        Self { inner, options }
+    }
+
+    /// Feed the password to sudo on stdin. The command itself then has no stdin.
+    fn with_password(&self, mut options: ExecOptions) -> ExecOptions {
+        if let Some(password) = self.options.password_input() {
+            options.stdin = Some(password);
+        }
+        options
+    }
+
+    fn mask_password(&self, text: String) -> String {
+        match self.options.password.as_deref() {
+            Some(password) if !password.is_empty() => text.replace(password, SUDO_PASSWORD_MASK),
+            _ => text,
+        }
+    }
+
+    /// Create an empty file in /tmp that only the connecting user can read
+    async fn create_private_temp_file(&self) -> Result<String> {
+        let path = format!("/tmp/.stakpak-sudo-{}", uuid::Uuid::new_v4());
+        exec_checked(
+            self.inner.as_ref(),
+            &format!("umask 077 && : > {}", shell_quote(&path)),
+        )
+        .await?;
+        Ok(path)
+    }
+
+    async fn remove_temp_file(&self, path: &str) {
+        let _ = self
+            .inner
+            .exec(
+                &format!("rm -f {}", shell_quote(path)),
+                ExecOptions::default(),
+            )
+            .await;
+    }
+}
+
+#[async_trait]
+impl ExecTarget for SudoTarget {
+    fn kind(&self) -> TargetKind {
+        self.inner.kind()
+    }
+
+    fn path_prefix(&self) -> String {
+        self.inner.path_prefix()
+    }
+
+    /// The password is masked in the result. Streamed chunks are forwarded as they are,
+    /// since the password may be split across them; callers mask it with a
+    /// `StreamingRedactor`.
+    async fn exec(&self, command: &str, options: ExecOptions) -> Result<ExecOutput> {
+        let mut result = self
+            .inner
+            .exec(&self.options.wrap(command), self.with_password(options))
+            .await
+            .map_err(|e| anyhow!(self.mask_password(e.to_string())))?;
+        result.output = self.mask_password(result.output);
+        Ok(result)
+    }
+
+    async fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        let temp_path = self.create_private_temp_file().await?;
+        // The redirect runs as the connecting user, so the temp file stays private
+        let copy = format!(
+            "{} > {}",
+            self.options.wrap(&format!("cat -- {}", shell_quote(path))),
+            shell_quote(&temp_path)
+        );
+        let result = match self
+            .inner
+            .exec(&copy, self.with_password(ExecOptions::default()))
+            .await
+        {
+            Ok(copied) if copied.exit_code == 0 => self.inner.read_file(&temp_path).await,
+            Ok(copied) => Err(anyhow!(
+                "Failed to read file {}: {}",
+                path,
+                self.mask_password(copied.output).trim()
+            )),
+            Err(e) => Err(e),
+        };
+        self.remove_temp_file(&temp_path).await;
+        result
+    }
+
+    async fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        let temp_path = self.create_private_temp_file().await?;
+        let result = async {
+            self.inner.write_file(&temp_path, data).await?;
+            exec_checked(
+                self,
+                &format!("cat -- {} > {}", shell_quote(&temp_path), shell_quote(path)),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to write file {}: {}", path, e))
+        }
+        .await;
+        self.remove_temp_file(&temp_path).await;
+        result.map(|_| ())
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<Vec<DirectoryEntry>> {
+        let listing = exec_checked(self, &format!("ls -1Ap -- {}", shell_quote(path)))
+            .await
+            .map_err(|e| anyhow!("Failed to read directory {}: {}", path, e))?;
+        Ok(parse_listing(path, &listing))
+    }
+
+    async fn rename(&self, old_path: &str, new_path: &str) -> Result<()> {
+        exec_checked(
+            self,
+            &format!("mv -- {} {}", shell_quote(old_path), shell_quote(new_path)),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to rename '{}' to '{}': {}", old_path, new_path, e))?;
+        Ok(())
+    }
+
+    async fn backup_directory(&self, session_id: &str) -> Result<String> {
+        // Backups live in the connecting user's session store, not root's
+        self.inner.backup_directory(session_id).await
+    }
+}
+
+/// An in-memory file system with scripted command output, for exercising tools in tests
+#[derive(Default)]
+pub struct InMemoryTarget {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+    directories: Mutex<BTreeSet<String>>,
+    command_outputs: Mutex<HashMap<String, ExecOutput>>,
+    executed_commands: Mutex<Vec<String>>,
+    command_inputs: Mutex<Vec<Option<Vec<u8>>>>,
+}
+
+impl InMemoryTarget {
+    pub fn new() -> Self {
+        let target = Self::default();
+        target.add_directories("/");
+        target
+    }
+
+    pub fn with_file(self, path: &str, contents: impl Into<Vec<u8>>) -> Self {
+        self.insert_file(path, contents.into());
+        self
+    }
+
+    pub fn with_command_output(self, command: &str, output: &str, exit_code: i32) -> Self {
+        if let Ok(mut outputs) = self.command_outputs.lock() {
+            outputs.insert(
+                command.to_string(),
+                ExecOutput {
+                    output: output.to_string(),
+                    exit_code,
+                },
+            );
+        }
+        self
+    }
+
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        self.files.lock().ok()?.get(path).cloned()
+    }
+
+    pub fn executed_commands(&self) -> Vec<String> {
+        self.executed_commands
+            .lock()
+            .map(|commands| commands.clone())
+            .unwrap_or_default()
+    }
+
+    /// Stdin passed to each executed command, in the same order as `executed_commands`
+    pub fn command_inputs(&self) -> Vec<Option<Vec<u8>>> {
+        self.command_inputs
+            .lock()
+            .map(|inputs| inputs.clone())
+            .unwrap_or_default()
+    }
+
+    fn insert_file(&self, path: &str, contents: Vec<u8>) {
+        if let Some(parent) = Path::new(path).parent() {
+            self.add_directories(&parent.to_string_lossy());
+        }
+        if let Ok(mut files) = self.files.lock() {
+            files.insert(path.to_string(), contents);
+        }
+    }
+
+    fn add_directories(&self, path: &str) {
+        if let Ok(mut directories) = self.directories.lock() {
+            for ancestor in Path::new(path).ancestors() {
+                let ancestor = ancestor.to_string_lossy();
+                if !ancestor.is_empty() {
+                    directories.insert(ancestor.to_string());
+                }
+            }
+        }
+    }
+
+    fn lock_error<T>(_: T) -> anyhow::Error {
+        anyhow!("In-memory target lock poisoned")
+    }
+}
+
+fn is_same_or_below(path: &str, root: &str) -> bool {
+    path == root || path.starts_with(&format!("{}/", root.trim_end_matches('/')))
+}
+
+/// Move `path` from below `old_root` to the same position below `new_root`
+fn rebase_path(path: &str, old_root: &str, new_root: &str) -> String {
+    format!("{}{}", new_root, &path[old_root.len()..])
+}
+
+#[async_trait]
+impl ExecTarget for InMemoryTarget {
+    fn kind(&self) -> TargetKind {
+        TargetKind::Memory
+    }
+
+    fn path_prefix(&self) -> String {
+        "memory:".to_string()
+    }
+
+    async fn exec(&self, command: &str, options: ExecOptions) -> Result<ExecOutput> {
+        self.executed_commands
+            .lock()
+            .map_err(Self::lock_error)?
+            .push(command.to_string());
+        self.command_inputs
+            .lock()
+            .map_err(Self::lock_error)?
+            .push(options.stdin.clone());
+
+        let result = self
+            .command_outputs
+            .lock()
+            .map_err(Self::lock_error)?
+            .get(command)
+            .cloned()
+            .unwrap_or_else(|| ExecOutput {
+                output: format!("sh: {}: command not found\n", command),
+                exit_code: 127,
+            });
+
+        if let Some(tx) = &options.output_tx {
+            for line in result.output.split_inclusive('\n') {
+                let _ = tx.send(line.to_string());
+            }
+        }
+        Ok(result)
+    }
+
+    async fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.file(path)
+            .ok_or_else(|| anyhow!("Failed to read file {}: No such file", path))
+    }
+
+    async fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        if self.is_directory(path).await {
+            return Err(anyhow!("Failed to write file {}: Is a directory", path));
+        }
+        self.insert_file(path, data.to_vec());
+        Ok(())
+    }
+
+    async fn list_directory(&self, path: &str) -> Result<Vec<DirectoryEntry>> {
+        if !self.is_directory(path).await {
+            return Err(anyhow!(
+                "Failed to read directory {}: Not a directory",
+                path
+            ));
+        }
+        let is_child = |candidate: &str| {
+            candidate != path
+                && Path::new(candidate)
+                    .parent()
+                    .is_some_and(|parent| parent == Path::new(path))
+        };
+        let entry = |candidate: &str, is_directory: bool| DirectoryEntry {
+            name: candidate
+                .rsplit('/')
+                .next()
+                .unwrap_or(candidate)
+                .to_string(),
+            path: candidate.to_string(),
+            is_directory,
+        };
+
+        let mut entries: Vec<DirectoryEntry> = self
+            .directories
+            .lock()
+            .map_err(Self::lock_error)?
+            .iter()
+            .filter(|candidate| is_child(candidate))
+            .map(|candidate| entry(candidate, true))
+            .collect();
+        entries.extend(
+            self.files
+                .lock()
+                .map_err(Self::lock_error)?
+                .keys()
+                .filter(|candidate| is_child(candidate))
+                .map(|candidate| entry(candidate, false)),
+        );
+        Ok(entries)
+    }
+
+    async fn rename(&self, old_path: &str, new_path: &str) -> Result<()> {
+        if !self.exists(old_path).await {
+            return Err(anyhow!(
+                "Failed to rename '{}' to '{}': No such file or directory",
+                old_path,
+                new_path
+            ));
+        }
+        if let Some(parent) = Path::new(new_path).parent() {
+            self.add_directories(&parent.to_string_lossy());
+        }
+
+        let mut files = self.files.lock().map_err(Self::lock_error)?;
+        let moved: Vec<String> = files
+            .keys()
+            .filter(|file| is_same_or_below(file, old_path))
+            .cloned()
+            .collect();
+        for file in moved {
+            if let Some(contents) = files.remove(&file) {
+                files.insert(rebase_path(&file, old_path, new_path), contents);
+            }
+        }
+
+        let mut directories = self.directories.lock().map_err(Self::lock_error)?;
+        let moved: Vec<String> = directories
+            .iter()
+            .filter(|directory| is_same_or_below(directory, old_path))
+            .cloned()
+            .collect();
+        for directory in moved {
+            directories.remove(&directory);
+            directories.insert(rebase_path(&directory, old_path, new_path));
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, path: &str) -> bool {
+        self.file(path).is_some() || self.is_directory(path).await
+    }
+
+    async fn is_directory(&self, path: &str) -> bool {
+        self.directories
+            .lock()
+            .map(|directories| directories.contains(path))
+            .unwrap_or(false)
+    }
+
+    async fn create_directories(&self, path: &str) -> Result<()> {
+        if self.file(path).is_some() {
+            return Err(anyhow!("Failed to create directory {}: File exists", path));
+        }
+        self.add_directories(path);
+        Ok(())
+    }
+
+    async fn canonicalize(&self, path: &str) -> Result<String> {
+        let absolute = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            format!("/{}", path)
+        };
+        if self.exists(&absolute).await {
+            Ok(absolute)
+        } else {
+            Err(anyhow!(
+                "Failed to canonicalize path {}: No such file",
+                path
+            ))
+        }
+    }
+}
+
+/// Where a tool path or command target lives
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetLocation {
+    Local,
+    Ssh(RemoteConnectionInfo),
+    Container {
+        /// `None` for `container://`, which picks whichever runtime is installed
+        runtime: Option<ContainerRuntime>,
+        name: String,
+    },
+    Pod {
+        namespace: Option<String>,
+        name: String,
+        container: Option<String>,
+    },
+}
+
+impl TargetLocation {
+    /// Parse a tool path into its target and the path on that target.
+    ///
+    /// Supports local paths, `user@host:/path`, `ssh://user@host/path`,
+    /// `container://name:/path` (also `docker://` and `podman://`) and
+    /// `pod://[namespace/]name[#container]:/path`.
+    pub fn parse_path(path: &str) -> Result<(Self, String)> {
+        if let Some((location, rest)) = Self::parse_scheme(path)? {
+            let (_, target_path) = rest
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Missing path in '{}', expected {{target}}:/path", path))?;
+            if !target_path.starts_with('/') {
+                return Err(anyhow!(
+                    "Paths inside containers and pods must be absolute: '{}'",
+                    path
+                ));
+            }
+            return Ok((location, target_path.to_string()));
+        }
+
+        match PathLocation::parse(path)? {
+            PathLocation::Local(local_path) => Ok((TargetLocation::Local, local_path)),
+            PathLocation::Remote { connection, path } => {
+                Ok((TargetLocation::Ssh(connection), path))
+            }
+        }
+    }
+
+    /// Parse a path for tools that only reach files locally or over SSH, returning the
+    /// connection and remote path, or `None` for a local path. Container and pod paths
+    /// are rejected rather than mistaken for local ones.
+    pub fn parse_ssh_path(path: &str) -> Result<Option<(RemoteConnectionInfo, String)>> {
+        match Self::parse_path(path)? {
+            (TargetLocation::Local, _) => Ok(None),
+            (TargetLocation::Ssh(connection), remote_path) => Ok(Some((connection, remote_path))),
+            (TargetLocation::Container { .. } | TargetLocation::Pod { .. }, _) => Err(anyhow!(
+                "'{}' is inside a container or pod, which this tool does not support yet. \
+                 Use run_command with remote set to the container or pod instead",
+                path
+            )),
+        }
+    }
+
+    /// Parse a command target: `user@host[:port]`, `container://name` or `pod://[namespace/]name[#container]`
+    pub fn parse_target(target: &str) -> Result<Self> {
+        if let Some((location, _)) = Self::parse_scheme(target)? {
+            return Ok(location);
+        }
+        if target.trim().is_empty() {
+            return Err(anyhow!("Empty remote target"));
+        }
+        Ok(TargetLocation::Ssh(RemoteConnectionInfo {
+            connection_string: target.to_string(),
+            password: None,
+            private_key_path: None,
+        }))
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self, TargetLocation::Local)
+    }
+
+    /// Parse a container or pod scheme, returning the location and the text after the scheme
+    fn parse_scheme(value: &str) -> Result<Option<(Self, &str)>> {
+        let container_schemes = [
+            ("container://", None),
+            ("docker://", Some(ContainerRuntime::Docker)),
+            ("podman://", Some(ContainerRuntime::Podman)),
+        ];
+        for (scheme, runtime) in container_schemes {
+            if let Some(rest) = value.strip_prefix(scheme) {
+                let name = rest.split(':').next().unwrap_or_default();
+                if name.is_empty() {
+                    return Err(anyhow!("Missing container name in '{}'", value));
+                }
+                return Ok(Some((
+                    TargetLocation::Container {
+                        runtime,
+                        name: name.to_string(),
+                    },
+                    rest,
+                )));
+            }
+        }
+
+        if let Some(rest) = value.strip_prefix("pod://") {
+            let spec = rest.split(':').next().unwrap_or_default();
+            let (spec, container) = match spec.split_once('#') {
+                Some((spec, container)) => (spec, Some(container.to_string())),
+                None => (spec, None),
+            };
+            let (namespace, name) = match spec.split_once('/') {
+                Some((namespace, name)) => (Some(namespace.to_string()), name),
+                None => (None, spec),
+            };
+            if name.is_empty()
+                || namespace.as_deref() == Some("")
+                || container.as_deref() == Some("")
+            {
+                return Err(anyhow!(
+                    "Invalid pod target '{}', expected pod://[namespace/]name[#container]",
+                    value
+                ));
+            }
+            return Ok(Some((
+                TargetLocation::Pod {
+                    namespace,
+                    name: name.to_string(),
+                    container,
+                },
+                rest,
+            )));
+        }
+
+        Ok(None)
+    }
+
+    /// Get a target for this location, reusing pooled SSH connections
+    pub async fn connect(
+        self,
+        connection_manager: &RemoteConnectionManager,
+    ) -> Result<Arc<dyn ExecTarget>> {
+        Ok(match self {
+            TargetLocation::Local => Arc::new(LocalTarget),
+            TargetLocation::Ssh(connection_info) => Arc::new(SshTarget::new(
+                connection_manager.get_connection(&connection_info).await?,
+            )),
+            TargetLocation::Container { runtime, name } => Arc::new(CliExecTarget::container(
+                runtime.unwrap_or_else(ContainerRuntime::detect),
+                &name,
+            )),
+            TargetLocation::Pod {
+                namespace,
+                name,
+                container,
+            } => Arc::new(CliExecTarget::pod(
+                namespace.as_deref(),
+                &name,
+                container.as_deref(),
+            )),
+        })
+    }
+}
+
+/// Adapts an exec target to the directory tree generator
+pub struct ExecTargetFileSystemProvider<'a> {
+    target: &'a dyn ExecTarget,
+}
+
+impl<'a> ExecTargetFileSystemProvider<'a> {
+    pub fn new(target: &'a dyn ExecTarget) -> Self {
+        Self { target }
+    }
+}
+
+#[async_trait]
+impl FileSystemProvider for ExecTargetFileSystemProvider<'_> {
+    type Error = String;
+
+    async fn list_directory(&self, path: &str) -> Result<Vec<DirectoryEntry>, Self::Error> {
+        self.target
+            .list_directory(path)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_container_and_pod_paths() {
+        assert_eq!(
+            TargetLocation::parse_path("container://web:/etc/nginx/nginx.conf").unwrap(),
+            (
+                TargetLocation::Container {
+                    runtime: None,
+                    name: "web".to_string()
+                },
+                "/etc/nginx/nginx.conf".to_string()
+            )
+        );
+        assert_eq!(
+            TargetLocation::parse_path("podman://db:/var/lib")
+                .unwrap()
+                .0,
+            TargetLocation::Container {
+                runtime: Some(ContainerRuntime::Podman),
+                name: "db".to_string()
+            }
+        );
+        assert_eq!(
+            TargetLocation::parse_path("pod://prod/api-7f9#app:/srv/config.yaml").unwrap(),
+            (
+                TargetLocation::Pod {
+                    namespace: Some("prod".to_string()),
+                    name: "api-7f9".to_string(),
+                    container: Some("app".to_string())
+                },
+                "/srv/config.yaml".to_string()
+            )
+        );
+        assert!(TargetLocation::parse_path("container://web:relative/path").is_err());
+        assert!(TargetLocation::parse_path("pod://prod/:/tmp").is_err());
+        assert!(TargetLocation::parse_path("container://web").is_err());
+    }
+
+    #[test]
+    fn test_parse_falls_back_to_local_and_ssh() {
+        assert_eq!(
+            TargetLocation::parse_path("src/main.rs").unwrap(),
+            (TargetLocation::Local, "src/main.rs".to_string())
+        );
+        let (location, path) = TargetLocation::parse_path("admin@host:/etc/hosts").unwrap();
+        assert!(
+            matches!(location, TargetLocation::Ssh(info) if info.connection_string == "admin@host")
+        );
+        assert_eq!(path, "/etc/hosts");
+
+        assert_eq!(
+            TargetLocation::parse_target("pod://api").unwrap(),
+            TargetLocation::Pod {
+                namespace: None,
+                name: "api".to_string(),
+                container: None
+            }
+        );
+        assert!(matches!(
+            TargetLocation::parse_target("admin@host:2222").unwrap(),
+            TargetLocation::Ssh(info) if info.connection_string == "admin@host:2222"
+        ));
+    }
+
+    #[test]
+    fn test_parse_ssh_path_rejects_containers_and_pods() {
+        assert!(
+            TargetLocation::parse_ssh_path("/var/log/syslog")
+                .unwrap()
+                .is_none()
+        );
+        let (connection, path) = TargetLocation::parse_ssh_path("ssh://admin@host/var/log")
+            .unwrap()
+            .unwrap();
+        assert_eq!(connection.connection_string, "admin@host");
+        assert_eq!(path, "/var/log");
+
+        for path in ["container://web:/x", "docker://web:/x", "pod://prod/api:/x"] {
+            let error = TargetLocation::parse_ssh_path(path).unwrap_err();
+            assert!(error.to_string().contains("container or pod"), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote("/tmp/it's here"), "'/tmp/it'\\''s here'");
+    }
+
+    #[test]
+    fn test_pod_target_builds_kubectl_arguments() {
+        let target = CliExecTarget::pod(Some("prod"), "api", Some("app"));
+        assert_eq!(target.program, "kubectl");
+        assert_eq!(
+            target.args,
+            vec!["exec", "-i", "-n", "prod", "api", "-c", "app", "--"]
+        );
+        assert_eq!(target.path_prefix(), "pod://prod/api#app:");
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_target_file_operations() {
+        let target = InMemoryTarget::new()
+            .with_file("/app/config.toml", "port = 80\n")
+            .with_command_output("uptime", "up 3 days\n", 0);
+
+        assert!(target.is_directory("/app").await);
+        assert_eq!(
+            target
+                .read_file_to_string("/app/config.toml")
+                .await
+                .unwrap(),
+            "port = 80\n"
+        );
+
+        target.create_directories("/app/logs").await.unwrap();
+        target.rename("/app", "/srv").await.unwrap();
+        assert!(!target.exists("/app/config.toml").await);
+        assert!(target.is_directory("/srv/logs").await);
+
+        let mut names: Vec<String> = target
+            .list_directory("/srv")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["config.toml", "logs"]);
+
+        let result = target.exec("uptime", ExecOptions::default()).await.unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(
+            target
+                .exec("missing", ExecOptions::default())
+                .await
+                .unwrap()
+                .exit_code,
+            127
+        );
+        assert_eq!(target.executed_commands(), vec!["uptime", "missing"]);
+    }
+
+    #[tokio::test]
+    async fn test_sudo_target_feeds_password_on_stdin_and_masks_it() {
+        let wrapped = "sudo -k -S -p '' -u 'postgres' -- sh -c 'exec < /dev/null; psql -c '\\''select 1'\\'''";
+        let inner = Arc::new(InMemoryTarget::new().with_command_output(
+            wrapped,
+            "Sorry, hunter2 is not right\n",
+            1,
+        ));
+        let target = SudoTarget::new(
+            inner.clone(),
+            SudoOptions {
+                user: Some("postgres".to_string()),
+                password: Some("hunter2".to_string()),
+            },
+        );
+
+        let result = target
+            .exec("psql -c 'select 1'", ExecOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(inner.executed_commands(), vec![wrapped]);
+        assert_eq!(inner.command_inputs(), vec![Some(b"hunter2\n".to_vec())]);
+        let masked = format!("Sorry, {} is not right\n", SUDO_PASSWORD_MASK);
+        assert_eq!(result.output, masked);
+    }
+
+    #[test]
+    fn test_sudo_without_password_never_prompts() {
+        assert_eq!(
+            SudoOptions::default().wrap("id -u"),
+            "sudo -n -- sh -c 'id -u'"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_local_target_exec_streams_and_times_out() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let result = LocalTarget
+            .exec(
+                "echo out; echo err >&2; exit 3",
+                ExecOptions {
+                    output_tx: Some(tx),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.exit_code, 3);
+        assert!(result.output.contains("out\n") && result.output.contains("err\n"));
+
+        let mut streamed = Vec::new();
+        while let Ok(line) = rx.try_recv() {
+            streamed.push(line);
+        }
+        streamed.sort();
+        assert_eq!(streamed, vec!["err\n", "out\n"]);
+
+        let result = LocalTarget
+            .exec(
+                "sleep 5",
+                ExecOptions {
+                    timeout: Some(Duration::from_millis(100)),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.exit_code, -1);
+        assert!(result.output.contains("timed out"));
+
+        let result = LocalTarget
+            .exec(
+                "read line; echo \"got $line\"",
+                ExecOptions {
+                    stdin: Some(b"input\n".to_vec()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.output, "got input\n");
+    }
+
+    #[tokio::test]
+    async fn test_default_operations_run_through_exec() {
+        let target = InMemoryTarget::new();
+        assert!(!exec_succeeds(&target, "test -e '/nope'").await);
+        assert!(exec_checked(&target, "true").await.is_err());
+
+        let target = target.with_command_output("true", "", 0);
+        assert!(exec_checked(&target, "true").await.is_ok());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::exec_target::ExecTarget;
 use crate::local_store::LocalStore;
 use crate::remote_connection::RemoteConnection;
 use crate::remote_store::RemoteStore;
@@ -69,6 +70,30 @@ impl FileBackupManager {
         }
     }
 
+    /// Move a path (file or directory) on any exec target to that target's backup location
+    pub async fn move_target_path_to_backup(
+        target: &dyn ExecTarget,
+        path: &str,
+    ) -> Result<String, String> {
+        let backup_session_id = Uuid::new_v4().to_string();
+
+        let backup_dir = target
+            .backup_directory(&backup_session_id)
+            .await
+            .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+
+        let item_name = Path::new(&path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown_item");
+        let backup_path = format!("{}/{}", backup_dir.trim_end_matches('/'), item_name);
+
+        match target.rename(path, &backup_path).await {
+            Ok(()) => Ok(backup_path),
+            Err(e) => Err(format!("Failed to move '{}' to backup: {}", path, e)),
+        }
+    }
+
     /// Format backup mapping into XML structure with location type
     pub fn format_backup_xml(
         backup_mapping: &std::collections::HashMap<String, String>,
@@ -0,0 +1,791 @@
+use crate::remote_connection::RemoteConnection;
+use crate::utils::format_bytes;
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+/// Default cap on the total number of bytes a single copy may move
+pub const DEFAULT_MAX_TRANSFER_SIZE: u64 = 1024 * 1024 * 1024;
+
+const TRANSFER_BUFFER_SIZE: usize = 64 * 1024;
+
+/// One side of a transfer: a local path or a path on an SSH host
+#[derive(Clone)]
+pub enum TransferEndpoint {
+    Local(String),
+    Remote {
+        connection: Arc<RemoteConnection>,
+        path: String,
+    },
+}
+
+impl Display for TransferEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferEndpoint::Local(path) => write!(f, "{}", path),
+            TransferEndpoint::Remote { connection, path } => {
+                write!(f, "{}:{}", connection.connection_string(), path)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TransferOptions {
+    /// Copy directories and their contents
+    pub recursive: bool,
+    /// Continue partially transferred files instead of starting over
+    pub resume: bool,
+    /// Apply the source permission bits to copied files and directories
+    pub preserve_permissions: bool,
+    /// Compare SHA-256 checksums of source and destination after each file
+    pub verify_checksum: bool,
+    /// Refuse transfers larger than this many bytes in total
+    pub max_size: Option<u64>,
+}
+
+impl Default for TransferOptions {
+    fn default() -> Self {
+        Self {
+            recursive: false,
+            resume: false,
+            preserve_permissions: true,
+            verify_checksum: true,
+            max_size: Some(DEFAULT_MAX_TRANSFER_SIZE),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TransferProgress {
+    pub file: String,
+    pub file_bytes: u64,
+    pub file_size: u64,
+    pub total_bytes: u64,
+    pub total_size: u64,
+    pub files_completed: usize,
+    pub files_total: usize,
+}
+
+impl TransferProgress {
+    pub fn percent(&self) -> u64 {
+        (self.total_bytes * 100)
+            .checked_div(self.total_size)
+            .unwrap_or(100)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTransferResult {
+    pub source: String,
+    pub destination: String,
+    pub size: u64,
+    /// Bytes actually sent; smaller than `size` when the file was resumed
+    pub bytes_transferred: u64,
+    pub resumed_from: u64,
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferReport {
+    pub source: String,
+    pub destination: String,
+    pub files: Vec<FileTransferResult>,
+    pub directories_created: usize,
+    pub duration: Duration,
+}
+
+impl TransferReport {
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+
+    pub fn bytes_transferred(&self) -> u64 {
+        self.files.iter().map(|f| f.bytes_transferred).sum()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Copied {} -> {}\nFiles: {} | Directories created: {} | Size: {} | Transferred: {} | Time: {:.1}s",
+            self.source,
+            self.destination,
+            self.files.len(),
+            self.directories_created,
+            format_bytes(self.total_size()),
+            format_bytes(self.bytes_transferred()),
+            self.duration.as_secs_f64()
+        );
+
+        let resumed = self.files.iter().filter(|f| f.resumed_from > 0).count();
+        if resumed > 0 {
+            summary.push_str(&format!("\nResumed files: {}", resumed));
+        }
+
+        if self.files.len() == 1
+            && let Some(checksum) = &self.files[0].checksum
+        {
+            summary.push_str(&format!("\nSHA-256 verified: {}", checksum));
+        } else if self.files.iter().any(|f| f.checksum.is_some()) {
+            summary.push_str("\nSHA-256 checksums verified for all files");
+        }
+
+        summary
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EntryInfo {
+    is_dir: bool,
+    size: u64,
+    mode: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct PlannedFile {
+    source: String,
+    destination: String,
+    size: u64,
+    mode: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+struct TransferPlan {
+    directories: Vec<(String, Option<u32>)>,
+    files: Vec<PlannedFile>,
+}
+
+impl TransferEndpoint {
+    fn path(&self) -> &str {
+        match self {
+            TransferEndpoint::Local(path) => path,
+            TransferEndpoint::Remote { path, .. } => path,
+        }
+    }
+
+    fn join(&self, base: &str, name: &str) -> String {
+        match self {
+            TransferEndpoint::Local(_) => Path::new(base).join(name).to_string_lossy().to_string(),
+            TransferEndpoint::Remote { .. } => {
+                format!("{}/{}", base.trim_end_matches('/'), name)
+            }
+        }
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<EntryInfo>> {
+        match self {
+            TransferEndpoint::Local(_) => match tokio::fs::metadata(path).await {
+                Ok(metadata) => Ok(Some(EntryInfo {
+                    is_dir: metadata.is_dir(),
+                    size: metadata.len(),
+                    mode: local_mode(&metadata),
+                })),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(anyhow!("Failed to get metadata for {}: {}", path, e)),
+            },
+            TransferEndpoint::Remote { connection, .. } => {
+                if !connection.exists(path).await {
+                    return Ok(None);
+                }
+                let metadata = connection.metadata(path).await?;
+                Ok(Some(EntryInfo {
+                    is_dir: metadata.is_dir(),
+                    size: metadata.len(),
+                    mode: metadata.permissions.map(|mode| mode & 0o7777),
+                }))
+            }
+        }
+    }
+
+    async fn list_names(&self, path: &str) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        match self {
+            TransferEndpoint::Local(_) => {
+                let mut entries = tokio::fs::read_dir(path)
+                    .await
+                    .map_err(|e| anyhow!("Failed to read directory {}: {}", path, e))?;
+                while let Some(entry) = entries.next_entry().await? {
+                    names.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+            TransferEndpoint::Remote { connection, .. } => {
+                for entry in connection.list_directory(path).await? {
+                    if let Some(name) = entry.rsplit('/').next()
+                        && name != "."
+                        && name != ".."
+                    {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    async fn create_dir_all(&self, path: &str) -> Result<()> {
+        match self {
+            TransferEndpoint::Local(_) => tokio::fs::create_dir_all(path)
+                .await
+                .map_err(|e| anyhow!("Failed to create directory {}: {}", path, e)),
+            TransferEndpoint::Remote { connection, .. } => {
+                connection.create_directories(path).await
+            }
+        }
+    }
+
+    async fn set_mode(&self, path: &str, mode: u32) -> Result<()> {
+        match self {
+            TransferEndpoint::Local(_) => set_local_mode(path, mode).await,
+            TransferEndpoint::Remote { connection, .. } => {
+                connection.set_permissions(path, mode).await
+            }
+        }
+    }
+
+    async fn open_read(
+        &self,
+        path: &str,
+        offset: u64,
+    ) -> Result<Box<dyn AsyncRead + Send + Unpin>> {
+        match self {
+            TransferEndpoint::Local(_) => {
+                let mut file = tokio::fs::File::open(path)
+                    .await
+                    .map_err(|e| anyhow!("Failed to open file {}: {}", path, e))?;
+                if offset > 0 {
+                    file.seek(std::io::SeekFrom::Start(offset)).await?;
+                }
+                Ok(Box::new(file))
+            }
+            TransferEndpoint::Remote { connection, .. } => {
+                Ok(Box::new(connection.open_file_for_read(path, offset).await?))
+            }
+        }
+    }
+
+    async fn open_write(
+        &self,
+        path: &str,
+        offset: u64,
+    ) -> Result<Box<dyn AsyncWrite + Send + Unpin>> {
+        match self {
+            TransferEndpoint::Local(_) => {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(offset == 0)
+                    .open(path)
+                    .await
+                    .map_err(|e| anyhow!("Failed to open file {} for writing: {}", path, e))?;
+                if offset > 0 {
+                    file.seek(std::io::SeekFrom::Start(offset)).await?;
+                }
+                Ok(Box::new(file))
+            }
+            TransferEndpoint::Remote { connection, .. } => Ok(Box::new(
+                connection.open_file_for_write(path, offset).await?,
+            )),
+        }
+    }
+
+    async fn sha256(&self, path: &str) -> Result<String> {
+        let mut reader = self.open_read(path, 0).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; TRANSFER_BUFFER_SIZE];
+        loop {
+            let read = reader
+                .read(&mut buffer)
+                .await
+                .map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+#[cfg(unix)]
+fn local_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn local_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+async fn set_local_mode(path: &str, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .await
+        .map_err(|e| anyhow!("Failed to set permissions on {}: {}", path, e))
+}
+
+#[cfg(not(unix))]
+async fn set_local_mode(_path: &str, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+/// Last path component, ignoring trailing separators
+fn base_name(path: &str) -> Option<&str> {
+    path.trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\'])
+        .next()
+        .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+}
+
+/// Work out every directory and file to create, following `cp -r` semantics: copying into
+/// an existing directory places the source inside it, otherwise the destination is the copy
+async fn plan_transfer(
+    source: &TransferEndpoint,
+    destination: &TransferEndpoint,
+    options: &TransferOptions,
+) -> Result<TransferPlan> {
+    let source_root = source.path().to_string();
+    let source_info = source
+        .stat(&source_root)
+        .await?
+        .ok_or_else(|| anyhow!("Source {} does not exist", source))?;
+
+    if source_info.is_dir && !options.recursive {
+        return Err(anyhow!(
+            "Source {} is a directory; set recursive to copy directories",
+            source
+        ));
+    }
+
+    let destination_path = destination.path().to_string();
+    let destination_info = destination.stat(&destination_path).await?;
+    let into_directory = destination_info.as_ref().is_some_and(|info| info.is_dir)
+        || destination_path.ends_with('/');
+
+    let destination_root = if into_directory {
+        let name = base_name(&source_root)
+            .ok_or_else(|| anyhow!("Cannot determine a file name for {}", source))?;
+        destination.join(&destination_path, name)
+    } else {
+        if source_info.is_dir && destination_info.as_ref().is_some_and(|info| !info.is_dir) {
+            return Err(anyhow!(
+                "Cannot copy directory {} over file {}",
+                source,
+                destination
+            ));
+        }
+        destination_path
+    };
+
+    let mut plan = TransferPlan::default();
+    if !source_info.is_dir {
+        plan.files.push(PlannedFile {
+            source: source_root,
+            destination: destination_root,
+            size: source_info.size,
+            mode: source_info.mode,
+        });
+        return Ok(plan);
+    }
+
+    let mut pending = vec![(source_root, destination_root, source_info.mode)];
+    while let Some((source_dir, destination_dir, mode)) = pending.pop() {
+        for name in source.list_names(&source_dir).await? {
+            let source_path = source.join(&source_dir, &name);
+            let destination_path = destination.join(&destination_dir, &name);
+            let Some(info) = source.stat(&source_path).await? else {
+                continue;
+            };
+            if info.is_dir {
+                pending.push((source_path, destination_path, info.mode));
+            } else {
+                plan.files.push(PlannedFile {
+                    source: source_path,
+                    destination: destination_path,
+                    size: info.size,
+                    mode: info.mode,
+                });
+            }
+        }
+        plan.directories.push((destination_dir, mode));
+    }
+
+    plan.files.sort_by(|a, b| a.source.cmp(&b.source));
+    Ok(plan)
+}
+
+/// Copy a file or directory tree between any two endpoints, streaming data through this
+/// machine. `on_progress` is called after every chunk written.
+pub async fn copy<F>(
+    source: &TransferEndpoint,
+    destination: &TransferEndpoint,
+    options: &TransferOptions,
+    on_progress: F,
+) -> Result<TransferReport>
+where
+    F: Fn(&TransferProgress),
+{
+    let started = Instant::now();
+    let plan = plan_transfer(source, destination, options).await?;
+
+    let total_size: u64 = plan.files.iter().map(|f| f.size).sum();
+    if let Some(max_size) = options.max_size
+        && total_size > max_size
+    {
+        return Err(anyhow!(
+            "Transfer size {} exceeds the limit of {}",
+            format_bytes(total_size),
+            format_bytes(max_size)
+        ));
+    }
+
+    let mut directories_created = 0;
+    for (directory, _) in &plan.directories {
+        if destination.stat(directory).await?.is_none() {
+            destination.create_dir_all(directory).await?;
+            directories_created += 1;
+        }
+    }
+    if plan.directories.is_empty()
+        && let Some(file) = plan.files.first()
+        && let Some(parent) = Path::new(&file.destination).parent()
+    {
+        let parent = parent.to_string_lossy();
+        if !parent.is_empty() && destination.stat(&parent).await?.is_none() {
+            return Err(anyhow!("Destination directory {} does not exist", parent));
+        }
+    }
+
+    let mut progress = TransferProgress {
+        file: String::new(),
+        file_bytes: 0,
+        file_size: 0,
+        total_bytes: 0,
+        total_size,
+        files_completed: 0,
+        files_total: plan.files.len(),
+    };
+    let mut files = Vec::with_capacity(plan.files.len());
+
+    for planned in &plan.files {
+        let result = copy_file(
+            source,
+            destination,
+            planned,
+            options,
+            &mut progress,
+            &on_progress,
+        )
+        .await?;
+        progress.files_completed += 1;
+        files.push(result);
+    }
+
+    if options.preserve_permissions {
+        // Apply directory modes last so read-only directories don't block their own contents
+        for (directory, mode) in plan.directories.iter().rev() {
+            if let Some(mode) = mode {
+                destination.set_mode(directory, *mode).await?;
+            }
+        }
+    }
+
+    Ok(TransferReport {
+        source: source.to_string(),
+        destination: destination.to_string(),
+        files,
+        directories_created,
+        duration: started.elapsed(),
+    })
+}
+
+async fn copy_file<F>(
+    source: &TransferEndpoint,
+    destination: &TransferEndpoint,
+    planned: &PlannedFile,
+    options: &TransferOptions,
+    progress: &mut TransferProgress,
+    on_progress: &F,
+) -> Result<FileTransferResult>
+where
+    F: Fn(&TransferProgress),
+{
+    let resume_offset = if options.resume {
+        match destination.stat(&planned.destination).await? {
+            Some(info) if !info.is_dir && info.size <= planned.size => info.size,
+            _ => 0,
+        }
+    } else {
+        0
+    };
+
+    progress.file = planned.source.clone();
+    progress.file_size = planned.size;
+    progress.file_bytes = resume_offset;
+    progress.total_bytes += resume_offset;
+
+    // Hash while streaming when the whole file passes through; resumed files are hashed
+    // separately afterwards
+    let mut hasher = (options.verify_checksum && resume_offset == 0).then(Sha256::new);
+
+    if resume_offset < planned.size || planned.size == 0 {
+        let mut reader = source.open_read(&planned.source, resume_offset).await?;
+        let mut writer = destination
+            .open_write(&planned.destination, resume_offset)
+            .await?;
+        let mut buffer = vec![0u8; TRANSFER_BUFFER_SIZE];
+
+        loop {
+            let read = reader
+                .read(&mut buffer)
+                .await
+                .map_err(|e| anyhow!("Failed to read {}: {}", planned.source, e))?;
+            if read == 0 {
+                break;
+            }
+            writer
+                .write_all(&buffer[..read])
+                .await
+                .map_err(|e| anyhow!("Failed to write {}: {}", planned.destination, e))?;
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&buffer[..read]);
+            }
+            progress.file_bytes += read as u64;
+            progress.total_bytes += read as u64;
+            on_progress(progress);
+        }
+
+        writer
+            .shutdown()
+            .await
+            .map_err(|e| anyhow!("Failed to finish writing {}: {}", planned.destination, e))?;
+    } else {
+        on_progress(progress);
+    }
+
+    if options.preserve_permissions
+        && let Some(mode) = planned.mode
+    {
+        destination.set_mode(&planned.destination, mode).await?;
+    }
+
+    let checksum = if options.verify_checksum {
+        let source_checksum = match hasher {
+            Some(hasher) => format!("{:x}", hasher.finalize()),
+            None => source.sha256(&planned.source).await?,
+        };
+        let destination_checksum = destination.sha256(&planned.destination).await?;
+        if source_checksum != destination_checksum {
+            return Err(anyhow!(
+                "Checksum mismatch for {}: source {} != destination {}",
+                planned.destination,
+                source_checksum,
+                destination_checksum
+            ));
+        }
+        Some(source_checksum)
+    } else {
+        None
+    };
+
+    Ok(FileTransferResult {
+        source: planned.source.clone(),
+        destination: planned.destination.clone(),
+        size: planned.size,
+        bytes_transferred: progress.file_bytes - resume_offset,
+        resumed_from: resume_offset,
+        checksum,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    fn local(path: &Path) -> TransferEndpoint {
+        TransferEndpoint::Local(path.to_string_lossy().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_copy_file_into_existing_directory() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("report.txt");
+        let target_dir = temp.path().join("out");
+        std::fs::write(&source, b"hello transfer").unwrap();
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let calls = Mutex::new(0);
+        let report = copy(
+            &local(&source),
+            &local(&target_dir),
+            &TransferOptions::default(),
+            |_| *calls.lock().unwrap() += 1,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read(target_dir.join("report.txt")).unwrap(),
+            b"hello transfer"
+        );
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.bytes_transferred(), 14);
+        assert!(report.files[0].checksum.is_some());
+        assert!(*calls.lock().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_copy_directory_requires_recursive() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("src");
+        std::fs::create_dir(&source).unwrap();
+
+        let result = copy(
+            &local(&source),
+            &local(&temp.path().join("dst")),
+            &TransferOptions::default(),
+            |_| {},
+        )
+        .await;
+
+        assert!(result.unwrap_err().to_string().contains("recursive"));
+    }
+
+    #[tokio::test]
+    async fn test_copy_directory_recursively() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("src");
+        std::fs::create_dir_all(source.join("nested/deeper")).unwrap();
+        std::fs::write(source.join("a.txt"), b"a").unwrap();
+        std::fs::write(source.join("nested/deeper/b.txt"), b"bb").unwrap();
+        let destination = temp.path().join("dst");
+
+        let options = TransferOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let report = copy(&local(&source), &local(&destination), &options, |_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(destination.join("a.txt")).unwrap(), b"a");
+        assert_eq!(
+            std::fs::read(destination.join("nested/deeper/b.txt")).unwrap(),
+            b"bb"
+        );
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.directories_created, 3);
+    }
+
+    #[tokio::test]
+    async fn test_resume_appends_missing_bytes() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("big.bin");
+        let destination = temp.path().join("big.copy");
+        std::fs::write(&source, b"0123456789").unwrap();
+        std::fs::write(&destination, b"01234").unwrap();
+
+        let options = TransferOptions {
+            resume: true,
+            ..Default::default()
+        };
+        let report = copy(&local(&source), &local(&destination), &options, |_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&destination).unwrap(), b"0123456789");
+        assert_eq!(report.files[0].resumed_from, 5);
+        assert_eq!(report.files[0].bytes_transferred, 5);
+    }
+
+    #[tokio::test]
+    async fn test_resume_detects_corrupt_prefix() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("big.bin");
+        let destination = temp.path().join("big.copy");
+        std::fs::write(&source, b"0123456789").unwrap();
+        std::fs::write(&destination, b"XXXXX").unwrap();
+
+        let options = TransferOptions {
+            resume: true,
+            ..Default::default()
+        };
+        let result = copy(&local(&source), &local(&destination), &options, |_| {}).await;
+
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Checksum mismatch")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_size_limit_is_enforced() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("big.bin");
+        std::fs::write(&source, vec![0u8; 2048]).unwrap();
+
+        let options = TransferOptions {
+            max_size: Some(1024),
+            ..Default::default()
+        };
+        let result = copy(
+            &local(&source),
+            &local(&temp.path().join("copy.bin")),
+            &options,
+            |_| {},
+        )
+        .await;
+
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("exceeds the limit")
+        );
+        assert!(!temp.path().join("copy.bin").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_permissions_are_preserved() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("run.sh");
+        let destination = temp.path().join("run-copy.sh");
+        std::fs::write(&source, b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+        copy(
+            &local(&source),
+            &local(&destination),
+            &TransferOptions::default(),
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        let mode = std::fs::metadata(&destination)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[test]
+    fn test_base_name() {
+        assert_eq!(base_name("/var/log/app/"), Some("app"));
+        assert_eq!(base_name("bundle.tar.gz"), Some("bundle.tar.gz"));
+        assert_eq!(base_name("/"), None);
+    }
+}
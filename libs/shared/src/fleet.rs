@@ -0,0 +1,325 @@
+use crate::inventory::InventoryHost;
+use crate::remote_connection::RemoteConnectionManager;
+use futures::StreamExt;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_FLEET_CONCURRENCY: usize = 10;
+pub const DEFAULT_FLEET_HOST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of output lines shown per output group in the aggregated report
+const MAX_GROUP_OUTPUT_LINES: usize = 40;
+
+#[derive(Debug, Clone)]
+pub struct FleetOptions {
+    /// Maximum number of hosts running the command at the same time
+    pub concurrency: usize,
+    /// Timeout applied to each host, covering both connecting and running the command
+    pub host_timeout: Duration,
+}
+
+impl Default for FleetOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_FLEET_CONCURRENCY,
+            host_timeout: DEFAULT_FLEET_HOST_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostResult {
+    pub host: String,
+    pub output: String,
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration: Duration,
+}
+
+impl HostResult {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && self.exit_code == Some(0)
+    }
+}
+
+/// Hosts that produced the same (whitespace-trimmed) output
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputGroup {
+    pub output: String,
+    pub hosts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FleetReport {
+    pub command: String,
+    pub results: Vec<HostResult>,
+}
+
+impl FleetReport {
+    pub fn failures(&self) -> Vec<&HostResult> {
+        self.results.iter().filter(|r| !r.is_success()).collect()
+    }
+
+    pub fn succeeded_count(&self) -> usize {
+        self.results.iter().filter(|r| r.is_success()).count()
+    }
+
+    /// Group successful hosts by identical output, largest group first
+    pub fn output_groups(&self) -> Vec<OutputGroup> {
+        let mut groups: Vec<OutputGroup> = Vec::new();
+
+        for result in self.results.iter().filter(|r| r.is_success()) {
+            let output = result.output.trim();
+            match groups.iter_mut().find(|g| g.output == output) {
+                Some(group) => group.hosts.push(result.host.clone()),
+                None => groups.push(OutputGroup {
+                    output: output.to_string(),
+                    hosts: vec![result.host.clone()],
+                }),
+            }
+        }
+
+        for group in &mut groups {
+            group.hosts.sort();
+        }
+        groups.sort_by(|a, b| {
+            b.hosts
+                .len()
+                .cmp(&a.hosts.len())
+                .then(a.hosts.cmp(&b.hosts))
+        });
+        groups
+    }
+
+    /// Render the aggregated report as markdown: failures first, then the most common
+    /// output, then outliers (any output shared by fewer hosts than the most common one)
+    pub fn to_markdown(&self) -> String {
+        let groups = self.output_groups();
+        let failures = self.failures();
+
+        let mut report = format!(
+            "# Fleet Command: `{}`\n\nHosts: {} | Succeeded: {} | Failed: {} | Distinct outputs: {}\n",
+            self.command,
+            self.results.len(),
+            self.succeeded_count(),
+            failures.len(),
+            groups.len()
+        );
+
+        if !failures.is_empty() {
+            report.push_str(&format!("\n## Failures ({})\n", failures.len()));
+            for failure in &failures {
+                let reason = match (&failure.error, failure.exit_code) {
+                    (Some(error), _) => error.clone(),
+                    (None, Some(code)) => format!("exit code {}", code),
+                    (None, None) => "unknown error".to_string(),
+                };
+                report.push_str(&format!("\n### {} ({})\n", failure.host, reason));
+                let output = failure.output.trim();
+                if !output.is_empty() {
+                    report.push_str(&format!("```\n{}\n```\n", tail_lines(output)));
+                }
+            }
+        }
+
+        let mut groups = groups.into_iter();
+        if let Some(common) = groups.next() {
+            report.push_str(&format!(
+                "\n## Common Output ({} hosts)\nHosts: {}\n```\n{}\n```\n",
+                common.hosts.len(),
+                common.hosts.join(", "),
+                tail_lines(&common.output)
+            ));
+        }
+
+        let outliers: Vec<OutputGroup> = groups.collect();
+        if !outliers.is_empty() {
+            report.push_str(&format!("\n## Outliers ({} groups)\n", outliers.len()));
+            for group in outliers {
+                report.push_str(&format!(
+                    "\n### {} host(s): {}\n```\n{}\n```\n",
+                    group.hosts.len(),
+                    group.hosts.join(", "),
+                    tail_lines(&group.output)
+                ));
+            }
+        }
+
+        report
+    }
+}
+
+fn tail_lines(output: &str) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+    if lines.len() <= MAX_GROUP_OUTPUT_LINES {
+        return output.to_string();
+    }
+    format!(
+        "... ({} lines omitted)\n{}",
+        lines.len() - MAX_GROUP_OUTPUT_LINES,
+        lines[lines.len() - MAX_GROUP_OUTPUT_LINES..].join("\n")
+    )
+}
+
+/// Run a command on a single host, bounded by the per-host timeout
+async fn run_on_host(
+    connection_manager: &RemoteConnectionManager,
+    host: &InventoryHost,
+    command: &str,
+    host_timeout: Duration,
+) -> HostResult {
+    let started = Instant::now();
+    let connection_info = host.connection_info();
+
+    let execution = async {
+        let connection = connection_manager
+            .get_connection(&connection_info)
+            .await
+            .map_err(|e| format!("connection failed: {}", e))?;
+        connection
+            .execute_command(command, Some(host_timeout), None)
+            .await
+            .map_err(|e| e.to_string())
+    };
+
+    let (output, exit_code, error) = match tokio::time::timeout(host_timeout, execution).await {
+        Ok(Ok((output, exit_code))) => (output, Some(exit_code), None),
+        Ok(Err(error)) => (String::new(), None, Some(error)),
+        Err(_) => (
+            String::new(),
+            None,
+            Some(format!("timed out after {}s", host_timeout.as_secs())),
+        ),
+    };
+
+    if error.is_some() {
+        // Drop cached connections that failed so the next run reconnects
+        connection_manager
+            .remove_connection(&connection_info.connection_string)
+            .await;
+    }
+
+    HostResult {
+        host: host.name.clone(),
+        output,
+        exit_code,
+        error,
+        duration: started.elapsed(),
+    }
+}
+
+/// Run a command across hosts over SSH with bounded concurrency.
+///
+/// `on_result` is invoked as each host finishes, in completion order. The returned report
+/// lists results in inventory order.
+pub async fn run_fleet_command<F>(
+    connection_manager: &RemoteConnectionManager,
+    hosts: &[InventoryHost],
+    command: &str,
+    options: &FleetOptions,
+    on_result: F,
+) -> FleetReport
+where
+    F: Fn(&HostResult),
+{
+    let concurrency = options.concurrency.max(1);
+
+    // Build the per-host futures up front: mapping inside the stream combinator trips
+    // higher-ranked lifetime inference when this future is spawned onto a task
+    let host_runs: Vec<_> = hosts
+        .iter()
+        .enumerate()
+        .map(|(index, host)| async move {
+            (
+                index,
+                run_on_host(connection_manager, host, command, options.host_timeout).await,
+            )
+        })
+        .collect();
+
+    let mut results = futures::stream::iter(host_runs).buffer_unordered(concurrency);
+    let mut indexed_results: Vec<(usize, HostResult)> = Vec::with_capacity(hosts.len());
+    while let Some((index, result)) = results.next().await {
+        on_result(&result);
+        indexed_results.push((index, result));
+    }
+
+    indexed_results.sort_by_key(|(index, _)| *index);
+
+    FleetReport {
+        command: command.to_string(),
+        results: indexed_results.into_iter().map(|(_, r)| r).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(host: &str, output: &str, exit_code: Option<i32>, error: Option<&str>) -> HostResult {
+        HostResult {
+            host: host.to_string(),
+            output: output.to_string(),
+            exit_code,
+            error: error.map(|e| e.to_string()),
+            duration: Duration::from_millis(10),
+        }
+    }
+
+    fn sample_report() -> FleetReport {
+        FleetReport {
+            command: "df -h /".to_string(),
+            results: vec![
+                result("web1", "42% used\n", Some(0), None),
+                result("web2", "42% used", Some(0), None),
+                result("web3", "97% used", Some(0), None),
+                result("web4", "42% used\n", Some(0), None),
+                result("web5", "df: /: No such file", Some(1), None),
+                result("web6", "", None, Some("timed out after 60s")),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_output_groups_merge_identical_outputs() {
+        let groups = sample_report().output_groups();
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].output, "42% used");
+        assert_eq!(groups[0].hosts, vec!["web1", "web2", "web4"]);
+        assert_eq!(groups[1].hosts, vec!["web3"]);
+    }
+
+    #[test]
+    fn test_failures_include_errors_and_non_zero_exits() {
+        let report = sample_report();
+        let failures: Vec<&str> = report.failures().iter().map(|r| r.host.as_str()).collect();
+
+        assert_eq!(failures, vec!["web5", "web6"]);
+        assert_eq!(report.succeeded_count(), 4);
+    }
+
+    #[test]
+    fn test_markdown_report_sections() {
+        let markdown = sample_report().to_markdown();
+
+        assert!(markdown.contains("Hosts: 6 | Succeeded: 4 | Failed: 2 | Distinct outputs: 2"));
+        assert!(markdown.contains("### web5 (exit code 1)"));
+        assert!(markdown.contains("### web6 (timed out after 60s)"));
+        assert!(markdown.contains("## Common Output (3 hosts)\nHosts: web1, web2, web4"));
+        assert!(markdown.contains("## Outliers (1 groups)"));
+        assert!(markdown.contains("### 1 host(s): web3"));
+    }
+
+    #[test]
+    fn test_tail_lines_truncates_long_output() {
+        let output = (0..100)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let tailed = tail_lines(&output);
+
+        assert!(tailed.starts_with("... (60 lines omitted)"));
+        assert!(tailed.ends_with("99"));
+    }
+}
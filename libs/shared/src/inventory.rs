@@ -0,0 +1,598 @@
+use crate::remote_connection::RemoteConnectionInfo;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Default inventory file names looked up inside `.stakpak/`, in order of preference
+const INVENTORY_FILE_NAMES: [&str; 4] = [
+    "inventory.yml",
+    "inventory.yaml",
+    "inventory.ini",
+    "inventory",
+];
+
+/// Name of the implicit group every host belongs to
+pub const ALL_GROUP: &str = "all";
+/// Name of the implicit group for hosts declared outside any group
+pub const UNGROUPED_GROUP: &str = "ungrouped";
+
+/// A single host entry from an Ansible-style inventory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InventoryHost {
+    pub name: String,
+    pub vars: HashMap<String, String>,
+}
+
+impl InventoryHost {
+    /// The address used to reach the host (`ansible_host`, falling back to the inventory name)
+    pub fn address(&self) -> &str {
+        self.vars
+            .get("ansible_host")
+            .map(|s| s.as_str())
+            .unwrap_or(&self.name)
+    }
+
+    /// Build the SSH connection info for this host from its inventory variables
+    pub fn connection_info(&self) -> RemoteConnectionInfo {
+        let user = self
+            .vars
+            .get("ansible_user")
+            .or_else(|| self.vars.get("ansible_ssh_user"))
+            .cloned()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "root".to_string());
+
+        let connection_string = match self
+            .vars
+            .get("ansible_port")
+            .or_else(|| self.vars.get("ansible_ssh_port"))
+        {
+            Some(port) if port != "22" => format!("{}@{}:{}", user, self.address(), port),
+            _ => format!("{}@{}", user, self.address()),
+        };
+
+        RemoteConnectionInfo {
+            connection_string,
+            password: self
+                .vars
+                .get("ansible_password")
+                .or_else(|| self.vars.get("ansible_ssh_pass"))
+                .cloned(),
+            private_key_path: self.vars.get("ansible_ssh_private_key_file").cloned(),
+        }
+    }
+}
+
+/// Ansible-style host inventory (YAML or INI) with groups, child groups and variables
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    hosts: BTreeMap<String, HashMap<String, String>>,
+    group_hosts: BTreeMap<String, BTreeSet<String>>,
+    group_children: BTreeMap<String, BTreeSet<String>>,
+    group_vars: BTreeMap<String, HashMap<String, String>>,
+}
+
+impl Inventory {
+    /// Get the local project inventory directory path
+    pub fn get_local_inventory_dir() -> PathBuf {
+        std::env::current_dir()
+            .unwrap_or_else(|_| PathBuf::from("."))
+            .join(".stakpak")
+    }
+
+    /// Find the default inventory file in `.stakpak/`
+    pub fn find_default_path() -> Option<PathBuf> {
+        let dir = Self::get_local_inventory_dir();
+        INVENTORY_FILE_NAMES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// Load an inventory from an explicit path, or from the default location in `.stakpak/`
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => Self::find_default_path().ok_or_else(|| {
+                anyhow!(
+                    "No inventory found. Create one of {} in {}",
+                    INVENTORY_FILE_NAMES.join(", "),
+                    Self::get_local_inventory_dir().display()
+                )
+            })?,
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read inventory {}: {}", path.display(), e))?;
+
+        Self::parse(&content, Some(&path))
+    }
+
+    /// Parse inventory content, detecting the format from the file extension or content
+    pub fn parse(content: &str, path: Option<&Path>) -> Result<Self> {
+        let extension = path
+            .and_then(|p| p.extension())
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match extension.as_deref() {
+            Some("yml") | Some("yaml") => Self::parse_yaml(content),
+            Some("ini") | Some("cfg") => Self::parse_ini(content),
+            _ => {
+                let looks_like_ini = content
+                    .lines()
+                    .map(|l| l.trim())
+                    .find(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with(';'))
+                    .is_some_and(|l| l.starts_with('['));
+                if looks_like_ini {
+                    Self::parse_ini(content)
+                } else {
+                    Self::parse_yaml(content).or_else(|_| Self::parse_ini(content))
+                }
+            }
+        }
+    }
+
+    /// Parse an Ansible YAML inventory
+    pub fn parse_yaml(content: &str) -> Result<Self> {
+        let value: serde_yaml::Value =
+            serde_yaml::from_str(content).map_err(|e| anyhow!("Invalid YAML inventory: {}", e))?;
+
+        let mut inventory = Inventory::default();
+        let Some(root) = value.as_mapping() else {
+            if value.is_null() {
+                return Ok(inventory);
+            }
+            return Err(anyhow!(
+                "Invalid YAML inventory: expected a mapping of groups"
+            ));
+        };
+
+        for (name, group) in root {
+            let name = yaml_key(name)?;
+            inventory.parse_yaml_group(&name, group)?;
+        }
+
+        Ok(inventory)
+    }
+
+    fn parse_yaml_group(&mut self, group_name: &str, group: &serde_yaml::Value) -> Result<()> {
+        self.group_hosts.entry(group_name.to_string()).or_default();
+
+        let Some(group) = group.as_mapping() else {
+            return Ok(());
+        };
+
+        if let Some(hosts) = group.get("hosts").and_then(|h| h.as_mapping()) {
+            for (host_name, host_vars) in hosts {
+                let host_name = yaml_key(host_name)?;
+                let vars = yaml_vars(host_vars);
+                self.add_host(&host_name, vars);
+                self.group_hosts
+                    .entry(group_name.to_string())
+                    .or_default()
+                    .insert(host_name);
+            }
+        }
+
+        if let Some(vars) = group.get("vars") {
+            self.group_vars
+                .entry(group_name.to_string())
+                .or_default()
+                .extend(yaml_vars(vars));
+        }
+
+        if let Some(children) = group.get("children").and_then(|c| c.as_mapping()) {
+            for (child_name, child) in children {
+                let child_name = yaml_key(child_name)?;
+                self.group_children
+                    .entry(group_name.to_string())
+                    .or_default()
+                    .insert(child_name.clone());
+                self.parse_yaml_group(&child_name, child)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse an Ansible INI inventory
+    pub fn parse_ini(content: &str) -> Result<Self> {
+        enum Section {
+            Hosts(String),
+            Vars(String),
+            Children(String),
+        }
+
+        let mut inventory = Inventory::default();
+        let mut section = Section::Hosts(UNGROUPED_GROUP.to_string());
+
+        for (line_number, raw_line) in content.lines().enumerate() {
+            let line = raw_line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header.strip_suffix(']').ok_or_else(|| {
+                    anyhow!(
+                        "Invalid INI inventory: unterminated section header on line {}",
+                        line_number + 1
+                    )
+                })?;
+                section = match header.split_once(':') {
+                    Some((group, "vars")) => Section::Vars(group.to_string()),
+                    Some((group, "children")) => Section::Children(group.to_string()),
+                    Some((_, kind)) => {
+                        return Err(anyhow!(
+                            "Invalid INI inventory: unknown section type '{}' on line {}",
+                            kind,
+                            line_number + 1
+                        ));
+                    }
+                    None => Section::Hosts(header.to_string()),
+                };
+                let (Section::Hosts(group) | Section::Vars(group) | Section::Children(group)) =
+                    &section;
+                inventory.group_hosts.entry(group.clone()).or_default();
+                continue;
+            }
+
+            match &section {
+                Section::Hosts(group) => {
+                    let mut tokens = line.split_whitespace();
+                    let Some(host_name) = tokens.next() else {
+                        continue;
+                    };
+                    let vars = tokens
+                        .filter_map(|token| token.split_once('='))
+                        .map(|(k, v)| (k.to_string(), unquote(v).to_string()))
+                        .collect();
+                    inventory.add_host(host_name, vars);
+                    inventory
+                        .group_hosts
+                        .entry(group.clone())
+                        .or_default()
+                        .insert(host_name.to_string());
+                }
+                Section::Vars(group) => {
+                    if let Some((key, value)) = line.split_once('=') {
+                        inventory
+                            .group_vars
+                            .entry(group.clone())
+                            .or_default()
+                            .insert(key.trim().to_string(), unquote(value.trim()).to_string());
+                    }
+                }
+                Section::Children(group) => {
+                    inventory
+                        .group_children
+                        .entry(group.clone())
+                        .or_default()
+                        .insert(line.to_string());
+                    inventory.group_hosts.entry(line.to_string()).or_default();
+                }
+            }
+        }
+
+        Ok(inventory)
+    }
+
+    fn add_host(&mut self, name: &str, vars: HashMap<String, String>) {
+        self.hosts.entry(name.to_string()).or_default().extend(vars);
+    }
+
+    /// All group names defined in the inventory, including the implicit `all` group
+    pub fn group_names(&self) -> Vec<String> {
+        let mut names: BTreeSet<String> = self.group_hosts.keys().cloned().collect();
+        names.insert(ALL_GROUP.to_string());
+        names.into_iter().collect()
+    }
+
+    /// All host names defined in the inventory
+    pub fn host_names(&self) -> Vec<String> {
+        self.hosts.keys().cloned().collect()
+    }
+
+    /// Resolve a group (including its child groups) into the set of host names it contains
+    fn group_members(&self, group: &str, visited: &mut BTreeSet<String>) -> BTreeSet<String> {
+        if group == ALL_GROUP {
+            return self.hosts.keys().cloned().collect();
+        }
+        if !visited.insert(group.to_string()) {
+            return BTreeSet::new();
+        }
+
+        let mut members = self.group_hosts.get(group).cloned().unwrap_or_default();
+        if let Some(children) = self.group_children.get(group) {
+            for child in children {
+                members.extend(self.group_members(child, visited));
+            }
+        }
+        members
+    }
+
+    /// Groups a host belongs to, ordered from the most general to the most specific
+    fn host_groups(&self, host: &str) -> Vec<String> {
+        let mut groups: Vec<(usize, String)> = self
+            .group_hosts
+            .keys()
+            .filter(|group| {
+                self.group_members(group, &mut BTreeSet::new())
+                    .contains(host)
+            })
+            .map(|group| (self.group_depth(group, &mut BTreeSet::new()), group.clone()))
+            .collect();
+        groups.sort();
+        groups.into_iter().map(|(_, group)| group).collect()
+    }
+
+    /// Depth of a group in the children hierarchy (top level groups have depth 0)
+    fn group_depth(&self, group: &str, visited: &mut BTreeSet<String>) -> usize {
+        if !visited.insert(group.to_string()) {
+            return 0;
+        }
+        self.group_children
+            .iter()
+            .filter(|(_, children)| children.contains(group))
+            .map(|(parent, _)| self.group_depth(parent, visited) + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Get a host with its effective variables (group vars overridden by host vars)
+    pub fn host(&self, name: &str) -> Option<InventoryHost> {
+        let host_vars = self.hosts.get(name)?;
+
+        let mut vars = self.group_vars.get(ALL_GROUP).cloned().unwrap_or_default();
+        for group in self.host_groups(name) {
+            if let Some(group_vars) = self.group_vars.get(&group) {
+                vars.extend(group_vars.clone());
+            }
+        }
+        vars.extend(host_vars.clone());
+
+        Some(InventoryHost {
+            name: name.to_string(),
+            vars,
+        })
+    }
+
+    /// Select hosts using an Ansible-like pattern.
+    ///
+    /// Patterns are separated by `,` or `:`. Each part is a group name, a host name or a
+    /// `*` wildcard pattern matched against host names. Parts prefixed with `!` are excluded
+    /// and parts prefixed with `&` are intersected, e.g. `web:&canary:!web7`.
+    pub fn select(&self, pattern: &str) -> Result<Vec<InventoryHost>> {
+        let mut selected: BTreeSet<String> = BTreeSet::new();
+        let mut intersections: Vec<BTreeSet<String>> = Vec::new();
+        let mut exclusions: BTreeSet<String> = BTreeSet::new();
+
+        for part in pattern
+            .split([',', ':'])
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+        {
+            if let Some(excluded) = part.strip_prefix('!') {
+                exclusions.extend(self.resolve_pattern_part(excluded)?);
+            } else if let Some(intersected) = part.strip_prefix('&') {
+                intersections.push(self.resolve_pattern_part(intersected)?);
+            } else {
+                selected.extend(self.resolve_pattern_part(part)?);
+            }
+        }
+
+        for intersection in intersections {
+            selected.retain(|host| intersection.contains(host));
+        }
+        selected.retain(|host| !exclusions.contains(host));
+
+        Ok(selected.iter().filter_map(|name| self.host(name)).collect())
+    }
+
+    fn resolve_pattern_part(&self, part: &str) -> Result<BTreeSet<String>> {
+        if part == ALL_GROUP || part == "*" || self.group_hosts.contains_key(part) {
+            return Ok(self.group_members(part, &mut BTreeSet::new()));
+        }
+
+        if self.hosts.contains_key(part) {
+            return Ok(BTreeSet::from([part.to_string()]));
+        }
+
+        if part.contains('*') {
+            return Ok(self
+                .hosts
+                .keys()
+                .filter(|host| crate::utils::pattern_matches_glob(part, host))
+                .cloned()
+                .collect());
+        }
+
+        Err(anyhow!(
+            "Unknown host or group '{}'. Available groups: {}",
+            part,
+            self.group_names().join(", ")
+        ))
+    }
+}
+
+fn yaml_key(value: &serde_yaml::Value) -> Result<String> {
+    match value {
+        serde_yaml::Value::String(s) => Ok(s.clone()),
+        serde_yaml::Value::Number(n) => Ok(n.to_string()),
+        serde_yaml::Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(anyhow!(
+            "Invalid YAML inventory: unsupported key {:?}",
+            value
+        )),
+    }
+}
+
+fn yaml_vars(value: &serde_yaml::Value) -> HashMap<String, String> {
+    let Some(mapping) = value.as_mapping() else {
+        return HashMap::new();
+    };
+
+    mapping
+        .iter()
+        .filter_map(|(key, value)| {
+            let key = yaml_key(key).ok()?;
+            let value = match value {
+                serde_yaml::Value::String(s) => s.clone(),
+                serde_yaml::Value::Number(n) => n.to_string(),
+                serde_yaml::Value::Bool(b) => b.to_string(),
+                serde_yaml::Value::Null => return None,
+                other => serde_yaml::to_string(other).ok()?.trim().to_string(),
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INI_INVENTORY: &str = r#"
+bastion ansible_host=203.0.113.10
+
+[web]
+web1 ansible_host=10.0.0.1
+web2 ansible_host=10.0.0.2 ansible_port=2222
+web3 ansible_host=10.0.0.3
+
+[canary]
+web3
+
+[db]
+db1 ansible_host=10.0.1.1 ansible_user=postgres
+
+[web:vars]
+ansible_user=ubuntu
+
+[prod:children]
+web
+db
+"#;
+
+    const YAML_INVENTORY: &str = r#"
+all:
+  vars:
+    ansible_user: admin
+  hosts:
+    bastion:
+      ansible_host: 203.0.113.10
+  children:
+    web:
+      vars:
+        ansible_user: ubuntu
+      hosts:
+        web1:
+          ansible_host: 10.0.0.1
+        web2:
+          ansible_host: 10.0.0.2
+          ansible_port: 2222
+    db:
+      hosts:
+        db1:
+    prod:
+      children:
+        web:
+        db:
+"#;
+
+    fn names(hosts: &[InventoryHost]) -> Vec<&str> {
+        hosts.iter().map(|h| h.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_ini_groups_and_children() {
+        let inventory = Inventory::parse_ini(INI_INVENTORY).expect("valid inventory");
+
+        assert_eq!(
+            names(&inventory.select("web").expect("web group")),
+            vec!["web1", "web2", "web3"]
+        );
+        assert_eq!(
+            names(&inventory.select("prod").expect("prod group")),
+            vec!["db1", "web1", "web2", "web3"]
+        );
+        assert_eq!(
+            names(&inventory.select("ungrouped").expect("ungrouped group")),
+            vec!["bastion"]
+        );
+        assert_eq!(inventory.select("all").expect("all group").len(), 5);
+    }
+
+    #[test]
+    fn test_parse_ini_connection_info() {
+        let inventory = Inventory::parse_ini(INI_INVENTORY).expect("valid inventory");
+
+        let web2 = inventory.host("web2").expect("web2 exists");
+        assert_eq!(
+            web2.connection_info().connection_string,
+            "ubuntu@10.0.0.2:2222"
+        );
+
+        let db1 = inventory.host("db1").expect("db1 exists");
+        assert_eq!(db1.connection_info().connection_string, "postgres@10.0.1.1");
+    }
+
+    #[test]
+    fn test_parse_yaml_inventory() {
+        let inventory = Inventory::parse_yaml(YAML_INVENTORY).expect("valid inventory");
+
+        assert_eq!(
+            names(&inventory.select("prod").expect("prod group")),
+            vec!["db1", "web1", "web2"]
+        );
+
+        // Group vars of the more specific group win over `all` vars
+        let web1 = inventory.host("web1").expect("web1 exists");
+        assert_eq!(web1.connection_info().connection_string, "ubuntu@10.0.0.1");
+
+        let db1 = inventory.host("db1").expect("db1 exists");
+        assert_eq!(db1.connection_info().connection_string, "admin@db1");
+    }
+
+    #[test]
+    fn test_select_patterns() {
+        let inventory = Inventory::parse_ini(INI_INVENTORY).expect("valid inventory");
+
+        assert_eq!(
+            names(&inventory.select("web:!canary").expect("exclusion")),
+            vec!["web1", "web2"]
+        );
+        assert_eq!(
+            names(&inventory.select("prod:&canary").expect("intersection")),
+            vec!["web3"]
+        );
+        assert_eq!(
+            names(&inventory.select("db1,bastion").expect("hosts")),
+            vec!["bastion", "db1"]
+        );
+        assert_eq!(
+            names(&inventory.select("web*").expect("wildcard")),
+            vec!["web1", "web2", "web3"]
+        );
+        assert!(inventory.select("unknown").is_err());
+    }
+
+    #[test]
+    fn test_parse_detects_format() {
+        let ini = Inventory::parse(INI_INVENTORY, None).expect("ini detected");
+        assert_eq!(ini.host_names().len(), 5);
+
+        let yaml = Inventory::parse(YAML_INVENTORY, None).expect("yaml detected");
+        assert_eq!(yaml.host_names().len(), 4);
+    }
+}
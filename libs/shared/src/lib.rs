@@ -1,13 +1,23 @@
+pub mod attachments;
 pub mod cert_utils;
+pub mod dir_sync;
+pub mod exec_target;
 pub mod file_backup_manager;
+pub mod file_transfer;
 pub mod file_watcher;
+pub mod fleet;
 pub mod helper;
+pub mod inventory;
 pub mod local_store;
+pub mod log_tail;
 pub mod models;
+pub mod port_forward;
 pub mod remote_connection;
 pub mod remote_store;
 pub mod secret_manager;
+pub mod secret_store;
 pub mod secrets;
+pub mod session_store;
 pub mod task_manager;
 pub mod tls_client;
 pub mod utils;
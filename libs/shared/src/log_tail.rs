@@ -0,0 +1,591 @@
+use crate::remote_connection::RemoteConnection;
+use anyhow::{Result, anyhow};
+use chrono::{Datelike, NaiveDateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+pub const DEFAULT_TAIL_LINES: usize = 10;
+pub const DEFAULT_TAIL_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How far back from the end of a file to look for the initial lines
+const INITIAL_READ_WINDOW: u64 = 64 * 1024;
+/// Upper bound on bytes read from a single file per poll, so a burst can't stall other files
+const MAX_READ_PER_POLL: u64 = 1024 * 1024;
+
+/// A line paired with its own timestamp or the one inherited from the previous line
+type TimestampedLine = (Option<NaiveDateTime>, String);
+
+static ISO_TIMESTAMP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(\d{4}-\d{2}-\d{2})[T ](\d{2}:\d{2}:\d{2})(?:[.,](\d{1,9}))?")
+        .expect("valid ISO timestamp regex")
+});
+
+static SYSLOG_TIMESTAMP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^([A-Z][a-z]{2})\s+(\d{1,2}) (\d{2}:\d{2}:\d{2})")
+        .expect("valid syslog timestamp regex")
+});
+
+/// Extract a timestamp from the start of a log line (ISO 8601 or classic syslog format)
+pub fn parse_line_timestamp(line: &str) -> Option<NaiveDateTime> {
+    let head = line
+        .char_indices()
+        .nth(64)
+        .map_or(line, |(index, _)| &line[..index]);
+
+    if let Some(captures) = ISO_TIMESTAMP.captures(head)
+        && captures.get(0).is_some_and(|m| m.start() <= 8)
+    {
+        let fraction = captures
+            .get(3)
+            .map(|m| format!("{:0<9}", m.as_str()))
+            .unwrap_or_else(|| "000000000".to_string());
+        return NaiveDateTime::parse_from_str(
+            &format!("{} {}.{}", &captures[1], &captures[2], fraction),
+            "%Y-%m-%d %H:%M:%S%.9f",
+        )
+        .ok();
+    }
+
+    if let Some(captures) = SYSLOG_TIMESTAMP.captures(head) {
+        // Syslog omits the year; assume the current one
+        return NaiveDateTime::parse_from_str(
+            &format!(
+                "{} {} {} {}",
+                Utc::now().year(),
+                &captures[1],
+                &captures[2],
+                &captures[3]
+            ),
+            "%Y %b %d %H:%M:%S",
+        )
+        .ok();
+    }
+
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct TailOptions {
+    /// Only keep lines matching at least one of these patterns (all lines when empty)
+    pub include: Vec<Regex>,
+    /// Drop lines matching any of these patterns
+    pub exclude: Vec<Regex>,
+    /// Maximum lines emitted per second across all files; excess lines are counted and dropped
+    pub max_lines_per_second: Option<u32>,
+    /// Number of existing lines to show from the end of each file when starting
+    pub initial_lines: usize,
+}
+
+impl TailOptions {
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+        max_lines_per_second: Option<u32>,
+        initial_lines: usize,
+    ) -> Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| Regex::new(p).map_err(|e| anyhow!("Invalid regex '{}': {}", p, e)))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            max_lines_per_second,
+            initial_lines,
+        })
+    }
+
+    fn keeps(&self, line: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|re| re.is_match(line)))
+            && !self.exclude.iter().any(|re| re.is_match(line))
+    }
+}
+
+/// A file to follow, on this machine or on an SSH host
+pub enum TailTarget {
+    Local(String),
+    Remote {
+        connection: Arc<RemoteConnection>,
+        path: String,
+    },
+}
+
+impl TailTarget {
+    pub fn label(&self) -> String {
+        match self {
+            TailTarget::Local(path) => path.clone(),
+            TailTarget::Remote { connection, path } => {
+                format!("{}:{}", connection.connection_string(), path)
+            }
+        }
+    }
+
+    /// Current size and, where available, a file identity used to detect rotation
+    async fn stat(&self) -> Result<(u64, Option<u64>)> {
+        match self {
+            TailTarget::Local(path) => {
+                let metadata = tokio::fs::metadata(path)
+                    .await
+                    .map_err(|e| anyhow!("Failed to stat {}: {}", path, e))?;
+                #[cfg(unix)]
+                let identity = {
+                    use std::os::unix::fs::MetadataExt;
+                    Some(metadata.ino())
+                };
+                #[cfg(not(unix))]
+                let identity = None;
+                Ok((metadata.len(), identity))
+            }
+            TailTarget::Remote { connection, path } => {
+                let metadata = connection.metadata(path).await?;
+                Ok((metadata.len(), None))
+            }
+        }
+    }
+
+    async fn read_range(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(len as usize);
+        match self {
+            TailTarget::Local(path) => {
+                let mut file = tokio::fs::File::open(path)
+                    .await
+                    .map_err(|e| anyhow!("Failed to open {}: {}", path, e))?;
+                file.seek(std::io::SeekFrom::Start(offset)).await?;
+                file.take(len).read_to_end(&mut buffer).await?;
+            }
+            TailTarget::Remote { connection, path } => {
+                let file = connection.open_file_for_read(path, offset).await?;
+                file.take(len).read_to_end(&mut buffer).await?;
+            }
+        }
+        Ok(buffer)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TailEvent {
+    Line { source: String, line: String },
+    Rotated { source: String },
+    Suppressed { count: u64 },
+    Error { source: String, message: String },
+}
+
+impl TailEvent {
+    /// Render the event as a single output line; file lines are prefixed with their source
+    /// when following more than one file
+    pub fn format(&self, with_source: bool) -> String {
+        match self {
+            TailEvent::Line { source, line } if with_source => format!("[{}] {}", source, line),
+            TailEvent::Line { line, .. } => line.clone(),
+            TailEvent::Rotated { source } => {
+                format!(
+                    "--- {} was rotated or truncated, following new file ---",
+                    source
+                )
+            }
+            TailEvent::Suppressed { count } => {
+                format!("--- {} lines suppressed by rate limit ---", count)
+            }
+            TailEvent::Error { source, message } => format!("--- {}: {} ---", source, message),
+        }
+    }
+}
+
+struct FollowedFile {
+    target: TailTarget,
+    label: String,
+    offset: u64,
+    identity: Option<u64>,
+    partial: Vec<u8>,
+    last_timestamp: Option<NaiveDateTime>,
+    failing: bool,
+}
+
+struct RateLimiter {
+    limit: u32,
+    window_start: Instant,
+    emitted: u32,
+    suppressed: u64,
+}
+
+impl RateLimiter {
+    /// Returns whether a line may be emitted, plus the number of lines suppressed during
+    /// the window that just ended (if any)
+    fn admit(&mut self, now: Instant) -> (bool, u64) {
+        let mut ended_suppressed = 0;
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            ended_suppressed = std::mem::take(&mut self.suppressed);
+            self.window_start = now;
+            self.emitted = 0;
+        }
+        if self.emitted < self.limit {
+            self.emitted += 1;
+            (true, ended_suppressed)
+        } else {
+            self.suppressed += 1;
+            (false, ended_suppressed)
+        }
+    }
+}
+
+/// Follows one or more log files by polling, handling rotation and truncation, filtering
+/// lines and merging lines from several files in timestamp order
+pub struct LogTailer {
+    files: Vec<FollowedFile>,
+    options: TailOptions,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl LogTailer {
+    pub async fn new(targets: Vec<TailTarget>, options: TailOptions) -> Result<Self> {
+        if targets.is_empty() {
+            return Err(anyhow!("No files to tail"));
+        }
+
+        let mut files = Vec::with_capacity(targets.len());
+        for target in targets {
+            let label = target.label();
+            let (size, identity) = target.stat().await?;
+            files.push(FollowedFile {
+                target,
+                label,
+                offset: size,
+                identity,
+                partial: Vec::new(),
+                last_timestamp: None,
+                failing: false,
+            });
+        }
+
+        let rate_limiter = options.max_lines_per_second.map(|limit| RateLimiter {
+            limit: limit.max(1),
+            window_start: Instant::now(),
+            emitted: 0,
+            suppressed: 0,
+        });
+
+        Ok(Self {
+            files,
+            options,
+            rate_limiter,
+        })
+    }
+
+    pub fn follows_multiple_files(&self) -> bool {
+        self.files.len() > 1
+    }
+
+    /// The last `initial_lines` lines of every file, merged by timestamp
+    pub async fn initial_events(&mut self) -> Vec<TailEvent> {
+        let wanted = self.options.initial_lines;
+        let mut batches = Vec::new();
+
+        for (index, file) in self.files.iter_mut().enumerate() {
+            if wanted == 0 || file.offset == 0 {
+                continue;
+            }
+            let start = file.offset.saturating_sub(INITIAL_READ_WINDOW);
+            let Ok(data) = file.target.read_range(start, file.offset - start).await else {
+                continue;
+            };
+            let text = String::from_utf8_lossy(&data);
+            let mut lines: Vec<&str> = text.lines().collect();
+            if start > 0 && !lines.is_empty() {
+                // The first line is probably cut in half by the read window
+                lines.remove(0);
+            }
+            let skip = lines.len().saturating_sub(wanted);
+            let lines: Vec<String> = lines.into_iter().skip(skip).map(str::to_string).collect();
+            batches.push((index, Self::timestamp_lines(file, lines)));
+        }
+
+        self.finish_batch(Vec::new(), batches)
+    }
+
+    /// Read whatever was appended since the last poll
+    pub async fn poll(&mut self) -> Vec<TailEvent> {
+        let mut events = Vec::new();
+        let mut batches = Vec::new();
+
+        for (index, file) in self.files.iter_mut().enumerate() {
+            let (size, identity) = match file.target.stat().await {
+                Ok(stat) => {
+                    file.failing = false;
+                    stat
+                }
+                Err(e) => {
+                    // Report once until the file comes back (e.g. mid-rotation)
+                    if !file.failing {
+                        file.failing = true;
+                        events.push(TailEvent::Error {
+                            source: file.label.clone(),
+                            message: e.to_string(),
+                        });
+                    }
+                    continue;
+                }
+            };
+
+            let replaced =
+                identity.is_some() && file.identity.is_some() && identity != file.identity;
+            if replaced || size < file.offset {
+                events.push(TailEvent::Rotated {
+                    source: file.label.clone(),
+                });
+                file.offset = 0;
+                file.partial.clear();
+                file.identity = identity;
+            }
+
+            if size <= file.offset {
+                continue;
+            }
+
+            let len = (size - file.offset).min(MAX_READ_PER_POLL);
+            let data = match file.target.read_range(file.offset, len).await {
+                Ok(data) => data,
+                Err(e) => {
+                    events.push(TailEvent::Error {
+                        source: file.label.clone(),
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            file.offset += data.len() as u64;
+            file.partial.extend_from_slice(&data);
+
+            let mut lines = Vec::new();
+            while let Some(newline) = file.partial.iter().position(|b| *b == b'\n') {
+                let raw: Vec<u8> = file.partial.drain(..=newline).collect();
+                let line = String::from_utf8_lossy(&raw)
+                    .trim_end_matches(['\n', '\r'])
+                    .to_string();
+                lines.push(line);
+            }
+            batches.push((index, Self::timestamp_lines(file, lines)));
+        }
+
+        self.finish_batch(events, batches)
+    }
+
+    /// Pair each line with a timestamp; continuation lines (stack traces, wrapped messages)
+    /// inherit the timestamp of the line before them
+    fn timestamp_lines(file: &mut FollowedFile, lines: Vec<String>) -> Vec<TimestampedLine> {
+        lines
+            .into_iter()
+            .map(|line| {
+                if let Some(timestamp) = parse_line_timestamp(&line) {
+                    file.last_timestamp = Some(timestamp);
+                }
+                (file.last_timestamp, line)
+            })
+            .collect()
+    }
+
+    fn finish_batch(
+        &mut self,
+        mut events: Vec<TailEvent>,
+        batches: Vec<(usize, Vec<TimestampedLine>)>,
+    ) -> Vec<TailEvent> {
+        let mut merged: Vec<(Option<NaiveDateTime>, usize, String)> = batches
+            .into_iter()
+            .flat_map(|(index, lines)| {
+                lines
+                    .into_iter()
+                    .map(move |(timestamp, line)| (timestamp, index, line))
+            })
+            .collect();
+        // Stable sort keeps per-file order for equal or missing timestamps
+        merged.sort_by_key(|(timestamp, _, _)| *timestamp);
+
+        let now = Instant::now();
+        for (_, index, line) in merged {
+            if !self.options.keeps(&line) {
+                continue;
+            }
+            if let Some(limiter) = self.rate_limiter.as_mut() {
+                let (admitted, ended_suppressed) = limiter.admit(now);
+                if ended_suppressed > 0 {
+                    events.push(TailEvent::Suppressed {
+                        count: ended_suppressed,
+                    });
+                }
+                if !admitted {
+                    continue;
+                }
+            }
+            events.push(TailEvent::Line {
+                source: self.files[index].label.clone(),
+                line,
+            });
+        }
+
+        // Report suppressed lines once the window ends even if nothing new arrives
+        if let Some(limiter) = self.rate_limiter.as_mut()
+            && limiter.suppressed > 0
+            && now.duration_since(limiter.window_start) >= Duration::from_secs(1)
+        {
+            events.push(TailEvent::Suppressed {
+                count: std::mem::take(&mut limiter.suppressed),
+            });
+            limiter.window_start = now;
+            limiter.emitted = 0;
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn lines(events: &[TailEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                TailEvent::Line { line, .. } => Some(line.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn append(path: &std::path::Path, content: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    fn local(path: &std::path::Path) -> TailTarget {
+        TailTarget::Local(path.to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn test_parse_line_timestamp_formats() {
+        assert!(parse_line_timestamp("2024-03-01T12:00:01.250Z INFO started").is_some());
+        assert!(parse_line_timestamp("[2024-03-01 12:00:01,250] WARN slow").is_some());
+        assert!(parse_line_timestamp("Mar  1 12:00:01 web1 sshd[42]: accepted").is_some());
+        assert!(parse_line_timestamp("    at com.example.Main").is_none());
+
+        let earlier = parse_line_timestamp("2024-03-01T12:00:01.250Z").unwrap();
+        let later = parse_line_timestamp("2024-03-01T12:00:01.3Z").unwrap();
+        assert!(earlier < later);
+    }
+
+    #[tokio::test]
+    async fn test_initial_lines_and_follow() {
+        let temp = TempDir::new().unwrap();
+        let log = temp.path().join("app.log");
+        append(&log, "one\ntwo\nthree\n");
+
+        let options = TailOptions::new(&[], &[], None, 2).unwrap();
+        let mut tailer = LogTailer::new(vec![local(&log)], options).await.unwrap();
+
+        assert_eq!(lines(&tailer.initial_events().await), vec!["two", "three"]);
+
+        append(&log, "four\nfiv");
+        assert_eq!(lines(&tailer.poll().await), vec!["four"]);
+        append(&log, "e\n");
+        assert_eq!(lines(&tailer.poll().await), vec!["five"]);
+    }
+
+    #[tokio::test]
+    async fn test_truncation_is_detected() {
+        let temp = TempDir::new().unwrap();
+        let log = temp.path().join("app.log");
+        append(&log, "old line one\nold line two\n");
+
+        let options = TailOptions::new(&[], &[], None, 0).unwrap();
+        let mut tailer = LogTailer::new(vec![local(&log)], options).await.unwrap();
+
+        std::fs::write(&log, "new\n").unwrap();
+        let events = tailer.poll().await;
+
+        assert!(matches!(events[0], TailEvent::Rotated { .. }));
+        assert_eq!(lines(&events), vec!["new"]);
+    }
+
+    #[tokio::test]
+    async fn test_include_and_exclude_filters() {
+        let temp = TempDir::new().unwrap();
+        let log = temp.path().join("app.log");
+        append(&log, "");
+
+        let options = TailOptions::new(
+            &["ERROR|WARN".to_string()],
+            &["healthcheck".to_string()],
+            None,
+            0,
+        )
+        .unwrap();
+        let mut tailer = LogTailer::new(vec![local(&log)], options).await.unwrap();
+
+        append(
+            &log,
+            "INFO ok\nERROR db down\nWARN healthcheck slow\nWARN disk 90%\n",
+        );
+        assert_eq!(
+            lines(&tailer.poll().await),
+            vec!["ERROR db down", "WARN disk 90%"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_suppresses_excess_lines() {
+        let temp = TempDir::new().unwrap();
+        let log = temp.path().join("app.log");
+        append(&log, "");
+
+        let options = TailOptions::new(&[], &[], Some(2), 0).unwrap();
+        let mut tailer = LogTailer::new(vec![local(&log)], options).await.unwrap();
+
+        append(&log, "1\n2\n3\n4\n5\n");
+        assert_eq!(lines(&tailer.poll().await), vec!["1", "2"]);
+
+        tailer.rate_limiter.as_mut().unwrap().window_start -= Duration::from_secs(2);
+        let events = tailer.poll().await;
+        assert_eq!(events, vec![TailEvent::Suppressed { count: 3 }]);
+    }
+
+    #[tokio::test]
+    async fn test_multiple_files_merge_by_timestamp() {
+        let temp = TempDir::new().unwrap();
+        let web = temp.path().join("web.log");
+        let db = temp.path().join("db.log");
+        append(&web, "");
+        append(&db, "");
+
+        let options = TailOptions::new(&[], &[], None, 0).unwrap();
+        let mut tailer = LogTailer::new(vec![local(&web), local(&db)], options)
+            .await
+            .unwrap();
+
+        append(
+            &web,
+            "2024-03-01T12:00:01Z web request\n2024-03-01T12:00:03Z web error\n",
+        );
+        append(&db, "2024-03-01T12:00:02Z db slow query\n  detail line\n");
+
+        assert_eq!(
+            lines(&tailer.poll().await),
+            vec![
+                "2024-03-01T12:00:01Z web request",
+                "2024-03-01T12:00:02Z db slow query",
+                "  detail line",
+                "2024-03-01T12:00:03Z web error",
+            ]
+        );
+        assert!(tailer.follows_multiple_files());
+    }
+}
@@ -25,6 +25,9 @@ impl std::fmt::Display for Role {
     }
 }
 
+/// Model requested when none is configured
+pub const DEFAULT_MODEL: &str = "pablo-v1";
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ChatCompletionRequest {
     pub model: String,
@@ -50,6 +53,8 @@ pub struct ChatCompletionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
@@ -66,7 +71,7 @@ pub struct ChatCompletionRequest {
 impl ChatCompletionRequest {
     pub fn new(messages: Vec<ChatMessage>, tools: Option<Vec<Tool>>, stream: Option<bool>) -> Self {
         Self {
-            model: "pablo-v1".to_string(),
+            model: DEFAULT_MODEL.to_string(),
             messages,
             frequency_penalty: None,
             logit_bias: None,
@@ -77,6 +82,10 @@ impl ChatCompletionRequest {
             response_format: None,
             seed: None,
             stop: None,
+            // Streams only report token usage when asked to, in a final chunk
+            stream_options: stream.unwrap_or(false).then_some(StreamOptions {
+                include_usage: true,
+            }),
             stream,
             temperature: None,
             top_p: None,
@@ -88,6 +97,11 @@ impl ChatCompletionRequest {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ChatMessage {
     pub role: Role,
@@ -373,7 +387,7 @@ pub struct TokenLogprob {
     pub bytes: Option<Vec<u8>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
@@ -387,6 +401,9 @@ pub struct ChatCompletionStreamResponse {
     pub created: u64,
     pub model: String,
     pub choices: Vec<ChatCompletionStreamChoice>,
+    /// Only set on the final chunk, and only when requested with `stream_options`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ChatCompletionStreamChoice {
@@ -520,6 +537,7 @@ mod tests {
             seed: None,
             stop: None,
             stream: None,
+            stream_options: None,
             temperature: Some(0.7),
             top_p: None,
             tools: None,
@@ -629,6 +647,7 @@ mod tests {
             seed: None,
             stop: None,
             stream: None,
+            stream_options: None,
             top_p: None,
             user: None,
             context: None,
@@ -761,6 +780,7 @@ mod tests {
             seed: None,
             stop: None,
             stream: None,
+            stream_options: None,
             top_p: None,
             tools: None,
             tool_choice: None,
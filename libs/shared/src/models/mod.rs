@@ -2,3 +2,4 @@ pub mod indexing;
 pub mod integrations;
 pub mod llm;
 pub mod subagent;
+pub mod usage;
@@ -0,0 +1,261 @@
+use crate::models::integrations::openai::Usage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Price table key used for models that have no entry of their own
+pub const DEFAULT_PRICE_KEY: &str = "default";
+
+/// Price of a model in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.prompt + completion_tokens as f64 * self.completion)
+            / 1_000_000.0
+    }
+}
+
+/// Prices by model name, falling back to the `default` entry
+pub type PriceTable = HashMap<String, ModelPrice>;
+
+/// Limits after which an agent session stops. Unset limits are not enforced.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageBudget {
+    /// Prompt and completion tokens combined
+    pub max_tokens: Option<u64>,
+    /// Cost in USD, which needs a price table to be tracked
+    pub max_cost: Option<f64>,
+    /// Model responses
+    pub max_steps: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Cost in USD, `None` when the model has no known price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost = match (self.cost, other.cost) {
+            (None, None) => None,
+            (cost, other_cost) => Some(cost.unwrap_or(0.0) + other_cost.unwrap_or(0.0)),
+        };
+    }
+}
+
+/// Token usage and cost of an agent session, turn by turn
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub total: TokenUsage,
+    pub last_turn: TokenUsage,
+    /// Number of model responses so far
+    pub steps: usize,
+}
+
+fn priced(usage: &Usage, model: &str, prices: Option<&PriceTable>) -> TokenUsage {
+    let prompt_tokens = u64::from(usage.prompt_tokens);
+    let completion_tokens = u64::from(usage.completion_tokens);
+    let cost = prices
+        .and_then(|prices| prices.get(model).or_else(|| prices.get(DEFAULT_PRICE_KEY)))
+        .map(|price| price.cost(prompt_tokens, completion_tokens));
+    TokenUsage {
+        prompt_tokens,
+        completion_tokens,
+        cost,
+    }
+}
+
+/// The budget limit a session ran into
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetExceeded {
+    Tokens { used: u64, limit: u64 },
+    Cost { used: f64, limit: f64 },
+    Steps { used: usize, limit: usize },
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetExceeded::Tokens { used, limit } => {
+                write!(
+                    f,
+                    "Token budget exceeded: {} of {} tokens used",
+                    used, limit
+                )
+            }
+            BudgetExceeded::Cost { used, limit } => {
+                write!(
+                    f,
+                    "Cost budget exceeded: ${:.4} of ${:.4} spent",
+                    used, limit
+                )
+            }
+            BudgetExceeded::Steps { used, limit } => {
+                write!(f, "Step budget exceeded: {} of {} steps taken", used, limit)
+            }
+        }
+    }
+}
+
+impl SessionUsage {
+    /// Record the usage of one model response, priced from `prices` when given
+    pub fn record_turn(&mut self, usage: &Usage, model: &str, prices: Option<&PriceTable>) {
+        self.last_turn = priced(usage, model, prices);
+        self.total.add(&self.last_turn);
+        self.steps += 1;
+    }
+
+    /// Record the usage of a model response that isn't an agent step, like a conversation
+    /// summary. It counts towards the totals and the budget, but not the steps.
+    pub fn record_extra(&mut self, usage: &Usage, model: &str, prices: Option<&PriceTable>) {
+        self.total.add(&priced(usage, model, prices));
+    }
+
+    /// The first limit of `budget` that has been reached, if any
+    pub fn exceeded(&self, budget: &UsageBudget) -> Option<BudgetExceeded> {
+        if let Some(limit) = budget.max_steps
+            && self.steps >= limit
+        {
+            return Some(BudgetExceeded::Steps {
+                used: self.steps,
+                limit,
+            });
+        }
+        if let Some(limit) = budget.max_tokens
+            && self.total.total_tokens() >= limit
+        {
+            return Some(BudgetExceeded::Tokens {
+                used: self.total.total_tokens(),
+                limit,
+            });
+        }
+        if let Some(limit) = budget.max_cost
+            && let Some(used) = self.total.cost
+            && used >= limit
+        {
+            return Some(BudgetExceeded::Cost { used, limit });
+        }
+        None
+    }
+
+    /// One line summary, e.g. `12.3k tokens (10.1k in, 2.2k out) · $0.0421`
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} tokens ({} in, {} out)",
+            format_tokens(self.total.total_tokens()),
+            format_tokens(self.total.prompt_tokens),
+            format_tokens(self.total.completion_tokens)
+        );
+        if let Some(cost) = self.total.cost {
+            summary.push_str(&format!(" · ${:.4}", cost));
+        }
+        summary
+    }
+}
+
+/// Abbreviate token counts above a thousand, e.g. `12.3k` or `1.2M`
+pub fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..1_000 => tokens.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", tokens as f64 / 1_000.0),
+        _ => format!("{:.1}M", tokens as f64 / 1_000_000.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u32, completion_tokens: u32) -> Usage {
+        Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+
+    #[test]
+    fn test_records_turns_with_fallback_price() {
+        let prices = PriceTable::from([(
+            DEFAULT_PRICE_KEY.to_string(),
+            ModelPrice {
+                prompt: 3.0,
+                completion: 15.0,
+            },
+        )]);
+        let mut session = SessionUsage::default();
+        session.record_turn(&usage(1_000_000, 0), "pablo-v1", Some(&prices));
+        session.record_turn(&usage(2_000, 1_000), "pablo-v1", Some(&prices));
+
+        assert_eq!(session.steps, 2);
+        assert_eq!(session.total.total_tokens(), 1_003_000);
+        assert_eq!(session.last_turn.total_tokens(), 3_000);
+        assert!(
+            session
+                .total
+                .cost
+                .is_some_and(|cost| (cost - 3.021).abs() < 1e-9)
+        );
+        assert_eq!(
+            session.summary(),
+            "1.0M tokens (1.0M in, 1.0k out) · $3.0210"
+        );
+
+        session.record_extra(&usage(10_000, 0), "pablo-v1", Some(&prices));
+        assert_eq!(session.steps, 2);
+        assert_eq!(session.total.total_tokens(), 1_013_000);
+        assert_eq!(session.last_turn.total_tokens(), 3_000);
+
+        let mut unpriced = SessionUsage::default();
+        unpriced.record_turn(&usage(10, 5), "pablo-v1", None);
+        assert_eq!(unpriced.total.cost, None);
+        assert_eq!(unpriced.summary(), "15 tokens (10 in, 5 out)");
+    }
+
+    #[test]
+    fn test_budget_limits() {
+        let mut session = SessionUsage::default();
+        session.record_turn(&usage(800, 200), "pablo-v1", None);
+
+        assert_eq!(session.exceeded(&UsageBudget::default()), None);
+        assert_eq!(
+            session.exceeded(&UsageBudget {
+                max_tokens: Some(1_000),
+                ..Default::default()
+            }),
+            Some(BudgetExceeded::Tokens {
+                used: 1_000,
+                limit: 1_000
+            })
+        );
+        assert!(matches!(
+            session.exceeded(&UsageBudget {
+                max_steps: Some(1),
+                ..Default::default()
+            }),
+            Some(BudgetExceeded::Steps { .. })
+        ));
+        // Cost limits cannot be enforced without prices
+        assert_eq!(
+            session.exceeded(&UsageBudget {
+                max_cost: Some(0.0),
+                ..Default::default()
+            }),
+            None
+        );
+    }
+}
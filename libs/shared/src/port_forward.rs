@@ -0,0 +1,336 @@
+use crate::remote_connection::RemoteConnection;
+use crate::utils::format_bytes;
+use anyhow::{Result, anyhow};
+use russh::{Channel, client};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+const COPY_BUFFER_SIZE: usize = 32 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardDirection {
+    /// Listen locally and forward connections to a host reachable from the remote machine (`ssh -L`)
+    LocalToRemote,
+    /// Listen on the remote machine and forward connections to a host reachable locally (`ssh -R`)
+    RemoteToLocal,
+}
+
+impl std::str::FromStr for ForwardDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" | "local_to_remote" | "l" => Ok(ForwardDirection::LocalToRemote),
+            "remote" | "remote_to_local" | "r" => Ok(ForwardDirection::RemoteToLocal),
+            _ => Err(format!(
+                "Invalid forward direction: {} (expected 'local' or 'remote')",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortForwardSpec {
+    pub direction: ForwardDirection,
+    /// Local address: the listen address for local forwards, the target for remote forwards
+    pub local_host: String,
+    pub local_port: u16,
+    /// Remote address: the target for local forwards, the listen address for remote forwards
+    pub remote_host: String,
+    pub remote_port: u16,
+}
+
+impl Display for PortForwardSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.direction {
+            ForwardDirection::LocalToRemote => write!(
+                f,
+                "local {}:{} -> remote {}:{}",
+                self.local_host, self.local_port, self.remote_host, self.remote_port
+            ),
+            ForwardDirection::RemoteToLocal => write!(
+                f,
+                "remote {}:{} -> local {}:{}",
+                self.remote_host, self.remote_port, self.local_host, self.local_port
+            ),
+        }
+    }
+}
+
+/// Live counters for a running port forward
+#[derive(Debug, Default)]
+pub struct PortForwardStats {
+    pub active_connections: AtomicUsize,
+    pub total_connections: AtomicU64,
+    /// Bytes sent from the local side to the remote side
+    pub bytes_sent: AtomicU64,
+    /// Bytes received from the remote side to the local side
+    pub bytes_received: AtomicU64,
+    pub last_error: std::sync::Mutex<Option<String>>,
+}
+
+impl PortForwardStats {
+    fn record_error(&self, error: String) {
+        debug!("Port forward connection error: {}", error);
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = Some(error);
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Active connections: {} | Total connections: {} | Sent: {} | Received: {}",
+            self.active_connections.load(Ordering::Relaxed),
+            self.total_connections.load(Ordering::Relaxed),
+            format_bytes(self.bytes_sent.load(Ordering::Relaxed)),
+            format_bytes(self.bytes_received.load(Ordering::Relaxed)),
+        );
+        if let Ok(last_error) = self.last_error.lock()
+            && let Some(error) = last_error.as_ref()
+        {
+            summary.push_str(&format!("\nLast connection error: {}", error));
+        }
+        summary
+    }
+}
+
+enum ForwardListener {
+    Local(TcpListener),
+    Remote(mpsc::UnboundedReceiver<Channel<client::Msg>>),
+}
+
+/// Cancels a remote forward on the server when the forward is dropped (stopped or cancelled)
+struct RemoteForwardGuard {
+    connection: Arc<RemoteConnection>,
+    address: String,
+    port: u32,
+}
+
+impl Drop for RemoteForwardGuard {
+    fn drop(&mut self) {
+        let connection = self.connection.clone();
+        let address = std::mem::take(&mut self.address);
+        let port = self.port;
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                let _ = connection.cancel_remote_forward(&address, port).await;
+            });
+        }
+    }
+}
+
+/// A bound port forward over an existing SSH connection
+pub struct PortForward {
+    connection: Arc<RemoteConnection>,
+    spec: PortForwardSpec,
+    listener: ForwardListener,
+    _remote_guard: Option<RemoteForwardGuard>,
+}
+
+impl PortForward {
+    /// Start listening for the forward. Port `0` on the listening side picks a free port;
+    /// the effective port is reflected in `spec()`.
+    pub async fn bind(
+        connection: Arc<RemoteConnection>,
+        mut spec: PortForwardSpec,
+    ) -> Result<Self> {
+        match spec.direction {
+            ForwardDirection::LocalToRemote => {
+                let listener = TcpListener::bind((spec.local_host.as_str(), spec.local_port))
+                    .await
+                    .map_err(|e| {
+                        anyhow!(
+                            "Failed to listen on {}:{}: {}",
+                            spec.local_host,
+                            spec.local_port,
+                            e
+                        )
+                    })?;
+                spec.local_port = listener.local_addr()?.port();
+
+                Ok(Self {
+                    connection,
+                    spec,
+                    listener: ForwardListener::Local(listener),
+                    _remote_guard: None,
+                })
+            }
+            ForwardDirection::RemoteToLocal => {
+                let (bound_port, channels) = connection
+                    .request_remote_forward(&spec.remote_host, spec.remote_port as u32)
+                    .await?;
+                spec.remote_port = u16::try_from(bound_port)
+                    .map_err(|_| anyhow!("Server bound an invalid port: {}", bound_port))?;
+
+                Ok(Self {
+                    _remote_guard: Some(RemoteForwardGuard {
+                        connection: connection.clone(),
+                        address: spec.remote_host.clone(),
+                        port: bound_port,
+                    }),
+                    connection,
+                    spec,
+                    listener: ForwardListener::Remote(channels),
+                })
+            }
+        }
+    }
+
+    pub fn spec(&self) -> &PortForwardSpec {
+        &self.spec
+    }
+
+    /// Accept and forward connections until the listener fails or the SSH session closes
+    pub async fn serve(mut self, stats: Arc<PortForwardStats>) -> Result<()> {
+        loop {
+            match &mut self.listener {
+                ForwardListener::Local(listener) => {
+                    let (stream, peer) = listener
+                        .accept()
+                        .await
+                        .map_err(|e| anyhow!("Failed to accept local connection: {}", e))?;
+
+                    let connection = self.connection.clone();
+                    let spec = self.spec.clone();
+                    let stats = stats.clone();
+                    tokio::spawn(async move {
+                        let channel = match connection
+                            .open_direct_tcpip(
+                                &spec.remote_host,
+                                spec.remote_port as u32,
+                                &peer.ip().to_string(),
+                                peer.port() as u32,
+                            )
+                            .await
+                        {
+                            Ok(channel) => channel,
+                            Err(e) => {
+                                stats.record_error(e.to_string());
+                                return;
+                            }
+                        };
+                        pipe_connection(stream, channel, &stats).await;
+                    });
+                }
+                ForwardListener::Remote(channels) => {
+                    let channel = channels
+                        .recv()
+                        .await
+                        .ok_or_else(|| anyhow!("SSH session closed"))?;
+
+                    let spec = self.spec.clone();
+                    let stats = stats.clone();
+                    tokio::spawn(async move {
+                        let stream =
+                            match TcpStream::connect((spec.local_host.as_str(), spec.local_port))
+                                .await
+                            {
+                                Ok(stream) => stream,
+                                Err(e) => {
+                                    stats.record_error(format!(
+                                        "Failed to connect to {}:{}: {}",
+                                        spec.local_host, spec.local_port, e
+                                    ));
+                                    let _ = channel.close().await;
+                                    return;
+                                }
+                            };
+                        pipe_connection(stream, channel, &stats).await;
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Copy data in both directions between a local TCP stream and an SSH channel
+async fn pipe_connection(
+    stream: TcpStream,
+    channel: Channel<client::Msg>,
+    stats: &PortForwardStats,
+) {
+    stats.active_connections.fetch_add(1, Ordering::Relaxed);
+    stats.total_connections.fetch_add(1, Ordering::Relaxed);
+
+    let (tcp_read, tcp_write) = stream.into_split();
+    let (channel_read, channel_write) = tokio::io::split(channel.into_stream());
+
+    let (upstream, downstream) = tokio::join!(
+        copy_counted(tcp_read, channel_write, &stats.bytes_sent),
+        copy_counted(channel_read, tcp_write, &stats.bytes_received),
+    );
+
+    if let Err(e) = upstream.and(downstream) {
+        stats.record_error(e.to_string());
+    }
+
+    stats.active_connections.fetch_sub(1, Ordering::Relaxed);
+}
+
+async fn copy_counted<R, W>(
+    mut reader: R,
+    mut writer: W,
+    counter: &AtomicU64,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            let _ = writer.shutdown().await;
+            return Ok(());
+        }
+        writer.write_all(&buffer[..read]).await?;
+        counter.fetch_add(read as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forward_direction_from_str() {
+        assert_eq!(
+            "local".parse::<ForwardDirection>(),
+            Ok(ForwardDirection::LocalToRemote)
+        );
+        assert_eq!(
+            "R".parse::<ForwardDirection>(),
+            Ok(ForwardDirection::RemoteToLocal)
+        );
+        assert!("sideways".parse::<ForwardDirection>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_copy_counted_counts_bytes() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (server_read, _server_write) = tokio::io::split(server);
+        let mut sink = Vec::new();
+        let counter = AtomicU64::new(0);
+
+        client
+            .write_all(b"hello port forward")
+            .await
+            .expect("write to duplex");
+        drop(client);
+
+        copy_counted(server_read, &mut sink, &counter)
+            .await
+            .expect("copy succeeds");
+
+        assert_eq!(sink, b"hello port forward");
+        assert_eq!(counter.load(Ordering::Relaxed), 18);
+    }
+}
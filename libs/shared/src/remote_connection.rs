@@ -1,8 +1,12 @@
+use crate::exec_target::shell_quote;
 use crate::utils::{DirectoryEntry, FileSystemProvider};
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
+use russh::Channel;
 use russh::client::{self, Handler};
 use russh_sftp::client::SftpSession;
+use russh_sftp::client::fs::{File as SftpFile, Metadata};
+use russh_sftp::protocol::OpenFlags;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -12,10 +16,9 @@ use std::{
     sync::Arc,
     time::Duration,
 };
-use tokio::io::AsyncWriteExt;
-use tokio::sync::RwLock;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{Mutex, RwLock, mpsc};
 use tracing::debug;
-use uuid;
 
 #[derive(Debug)]
 struct ParsedConnection {
@@ -26,11 +29,12 @@ struct ParsedConnection {
 
 pub struct CommandOptions {
     pub timeout: Option<Duration>,
-    pub with_progress: bool,
     pub simple: bool,
+    /// Data written to the command's stdin before it is closed
+    pub stdin: Option<Vec<u8>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RemoteConnectionInfo {
     pub connection_string: String, // format: user@host:port
     pub password: Option<String>,
@@ -60,7 +64,15 @@ impl RemoteConnectionInfo {
     }
 }
 
-pub struct SSHClient;
+/// Channels opened by the server for remote (`-R` style) port forwards, keyed by the
+/// remote port they were requested on
+pub type ForwardedChannelRoutes =
+    Arc<std::sync::Mutex<HashMap<u32, mpsc::UnboundedSender<Channel<client::Msg>>>>>;
+
+#[derive(Default)]
+pub struct SSHClient {
+    forwarded_channel_routes: ForwardedChannelRoutes,
+}
 
 impl Handler for SSHClient {
     type Error = russh::Error;
@@ -73,10 +85,44 @@ impl Handler for SSHClient {
         // For now, we accept all keys to avoid "Unknown server key" errors
         Ok(true)
     }
+
+    async fn server_channel_open_forwarded_tcpip(
+        &mut self,
+        channel: Channel<client::Msg>,
+        connected_address: &str,
+        connected_port: u32,
+        originator_address: &str,
+        originator_port: u32,
+        _session: &mut client::Session,
+    ) -> Result<(), Self::Error> {
+        debug!(
+            "Forwarded connection to {}:{} from {}:{}",
+            connected_address, connected_port, originator_address, originator_port
+        );
+
+        let route = self
+            .forwarded_channel_routes
+            .lock()
+            .ok()
+            .and_then(|routes| routes.get(&connected_port).cloned());
+
+        match route {
+            Some(route) => {
+                let _ = route.send(channel);
+            }
+            None => {
+                debug!("No forward registered for remote port {}", connected_port);
+                let _ = channel.close().await;
+            }
+        }
+        Ok(())
+    }
 }
 
 pub struct RemoteConnection {
     sftp: SftpSession,
+    session: Mutex<client::Handle<SSHClient>>,
+    forwarded_channel_routes: ForwardedChannelRoutes,
     connection_info: RemoteConnectionInfo,
 }
 
@@ -94,6 +140,13 @@ impl RemoteConnection {
 
     async fn create_authenticated_session_static(
         connection_info: &RemoteConnectionInfo,
+    ) -> Result<client::Handle<SSHClient>> {
+        Self::create_authenticated_session_with_handler(connection_info, SSHClient::default()).await
+    }
+
+    async fn create_authenticated_session_with_handler(
+        connection_info: &RemoteConnectionInfo,
+        handler: SSHClient,
     ) -> Result<client::Handle<SSHClient>> {
         let parsed = connection_info.parse_connection_string()?;
 
@@ -106,7 +159,7 @@ impl RemoteConnection {
         let mut session = client::connect(
             config.into(),
             (parsed.hostname.as_str(), parsed.port),
-            SSHClient {},
+            handler,
         )
         .await
         .map_err(|e| Self::map_ssh_error(e, "connection failed"))?;
@@ -220,7 +273,14 @@ impl RemoteConnection {
     }
 
     pub async fn new(connection_info: RemoteConnectionInfo) -> Result<Self> {
-        let session = Self::create_authenticated_session_static(&connection_info).await?;
+        let forwarded_channel_routes = ForwardedChannelRoutes::default();
+        let session = Self::create_authenticated_session_with_handler(
+            &connection_info,
+            SSHClient {
+                forwarded_channel_routes: forwarded_channel_routes.clone(),
+            },
+        )
+        .await?;
 
         // Open SFTP channel
         let channel = session
@@ -241,6 +301,8 @@ impl RemoteConnection {
 
         Ok(Self {
             sftp,
+            session: Mutex::new(session),
+            forwarded_channel_routes,
             connection_info,
         })
     }
@@ -411,6 +473,70 @@ impl RemoteConnection {
         Ok(metadata.len())
     }
 
+    pub async fn metadata(&self, path: &str) -> Result<Metadata> {
+        self.sftp
+            .metadata(path)
+            .await
+            .map_err(|e| anyhow!("Failed to get metadata for {}: {}", path, e))
+    }
+
+    /// Set the permission bits of a remote path, keeping its file type bits
+    pub async fn set_permissions(&self, path: &str, mode: u32) -> Result<()> {
+        let mut metadata = Metadata::empty();
+        metadata.permissions = Some(mode & 0o7777);
+        self.sftp
+            .set_metadata(path, metadata)
+            .await
+            .map_err(|e| anyhow!("Failed to set permissions on {}: {}", path, e))
+    }
+
+    /// Set the access and modification times of a remote path (seconds since the epoch)
+    pub async fn set_modified_time(&self, path: &str, mtime: u32) -> Result<()> {
+        let mut metadata = Metadata::empty();
+        metadata.atime = Some(mtime);
+        metadata.mtime = Some(mtime);
+        self.sftp
+            .set_metadata(path, metadata)
+            .await
+            .map_err(|e| anyhow!("Failed to set modification time on {}: {}", path, e))
+    }
+
+    /// Open a remote file for streaming reads, starting at `offset`
+    pub async fn open_file_for_read(&self, path: &str, offset: u64) -> Result<SftpFile> {
+        let mut file = self
+            .sftp
+            .open(path)
+            .await
+            .map_err(|e| anyhow!("Failed to open file {}: {}", path, e))?;
+        if offset > 0 {
+            file.seek(std::io::SeekFrom::Start(offset))
+                .await
+                .map_err(|e| anyhow!("Failed to seek in file {}: {}", path, e))?;
+        }
+        Ok(file)
+    }
+
+    /// Open a remote file for streaming writes. With `offset` 0 the file is created or
+    /// truncated; otherwise writing continues at `offset` in the existing file.
+    pub async fn open_file_for_write(&self, path: &str, offset: u64) -> Result<SftpFile> {
+        let flags = if offset == 0 {
+            OpenFlags::CREATE | OpenFlags::TRUNCATE | OpenFlags::WRITE
+        } else {
+            OpenFlags::WRITE
+        };
+        let mut file = self
+            .sftp
+            .open_with_flags(path, flags)
+            .await
+            .map_err(|e| anyhow!("Failed to open file {} for writing: {}", path, e))?;
+        if offset > 0 {
+            file.seek(std::io::SeekFrom::Start(offset))
+                .await
+                .map_err(|e| anyhow!("Failed to seek in file {}: {}", path, e))?;
+        }
+        Ok(file)
+    }
+
     pub async fn rename(&self, old_path: &str, new_path: &str) -> Result<()> {
         self.sftp
             .rename(old_path, new_path)
@@ -441,8 +567,11 @@ impl RemoteConnection {
             command.to_string()
         } else {
             format!(
-                "bash -c 'echo \"PID:$$\"; exec bash -c \"{}\"'",
-                command.replace('\\', "\\\\").replace('"', "\\\"")
+                "bash -c {}",
+                shell_quote(&format!(
+                    "echo \"PID:$$\"; exec bash -c {}",
+                    shell_quote(command)
+                ))
             )
         };
 
@@ -451,10 +580,20 @@ impl RemoteConnection {
             .await
             .map_err(|e| Self::map_ssh_error(e, "failed to execute command"))?;
 
+        if let Some(input) = &options.stdin {
+            channel
+                .data(&input[..])
+                .await
+                .map_err(|e| Self::map_ssh_error(e, "failed to send command input"))?;
+            channel
+                .eof()
+                .await
+                .map_err(|e| Self::map_ssh_error(e, "failed to close command input"))?;
+        }
+
         let mut output = String::new();
         let mut exit_code = 0i32;
         let mut remote_pid: Option<String> = None;
-        let progress_id = uuid::Uuid::new_v4();
 
         // Compile regex for PID extraction if needed
         let pid_regex = if !options.simple {
@@ -463,7 +602,7 @@ impl RemoteConnection {
             None
         };
 
-        // Stream output with progress notifications
+        // Stream output to the progress callback; callers redact it before forwarding
         let command_execution = async {
             while let Some(msg) = channel.wait().await {
                 match msg {
@@ -491,46 +630,14 @@ impl RemoteConnection {
                         // Normal output processing
                         output.push_str(&text);
                         if let Some(ref callback) = progress_callback {
-                            callback(text.clone());
-                        }
-
-                        // Send MCP progress notification if context is provided
-                        if let Some(ctx) = &ctx
-                            && options.with_progress
-                            && !text.trim().is_empty()
-                        {
-                            let _ = ctx.peer.notify_progress(rmcp::model::ProgressNotificationParam {
-                                    progress_token: rmcp::model::ProgressToken(rmcp::model::NumberOrString::Number(0)),
-                                    progress: 50,
-                                    total: Some(100),
-                                    message: Some(serde_json::to_string(&crate::models::integrations::openai::ToolCallResultProgress {
-                                        id: progress_id,
-                                        message: text,
-                                    }).unwrap_or_default()),
-                                }).await;
+                            callback(text);
                         }
                     }
                     russh::ChannelMsg::ExtendedData { data, ext: _ } => {
                         let text = String::from_utf8_lossy(&data).to_string();
                         output.push_str(&text);
                         if let Some(ref callback) = progress_callback {
-                            callback(text.clone());
-                        }
-
-                        // Send MCP progress notification for stderr if context is provided
-                        if let Some(ctx) = &ctx
-                            && options.with_progress
-                            && !text.trim().is_empty()
-                        {
-                            let _ = ctx.peer.notify_progress(rmcp::model::ProgressNotificationParam {
-                                    progress_token: rmcp::model::ProgressToken(rmcp::model::NumberOrString::Number(0)),
-                                    progress: 50,
-                                    total: Some(100),
-                                    message: Some(serde_json::to_string(&crate::models::integrations::openai::ToolCallResultProgress {
-                                        id: progress_id,
-                                        message: text,
-                                    }).unwrap_or_default()),
-                                }).await;
+                            callback(text);
                         }
                     }
                     russh::ChannelMsg::ExitStatus { exit_status } => {
@@ -604,8 +711,8 @@ impl RemoteConnection {
     ) -> Result<(String, i32)> {
         let options = CommandOptions {
             timeout,
-            with_progress: true,
             simple: false,
+            stdin: None,
         };
 
         let (_cancel_tx, mut cancel_rx) = tokio::sync::oneshot::channel();
@@ -626,8 +733,8 @@ impl RemoteConnection {
     {
         let options = CommandOptions {
             timeout,
-            with_progress: false,
             simple: false,
+            stdin: None,
         };
 
         self.execute_command_unified(command, options, cancel_rx, Some(progress_callback), None)
@@ -637,6 +744,82 @@ impl RemoteConnection {
     pub fn connection_string(&self) -> &str {
         &self.connection_info.connection_string
     }
+
+    /// Open a `direct-tcpip` channel to `host:port` as seen from the remote machine,
+    /// reusing this connection's authenticated SSH session
+    pub async fn open_direct_tcpip(
+        &self,
+        host: &str,
+        port: u32,
+        originator_address: &str,
+        originator_port: u32,
+    ) -> Result<Channel<client::Msg>> {
+        self.session
+            .lock()
+            .await
+            .channel_open_direct_tcpip(host, port, originator_address, originator_port)
+            .await
+            .map_err(|e| Self::map_ssh_error(e, &format!("direct-tcpip to {}:{}", host, port)))
+    }
+
+    /// Ask the remote server to listen on `address:port` and forward incoming connections
+    /// back over this session. Returns the port actually bound (useful when `port` is 0)
+    /// and a receiver yielding one channel per forwarded connection.
+    pub async fn request_remote_forward(
+        &self,
+        address: &str,
+        port: u32,
+    ) -> Result<(u32, mpsc::UnboundedReceiver<Channel<client::Msg>>)> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        // Register before requesting so no early connection is dropped for known ports
+        if port != 0 {
+            self.set_forwarded_channel_route(port, Some(tx.clone()));
+        }
+
+        let bound_port = match self.session.lock().await.tcpip_forward(address, port).await {
+            Ok(0) => port,
+            Ok(bound_port) => bound_port,
+            Err(e) => {
+                self.set_forwarded_channel_route(port, None);
+                return Err(Self::map_ssh_error(
+                    e,
+                    &format!("remote forward on {}:{}", address, port),
+                ));
+            }
+        };
+
+        self.set_forwarded_channel_route(bound_port, Some(tx));
+        Ok((bound_port, rx))
+    }
+
+    /// Stop a remote forward previously requested with `request_remote_forward`
+    pub async fn cancel_remote_forward(&self, address: &str, port: u32) -> Result<()> {
+        self.set_forwarded_channel_route(port, None);
+        self.session
+            .lock()
+            .await
+            .cancel_tcpip_forward(address, port)
+            .await
+            .map_err(|e| Self::map_ssh_error(e, "cancel remote forward"))
+    }
+
+    fn set_forwarded_channel_route(
+        &self,
+        port: u32,
+        route: Option<mpsc::UnboundedSender<Channel<client::Msg>>>,
+    ) {
+        if let Ok(mut routes) = self.forwarded_channel_routes.lock() {
+            match route {
+                Some(route) => {
+                    routes.insert(port, route);
+                }
+                None => {
+                    routes.remove(&port);
+                }
+            }
+        }
+    }
 }
 
 /// Remote file system provider implementation for tree generation
@@ -648,6 +831,96 @@ impl RemoteFileSystemProvider {
     pub fn new(connection: Arc<RemoteConnection>) -> Self {
         Self { connection }
     }
+
+    pub fn connection(&self) -> &Arc<RemoteConnection> {
+        &self.connection
+    }
+
+    /// Recursively list regular files under `root` with their size and modification time.
+    /// Paths are relative to `root` and use `/` separators; directories matching `skip_dir`
+    /// are not descended into.
+    pub async fn walk_files(
+        &self,
+        root: &str,
+        skip_dir: impl Fn(&str) -> bool,
+    ) -> Result<Vec<RemoteFileEntry>, String> {
+        let root = root.trim_end_matches('/');
+        let mut files = Vec::new();
+        let mut pending = vec![String::new()];
+
+        while let Some(relative_dir) = pending.pop() {
+            let dir = if relative_dir.is_empty() {
+                root.to_string()
+            } else {
+                format!("{}/{}", root, relative_dir)
+            };
+            let entries = self
+                .connection
+                .sftp
+                .read_dir(&dir)
+                .await
+                .map_err(|e| format!("Failed to read remote directory {}: {}", dir, e))?;
+
+            for entry in entries {
+                let name = entry.file_name();
+                if name == "." || name == ".." {
+                    continue;
+                }
+                let relative_path = if relative_dir.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", relative_dir, name)
+                };
+                let metadata = entry.metadata();
+                if metadata.is_dir() {
+                    if !skip_dir(&relative_path) {
+                        pending.push(relative_path);
+                    }
+                } else if metadata.file_type().is_file() {
+                    files.push(RemoteFileEntry {
+                        relative_path,
+                        size: metadata.len(),
+                        mtime: metadata.mtime,
+                    });
+                }
+            }
+        }
+
+        files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        Ok(files)
+    }
+
+    /// SHA-256 of a remote file, streamed over SFTP
+    pub async fn file_sha256(&self, path: &str) -> Result<String, String> {
+        use sha2::{Digest, Sha256};
+        use tokio::io::AsyncReadExt;
+
+        let mut file = self
+            .connection
+            .open_file_for_read(path, 0)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = file
+                .read(&mut buffer)
+                .await
+                .map_err(|e| format!("Failed to read remote file {}: {}", path, e))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteFileEntry {
+    pub relative_path: String,
+    pub size: u64,
+    pub mtime: Option<u32>,
 }
 
 #[async_trait]
@@ -0,0 +1,515 @@
+use crate::local_store::LocalStore;
+use crate::secrets::egress::SecretProvenance;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// File name of the AEAD key, stored under `~/.stakpak`
+pub const KEY_FILE_NAME: &str = "secrets.key";
+/// File name of the encrypted redaction map inside the session directory
+pub const ENCRYPTED_MAP_FILE: &str = "secrets.enc";
+/// File name of the legacy plaintext redaction map, migrated on first read
+pub const LEGACY_MAP_FILE: &str = "secrets.json";
+
+const MAGIC: &[u8; 4] = b"SPK1";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Provenance of redaction map entries, keyed by placeholder
+pub type ProvenanceMap = HashMap<String, SecretProvenance>;
+
+#[derive(Serialize, Deserialize)]
+struct StoredMap {
+    entries: HashMap<String, String>,
+    #[serde(default)]
+    provenance: ProvenanceMap,
+}
+
+/// Maps written before provenance was tracked are a plain placeholder to value object
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredFormat {
+    WithProvenance(StoredMap),
+    Plain(HashMap<String, String>),
+}
+
+/// Encrypted at-rest storage for the session redaction map.
+///
+/// The map is serialized as JSON and sealed with AES-256-GCM. The key lives in a
+/// `0600` file outside the project directory, so a copied or committed session
+/// directory does not expose the secrets it references.
+#[derive(Debug, Clone)]
+pub struct SecretStore {
+    key_path: PathBuf,
+    session_dir: PathBuf,
+}
+
+impl SecretStore {
+    pub fn new(key_path: PathBuf, session_dir: PathBuf) -> Self {
+        Self {
+            key_path,
+            session_dir,
+        }
+    }
+
+    /// Store for the session directory of the current working directory
+    pub fn for_current_session() -> Self {
+        Self::new(
+            Self::default_key_path(),
+            LocalStore::get_local_session_store_path(),
+        )
+    }
+
+    pub fn default_key_path() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".stakpak")
+            .join(KEY_FILE_NAME)
+    }
+
+    pub fn key_path(&self) -> &Path {
+        &self.key_path
+    }
+
+    pub fn encrypted_map_path(&self) -> PathBuf {
+        self.session_dir.join(ENCRYPTED_MAP_FILE)
+    }
+
+    pub fn legacy_map_path(&self) -> PathBuf {
+        self.session_dir.join(LEGACY_MAP_FILE)
+    }
+
+    /// Load the redaction map, migrating a legacy plaintext map if one is found
+    pub fn load(&self) -> Result<HashMap<String, String>, String> {
+        self.load_with_provenance().map(|(map, _)| map)
+    }
+
+    /// Load the redaction map together with the provenance of its entries
+    pub fn load_with_provenance(&self) -> Result<(HashMap<String, String>, ProvenanceMap), String> {
+        let encrypted_path = self.encrypted_map_path();
+        if encrypted_path.exists() {
+            let data = fs::read(&encrypted_path).map_err(|e| {
+                format!(
+                    "Failed to read redaction map from {}: {}",
+                    encrypted_path.display(),
+                    e
+                )
+            })?;
+            let key = self.load_or_create_key()?;
+            let plaintext = decrypt(&key, &data)?;
+            return match serde_json::from_slice(&plaintext)
+                .map_err(|e| format!("Failed to parse redaction map: {}", e))?
+            {
+                StoredFormat::WithProvenance(stored) => Ok((stored.entries, stored.provenance)),
+                StoredFormat::Plain(map) => Ok((map, ProvenanceMap::new())),
+            };
+        }
+
+        let legacy_path = self.legacy_map_path();
+        if legacy_path.exists() {
+            let content = fs::read_to_string(&legacy_path).map_err(|e| {
+                format!(
+                    "Failed to read legacy redaction map from {}: {}",
+                    legacy_path.display(),
+                    e
+                )
+            })?;
+            let map: HashMap<String, String> = if content.trim().is_empty() {
+                HashMap::new()
+            } else {
+                serde_json::from_str(&content)
+                    .map_err(|e| format!("Failed to parse legacy redaction map: {}", e))?
+            };
+            self.save(&map)?;
+            wipe_file(&legacy_path)?;
+            return Ok((map, ProvenanceMap::new()));
+        }
+
+        Ok((HashMap::new(), ProvenanceMap::new()))
+    }
+
+    /// Encrypt and persist the redaction map
+    pub fn save(&self, map: &HashMap<String, String>) -> Result<(), String> {
+        self.save_with_provenance(map, &ProvenanceMap::new())
+    }
+
+    /// Encrypt and persist the redaction map together with the provenance of its entries
+    pub fn save_with_provenance(
+        &self,
+        map: &HashMap<String, String>,
+        provenance: &ProvenanceMap,
+    ) -> Result<(), String> {
+        let stored = StoredMap {
+            entries: map.clone(),
+            provenance: provenance.clone(),
+        };
+        let plaintext = serde_json::to_vec(&stored)
+            .map_err(|e| format!("Failed to serialize redaction map: {}", e))?;
+        let key = self.load_or_create_key()?;
+        let sealed = encrypt(&key, &plaintext)?;
+
+        fs::create_dir_all(&self.session_dir)
+            .map_err(|e| format!("Failed to create session directory: {}", e))?;
+
+        // Write to a sibling file and rename so readers never see a torn map
+        let path = self.encrypted_map_path();
+        let tmp_path =
+            self.session_dir
+                .join(format!(".{}.{}", ENCRYPTED_MAP_FILE, uuid::Uuid::new_v4()));
+        write_private_file(&tmp_path, &sealed)?;
+        fs::rename(&tmp_path, &path).map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            format!("Failed to write redaction map to {}: {}", path.display(), e)
+        })
+    }
+
+    /// Wipe the session redaction maps. With `remove_key`, the key file is deleted as
+    /// well, which renders every map ever encrypted under it unreadable.
+    ///
+    /// Returns the paths that were removed.
+    pub fn purge(&self, remove_key: bool) -> Result<Vec<PathBuf>, String> {
+        let mut removed = Vec::new();
+        let mut paths = vec![self.encrypted_map_path(), self.legacy_map_path()];
+        if remove_key {
+            paths.push(self.key_path.clone());
+        }
+
+        for path in paths {
+            if path.exists() {
+                wipe_file(&path)?;
+                removed.push(path);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    fn load_or_create_key(&self) -> Result<[u8; KEY_LEN], String> {
+        match fs::read(&self.key_path) {
+            Ok(bytes) => return parse_key(&self.key_path, &bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read secrets key from {}: {}",
+                    self.key_path.display(),
+                    e
+                ));
+            }
+        }
+
+        // A new key can't decrypt the existing map, and saving under it would silently
+        // replace the map
+        let encrypted_path = self.encrypted_map_path();
+        if encrypted_path.exists() {
+            return Err(format!(
+                "Secrets key {} is missing, so the redaction map at {} can't be decrypted. Restore the key, or run `stakpak secrets purge` to discard the map",
+                self.key_path.display(),
+                encrypted_path.display()
+            ));
+        }
+
+        if let Some(parent) = self.key_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        let key: [u8; KEY_LEN] = rand::random();
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        match options.open(&self.key_path) {
+            Ok(mut file) => {
+                file.write_all(&key).map_err(|e| {
+                    format!(
+                        "Failed to write secrets key to {}: {}",
+                        self.key_path.display(),
+                        e
+                    )
+                })?;
+                Ok(key)
+            }
+            // Another process created the key first, use theirs
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let bytes = fs::read(&self.key_path).map_err(|e| {
+                    format!(
+                        "Failed to read secrets key from {}: {}",
+                        self.key_path.display(),
+                        e
+                    )
+                })?;
+                parse_key(&self.key_path, &bytes)
+            }
+            Err(e) => Err(format!(
+                "Failed to create secrets key at {}: {}",
+                self.key_path.display(),
+                e
+            )),
+        }
+    }
+}
+
+fn parse_key(path: &Path, bytes: &[u8]) -> Result<[u8; KEY_LEN], String> {
+    bytes.try_into().map_err(|_| {
+        format!(
+            "Secrets key at {} is invalid: expected {} bytes, found {}",
+            path.display(),
+            KEY_LEN,
+            bytes.len()
+        )
+    })
+}
+
+fn encrypt(key: &[u8; KEY_LEN], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce_bytes: [u8; NONCE_LEN] = rand::random();
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce_bytes),
+            Payload {
+                msg: plaintext,
+                aad: MAGIC,
+            },
+        )
+        .map_err(|_| "Failed to encrypt redaction map".to_string())?;
+
+    let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(MAGIC);
+    sealed.extend_from_slice(&nonce_bytes);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+fn decrypt(key: &[u8; KEY_LEN], sealed: &[u8]) -> Result<Vec<u8>, String> {
+    let header_len = MAGIC.len() + NONCE_LEN;
+    if sealed.len() < header_len || &sealed[..MAGIC.len()] != MAGIC {
+        return Err("Redaction map is not in a recognized encrypted format".to_string());
+    }
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher
+        .decrypt(
+            Nonce::from_slice(&sealed[MAGIC.len()..header_len]),
+            Payload {
+                msg: &sealed[header_len..],
+                aad: MAGIC,
+            },
+        )
+        .map_err(|_| {
+            "Failed to decrypt redaction map: the secrets key does not match or the file is corrupted"
+                .to_string()
+        })
+}
+
+fn write_private_file(path: &Path, data: &[u8]) -> Result<(), String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    file.write_all(data)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Overwrite a file with zeros before unlinking it, so the old contents are not left
+/// behind in the freed blocks on filesystems that write in place
+fn wipe_file(path: &Path) -> Result<(), String> {
+    if let Ok(metadata) = fs::metadata(path)
+        && let Ok(mut file) = fs::OpenOptions::new().write(true).open(path)
+    {
+        let zeros = vec![0u8; metadata.len() as usize];
+        let _ = file.write_all(&zeros).and_then(|_| file.sync_all());
+    }
+
+    fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn store(dir: &TempDir) -> SecretStore {
+        SecretStore::new(
+            dir.path().join("home").join(KEY_FILE_NAME),
+            dir.path().join("session"),
+        )
+    }
+
+    fn sample_map() -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        map.insert(
+            "[REDACTED_SECRET:api-key:abc123]".to_string(),
+            "sk_live_supersecretvalue".to_string(),
+        );
+        map
+    }
+
+    #[test]
+    fn test_round_trip_does_not_store_plaintext() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+
+        store.save(&sample_map()).unwrap();
+
+        let raw = fs::read(store.encrypted_map_path()).unwrap();
+        assert!(raw.starts_with(MAGIC));
+        assert!(
+            !String::from_utf8_lossy(&raw).contains("sk_live_supersecretvalue"),
+            "secret value must not appear in the file"
+        );
+        assert_eq!(store.load().unwrap(), sample_map());
+    }
+
+    #[test]
+    fn test_missing_map_loads_empty() {
+        let dir = TempDir::new().unwrap();
+        assert!(store(&dir).load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_migrates_legacy_plaintext_map() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        fs::create_dir_all(dir.path().join("session")).unwrap();
+        fs::write(
+            store.legacy_map_path(),
+            serde_json::to_string_pretty(&sample_map()).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(store.load().unwrap(), sample_map());
+        assert!(!store.legacy_map_path().exists());
+        assert!(store.encrypted_map_path().exists());
+        assert_eq!(store.load().unwrap(), sample_map());
+    }
+
+    #[test]
+    fn test_round_trip_with_provenance() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        let mut provenance = ProvenanceMap::new();
+        provenance.insert(
+            "[REDACTED_SECRET:api-key:abc123]".to_string(),
+            SecretProvenance {
+                rule_id: "api-key".to_string(),
+                source: Some("/app/.env".to_string()),
+                host: None,
+            },
+        );
+
+        store
+            .save_with_provenance(&sample_map(), &provenance)
+            .unwrap();
+        assert_eq!(
+            store.load_with_provenance().unwrap(),
+            (sample_map(), provenance)
+        );
+    }
+
+    #[test]
+    fn test_loads_maps_without_provenance() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        let plaintext = serde_json::to_vec(&sample_map()).unwrap();
+        let sealed = encrypt(&store.load_or_create_key().unwrap(), &plaintext).unwrap();
+        fs::create_dir_all(dir.path().join("session")).unwrap();
+        fs::write(store.encrypted_map_path(), sealed).unwrap();
+
+        let (map, provenance) = store.load_with_provenance().unwrap();
+        assert_eq!(map, sample_map());
+        assert!(provenance.is_empty());
+    }
+
+    #[test]
+    fn test_wrong_key_fails_to_decrypt() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        store.save(&sample_map()).unwrap();
+
+        fs::write(store.key_path(), [7u8; KEY_LEN]).unwrap();
+        let err = store.load().unwrap_err();
+        assert!(err.contains("does not match"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_missing_key_with_existing_map_fails() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        store.save(&sample_map()).unwrap();
+        let sealed = fs::read(store.encrypted_map_path()).unwrap();
+
+        fs::remove_file(store.key_path()).unwrap();
+        let err = store.load().unwrap_err();
+        assert!(err.contains("is missing"), "unexpected error: {}", err);
+        assert!(store.save(&sample_map()).is_err());
+        assert!(!store.key_path().exists());
+        assert_eq!(fs::read(store.encrypted_map_path()).unwrap(), sealed);
+    }
+
+    #[test]
+    fn test_rejects_truncated_key() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        fs::create_dir_all(store.key_path().parent().unwrap()).unwrap();
+        fs::write(store.key_path(), b"short").unwrap();
+
+        let err = store.save(&sample_map()).unwrap_err();
+        assert!(
+            err.contains("expected 32 bytes"),
+            "unexpected error: {}",
+            err
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_and_map_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        store.save(&sample_map()).unwrap();
+
+        let key_mode = fs::metadata(store.key_path()).unwrap().permissions().mode();
+        let map_mode = fs::metadata(store.encrypted_map_path())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(key_mode & 0o777, 0o600);
+        assert_eq!(map_mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_purge_removes_maps_and_optionally_key() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        store.save(&sample_map()).unwrap();
+        fs::write(store.legacy_map_path(), "{}").unwrap();
+
+        let removed = store.purge(false).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert!(!store.encrypted_map_path().exists());
+        assert!(!store.legacy_map_path().exists());
+        assert!(store.key_path().exists());
+
+        let removed = store.purge(true).unwrap();
+        assert_eq!(removed, vec![store.key_path().to_path_buf()]);
+        assert!(!store.key_path().exists());
+    }
+}
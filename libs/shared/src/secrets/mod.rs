@@ -1,10 +1,20 @@
+pub mod audit;
+pub mod egress;
 pub mod gitleaks;
+pub mod pseudonym;
+pub mod references;
+pub mod scanner;
+pub mod stream;
+pub mod structured;
 use crate::helper::generate_simple_id;
 /// Re-export the gitleaks initialization function for external access
 pub use gitleaks::initialize_gitleaks_config;
 use gitleaks::{DetectedSecret, detect_secrets};
+use pseudonym::{allocate_pseudonym, is_pseudonym, is_standalone};
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fmt;
+use structured::{KUBERNETES_SECRET_RULE, detect_structured_secrets};
 
 /// A result containing both the redacted string and the mapping of redaction keys to original secrets
 #[derive(Debug, Clone)]
@@ -32,14 +42,58 @@ impl fmt::Display for RedactionResult {
 
 /// Redacts secrets from the input string and returns both the redacted string and redaction mapping
 ///
-/// When privacy_mode is enabled, also detects and redacts private data like IP addresses and AWS account IDs
+/// JSON, YAML, TOML, .env and HCL content, recognised by `path` or by its content, is also
+/// parsed so values under sensitive keys and in Kubernetes Secrets are redacted even when
+/// they look innocuous.
+///
+/// When privacy_mode is enabled, also detects private data like IP addresses, AWS account
+/// IDs and emails, and replaces it with session-wide pseudonyms such as `[PRIVATE:ip-1.2]`
 pub fn redact_secrets(
     content: &str,
     path: Option<&str>,
     old_redaction_map: &HashMap<String, String>,
     privacy_mode: bool,
 ) -> RedactionResult {
-    let mut secrets = detect_secrets(content, path, privacy_mode);
+    redact_secrets_with_context("", content, path, old_redaction_map, privacy_mode)
+}
+
+/// Redacts secrets from `content`, scanning it together with the text that preceded it
+///
+/// Used for streamed output: `context` is the tail of what was already emitted, so a
+/// keyword or prefix that arrived in an earlier chunk still leads to the value being
+/// detected. Only `content` is rewritten; a secret that started inside `context` is
+/// redacted from the beginning of `content`. Structured documents are recognised in
+/// `content` on its own.
+pub fn redact_secrets_with_context(
+    context: &str,
+    content: &str,
+    path: Option<&str>,
+    old_redaction_map: &HashMap<String, String>,
+    privacy_mode: bool,
+) -> RedactionResult {
+    let structured = detect_structured_secrets(content, path);
+    let decoded_values = structured.decoded;
+    let mut secrets = if context.is_empty() {
+        detect_secrets(content, path, privacy_mode)
+    } else {
+        let offset = context.len();
+        let haystack = format!("{context}{content}");
+        detect_secrets(&haystack, path, privacy_mode)
+            .into_iter()
+            .filter(|secret| secret.end_pos > offset)
+            .map(|secret| {
+                let start_pos = secret.start_pos.saturating_sub(offset);
+                let end_pos = secret.end_pos - offset;
+                DetectedSecret {
+                    rule_id: secret.rule_id,
+                    value: content[start_pos..end_pos].to_string(),
+                    start_pos,
+                    end_pos,
+                }
+            })
+            .collect()
+    };
+    secrets.extend(structured.secrets);
 
     let mut redaction_map = old_redaction_map.clone();
     let mut reverse_redaction_map: HashMap<String, String> = old_redaction_map
@@ -49,19 +103,32 @@ pub fn redact_secrets(
         .collect();
 
     for (original_secret, redaction_key) in &reverse_redaction_map {
-        // Extract rule_id from redaction_key format: [REDACTED_SECRET:rule_id:id]
-        let key_parts = redaction_key.split(':').collect::<Vec<&str>>();
-        if key_parts.len() == 3 {
-            let rule_id = key_parts[1].to_string();
-            if let Some(start) = content.find(original_secret) {
-                let end = start + original_secret.len();
-                secrets.push(DetectedSecret {
-                    rule_id,
-                    value: original_secret.clone(),
-                    start_pos: start,
-                    end_pos: end,
-                });
+        // Extract rule_id from redaction_key format: [REDACTED_SECRET:rule_id:id], or the
+        // provider of a secret reference: {{secret:provider:name}}. Pseudonyms have no
+        // rule id; it is only needed for new entries anyway.
+        let pseudonymized = is_pseudonym(redaction_key);
+        let rule_id = if pseudonymized {
+            redaction_key.as_str()
+        } else {
+            let Some(rule_id) = redaction_key.split(':').nth(1) else {
+                continue;
+            };
+            rule_id
+        };
+        if original_secret.is_empty() {
+            continue;
+        }
+        for (start, _) in content.match_indices(original_secret.as_str()) {
+            // Private data such as an IP address may be part of a longer one
+            if pseudonymized && !is_standalone(content, start, start + original_secret.len()) {
+                continue;
             }
+            secrets.push(DetectedSecret {
+                rule_id: rule_id.to_string(),
+                value: original_secret.clone(),
+                start_pos: start,
+                end_pos: start + original_secret.len(),
+            });
         }
     }
 
@@ -69,45 +136,33 @@ pub fn redact_secrets(
         return RedactionResult::new(content.to_string(), HashMap::new());
     }
 
-    let mut redacted_string = content.to_string();
-
-    // Deduplicate overlapping secrets - keep the longest one
-    let mut deduplicated_secrets: Vec<DetectedSecret> = Vec::new();
-    let mut sorted_by_start = secrets;
-    sorted_by_start.sort_by(|a, b| a.start_pos.cmp(&b.start_pos));
-
-    for secret in sorted_by_start {
-        let mut should_add = true;
-        let mut to_remove = Vec::new();
-
-        for (i, existing) in deduplicated_secrets.iter().enumerate() {
-            // Check if secrets overlap
-            let overlaps =
-                secret.start_pos < existing.end_pos && secret.end_pos > existing.start_pos;
-
-            if overlaps {
-                // Keep the longer secret (more specific)
-                if secret.value.len() > existing.value.len() {
-                    to_remove.push(i);
-                } else {
-                    should_add = false;
-                    break;
-                }
-            }
-        }
-
-        // Remove secrets that should be replaced by this longer one
-        for &i in to_remove.iter().rev() {
-            deduplicated_secrets.remove(i);
+    // Remember decoded Kubernetes Secret values so their plaintext is redacted later on
+    for value in decoded_values {
+        if let Entry::Vacant(entry) = reverse_redaction_map.entry(value) {
+            let key = generate_redaction_key(KUBERNETES_SECRET_RULE);
+            redaction_map.insert(key.clone(), entry.key().clone());
+            entry.insert(key);
         }
+    }
 
-        if should_add {
-            deduplicated_secrets.push(secret);
+    let mut redacted_string = content.to_string();
+    let mut deduplicated_secrets = deduplicate_secrets(secrets);
+
+    // Number pseudonyms of private data in order of appearance
+    if privacy_mode {
+        for secret in &deduplicated_secrets {
+            if let Entry::Vacant(entry) = reverse_redaction_map.entry(secret.value.clone())
+                && let Some(pseudonym) =
+                    allocate_pseudonym(&secret.rule_id, &secret.value, &redaction_map)
+            {
+                redaction_map.insert(pseudonym.clone(), secret.value.clone());
+                entry.insert(pseudonym);
+            }
         }
     }
 
     // Sort by position in reverse order to avoid index shifting issues
-    deduplicated_secrets.sort_by(|a, b| b.start_pos.cmp(&a.start_pos));
+    deduplicated_secrets.sort_by_key(|s| std::cmp::Reverse(s.start_pos));
 
     for secret in deduplicated_secrets {
         // Validate character boundaries before replacement
@@ -140,6 +195,55 @@ pub fn redact_secrets(
     RedactionResult::new(redacted_string, redaction_map)
 }
 
+/// Finds the secrets that redaction would replace in `content`, ordered by position
+///
+/// Runs the same detection as [`redact_secrets`] on its own, so scans report exactly
+/// what the agent would redact.
+pub fn find_secrets(content: &str, path: Option<&str>, privacy_mode: bool) -> Vec<DetectedSecret> {
+    let mut secrets = detect_secrets(content, path, privacy_mode);
+    secrets.extend(detect_structured_secrets(content, path).secrets);
+    deduplicate_secrets(secrets)
+}
+
+/// Drops overlapping secrets, keeping the longest one, and sorts the rest by position
+fn deduplicate_secrets(secrets: Vec<DetectedSecret>) -> Vec<DetectedSecret> {
+    let mut deduplicated_secrets: Vec<DetectedSecret> = Vec::new();
+    let mut sorted_by_start = secrets;
+    sorted_by_start.sort_by_key(|a| a.start_pos);
+
+    for secret in sorted_by_start {
+        let mut should_add = true;
+        let mut to_remove = Vec::new();
+
+        for (i, existing) in deduplicated_secrets.iter().enumerate() {
+            // Check if secrets overlap
+            let overlaps =
+                secret.start_pos < existing.end_pos && secret.end_pos > existing.start_pos;
+
+            if overlaps {
+                // Keep the longer secret (more specific)
+                if secret.value.len() > existing.value.len() {
+                    to_remove.push(i);
+                } else {
+                    should_add = false;
+                    break;
+                }
+            }
+        }
+
+        // Remove secrets that should be replaced by this longer one
+        for &i in to_remove.iter().rev() {
+            deduplicated_secrets.remove(i);
+        }
+
+        if should_add {
+            deduplicated_secrets.push(secret);
+        }
+    }
+
+    deduplicated_secrets
+}
+
 /// Restores secrets in a redacted string using the provided redaction map
 pub fn restore_secrets(redacted_string: &str, redaction_map: &HashMap<String, String>) -> String {
     let mut restored = redacted_string.to_string();
@@ -253,7 +357,7 @@ mod tests {
         let result = redact_secrets(input, None, &HashMap::new(), false);
 
         // Should detect the API key and redact it
-        assert!(result.redaction_map.len() > 0);
+        assert!(!result.redaction_map.is_empty());
         assert!(result.redacted_string.contains("[REDACTED_"));
         println!("Input: {}", input);
         println!("Redacted: {}", result.redacted_string);
@@ -266,7 +370,7 @@ mod tests {
         let result = redact_secrets(input, None, &HashMap::new(), false);
 
         // Should detect the AWS access key
-        assert!(result.redaction_map.len() > 0);
+        assert!(!result.redaction_map.is_empty());
         println!("Input: {}", input);
         println!("Redacted: {}", result.redacted_string);
         println!("Mapping: {:?}", result.redaction_map);
@@ -303,7 +407,7 @@ mod tests {
         let result = redact_secrets(input, None, &HashMap::new(), false);
 
         // Should detect the GitHub PAT
-        assert!(result.redaction_map.len() > 0);
+        assert!(!result.redaction_map.is_empty());
         println!("Input: {}", input);
         println!("Redacted: {}", result.redacted_string);
         println!("Mapping: {:?}", result.redaction_map);
@@ -373,7 +477,7 @@ mod tests {
                 println!("\nTesting input: {}", input);
                 let result = redact_secrets(input, None, &HashMap::new(), false);
                 println!("  Detected secrets: {}", result.redaction_map.len());
-                if result.redaction_map.len() > 0 {
+                if !result.redaction_map.is_empty() {
                     println!("  Redacted: {}", result.redacted_string);
                 }
             }
@@ -584,7 +688,7 @@ mod tests {
                 "  Full function detected: {} secrets",
                 result.redaction_map.len()
             );
-            if result.redaction_map.len() > 0 {
+            if !result.redaction_map.is_empty() {
                 println!("  Redacted result: {}", result.redacted_string);
             }
         }
@@ -879,9 +983,7 @@ export PORT=3000
         let mut rules_processed = 0;
 
         for rule in &config.rules {
-            if rule.keywords.is_empty() {
-                rules_processed += 1;
-            } else if contains_any_keyword(non_secret_input, &rule.keywords) {
+            if rule.keywords.is_empty() || contains_any_keyword(non_secret_input, &rule.keywords) {
                 rules_processed += 1;
             } else {
                 rules_skipped += 1;
@@ -930,7 +1032,7 @@ export PORT=3000
             "Should find matching rules for secret input"
         );
         assert!(
-            result.redaction_map.len() >= 1,
+            !result.redaction_map.is_empty(),
             "Should detect at least one secret"
         );
     }
@@ -979,7 +1081,7 @@ export PORT=3000
 
         let api_secrets = detect_secrets(api_input, None, false);
         println!("Secrets detected: {} (expected: 1)", api_secrets.len());
-        assert!(api_secrets.len() >= 1, "Should detect at least 1 secrets");
+        assert!(!api_secrets.is_empty(), "Should detect at least 1 secrets");
         println!("✅ Test passed");
 
         // Test AWS keyword - should process aws-access-token rule
@@ -1000,7 +1102,7 @@ export PORT=3000
         println!("Secrets detected: {} (expected: 1)", aws_secrets.len());
 
         // Should detect AWS key
-        assert!(aws_secrets.len() >= 1, "Should detect at least 1 secrets");
+        assert!(!aws_secrets.is_empty(), "Should detect at least 1 secrets");
         println!("✅ Test passed");
     }
 
@@ -1114,13 +1216,10 @@ export PORT=3000
                         // Test global regex patterns
                         if let Some(regexes) = &global_allowlist.regexes {
                             for (i, pattern) in regexes.iter().enumerate() {
-                                if let Ok(regex) = Regex::new(pattern) {
-                                    if regex.is_match(match_text) {
-                                        println!(
-                                            "    ✗ FILTERED by global regex {}: '{}'",
-                                            i, pattern
-                                        );
-                                    }
+                                if let Ok(regex) = Regex::new(pattern)
+                                    && regex.is_match(match_text)
+                                {
+                                    println!("    ✗ FILTERED by global regex {}: '{}'", i, pattern);
                                 }
                             }
                         }
@@ -1143,13 +1242,13 @@ export PORT=3000
                             // Test rule regex patterns
                             if let Some(regexes) = &allowlist.regexes {
                                 for (i, pattern) in regexes.iter().enumerate() {
-                                    if let Ok(regex) = Regex::new(pattern) {
-                                        if regex.is_match(match_text) {
-                                            println!(
-                                                "    ✗ FILTERED by rule regex {}: '{}'",
-                                                i, pattern
-                                            );
-                                        }
+                                    if let Ok(regex) = Regex::new(pattern)
+                                        && regex.is_match(match_text)
+                                    {
+                                        println!(
+                                            "    ✗ FILTERED by rule regex {}: '{}'",
+                                            i, pattern
+                                        );
                                     }
                                 }
                             }
@@ -1430,4 +1529,90 @@ export PORT=3000
             "mysecretvalue123"
         );
     }
+
+    #[test]
+    fn test_privacy_mode_pseudonymizes_consistently() {
+        let content = "Connection from 203.0.113.7 and 203.0.113.42, later 198.51.100.20. Again 203.0.113.7\n\
+                       user jane.doe@acme.io assumed arn:aws:iam::210987654321:role/deployer\n\
+                       kubectl -n payments get pods\n\
+                       psql -h db-1.prod.corp -p 5432";
+
+        let result = redact_secrets(content, None, &HashMap::new(), true);
+        assert_eq!(
+            result.redacted_string,
+            "Connection from [PRIVATE:ip-1.1] and [PRIVATE:ip-1.2], later [PRIVATE:ip-2.1]. \
+             Again [PRIVATE:ip-1.1]\n\
+             user [PRIVATE:email-1] assumed arn:aws:iam::[PRIVATE:acct-1]:role/[PRIVATE:res-1]\n\
+             kubectl -n [PRIVATE:ns-1] get pods\n\
+             psql -h [PRIVATE:host-1] -p 5432"
+        );
+        assert_eq!(
+            restore_secrets(&result.redacted_string, &result.redaction_map),
+            content
+        );
+
+        // Later output reuses the session's pseudonyms and numbers new values after them
+        let later = redact_secrets(
+            "payments pod on 203.0.113.9, peer 203.0.113.7:443",
+            None,
+            &result.redaction_map,
+            true,
+        );
+        assert_eq!(
+            later.redacted_string,
+            "[PRIVATE:ns-1] pod on [PRIVATE:ip-1.3], peer [PRIVATE:ip-1.1]:443"
+        );
+
+        // Without privacy mode nothing of this is redacted
+        let plain = redact_secrets(content, None, &HashMap::new(), false);
+        assert_eq!(plain.redacted_string, content);
+    }
+
+    #[test]
+    fn test_redact_structured_documents_stay_valid() {
+        let env = "DB_HOST=localhost\nDB_PASSWORD=summer2024\n";
+        let result = redact_secrets(env, Some(".env"), &HashMap::new(), false);
+        assert!(!result.redacted_string.contains("summer2024"));
+        assert!(result.redacted_string.contains("DB_HOST=localhost"));
+        assert_eq!(
+            restore_secrets(&result.redacted_string, &result.redaction_map),
+            env
+        );
+
+        let json = r#"{"database": {"user": "app", "password": "summer2024"}}"#;
+        let result = redact_secrets(json, None, &HashMap::new(), false);
+        assert!(!result.redacted_string.contains("summer2024"));
+        assert!(serde_json::from_str::<serde_json::Value>(&result.redacted_string).is_ok());
+
+        let secret = "apiVersion: v1\nkind: Secret\nmetadata:\n  name: db\ndata:\n  password: c3VtbWVyMjAyNC1sb25n\n";
+        let result = redact_secrets(secret, Some("secret.yaml"), &HashMap::new(), false);
+        assert!(!result.redacted_string.contains("c3VtbWVyMjAyNC1sb25n"));
+        assert!(serde_yaml::from_str::<serde_yaml::Value>(&result.redacted_string).is_ok());
+        assert_eq!(
+            restore_secrets(&result.redacted_string, &result.redaction_map),
+            secret
+        );
+
+        // The decoded value is remembered, so the plaintext is redacted later on
+        let later = redact_secrets(
+            "password is summer2024-long",
+            None,
+            &result.redaction_map,
+            false,
+        );
+        assert!(!later.redacted_string.contains("summer2024-long"));
+    }
+
+    #[test]
+    fn test_redact_structured_content_with_context() {
+        let result = redact_secrets_with_context(
+            "Loading environment\n",
+            "DB_HOST=localhost\nDB_PASSWORD=summer2024\n",
+            None,
+            &HashMap::new(),
+            false,
+        );
+        assert!(!result.redacted_string.contains("summer2024"));
+        assert!(result.redacted_string.starts_with("DB_HOST=localhost\n"));
+    }
 }
@@ -0,0 +1,227 @@
+// Deterministic pseudonyms for private data in privacy mode
+//
+// Unlike secrets, private data such as IP addresses carries meaning the agent needs: two
+// log lines from the same host, or two hosts in the same subnet. Privacy mode therefore
+// replaces it with short names numbered per session, marked like `[PRIVATE:ip-2.1]` or
+// `[PRIVATE:acct-3]` so they can't be mistaken for real names, that are stored in the
+// redaction map and restored on tool input like any placeholder.
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+pub const PUBLIC_IPV4_RULE: &str = "public-ipv4";
+pub const PUBLIC_IPV6_RULE: &str = "public-ipv6";
+pub const AWS_ACCOUNT_ID_RULE: &str = "aws-account-id";
+pub const AWS_ARN_RESOURCE_RULE: &str = "aws-arn-resource";
+pub const EMAIL_RULE: &str = "email-address";
+pub const INTERNAL_HOSTNAME_RULE: &str = "internal-hostname";
+pub const KUBERNETES_NAMESPACE_RULE: &str = "kubernetes-namespace";
+
+/// Pseudonym prefix of each privacy rule. IP pseudonyms are `<network>.<host>` pairs,
+/// numbered per /24 (IPv4) or /64 (IPv6) network, so addresses that share a subnet
+/// share the network number without revealing the network itself.
+const PREFIXES: &[(&str, &str)] = &[
+    (PUBLIC_IPV4_RULE, "ip"),
+    (PUBLIC_IPV6_RULE, "ip6"),
+    (AWS_ACCOUNT_ID_RULE, "acct"),
+    (AWS_ARN_RESOURCE_RULE, "res"),
+    (EMAIL_RULE, "email"),
+    (INTERNAL_HOSTNAME_RULE, "host"),
+    (KUBERNETES_NAMESPACE_RULE, "ns"),
+];
+
+const MARKER: &str = "[PRIVATE:";
+
+static PSEUDONYM: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\[PRIVATE:(ip6?|acct|res|email|host|ns)-\d+(?:\.\d+)?\]$")
+        .expect("valid pseudonym regex")
+});
+
+/// Whether `key` of a redaction map is a pseudonym rather than a `[REDACTED_SECRET:...]`
+/// placeholder or a secret reference
+pub fn is_pseudonym(key: &str) -> bool {
+    PSEUDONYM.is_match(key)
+}
+
+/// Rule that produced the pseudonym `key`
+pub fn pseudonym_rule(key: &str) -> Option<&'static str> {
+    let prefix = PSEUDONYM.captures(key)?.get(1)?.as_str();
+    PREFIXES
+        .iter()
+        .find(|(_, candidate)| *candidate == prefix)
+        .map(|(rule, _)| *rule)
+}
+
+/// The next pseudonym for `value` found by `rule_id`, numbered after those already in
+/// `redaction_map`, or `None` for rules that are not pseudonymized
+pub fn allocate_pseudonym(
+    rule_id: &str,
+    value: &str,
+    redaction_map: &HashMap<String, String>,
+) -> Option<String> {
+    let prefix = PREFIXES
+        .iter()
+        .find(|(rule, _)| *rule == rule_id)
+        .map(|(_, prefix)| *prefix)?;
+
+    let network = match rule_id {
+        PUBLIC_IPV4_RULE => ipv4_network(value),
+        PUBLIC_IPV6_RULE => ipv6_network(value),
+        _ => {
+            let next = numbered(prefix, redaction_map)
+                .map(|(number, _)| number.0)
+                .max()
+                .unwrap_or(0)
+                + 1;
+            return Some(format!("{}{}-{}]", MARKER, prefix, next));
+        }
+    };
+    let network_of = |value: &str| match rule_id {
+        PUBLIC_IPV4_RULE => ipv4_network(value),
+        _ => ipv6_network(value),
+    };
+
+    let mut last_network = 0;
+    let mut same_network = None;
+    let mut last_host = 0;
+    for ((network_number, host_number), original) in numbered(prefix, redaction_map) {
+        last_network = last_network.max(network_number);
+        if network.is_some() && network_of(original) == network {
+            same_network = Some(network_number);
+            last_host = last_host.max(host_number.unwrap_or(0));
+        }
+    }
+    Some(match same_network {
+        Some(network_number) => {
+            format!("{}{}-{}.{}]", MARKER, prefix, network_number, last_host + 1)
+        }
+        None => format!("{}{}-{}.1]", MARKER, prefix, last_network + 1),
+    })
+}
+
+/// Numbers of the pseudonyms with `prefix` in `redaction_map`, with their values
+fn numbered<'a>(
+    prefix: &'a str,
+    redaction_map: &'a HashMap<String, String>,
+) -> impl Iterator<Item = ((u64, Option<u64>), &'a str)> + 'a {
+    redaction_map.iter().filter_map(move |(key, value)| {
+        let number = key
+            .strip_prefix(MARKER)?
+            .strip_suffix(']')?
+            .strip_prefix(prefix)?
+            .strip_prefix('-')?;
+        let (first, second) = match number.split_once('.') {
+            Some((first, second)) => (first, Some(second.parse().ok()?)),
+            None => (number, None),
+        };
+        Some(((first.parse().ok()?, second), value.as_str()))
+    })
+}
+
+fn ipv4_network(value: &str) -> Option<Vec<u16>> {
+    let octets = value.parse::<Ipv4Addr>().ok()?.octets();
+    Some(octets[..3].iter().map(|octet| u16::from(*octet)).collect())
+}
+
+fn ipv6_network(value: &str) -> Option<Vec<u16>> {
+    Some(value.parse::<Ipv6Addr>().ok()?.segments()[..4].to_vec())
+}
+
+/// Whether `text[start..end]` is a whole token rather than part of a longer name,
+/// address or number. A trailing `.` is allowed when it ends a sentence.
+pub fn is_standalone(text: &str, start: usize, end: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let before = text[..start].chars().next_back();
+    let mut after = text[end..].chars();
+    if before.is_some_and(|c| is_word(c) || c == '.') {
+        return false;
+    }
+    match after.next() {
+        Some('.') => !after.next().is_some_and(is_word),
+        Some(c) => !is_word(c),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::restore_secrets;
+
+    fn allocate_all(values: &[(&str, &str)]) -> (Vec<String>, HashMap<String, String>) {
+        let mut map = HashMap::new();
+        let mut pseudonyms = Vec::new();
+        for (rule_id, value) in values {
+            let pseudonym = allocate_pseudonym(rule_id, value, &map).unwrap();
+            map.insert(pseudonym.clone(), value.to_string());
+            pseudonyms.push(pseudonym);
+        }
+        (pseudonyms, map)
+    }
+
+    #[test]
+    fn test_ip_pseudonyms_keep_subnets() {
+        let (pseudonyms, _) = allocate_all(&[
+            (PUBLIC_IPV4_RULE, "203.0.113.7"),
+            (PUBLIC_IPV4_RULE, "198.51.100.20"),
+            (PUBLIC_IPV4_RULE, "203.0.113.42"),
+            (PUBLIC_IPV6_RULE, "2001:db8:1:2::10"),
+            (PUBLIC_IPV6_RULE, "2001:db8:1:2::20"),
+        ]);
+        assert_eq!(
+            pseudonyms,
+            [
+                "[PRIVATE:ip-1.1]",
+                "[PRIVATE:ip-2.1]",
+                "[PRIVATE:ip-1.2]",
+                "[PRIVATE:ip6-1.1]",
+                "[PRIVATE:ip6-1.2]"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pseudonyms_are_numbered_per_kind() {
+        let (pseudonyms, map) = allocate_all(&[
+            (AWS_ACCOUNT_ID_RULE, "210987654321"),
+            (EMAIL_RULE, "jane@corp.example"),
+            (AWS_ACCOUNT_ID_RULE, "345678901234"),
+            (KUBERNETES_NAMESPACE_RULE, "payments"),
+        ]);
+        assert_eq!(
+            pseudonyms,
+            [
+                "[PRIVATE:acct-1]",
+                "[PRIVATE:email-1]",
+                "[PRIVATE:acct-2]",
+                "[PRIVATE:ns-1]"
+            ]
+        );
+        assert!(allocate_pseudonym("github-pat", "ghp_x", &map).is_none());
+        assert!(pseudonyms.iter().all(|p| is_pseudonym(p)));
+        assert_eq!(
+            pseudonym_rule("[PRIVATE:acct-2]"),
+            Some(AWS_ACCOUNT_ID_RULE)
+        );
+        assert!(!is_pseudonym("acct-2"));
+        assert!(!is_pseudonym("[REDACTED_SECRET:github-pat:abc123]"));
+    }
+
+    #[test]
+    fn test_restore_only_marked_pseudonyms() {
+        let map = HashMap::from([
+            ("[PRIVATE:ip-1.1]".to_string(), "203.0.113.7".to_string()),
+            ("[PRIVATE:host-1]".to_string(), "db-1.prod.corp".to_string()),
+        ]);
+        assert_eq!(
+            restore_secrets("ssh [PRIVATE:host-1] && ping [PRIVATE:ip-1.1].", &map),
+            "ssh db-1.prod.corp && ping 203.0.113.7."
+        );
+        // Real names that look like pseudonyms are left alone
+        assert_eq!(
+            restore_secrets("ssh host-1 && ping ip-1.1 [PRIVATE:ip-1.10]", &map),
+            "ssh host-1 && ping ip-1.1 [PRIVATE:ip-1.10]"
+        );
+    }
+}
@@ -0,0 +1,311 @@
+// Secret references like `{{secret:env:NAME}}` that are resolved from providers at execution time
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a `cmd` reference may take before it is killed
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+static SECRET_REFERENCE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\{\{secret:([A-Za-z0-9_-]+):(.+?)\}\}").expect("valid secret reference regex")
+});
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretReferenceError {
+    #[error("No secret provider for '{0}'")]
+    UnknownProvider(String),
+    #[error("Secret '{0}' not found")]
+    NotFound(String),
+    #[error("Failed to resolve secret '{name}': {message}")]
+    Resolution { name: String, message: String },
+}
+
+/// A `{{secret:<provider>:<name>}}` reference found in a string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretReference {
+    /// The full reference text, used as its placeholder in the redaction map
+    pub placeholder: String,
+    pub provider: String,
+    pub name: String,
+}
+
+/// Finds all secret references in `input`, in order of appearance
+pub fn find_secret_references(input: &str) -> Vec<SecretReference> {
+    SECRET_REFERENCE
+        .captures_iter(input)
+        .map(|captures| SecretReference {
+            placeholder: captures[0].to_string(),
+            provider: captures[1].to_string(),
+            name: captures[2].to_string(),
+        })
+        .collect()
+}
+
+/// Source of secret values, selected by the provider part of a reference.
+///
+/// Resolution is synchronous and only happens right before a tool runs, so providers
+/// backed by a CLI (`op read`, `vault kv get`) can shell out directly.
+pub trait SecretProvider: Send + Sync {
+    /// Provider name used in references, e.g. `env` for `{{secret:env:NAME}}`
+    fn name(&self) -> &str;
+
+    fn resolve(&self, name: &str) -> Result<String, SecretReferenceError>;
+}
+
+/// Reads environment variables of the current process
+pub struct EnvSecretProvider;
+
+impl SecretProvider for EnvSecretProvider {
+    fn name(&self) -> &str {
+        "env"
+    }
+
+    fn resolve(&self, name: &str) -> Result<String, SecretReferenceError> {
+        std::env::var(name).map_err(|_| SecretReferenceError::NotFound(name.to_string()))
+    }
+}
+
+/// Reads a file, expanding a leading `~/`, without its trailing newline
+pub struct FileSecretProvider;
+
+impl SecretProvider for FileSecretProvider {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn resolve(&self, name: &str) -> Result<String, SecretReferenceError> {
+        let path = match (name.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => name.into(),
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Ok(trim_line_ending(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(SecretReferenceError::NotFound(name.to_string()))
+            }
+            Err(e) => Err(SecretReferenceError::Resolution {
+                name: name.to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+}
+
+/// Runs a shell command and uses its standard output, e.g. `pass show db/prod`
+pub struct CommandSecretProvider;
+
+impl SecretProvider for CommandSecretProvider {
+    fn name(&self) -> &str {
+        "cmd"
+    }
+
+    fn resolve(&self, name: &str) -> Result<String, SecretReferenceError> {
+        let failed = |message: String| SecretReferenceError::Resolution {
+            name: name.to_string(),
+            message,
+        };
+
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(name)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| failed(e.to_string()))?;
+
+        // Read while waiting so a large output cannot fill the pipe and stall the child
+        let reader = child.stdout.take().map(|mut stdout| {
+            std::thread::spawn(move || {
+                let mut output = String::new();
+                stdout.read_to_string(&mut output).map(|_| output)
+            })
+        });
+
+        let started = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if started.elapsed() >= COMMAND_TIMEOUT => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(failed(format!(
+                        "timed out after {}s",
+                        COMMAND_TIMEOUT.as_secs()
+                    )));
+                }
+                Ok(None) => std::thread::sleep(COMMAND_POLL_INTERVAL),
+                Err(e) => return Err(failed(e.to_string())),
+            }
+        };
+        if !status.success() {
+            return Err(failed(format!("command exited with {}", status)));
+        }
+
+        let output = match reader.map(|reader| reader.join()) {
+            Some(Ok(Ok(output))) => output,
+            Some(Ok(Err(e))) => return Err(failed(e.to_string())),
+            Some(Err(_)) => return Err(failed("failed to read command output".to_string())),
+            None => String::new(),
+        };
+        Ok(trim_line_ending(output))
+    }
+}
+
+/// Fixed values for tests and for embedding callers that already hold the secrets
+#[derive(Default)]
+pub struct StaticSecretProvider {
+    name: String,
+    values: HashMap<String, String>,
+}
+
+impl StaticSecretProvider {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn with_secret(mut self, name: &str, value: &str) -> Self {
+        self.values.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+impl SecretProvider for StaticSecretProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn resolve(&self, name: &str) -> Result<String, SecretReferenceError> {
+        self.values
+            .get(name)
+            .cloned()
+            .ok_or_else(|| SecretReferenceError::NotFound(name.to_string()))
+    }
+}
+
+/// Providers available for resolving references, keyed by name
+#[derive(Clone)]
+pub struct SecretProviders {
+    providers: HashMap<String, Arc<dyn SecretProvider>>,
+}
+
+impl Default for SecretProviders {
+    /// The `env`, `file` and `cmd` providers
+    fn default() -> Self {
+        Self::empty()
+            .with_provider(EnvSecretProvider)
+            .with_provider(FileSecretProvider)
+            .with_provider(CommandSecretProvider)
+    }
+}
+
+impl SecretProviders {
+    pub fn empty() -> Self {
+        Self {
+            providers: HashMap::new(),
+        }
+    }
+
+    /// Add a provider, replacing any existing one with the same name
+    pub fn with_provider(mut self, provider: impl SecretProvider + 'static) -> Self {
+        self.providers
+            .insert(provider.name().to_string(), Arc::new(provider));
+        self
+    }
+
+    pub fn resolve(&self, reference: &SecretReference) -> Result<String, SecretReferenceError> {
+        let provider = self
+            .providers
+            .get(&reference.provider)
+            .ok_or_else(|| SecretReferenceError::UnknownProvider(reference.provider.clone()))?;
+        let value = provider.resolve(&reference.name)?;
+        if value.is_empty() {
+            return Err(SecretReferenceError::NotFound(reference.name.clone()));
+        }
+        Ok(value)
+    }
+}
+
+fn trim_line_ending(mut value: String) -> String {
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn reference(input: &str) -> SecretReference {
+        find_secret_references(input).remove(0)
+    }
+
+    #[test]
+    fn test_find_secret_references() {
+        let references = find_secret_references(
+            "psql -p {{secret:env:PROD_DB_PASS}} && {{secret:cmd:pass show db/prod}}",
+        );
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[0].placeholder, "{{secret:env:PROD_DB_PASS}}");
+        assert_eq!(references[0].provider, "env");
+        assert_eq!(references[0].name, "PROD_DB_PASS");
+        assert_eq!(references[1].provider, "cmd");
+        assert_eq!(references[1].name, "pass show db/prod");
+    }
+
+    #[test]
+    fn test_file_and_command_providers() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("token");
+        std::fs::write(&path, "file-secret\n").unwrap();
+
+        let providers = SecretProviders::default();
+        let file_reference = reference(&format!("{{{{secret:file:{}}}}}", path.display()));
+        assert_eq!(providers.resolve(&file_reference).unwrap(), "file-secret");
+        assert_eq!(
+            providers
+                .resolve(&reference("{{secret:cmd:printf 'cmd-secret\\n'}}"))
+                .unwrap(),
+            "cmd-secret"
+        );
+        assert!(
+            providers
+                .resolve(&reference("{{secret:cmd:exit 3}}"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_unknown_and_missing_secrets() {
+        let providers = SecretProviders::empty()
+            .with_provider(StaticSecretProvider::new("vault").with_secret("db/prod", "s3cret"));
+
+        assert_eq!(
+            providers
+                .resolve(&reference("{{secret:vault:db/prod}}"))
+                .unwrap(),
+            "s3cret"
+        );
+        assert!(matches!(
+            providers.resolve(&reference("{{secret:vault:db/dev}}")),
+            Err(SecretReferenceError::NotFound(_))
+        ));
+        assert!(matches!(
+            providers.resolve(&reference("{{secret:env:HOME}}")),
+            Err(SecretReferenceError::UnknownProvider(_))
+        ));
+    }
+}
@@ -0,0 +1,852 @@
+// Format-aware detection for secrets that regex rules cannot recognise by their value
+use super::gitleaks::DetectedSecret;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::ops::Range;
+use std::path::Path;
+
+/// Rule id for values stored under a sensitive key name
+pub const SENSITIVE_KEY_RULE: &str = "sensitive-key";
+/// Rule id for values in the `data`, `stringData` and `binaryData` of a Kubernetes Secret
+pub const KUBERNETES_SECRET_RULE: &str = "kubernetes-secret";
+
+/// Larger inputs are left to the regex rules
+const MAX_STRUCTURED_BYTES: usize = 4 * 1024 * 1024;
+/// Decoded Kubernetes values shorter than this, such as user names, are not added to
+/// the redaction map, since they would then be redacted wherever they appear
+const MIN_DECODED_LENGTH: usize = 8;
+
+/// Key names are lowercased and stripped of separators before being matched against
+/// these suffixes, so `DB_PASSWORD`, `db-password` and `dbPassword` all match while
+/// `password_file` and `secretName` do not
+const SENSITIVE_KEY_SUFFIXES: &[&str] = &[
+    "password",
+    "passwd",
+    "passphrase",
+    "secret",
+    "token",
+    "apikey",
+    "accesskey",
+    "secretkey",
+    "privatekey",
+    "signingkey",
+    "encryptionkey",
+    "masterkey",
+    "credential",
+    "credentials",
+    "connectionstring",
+    "dsn",
+];
+
+const KUBERNETES_DATA_KEYS: &[&str] = &["data", "stringData", "binaryData"];
+
+/// Line prefix added by the read tool when it shows a file with line numbers
+static NUMBERED_PREFIX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^ *\d+: ").expect("valid numbered line regex"));
+
+static ENV_LINE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s*(?:export\s+)?([A-Za-z_][A-Za-z0-9_.-]*)\s*=\s*")
+        .expect("valid env assignment regex")
+});
+
+static YAML_KEY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^("(?:[^"\\]|\\.)*"|'[^']*'|[^\s#'"{\[\]},&*!|>%@`-][^:#]*?|-[^\s:#][^:#]*?)\s*:(?:\s+|$)"#)
+        .expect("valid YAML key regex")
+});
+
+static TOML_KEY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^\s*("(?:[^"\\]|\\.)*"|'[^']*'|[A-Za-z0-9_.-]+)\s*=\s*"#)
+        .expect("valid TOML key regex")
+});
+
+static HCL_BLOCK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^\s*([A-Za-z_][A-Za-z0-9_-]*)((?:\s+"[^"]*")*)\s*\{\s*$"#)
+        .expect("valid HCL block regex")
+});
+
+static KUBERNETES_MANIFEST: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?m)^(?:- )?apiVersion:\s*\S+\s*$").expect("valid manifest regex"));
+
+/// Configuration and data formats understood by the structured scanners
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Json,
+    Yaml,
+    Toml,
+    Env,
+    Hcl,
+}
+
+/// Secrets found by parsing the input as a structured document
+#[derive(Debug, Default)]
+pub struct StructuredSecrets {
+    /// Values to redact, positioned in the scanned input
+    pub secrets: Vec<DetectedSecret>,
+    /// Decoded Kubernetes Secret `data` values. They do not occur in the input, but are
+    /// added to the redaction map so the plaintext is redacted when it shows up later
+    pub decoded: Vec<String>,
+}
+
+/// Detects the format of `content` from its path, falling back to its content
+pub fn detect_format(content: &str, path: Option<&str>) -> Option<FileFormat> {
+    path.and_then(format_from_path)
+        .or_else(|| format_from_content(content))
+}
+
+fn format_from_path(path: &str) -> Option<FileFormat> {
+    let path = Path::new(path);
+    let file_name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if file_name == ".env" || file_name.starts_with(".env.") || file_name.ends_with(".env") {
+        return Some(FileFormat::Env);
+    }
+
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "json" => Some(FileFormat::Json),
+        "yaml" | "yml" => Some(FileFormat::Yaml),
+        "toml" => Some(FileFormat::Toml),
+        "tf" | "tfvars" | "hcl" => Some(FileFormat::Hcl),
+        "properties" => Some(FileFormat::Env),
+        _ => None,
+    }
+}
+
+/// Only formats that are unlikely to be confused with ordinary command output are
+/// recognised from content: JSON documents, Kubernetes manifests and `KEY=value` lists
+fn format_from_content(content: &str) -> Option<FileFormat> {
+    let trimmed = content.trim_start();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(content).is_ok()
+    {
+        return Some(FileFormat::Json);
+    }
+
+    if KUBERNETES_MANIFEST.is_match(content) && content.contains("kind:") {
+        return Some(FileFormat::Yaml);
+    }
+
+    let mut assignments = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .peekable();
+    if assignments.peek().is_some() && assignments.all(|line| ENV_LINE.is_match(line)) {
+        return Some(FileFormat::Env);
+    }
+
+    None
+}
+
+/// Parses `content` according to its detected format and returns the values stored under
+/// sensitive keys and in Kubernetes Secrets.
+///
+/// The scanners are line oriented and only report the span of each scalar value, so
+/// replacing the spans keeps the document structure intact. Lines numbered by the read
+/// tool are handled as if the numbers were not there.
+pub fn detect_structured_secrets(content: &str, path: Option<&str>) -> StructuredSecrets {
+    if content.len() > MAX_STRUCTURED_BYTES {
+        return StructuredSecrets::default();
+    }
+    let Some(format) = detect_format(content, path) else {
+        return StructuredSecrets::default();
+    };
+
+    let lines = split_lines(content);
+    let mut document = Document::default();
+    match format {
+        FileFormat::Json => scan_json(&lines, &mut document),
+        FileFormat::Yaml => scan_yaml(&lines, &mut document),
+        FileFormat::Toml => scan_toml(&lines, &mut document),
+        FileFormat::Env => scan_env(&lines, &mut document),
+        FileFormat::Hcl => scan_hcl(&lines, &mut document),
+    }
+
+    document.secrets(content)
+}
+
+/// Returns the plaintext of a base64 Kubernetes Secret value when it is text worth
+/// remembering
+fn decode_kubernetes_value(value: &str) -> Option<String> {
+    let decoded = BASE64.decode(value.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let decoded = decoded.trim();
+    (decoded.len() >= MIN_DECODED_LENGTH && !decoded.contains('\n')).then(|| decoded.to_string())
+}
+
+/// Whether a key name suggests that its value is a credential
+pub fn is_sensitive_key(key: &str) -> bool {
+    let normalized: String = key
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    SENSITIVE_KEY_SUFFIXES
+        .iter()
+        .any(|suffix| normalized.ends_with(suffix))
+}
+
+/// Values that are empty, placeholders or references to other values are left alone
+fn is_redactable_value(value: &str) -> bool {
+    let value = value.trim();
+    if value.is_empty() || value.contains("[REDACTED_") {
+        return false;
+    }
+    if value.starts_with('$') || value.starts_with("{{") {
+        return false;
+    }
+    if value.starts_with('<') && value.ends_with('>') {
+        return false;
+    }
+    !matches!(
+        value.to_ascii_lowercase().as_str(),
+        "true" | "false" | "yes" | "no" | "null" | "none" | "~"
+    )
+}
+
+/// A line of the input without its line terminator and read tool numbering
+struct Line<'a> {
+    /// Offset of `text` in the input
+    start: usize,
+    text: &'a str,
+}
+
+fn split_lines(content: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for raw in content.split_inclusive('\n') {
+        lines.push(Line {
+            start,
+            text: raw.trim_end_matches(['\n', '\r']),
+        });
+        start += raw.len();
+    }
+
+    // The read tool prints a header line followed by "  1: ..." lines
+    let numbered = lines.len() > 1
+        && lines[1..]
+            .iter()
+            .filter(|line| !line.text.is_empty() && line.text != "...")
+            .all(|line| NUMBERED_PREFIX.is_match(line.text));
+    if numbered {
+        for line in &mut lines[1..] {
+            if let Some(prefix) = NUMBERED_PREFIX.find(line.text) {
+                line.start += prefix.end();
+                line.text = &line.text[prefix.end()..];
+            }
+        }
+    }
+
+    lines
+}
+
+/// Mappings and scalar entries collected by a scanner. Mappings link to their parent so
+/// rules can look at the surrounding document, e.g. whether a `data` mapping belongs to
+/// a Secret.
+#[derive(Default)]
+struct Document {
+    mappings: Vec<Mapping>,
+    entries: Vec<Entry>,
+}
+
+struct Mapping {
+    parent: Option<usize>,
+    /// Key under which the mapping is nested in its parent
+    key: Option<String>,
+}
+
+struct Entry {
+    mapping: usize,
+    key: String,
+    /// Span of the scalar value in the input, without quotes
+    value: Range<usize>,
+}
+
+impl Document {
+    fn new_mapping(&mut self, parent: Option<usize>, key: Option<String>) -> usize {
+        self.mappings.push(Mapping { parent, key });
+        self.mappings.len() - 1
+    }
+
+    fn add_entry(&mut self, mapping: usize, key: String, value: Range<usize>) {
+        if !value.is_empty() {
+            self.entries.push(Entry {
+                mapping,
+                key,
+                value,
+            });
+        }
+    }
+
+    fn secrets(&self, content: &str) -> StructuredSecrets {
+        // First value of `kind` and `name` in each mapping
+        let mut kinds = vec![None; self.mappings.len()];
+        let mut names = vec![None; self.mappings.len()];
+        for entry in &self.entries {
+            let value = content[entry.value.clone()].trim();
+            match entry.key.as_str() {
+                "kind" => kinds[entry.mapping].get_or_insert(value),
+                "name" => names[entry.mapping].get_or_insert(value),
+                _ => continue,
+            };
+        }
+
+        let mut result = StructuredSecrets::default();
+        for entry in &self.entries {
+            let value = &content[entry.value.clone()];
+            if !is_redactable_value(value) {
+                continue;
+            }
+
+            let mapping = &self.mappings[entry.mapping];
+            let kubernetes_data_key = mapping
+                .key
+                .as_deref()
+                .filter(|key| KUBERNETES_DATA_KEYS.contains(key))
+                .filter(|_| {
+                    mapping
+                        .parent
+                        .is_some_and(|parent| kinds[parent] == Some("Secret"))
+                });
+
+            let rule_id = if let Some(data_key) = kubernetes_data_key {
+                if data_key == "data"
+                    && let Some(decoded) = decode_kubernetes_value(value)
+                {
+                    result.decoded.push(decoded);
+                }
+                KUBERNETES_SECRET_RULE
+            } else if is_sensitive_key(&entry.key) || self.is_named_value(entry, &names) {
+                SENSITIVE_KEY_RULE
+            } else {
+                continue;
+            };
+
+            result.secrets.push(DetectedSecret {
+                rule_id: rule_id.to_string(),
+                value: value.to_string(),
+                start_pos: entry.value.start,
+                end_pos: entry.value.end,
+            });
+        }
+
+        result
+    }
+
+    /// `value` or `default` entries named by a sensitive sibling `name` (Kubernetes env
+    /// vars, ECS task definitions) or by the enclosing block (HCL variables)
+    fn is_named_value(&self, entry: &Entry, names: &[Option<&str>]) -> bool {
+        if entry.key != "value" && entry.key != "default" {
+            return false;
+        }
+        names[entry.mapping].is_some_and(is_sensitive_key)
+            || self.mappings[entry.mapping]
+                .key
+                .as_deref()
+                .is_some_and(is_sensitive_key)
+    }
+}
+
+/// A scalar after a key, with the span of its text relative to the value start
+enum Scalar {
+    /// Nothing follows the key, so a nested block may
+    Empty,
+    /// A `|` or `>` block scalar whose content is on the following lines
+    Block,
+    Value(Range<usize>),
+    /// Flow collections, aliases, tags and anything else that is not a plain scalar
+    Other,
+}
+
+/// Span of a quoted value without its quotes, or of an unquoted value up to a comment
+fn quoted_or_plain(raw: &str) -> Option<Range<usize>> {
+    match raw.chars().next()? {
+        '"' => closing_double_quote(raw, 1).map(|end| 1..end),
+        '\'' => raw[1..].find('\'').map(|end| 1..end + 1),
+        _ => {
+            let end = raw.find(" #").unwrap_or(raw.len());
+            Some(0..raw[..end].trim_end().len())
+        }
+    }
+}
+
+/// Span of a quoted value without its quotes; unquoted values are not reported
+fn quoted(raw: &str) -> Option<Range<usize>> {
+    if raw.starts_with("\"\"\"") || raw.starts_with("'''") {
+        return None;
+    }
+    match raw.chars().next()? {
+        '"' | '\'' => quoted_or_plain(raw),
+        _ => None,
+    }
+}
+
+/// Position of the `"` closing a string that starts before `from`
+fn closing_double_quote(text: &str, from: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut index = from;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 2,
+            b'"' => return Some(index),
+            _ => index += 1,
+        }
+    }
+    None
+}
+
+fn unquote(key: &str) -> String {
+    key.trim()
+        .trim_matches(|c| c == '"' || c == '\'')
+        .to_string()
+}
+
+fn yaml_scalar(raw: &str) -> Scalar {
+    let trimmed = raw.trim_end();
+    match trimmed.chars().next() {
+        None | Some('#') => Scalar::Empty,
+        Some('|' | '>') => Scalar::Block,
+        Some('{' | '[' | '&' | '*' | '!' | '@' | '`') => Scalar::Other,
+        Some(_) => quoted_or_plain(trimmed).map_or(Scalar::Other, Scalar::Value),
+    }
+}
+
+/// Tracks block indentation to build the mapping tree of each YAML document.
+/// Flow collections and multi-line plain scalars are not descended into.
+fn scan_yaml(lines: &[Line], document: &mut Document) {
+    // (column of the mapping's keys, mapping)
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    // Key of the last entry without a value, which names a mapping nested below it
+    let mut block_key: Option<String> = None;
+    // (key column, mapping, key) of a block scalar whose content lines follow
+    let mut block_scalar: Option<(usize, usize, String)> = None;
+
+    for line in lines {
+        let text = line.text;
+        let trimmed = text.trim_start();
+        let indent = text.len() - trimmed.len();
+
+        if let Some((column, mapping, key)) = &block_scalar {
+            if trimmed.is_empty() {
+                continue;
+            }
+            if indent > *column {
+                let start = line.start + indent;
+                let end = line.start + text.trim_end().len();
+                document.add_entry(*mapping, key.clone(), start..end);
+                continue;
+            }
+            block_scalar = None;
+        }
+
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if trimmed.starts_with("---") || trimmed == "..." {
+            stack.clear();
+            block_key = None;
+            continue;
+        }
+
+        // Sequence item markers put the item's first key further right
+        let mut column = indent;
+        let mut rest = trimmed;
+        let mut item = false;
+        while let Some(after) = rest.strip_prefix("- ").or((rest == "-").then_some("")) {
+            item = true;
+            let after = after.trim_start();
+            column += rest.len() - after.len();
+            rest = after;
+        }
+
+        let Some(captures) = YAML_KEY.captures(rest) else {
+            continue;
+        };
+        let key = unquote(&captures[1]);
+        let value_offset = captures.get(0).map_or(rest.len(), |m| m.end());
+
+        // A new item starts a new mapping even at the column of the previous one
+        while stack
+            .last()
+            .is_some_and(|&(top, _)| top > column || (item && top == column))
+        {
+            stack.pop();
+        }
+        let mapping = match stack.last() {
+            Some(&(top, mapping)) if top == column => mapping,
+            _ => {
+                let parent = stack.last().map(|&(_, mapping)| mapping);
+                let mapping = document.new_mapping(parent, block_key.take());
+                stack.push((column, mapping));
+                mapping
+            }
+        };
+
+        let raw_value = &rest[value_offset..];
+        let value_start = line.start + (text.len() - rest.len()) + value_offset;
+        match yaml_scalar(raw_value) {
+            Scalar::Empty => block_key = Some(key),
+            Scalar::Block => {
+                block_key = None;
+                block_scalar = Some((column, mapping, key));
+            }
+            Scalar::Value(span) => {
+                block_key = None;
+                document.add_entry(
+                    mapping,
+                    key,
+                    value_start + span.start..value_start + span.end,
+                );
+            }
+            Scalar::Other => block_key = None,
+        }
+    }
+}
+
+enum JsonFrame {
+    Object {
+        mapping: usize,
+        /// Key whose value comes next
+        key: Option<String>,
+        expect_key: bool,
+    },
+    Array {
+        /// Key of the array, which names the objects inside it
+        key: Option<String>,
+    },
+}
+
+/// Tokenizes JSON just far enough to pair string values with their keys. Characters
+/// outside strings other than structural ones are skipped, which also tolerates headers
+/// around the document.
+fn scan_json(lines: &[Line], document: &mut Document) {
+    let mut stack: Vec<JsonFrame> = Vec::new();
+
+    for line in lines {
+        let bytes = line.text.as_bytes();
+        let mut index = 0;
+        while index < bytes.len() {
+            match bytes[index] {
+                b'{' => {
+                    let parent = stack.iter().rev().find_map(|frame| match frame {
+                        JsonFrame::Object { mapping, .. } => Some(*mapping),
+                        JsonFrame::Array { .. } => None,
+                    });
+                    let key = match stack.last_mut() {
+                        Some(JsonFrame::Object { key, .. }) => key.take(),
+                        Some(JsonFrame::Array { key }) => key.clone(),
+                        None => None,
+                    };
+                    let mapping = document.new_mapping(parent, key);
+                    stack.push(JsonFrame::Object {
+                        mapping,
+                        key: None,
+                        expect_key: true,
+                    });
+                }
+                b'[' => {
+                    let key = match stack.last_mut() {
+                        Some(JsonFrame::Object { key, .. }) => key.take(),
+                        Some(JsonFrame::Array { key }) => key.clone(),
+                        None => None,
+                    };
+                    stack.push(JsonFrame::Array { key });
+                }
+                b'}' | b']' => {
+                    stack.pop();
+                }
+                b',' => {
+                    if let Some(JsonFrame::Object {
+                        key, expect_key, ..
+                    }) = stack.last_mut()
+                    {
+                        *key = None;
+                        *expect_key = true;
+                    }
+                }
+                b'"' => {
+                    let Some(end) = closing_double_quote(line.text, index + 1) else {
+                        break;
+                    };
+                    if let Some(JsonFrame::Object {
+                        mapping,
+                        key,
+                        expect_key,
+                    }) = stack.last_mut()
+                    {
+                        if *expect_key {
+                            *key = Some(line.text[index + 1..end].to_string());
+                            *expect_key = false;
+                        } else if let Some(key) = key.take() {
+                            let start = line.start + index + 1;
+                            document.add_entry(*mapping, key, start..line.start + end);
+                        }
+                    }
+                    index = end;
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+    }
+}
+
+/// `KEY=value` lines, optionally prefixed with `export`
+fn scan_env(lines: &[Line], document: &mut Document) {
+    let mapping = document.new_mapping(None, None);
+    for line in lines {
+        if line.text.trim_start().starts_with('#') {
+            continue;
+        }
+        let Some(captures) = ENV_LINE.captures(line.text) else {
+            continue;
+        };
+        let value_offset = captures.get(0).map_or(line.text.len(), |m| m.end());
+        if let Some(span) = quoted_or_plain(&line.text[value_offset..]) {
+            let start = line.start + value_offset;
+            document.add_entry(
+                mapping,
+                captures[1].to_string(),
+                start + span.start..start + span.end,
+            );
+        }
+    }
+}
+
+/// Single-line string values of `key = "value"` pairs. Table headers are not tracked,
+/// since the key name alone decides whether a value is sensitive.
+fn scan_toml(lines: &[Line], document: &mut Document) {
+    let mapping = document.new_mapping(None, None);
+    for line in lines {
+        let Some(captures) = TOML_KEY.captures(line.text) else {
+            continue;
+        };
+        let value_offset = captures.get(0).map_or(line.text.len(), |m| m.end());
+        if let Some(span) = quoted(&line.text[value_offset..]) {
+            let start = line.start + value_offset;
+            document.add_entry(
+                mapping,
+                unquote(&captures[1]),
+                start + span.start..start + span.end,
+            );
+        }
+    }
+}
+
+/// String attributes inside blocks. A block is named by its last label, so the
+/// `default` of `variable "db_password" { ... }` counts as sensitive.
+fn scan_hcl(lines: &[Line], document: &mut Document) {
+    let root = document.new_mapping(None, None);
+    let mut stack = vec![root];
+    for line in lines {
+        let trimmed = line.text.trim();
+        if trimmed.starts_with('#') || trimmed.starts_with("//") {
+            continue;
+        }
+        if let Some(captures) = HCL_BLOCK.captures(line.text) {
+            let name = captures[2]
+                .rsplit('"')
+                .nth(1)
+                .map(str::to_string)
+                .unwrap_or_else(|| captures[1].to_string());
+            let parent = stack.last().copied();
+            stack.push(document.new_mapping(parent, Some(name)));
+            continue;
+        }
+        if trimmed.starts_with('}') {
+            if stack.len() > 1 {
+                stack.pop();
+            }
+            continue;
+        }
+        let Some(captures) = TOML_KEY.captures(line.text) else {
+            continue;
+        };
+        let value_offset = captures.get(0).map_or(line.text.len(), |m| m.end());
+        if let Some(span) = quoted(&line.text[value_offset..]) {
+            let start = line.start + value_offset;
+            let mapping = stack.last().copied().unwrap_or(root);
+            document.add_entry(
+                mapping,
+                unquote(&captures[1]),
+                start + span.start..start + span.end,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redacted_values(content: &str, path: Option<&str>) -> Vec<String> {
+        detect_structured_secrets(content, path)
+            .secrets
+            .into_iter()
+            .map(|secret| {
+                assert_eq!(&content[secret.start_pos..secret.end_pos], secret.value);
+                secret.value
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sensitive_key_names() {
+        for key in [
+            "DB_PASSWORD",
+            "db-password",
+            "dbPassword",
+            "api_key",
+            "GITHUB_TOKEN",
+        ] {
+            assert!(is_sensitive_key(key), "{key}");
+        }
+        for key in [
+            "password_file",
+            "secretName",
+            "PWD",
+            "token_ttl",
+            "username",
+        ] {
+            assert!(!is_sensitive_key(key), "{key}");
+        }
+    }
+
+    #[test]
+    fn test_format_detection() {
+        assert_eq!(detect_format("", Some("/app/.env")), Some(FileFormat::Env));
+        assert_eq!(
+            detect_format("", Some(".env.production")),
+            Some(FileFormat::Env)
+        );
+        assert_eq!(
+            detect_format("", Some("values.yml")),
+            Some(FileFormat::Yaml)
+        );
+        assert_eq!(detect_format("", Some("main.tf")), Some(FileFormat::Hcl));
+        assert_eq!(detect_format("{\"a\": 1}", None), Some(FileFormat::Json));
+        assert_eq!(
+            detect_format("DB_HOST=db\nDB_PASSWORD=x\n", None),
+            Some(FileFormat::Env)
+        );
+        assert_eq!(
+            detect_format("total 8\ndrwxr-xr-x 2 root root\n", None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_env_values() {
+        let content = "# db\nDB_HOST=localhost\nexport DB_PASSWORD=summer2024\nAPI_TOKEN=\"abc def\" # quoted\nREQUIRE_PASSWORD=true\n";
+        assert_eq!(
+            redacted_values(content, Some(".env")),
+            vec!["summer2024", "abc def"]
+        );
+    }
+
+    #[test]
+    fn test_json_values() {
+        let content = r#"{"db": {"host": "db", "password": "summer2024"}, "tokens": ["a"], "env": [{"name": "API_SECRET", "value": "hunter22"}, {"name": "MODE", "value": "prod"}]}"#;
+        assert_eq!(
+            redacted_values(content, None),
+            vec!["summer2024", "hunter22"]
+        );
+    }
+
+    #[test]
+    fn test_yaml_kubernetes_secret_multi_doc() {
+        let content = "\
+apiVersion: v1
+kind: ConfigMap
+data:
+  mode: production
+---
+apiVersion: v1
+kind: Secret
+metadata:
+  name: db
+data:
+  username: YWRtaW4=
+  password: c3VtbWVyMjAyNC1sb25n
+stringData:
+  config.yaml: |
+    user: admin
+    pass: plain
+---
+apiVersion: apps/v1
+kind: Deployment
+spec:
+  template:
+    spec:
+      containers:
+      - name: api
+        env:
+        - name: DB_PASSWORD
+          value: summer2024
+        - name: DB_HOST
+          value: db
+";
+        let result = detect_structured_secrets(content, Some("manifests.yaml"));
+        let values: Vec<&str> = result.secrets.iter().map(|s| s.value.as_str()).collect();
+        assert_eq!(
+            values,
+            vec![
+                "YWRtaW4=",
+                "c3VtbWVyMjAyNC1sb25n",
+                "user: admin",
+                "pass: plain",
+                "summer2024"
+            ]
+        );
+        assert_eq!(result.decoded, vec!["summer2024-long"]);
+    }
+
+    #[test]
+    fn test_yaml_secret_list_with_kind_after_data() {
+        let content = "\
+apiVersion: v1
+items:
+- apiVersion: v1
+  data:
+    token: dG9rZW4tdmFsdWUtMTIz
+  kind: Secret
+- apiVersion: v1
+  data:
+    color: Ymx1ZQ==
+  kind: ConfigMap
+kind: List
+";
+        assert_eq!(redacted_values(content, None), vec!["dG9rZW4tdmFsdWUtMTIz"]);
+    }
+
+    #[test]
+    fn test_numbered_read_tool_view() {
+        let content = "File: /app/config.yaml\n  1: database:\n  2:   password: \"summer2024\"\n  3:   host: db";
+        assert_eq!(
+            redacted_values(content, Some("/app/config.yaml")),
+            vec!["summer2024"]
+        );
+    }
+
+    #[test]
+    fn test_toml_and_hcl_values() {
+        let toml = "[database]\nhost = \"db\"\npassword = 'summer2024'\nport = 5432\n";
+        assert_eq!(
+            redacted_values(toml, Some("config.toml")),
+            vec!["summer2024"]
+        );
+
+        let hcl = "variable \"db_password\" {\n  type    = string\n  default = \"summer2024\"\n}\n\nresource \"aws_db_instance\" \"db\" {\n  password = var.db_password\n  master_password = \"hunter22\"\n}\n";
+        assert_eq!(
+            redacted_values(hcl, Some("main.tf")),
+            vec!["summer2024", "hunter22"]
+        );
+    }
+
+    #[test]
+    fn test_references_and_placeholders_are_kept() {
+        let content =
+            "DB_PASSWORD=${SECRET}\nAPI_TOKEN=<your-token>\nSECRET_KEY=[REDACTED_SECRET:x:abc]\n";
+        assert!(redacted_values(content, Some(".env")).is_empty());
+    }
+}
@@ -0,0 +1,474 @@
+use crate::models::integrations::openai::{ChatMessage, Role};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const SESSION_FILE_EXTENSION: &str = "jsonl";
+/// Longest a session title taken from its first prompt may be
+const MAX_TITLE_CHARS: usize = 80;
+
+/// One line of a session file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionEntry {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub record: SessionRecord,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionRecord {
+    Started {
+        cwd: String,
+        profile: String,
+    },
+    /// A message added to the conversation, including tool calls and their results
+    Message {
+        message: ChatMessage,
+    },
+    /// A checkpoint the backend made of the conversation so far
+    Checkpoint {
+        checkpoint_id: Uuid,
+    },
+    /// The backend session the conversation belongs to
+    RemoteSession {
+        session_id: Uuid,
+    },
+    Model {
+        model: String,
+    },
+    /// The conversation was rewritten, e.g. compacted, and carries on from these messages
+    Reset {
+        messages: Vec<ChatMessage>,
+    },
+}
+
+/// Overview of a stored session
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary {
+    pub id: String,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub messages: usize,
+    pub remote_session_id: Option<Uuid>,
+    pub checkpoints: Vec<Uuid>,
+    pub model: Option<String>,
+    pub cwd: Option<String>,
+}
+
+/// A stored session replayed from its file
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalSession {
+    pub summary: SessionSummary,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl LocalSession {
+    fn replay(id: &str, entries: Vec<SessionEntry>) -> Result<Self, String> {
+        let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+            return Err(format!("Session {} is empty", id));
+        };
+        let mut summary = SessionSummary {
+            id: id.to_string(),
+            title: String::new(),
+            created_at: first.at,
+            updated_at: last.at,
+            messages: 0,
+            remote_session_id: None,
+            checkpoints: Vec::new(),
+            model: None,
+            cwd: None,
+        };
+        let mut messages = Vec::new();
+
+        for entry in entries {
+            match entry.record {
+                SessionRecord::Started { cwd, .. } => summary.cwd = Some(cwd),
+                SessionRecord::Message { message } => messages.push(message),
+                SessionRecord::Checkpoint { checkpoint_id } => {
+                    summary.checkpoints.push(checkpoint_id)
+                }
+                SessionRecord::RemoteSession { session_id } => {
+                    summary.remote_session_id = Some(session_id)
+                }
+                SessionRecord::Model { model } => summary.model = Some(model),
+                SessionRecord::Reset { messages: reset } => messages = reset,
+            }
+        }
+
+        summary.messages = messages.len();
+        summary.title = session_title(&messages);
+        Ok(Self { summary, messages })
+    }
+}
+
+/// Sessions mirrored to disk as append-only JSONL, one file per session, so they can be
+/// listed, read and resumed without the backend
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Store in `~/.stakpak/sessions`
+    pub fn default_store() -> Self {
+        Self::new(
+            dirs::home_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join(".stakpak")
+                .join("sessions"),
+        )
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn session_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, SESSION_FILE_EXTENSION))
+    }
+
+    /// Stored sessions, most recently updated first. Files that cannot be read are skipped.
+    pub fn list(&self) -> Result<Vec<SessionSummary>, String> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let entries = fs::read_dir(&self.dir).map_err(|e| {
+            format!(
+                "Failed to read session directory {}: {}",
+                self.dir.display(),
+                e
+            )
+        })?;
+
+        let mut sessions: Vec<SessionSummary> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some(SESSION_FILE_EXTENSION) {
+                    return None;
+                }
+                let id = path.file_stem()?.to_str()?.to_string();
+                self.load_by_id(&id).ok().map(|session| session.summary)
+            })
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+        Ok(sessions)
+    }
+
+    /// Find a session by its id, a unique prefix of it, or the id of its backend session
+    pub fn load(&self, id: &str) -> Result<LocalSession, String> {
+        if id.is_empty() || id.contains(['/', '\\', '.']) {
+            return Err(format!("Invalid session id '{}'", id));
+        }
+        if self.session_path(id).exists() {
+            return self.load_by_id(id);
+        }
+
+        let sessions = self.list()?;
+        let matches: Vec<&SessionSummary> = sessions
+            .iter()
+            .filter(|session| {
+                session.id.starts_with(id)
+                    || session
+                        .remote_session_id
+                        .is_some_and(|remote_id| remote_id.to_string() == id)
+            })
+            .collect();
+        match matches.as_slice() {
+            [session] => self.load_by_id(&session.id),
+            [] => Err(format!("No local session matches '{}'", id)),
+            _ => Err(format!(
+                "'{}' matches {} local sessions, use a longer id",
+                id,
+                matches.len()
+            )),
+        }
+    }
+
+    fn load_by_id(&self, id: &str) -> Result<LocalSession, String> {
+        let path = self.session_path(id);
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read session {}: {}", path.display(), e))?;
+        // A line cut short by a crash is dropped rather than losing the whole session
+        let entries = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        LocalSession::replay(id, entries)
+    }
+
+    /// Delete a session, returning the path of the removed file
+    pub fn remove(&self, id: &str) -> Result<PathBuf, String> {
+        let session = self.load(id)?;
+        let path = self.session_path(&session.summary.id);
+        fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove session {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    /// Record a new session. Nothing is written until it has messages.
+    pub fn recorder(&self, profile: &str) -> SessionRecorder {
+        SessionRecorder {
+            path: self.session_path(&Uuid::new_v4().to_string()),
+            profile: profile.to_string(),
+            started: false,
+            written: 0,
+            last_message: None,
+            model: None,
+            remote_session_id: None,
+        }
+    }
+
+    /// Carry on recording `session` in its own file
+    pub fn resume_recorder(&self, session: &LocalSession, profile: &str) -> SessionRecorder {
+        SessionRecorder {
+            path: self.session_path(&session.summary.id),
+            profile: profile.to_string(),
+            started: true,
+            written: session.messages.len(),
+            last_message: session.messages.last().cloned(),
+            model: session.summary.model.clone(),
+            remote_session_id: session.summary.remote_session_id,
+        }
+    }
+}
+
+/// Appends a conversation to its session file as it grows
+#[derive(Debug)]
+pub struct SessionRecorder {
+    path: PathBuf,
+    profile: String,
+    started: bool,
+    /// Number of messages of the conversation already in the file
+    written: usize,
+    last_message: Option<ChatMessage>,
+    model: Option<String>,
+    remote_session_id: Option<Uuid>,
+}
+
+impl SessionRecorder {
+    pub fn id(&self) -> Option<&str> {
+        self.path.file_stem().and_then(|stem| stem.to_str())
+    }
+
+    /// Append what changed in `messages` since the last call. A conversation that no longer
+    /// starts with what was recorded, e.g. after compaction, is written out again in full.
+    pub fn record(&mut self, messages: &[ChatMessage], model: &str) -> Result<(), String> {
+        let mut entries = Vec::new();
+        if !self.started {
+            if messages.iter().all(|message| message.role == Role::System) {
+                return Ok(());
+            }
+            entries.push(SessionRecord::Started {
+                cwd: std::env::current_dir()
+                    .map(|dir| dir.display().to_string())
+                    .unwrap_or_default(),
+                profile: self.profile.clone(),
+            });
+        }
+        if self.model.as_deref() != Some(model) {
+            entries.push(SessionRecord::Model {
+                model: model.to_string(),
+            });
+        }
+
+        let continues = self.written <= messages.len()
+            && (self.written == 0 || messages.get(self.written - 1) == self.last_message.as_ref());
+        let new_messages = if continues {
+            &messages[self.written..]
+        } else {
+            entries.push(SessionRecord::Reset {
+                messages: messages.to_vec(),
+            });
+            &[]
+        };
+        for message in new_messages {
+            entries.push(SessionRecord::Message {
+                message: message.clone(),
+            });
+        }
+        for message in if continues { new_messages } else { messages } {
+            if message.role == Role::Assistant
+                && let Some(checkpoint_id) = message
+                    .content
+                    .as_ref()
+                    .and_then(|content| content.extract_checkpoint_id())
+            {
+                entries.push(SessionRecord::Checkpoint { checkpoint_id });
+            }
+        }
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.append(entries)?;
+        self.started = true;
+        self.written = messages.len();
+        self.last_message = messages.last().cloned();
+        self.model = Some(model.to_string());
+        Ok(())
+    }
+
+    /// Link the session to its backend session, once it is known
+    pub fn set_remote_session(&mut self, session_id: Uuid) -> Result<(), String> {
+        if !self.started || self.remote_session_id == Some(session_id) {
+            return Ok(());
+        }
+        self.append(vec![SessionRecord::RemoteSession { session_id }])?;
+        self.remote_session_id = Some(session_id);
+        Ok(())
+    }
+
+    fn append(&self, records: Vec<SessionRecord>) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                format!(
+                    "Failed to create session directory {}: {}",
+                    dir.display(),
+                    e
+                )
+            })?;
+        }
+
+        let at = Utc::now();
+        let mut lines = String::new();
+        for record in records {
+            let line = serde_json::to_string(&SessionEntry { at, record })
+                .map_err(|e| format!("Failed to serialize session record: {}", e))?;
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open session {}: {}", self.path.display(), e))?;
+        file.write_all(lines.as_bytes())
+            .map_err(|e| format!("Failed to write session {}: {}", self.path.display(), e))
+    }
+}
+
+/// First line of the first prompt, without the context added to it
+fn session_title(messages: &[ChatMessage]) -> String {
+    let title = messages
+        .iter()
+        .filter(|message| message.role == Role::User)
+        .filter_map(|message| message.content.as_ref())
+        .flat_map(|content| {
+            content
+                .to_string()
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('<'))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .next()
+        .unwrap_or_else(|| "Untitled session".to_string());
+    match title.char_indices().nth(MAX_TITLE_CHARS) {
+        Some((index, _)) => format!("{}...", &title[..index]),
+        None => title,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::integrations::openai::MessageContent;
+    use tempfile::TempDir;
+
+    fn message(role: Role, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: Some(MessageContent::String(content.to_string())),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_records_and_replays_sessions() {
+        let dir = TempDir::new().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf());
+        let mut recorder = store.recorder("default");
+
+        let mut messages = vec![message(Role::System, "You are a DevOps agent")];
+        recorder.record(&messages, "pablo-v1").unwrap();
+        assert!(store.list().unwrap().is_empty());
+
+        messages.push(message(
+            Role::User,
+            "Why is the api pod crashing?\n\n<local_context>\nlinux\n</local_context>",
+        ));
+        let checkpoint_id = Uuid::new_v4();
+        messages.push(message(
+            Role::Assistant,
+            &format!("Checking the logs\n<checkpoint_id>{checkpoint_id}</checkpoint_id>"),
+        ));
+        recorder.record(&messages, "pablo-v1").unwrap();
+        let remote_id = Uuid::new_v4();
+        recorder.set_remote_session(remote_id).unwrap();
+        messages.push(message(Role::User, "Thanks"));
+        recorder.record(&messages, "fast-model").unwrap();
+
+        let sessions = store.list().unwrap();
+        assert_eq!(sessions.len(), 1);
+        let summary = &sessions[0];
+        assert_eq!(Some(summary.id.as_str()), recorder.id());
+        assert_eq!(summary.title, "Why is the api pod crashing?");
+        assert_eq!(summary.messages, 4);
+        assert_eq!(summary.checkpoints, vec![checkpoint_id]);
+        assert_eq!(summary.model.as_deref(), Some("fast-model"));
+
+        let session = store.load(&remote_id.to_string()).unwrap();
+        assert_eq!(session.messages, messages);
+        assert_eq!(store.load(&summary.id[..8]).unwrap(), session);
+
+        // A compacted conversation replaces what came before
+        let compacted = vec![messages[0].clone(), message(Role::User, "<summary/>")];
+        let mut recorder = store.resume_recorder(&session, "default");
+        recorder.record(&compacted, "fast-model").unwrap();
+        assert_eq!(store.load(&summary.id).unwrap().messages, compacted);
+
+        store.remove(&summary.id).unwrap();
+        assert!(store.load(&summary.id).is_err());
+    }
+
+    #[test]
+    fn test_skips_truncated_lines() {
+        let dir = TempDir::new().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf());
+        let mut recorder = store.recorder("default");
+        recorder
+            .record(&[message(Role::User, "deploy")], "pablo-v1")
+            .unwrap();
+
+        let path = dir
+            .path()
+            .join(format!("{}.jsonl", recorder.id().unwrap_or_default()));
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(b"{\"at\":\"2025-01-01T00:00:00Z\",\"type\":\"mess")
+            .unwrap();
+
+        let sessions = store.list().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].messages, 1);
+    }
+}
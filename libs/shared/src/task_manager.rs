@@ -1,15 +1,22 @@
+use crate::fleet::{FleetOptions, HostResult, run_fleet_command};
 use crate::helper::generate_simple_id;
+use crate::inventory::InventoryHost;
+use crate::log_tail::{DEFAULT_TAIL_POLL_INTERVAL, LogTailer, TailEvent};
+use crate::port_forward::{PortForward, PortForwardStats};
 use crate::remote_connection::{RemoteConnectionInfo, RemoteConnectionManager};
 use chrono::{DateTime, Utc};
 use std::{collections::HashMap, process::Stdio, sync::Arc, time::Duration};
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
     process::Command,
     sync::{broadcast, mpsc, oneshot},
     time::timeout,
 };
 
 const START_TASK_WAIT_TIME: Duration = Duration::from_millis(300);
+const PORT_FORWARD_STATUS_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of most recent lines a tail task keeps as its output
+const TAIL_TASK_OUTPUT_LINES: usize = 200;
 
 pub type TaskId = String;
 
@@ -109,6 +116,26 @@ pub enum TaskMessage {
         command: String,
         remote_connection: Option<RemoteConnectionInfo>,
         timeout: Option<Duration>,
+        stdin: Option<Vec<u8>>,
+        response_tx: oneshot::Sender<Result<TaskId, TaskError>>,
+    },
+    StartFleet {
+        command: String,
+        target: String,
+        hosts: Vec<InventoryHost>,
+        options: FleetOptions,
+        connection_manager: Arc<RemoteConnectionManager>,
+        response_tx: oneshot::Sender<Result<TaskId, TaskError>>,
+    },
+    StartPortForward {
+        forward: PortForward,
+        label: String,
+        response_tx: oneshot::Sender<Result<TaskId, TaskError>>,
+    },
+    StartTail {
+        tailer: LogTailer,
+        duration: Option<Duration>,
+        label: String,
         response_tx: oneshot::Sender<Result<TaskId, TaskError>>,
     },
     Cancel {
@@ -137,6 +164,11 @@ pub enum TaskMessage {
         id: TaskId,
         output: String,
     },
+    /// Replace a running task's output, used for live status of long-lived tasks
+    SetOutput {
+        id: TaskId,
+        output: String,
+    },
 }
 
 pub struct TaskManager {
@@ -204,15 +236,57 @@ impl TaskManager {
                 command,
                 remote_connection,
                 timeout,
+                stdin,
                 response_tx,
             } => {
                 let task_id = id.unwrap_or_else(|| generate_simple_id(6));
                 let result = self
-                    .start_task(task_id.clone(), command, timeout, remote_connection)
+                    .start_task(task_id.clone(), command, timeout, remote_connection, stdin)
                     .await;
                 let _ = response_tx.send(result.map(|_| task_id.clone()));
                 false
             }
+            TaskMessage::StartFleet {
+                command,
+                target,
+                hosts,
+                options,
+                connection_manager,
+                response_tx,
+            } => {
+                let task_id = generate_simple_id(6);
+                let result = self.start_fleet_task(
+                    task_id.clone(),
+                    command,
+                    target,
+                    hosts,
+                    options,
+                    connection_manager,
+                );
+                let _ = response_tx.send(result.map(|_| task_id.clone()));
+                false
+            }
+            TaskMessage::StartPortForward {
+                forward,
+                label,
+                response_tx,
+            } => {
+                let task_id = generate_simple_id(6);
+                let result = self.start_port_forward_task(task_id.clone(), forward, label);
+                let _ = response_tx.send(result.map(|_| task_id.clone()));
+                false
+            }
+            TaskMessage::StartTail {
+                tailer,
+                duration,
+                label,
+                response_tx,
+            } => {
+                let task_id = generate_simple_id(6);
+                let result = self.start_tail_task(task_id.clone(), tailer, duration, label);
+                let _ = response_tx.send(result.map(|_| task_id.clone()));
+                false
+            }
             TaskMessage::Cancel { id, response_tx } => {
                 let result = self.cancel_task(&id).await;
                 let _ = response_tx.send(result);
@@ -234,7 +308,7 @@ impl TaskManager {
                     .values()
                     .map(|entry| TaskInfo::from(&entry.task))
                     .collect();
-                tasks.sort_by(|a, b| b.start_time.cmp(&a.start_time));
+                tasks.sort_by_key(|t| std::cmp::Reverse(t.start_time));
                 let _ = response_tx.send(tasks);
                 false
             }
@@ -271,6 +345,14 @@ impl TaskManager {
                 }
                 false
             }
+            TaskMessage::SetOutput { id, output } => {
+                if let Some(entry) = self.tasks.get_mut(&id)
+                    && matches!(entry.task.status, TaskStatus::Running)
+                {
+                    entry.task.output = Some(output);
+                }
+                false
+            }
             TaskMessage::Shutdown { response_tx } => {
                 self.shutdown_all_tasks().await;
                 let _ = response_tx.send(());
@@ -285,6 +367,7 @@ impl TaskManager {
         command: String,
         timeout: Option<Duration>,
         remote_connection: Option<RemoteConnectionInfo>,
+        stdin: Option<Vec<u8>>,
     ) -> Result<(), TaskError> {
         if self.tasks.contains_key(&id) {
             return Err(TaskError::TaskAlreadyRunning(id));
@@ -314,6 +397,7 @@ impl TaskManager {
             command,
             remote_connection,
             timeout,
+            stdin,
             cancel_rx,
             process_tx,
             task_tx,
@@ -342,6 +426,149 @@ impl TaskManager {
         Ok(())
     }
 
+    fn start_fleet_task(
+        &mut self,
+        id: TaskId,
+        command: String,
+        target: String,
+        hosts: Vec<InventoryHost>,
+        options: FleetOptions,
+        connection_manager: Arc<RemoteConnectionManager>,
+    ) -> Result<(), TaskError> {
+        if self.tasks.contains_key(&id) {
+            return Err(TaskError::TaskAlreadyRunning(id));
+        }
+
+        let task = Task {
+            id: id.clone(),
+            status: TaskStatus::Running,
+            command: format!("[fleet {} ({} hosts)] {}", target, hosts.len(), command),
+            remote_connection: None,
+            output: None,
+            error: None,
+            start_time: Utc::now(),
+            duration: None,
+            timeout: None,
+        };
+
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let task_tx = self.tx.clone();
+
+        let handle = tokio::spawn(Self::execute_fleet_task(
+            id.clone(),
+            command,
+            hosts,
+            options,
+            connection_manager,
+            cancel_rx,
+            task_tx,
+        ));
+
+        self.tasks.insert(
+            id,
+            TaskEntry {
+                task,
+                handle,
+                process_id: None,
+                cancel_tx: Some(cancel_tx),
+            },
+        );
+
+        Ok(())
+    }
+
+    fn start_port_forward_task(
+        &mut self,
+        id: TaskId,
+        forward: PortForward,
+        label: String,
+    ) -> Result<(), TaskError> {
+        if self.tasks.contains_key(&id) {
+            return Err(TaskError::TaskAlreadyRunning(id));
+        }
+
+        let task = Task {
+            id: id.clone(),
+            status: TaskStatus::Running,
+            command: label,
+            remote_connection: None,
+            output: Some(format!("Forwarding {}", forward.spec())),
+            error: None,
+            start_time: Utc::now(),
+            duration: None,
+            timeout: None,
+        };
+
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let task_tx = self.tx.clone();
+
+        let handle = tokio::spawn(Self::execute_port_forward_task(
+            id.clone(),
+            forward,
+            cancel_rx,
+            task_tx,
+        ));
+
+        self.tasks.insert(
+            id,
+            TaskEntry {
+                task,
+                handle,
+                process_id: None,
+                cancel_tx: Some(cancel_tx),
+            },
+        );
+
+        Ok(())
+    }
+
+    fn start_tail_task(
+        &mut self,
+        id: TaskId,
+        tailer: LogTailer,
+        duration: Option<Duration>,
+        label: String,
+    ) -> Result<(), TaskError> {
+        if self.tasks.contains_key(&id) {
+            return Err(TaskError::TaskAlreadyRunning(id));
+        }
+
+        let task = Task {
+            id: id.clone(),
+            status: TaskStatus::Running,
+            command: label,
+            remote_connection: None,
+            output: None,
+            error: None,
+            start_time: Utc::now(),
+            duration: None,
+            timeout: duration,
+        };
+
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let task_tx = self.tx.clone();
+
+        let handle = tokio::spawn(Self::execute_tail_task(
+            id.clone(),
+            tailer,
+            duration,
+            cancel_rx,
+            task_tx,
+        ));
+
+        self.tasks.insert(
+            id,
+            TaskEntry {
+                task,
+                handle,
+                process_id: None,
+                cancel_tx: Some(cancel_tx),
+            },
+        );
+
+        Ok(())
+    }
+
     async fn cancel_task(&mut self, id: &TaskId) -> Result<(), TaskError> {
         if let Some(mut entry) = self.tasks.remove(id) {
             entry.task.status = TaskStatus::Cancelled;
@@ -406,11 +633,13 @@ impl TaskManager {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn execute_task(
         id: TaskId,
         command: String,
         remote_connection: Option<RemoteConnectionInfo>,
         task_timeout: Option<Duration>,
+        stdin: Option<Vec<u8>>,
         mut cancel_rx: oneshot::Receiver<()>,
         process_tx: oneshot::Sender<u32>,
         task_tx: mpsc::UnboundedSender<TaskMessage>,
@@ -422,6 +651,7 @@ impl TaskManager {
                 command,
                 remote_info,
                 task_timeout,
+                stdin,
                 &mut cancel_rx,
                 &task_tx,
             )
@@ -432,6 +662,7 @@ impl TaskManager {
                 id.clone(),
                 command,
                 task_timeout,
+                stdin,
                 &mut cancel_rx,
                 process_tx,
                 &task_tx,
@@ -450,6 +681,7 @@ impl TaskManager {
         id: TaskId,
         command: String,
         task_timeout: Option<Duration>,
+        stdin: Option<Vec<u8>>,
         cancel_rx: &mut oneshot::Receiver<()>,
         process_tx: oneshot::Sender<u32>,
         task_tx: &mpsc::UnboundedSender<TaskMessage>,
@@ -457,6 +689,11 @@ impl TaskManager {
         let mut child = match Command::new("sh")
             .arg("-c")
             .arg(&command)
+            .stdin(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            })
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
@@ -471,6 +708,15 @@ impl TaskManager {
             }
         };
 
+        if let Some(input) = stdin
+            && let Some(mut child_stdin) = child.stdin.take()
+        {
+            tokio::spawn(async move {
+                let _ = child_stdin.write_all(&input).await;
+                let _ = child_stdin.shutdown().await;
+            });
+        }
+
         // Send the process ID back to the manager for tracking
         if let Some(process_id) = child.id() {
             let _ = process_tx.send(process_id);
@@ -599,6 +845,7 @@ impl TaskManager {
         command: String,
         remote_info: RemoteConnectionInfo,
         task_timeout: Option<Duration>,
+        stdin: Option<Vec<u8>>,
         cancel_rx: &mut oneshot::Receiver<()>,
         task_tx: &mpsc::UnboundedSender<TaskMessage>,
     ) -> TaskCompletion {
@@ -630,8 +877,8 @@ impl TaskManager {
         // Use unified execution with proper cancellation and timeout
         let options = crate::remote_connection::CommandOptions {
             timeout: task_timeout,
-            with_progress: false,
             simple: false,
+            stdin,
         };
 
         match connection
@@ -670,6 +917,183 @@ impl TaskManager {
         }
     }
 
+    async fn execute_fleet_task(
+        id: TaskId,
+        command: String,
+        hosts: Vec<InventoryHost>,
+        options: FleetOptions,
+        connection_manager: Arc<RemoteConnectionManager>,
+        mut cancel_rx: oneshot::Receiver<()>,
+        task_tx: mpsc::UnboundedSender<TaskMessage>,
+    ) {
+        // Stream a one-line summary per host as results come in
+        let on_result = |result: &HostResult| {
+            let line = match (&result.error, result.exit_code) {
+                (Some(error), _) => format!("[{}] failed: {}\n", result.host, error),
+                (None, Some(0)) => format!(
+                    "[{}] ok ({:.1}s)\n",
+                    result.host,
+                    result.duration.as_secs_f64()
+                ),
+                (None, code) => format!("[{}] exited with code {:?}\n", result.host, code),
+            };
+            let _ = task_tx.send(TaskMessage::PartialUpdate {
+                id: id.clone(),
+                output: line,
+            });
+        };
+
+        let completion = tokio::select! {
+            report = run_fleet_command(&connection_manager, &hosts, &command, &options, on_result) => {
+                let failed = report.failures().len();
+                TaskCompletion {
+                    output: report.to_markdown(),
+                    error: if failed > 0 {
+                        Some(format!("{} of {} hosts failed", failed, report.results.len()))
+                    } else {
+                        None
+                    },
+                    final_status: if failed > 0 && report.succeeded_count() == 0 {
+                        TaskStatus::Failed
+                    } else {
+                        TaskStatus::Completed
+                    },
+                }
+            }
+            _ = &mut cancel_rx => TaskCompletion {
+                output: String::new(),
+                error: Some("Tool call was cancelled and don't try to run it again".to_string()),
+                final_status: TaskStatus::Cancelled,
+            },
+        };
+
+        let _ = task_tx.send(TaskMessage::TaskUpdate { id, completion });
+    }
+
+    async fn execute_port_forward_task(
+        id: TaskId,
+        forward: PortForward,
+        mut cancel_rx: oneshot::Receiver<()>,
+        task_tx: mpsc::UnboundedSender<TaskMessage>,
+    ) {
+        let description = format!("Forwarding {}", forward.spec());
+        let stats = Arc::new(PortForwardStats::default());
+
+        let serve = forward.serve(stats.clone());
+        tokio::pin!(serve);
+        let mut status_interval = tokio::time::interval(PORT_FORWARD_STATUS_INTERVAL);
+
+        let completion = loop {
+            tokio::select! {
+                result = &mut serve => break TaskCompletion {
+                    output: format!("{}\n{}", description, stats.summary()),
+                    error: result.err().map(|e| format!("Port forward stopped: {}", e)),
+                    final_status: TaskStatus::Failed,
+                },
+                _ = &mut cancel_rx => break TaskCompletion {
+                    output: format!("{}\n{}", description, stats.summary()),
+                    error: None,
+                    final_status: TaskStatus::Cancelled,
+                },
+                // Publish live connection counters as the task output
+                _ = status_interval.tick() => {
+                    let _ = task_tx.send(TaskMessage::SetOutput {
+                        id: id.clone(),
+                        output: format!("{}\n{}", description, stats.summary()),
+                    });
+                }
+            }
+        };
+
+        let _ = task_tx.send(TaskMessage::TaskUpdate { id, completion });
+    }
+
+    async fn execute_tail_task(
+        id: TaskId,
+        mut tailer: LogTailer,
+        duration: Option<Duration>,
+        mut cancel_rx: oneshot::Receiver<()>,
+        task_tx: mpsc::UnboundedSender<TaskMessage>,
+    ) {
+        let with_source = tailer.follows_multiple_files();
+        let mut recent_lines = std::collections::VecDeque::with_capacity(TAIL_TASK_OUTPUT_LINES);
+        let mut total_lines = 0usize;
+
+        // Keep only the most recent lines as the task output instead of an ever-growing log
+        let mut record = |events: Vec<TailEvent>| -> Option<String> {
+            if events.is_empty() {
+                return None;
+            }
+            for event in events {
+                total_lines += 1;
+                if recent_lines.len() == TAIL_TASK_OUTPUT_LINES {
+                    recent_lines.pop_front();
+                }
+                recent_lines.push_back(event.format(with_source));
+            }
+            let mut output = String::new();
+            if total_lines > recent_lines.len() {
+                output.push_str(&format!(
+                    "(showing last {} of {} lines)\n",
+                    recent_lines.len(),
+                    total_lines
+                ));
+            }
+            output.push_str(
+                &recent_lines
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+            Some(output)
+        };
+
+        let mut latest_output = record(tailer.initial_events().await).unwrap_or_default();
+        if !latest_output.is_empty() {
+            let _ = task_tx.send(TaskMessage::SetOutput {
+                id: id.clone(),
+                output: latest_output.clone(),
+            });
+        }
+
+        let deadline = duration.map(|d| tokio::time::Instant::now() + d);
+        let mut poll_interval = tokio::time::interval(DEFAULT_TAIL_POLL_INTERVAL);
+
+        let completion = loop {
+            let until_deadline = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                _ = until_deadline => break TaskCompletion {
+                    output: latest_output,
+                    error: None,
+                    final_status: TaskStatus::Completed,
+                },
+                _ = &mut cancel_rx => break TaskCompletion {
+                    output: latest_output,
+                    error: None,
+                    final_status: TaskStatus::Cancelled,
+                },
+                _ = poll_interval.tick() => {
+                    if let Some(output) = record(tailer.poll().await) {
+                        latest_output = output.clone();
+                        let _ = task_tx.send(TaskMessage::SetOutput {
+                            id: id.clone(),
+                            output,
+                        });
+                    }
+                }
+            }
+        };
+
+        let _ = task_tx.send(TaskMessage::TaskUpdate { id, completion });
+    }
+
     async fn shutdown_all_tasks(&mut self) {
         for (_id, mut entry) in self.tasks.drain() {
             if let Some(cancel_tx) = entry.cancel_tx.take() {
@@ -740,6 +1164,18 @@ impl TaskManagerHandle {
         command: String,
         timeout: Option<Duration>,
         remote_connection: Option<RemoteConnectionInfo>,
+    ) -> Result<TaskInfo, TaskError> {
+        self.start_task_with_input(command, timeout, remote_connection, None)
+            .await
+    }
+
+    /// Start a task whose stdin receives `stdin` and is then closed, e.g. a sudo password
+    pub async fn start_task_with_input(
+        &self,
+        command: String,
+        timeout: Option<Duration>,
+        remote_connection: Option<RemoteConnectionInfo>,
+        stdin: Option<Vec<u8>>,
     ) -> Result<TaskInfo, TaskError> {
         let (response_tx, response_rx) = oneshot::channel();
 
@@ -749,6 +1185,7 @@ impl TaskManagerHandle {
                 command: command.clone(),
                 remote_connection: remote_connection.clone(),
                 timeout,
+                stdin,
                 response_tx,
             })
             .map_err(|_| TaskError::ManagerShutdown)?;
@@ -779,6 +1216,90 @@ impl TaskManagerHandle {
         Ok(task_info)
     }
 
+    /// Start a background task running a command across inventory hosts, connecting
+    /// through `connection_manager` so pooled SSH sessions are reused
+    pub async fn start_fleet_task(
+        &self,
+        command: String,
+        target: String,
+        hosts: Vec<InventoryHost>,
+        options: FleetOptions,
+        connection_manager: Arc<RemoteConnectionManager>,
+    ) -> Result<TaskInfo, TaskError> {
+        let (response_tx, response_rx) = oneshot::channel();
+
+        self.tx
+            .send(TaskMessage::StartFleet {
+                command,
+                target,
+                hosts,
+                options,
+                connection_manager,
+                response_tx,
+            })
+            .map_err(|_| TaskError::ManagerShutdown)?;
+
+        let task_id = response_rx
+            .await
+            .map_err(|_| TaskError::ManagerShutdown)??;
+
+        self.get_task_details(task_id.clone())
+            .await?
+            .ok_or(TaskError::TaskNotFound(task_id))
+    }
+
+    /// Start a background task serving an already bound port forward until it is cancelled
+    pub async fn start_port_forward_task(
+        &self,
+        forward: PortForward,
+        label: String,
+    ) -> Result<TaskInfo, TaskError> {
+        let (response_tx, response_rx) = oneshot::channel();
+
+        self.tx
+            .send(TaskMessage::StartPortForward {
+                forward,
+                label,
+                response_tx,
+            })
+            .map_err(|_| TaskError::ManagerShutdown)?;
+
+        let task_id = response_rx
+            .await
+            .map_err(|_| TaskError::ManagerShutdown)??;
+
+        self.get_task_details(task_id.clone())
+            .await?
+            .ok_or(TaskError::TaskNotFound(task_id))
+    }
+
+    /// Start a background task following log files, keeping the most recent lines as output
+    pub async fn start_tail_task(
+        &self,
+        tailer: LogTailer,
+        duration: Option<Duration>,
+        label: String,
+    ) -> Result<TaskInfo, TaskError> {
+        let (response_tx, response_rx) = oneshot::channel();
+
+        self.tx
+            .send(TaskMessage::StartTail {
+                tailer,
+                duration,
+                label,
+                response_tx,
+            })
+            .map_err(|_| TaskError::ManagerShutdown)?;
+
+        let task_id = response_rx
+            .await
+            .map_err(|_| TaskError::ManagerShutdown)??;
+
+        self.get_task_details(task_id.clone())
+            .await?
+            .ok_or(TaskError::TaskNotFound(task_id))
+    }
+
     pub async fn cancel_task(&self, id: TaskId) -> Result<TaskInfo, TaskError> {
         // Get the task info before cancelling
         let task_info = self
@@ -970,6 +1491,42 @@ mod tests {
             .expect("Failed to shutdown task manager");
     }
 
+    #[tokio::test]
+    async fn test_task_manager_feeds_stdin_to_task() {
+        let task_manager = TaskManager::new();
+        let handle = task_manager.handle();
+
+        let _manager_handle = tokio::spawn(async move {
+            task_manager.run().await;
+        });
+
+        let task_info = handle
+            .start_task_with_input(
+                "read line; echo \"got $line\"".to_string(),
+                None,
+                None,
+                Some(b"hunter2\n".to_vec()),
+            )
+            .await
+            .expect("Failed to start task");
+        assert!(!task_info.command.contains("hunter2"));
+
+        sleep(Duration::from_millis(500)).await;
+
+        let details = handle
+            .get_task_details(task_info.id.clone())
+            .await
+            .expect("Failed to get task details")
+            .expect("Task exists");
+        assert_eq!(details.status, TaskStatus::Completed);
+        assert_eq!(details.output.as_deref(), Some("got hunter2\n"));
+
+        handle
+            .shutdown()
+            .await
+            .expect("Failed to shutdown task manager");
+    }
+
     #[tokio::test]
     async fn test_task_manager_detects_immediate_failure() {
         let task_manager = TaskManager::new();
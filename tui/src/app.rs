@@ -17,8 +17,10 @@ use ratatui::text::Line;
 use stakpak_shared::models::integrations::openai::{
     ToolCall, ToolCallResult, ToolCallResultProgress,
 };
+use stakpak_shared::models::usage::SessionUsage;
 use stakpak_shared::secret_manager::SecretManager;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
@@ -53,6 +55,8 @@ pub struct SessionInfo {
 pub enum LoadingType {
     Llm,
     Sessions,
+    Reconnecting,
+    Compacting,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -61,6 +65,8 @@ pub enum LoadingOperation {
     ToolExecution,
     SessionsList,
     StreamProcessing,
+    StreamReconnecting,
+    Compacting,
     LocalContext,
     Rulebooks,
     CheckpointResume,
@@ -111,6 +117,16 @@ impl LoadingStateManager {
             .contains(&LoadingOperation::SessionsList)
         {
             LoadingType::Sessions
+        } else if self
+            .active_operations
+            .contains(&LoadingOperation::StreamReconnecting)
+        {
+            LoadingType::Reconnecting
+        } else if self
+            .active_operations
+            .contains(&LoadingOperation::Compacting)
+        {
+            LoadingType::Compacting
         } else {
             LoadingType::Llm
         }
@@ -146,6 +162,7 @@ pub struct AppState {
     pub show_sessions_dialog: bool,
     pub session_selected: usize,
     pub account_info: String,
+    pub session_usage: SessionUsage,
     pub pending_bash_message_id: Option<Uuid>,
     pub streaming_tool_results: HashMap<Uuid, String>,
     pub streaming_tool_result_id: Option<Uuid>,
@@ -191,6 +208,8 @@ pub struct AppState {
     pub processed_lines_cache: Option<(Vec<Message>, usize, Vec<Line<'static>>)>,
 
     pub pending_pastes: Vec<(String, String)>,
+    /// Files attached to the input, by the placeholder standing in for them
+    pub pending_attachments: Vec<(String, PathBuf)>,
     pub mouse_capture_enabled: bool,
     pub loading_manager: LoadingStateManager,
     pub has_user_messages: bool,
@@ -215,6 +234,9 @@ pub struct AppState {
     pub profile_switcher_selected: usize,
     pub current_profile_name: String,
     pub profile_switching_in_progress: bool,
+    // Model in use and the profile's named alternatives
+    pub current_model: String,
+    pub available_models: Vec<String>,
     pub profile_switch_status_message: Option<String>,
     pub rulebook_config: Option<crate::RulebookConfig>,
 }
@@ -232,6 +254,9 @@ pub enum InputEvent {
     InputChanged(char),
     ShellMode,
     GetStatus(String),
+    UsageUpdated(SessionUsage),
+    /// Estimated conversation size in tokens before and after compaction
+    ConversationCompacted(u64, u64),
     Error(String),
     SetSessions(Vec<SessionInfo>),
     InputBackspace,
@@ -301,19 +326,24 @@ pub enum InputEvent {
     ProfileSwitchFailed(String),
     ProfileSwitcherSelect,
     ProfileSwitcherCancel,
+    // Model events
+    ModelsLoaded(Vec<String>, String), // (available_models, current_model)
+    ModelSwitched(String),
 }
 
 #[derive(Debug)]
 pub enum OutputEvent {
-    UserMessage(String, Option<Vec<ToolCallResult>>),
+    UserMessage(String, Option<Vec<ToolCallResult>>, Vec<PathBuf>), // (input, shell tool calls, attachments)
     AcceptTool(ToolCall),
     RejectTool(ToolCall, bool),
     ListSessions,
     SwitchToSession(String),
     Memorize,
+    Compact,
     SendToolResult(ToolCallResult, bool, Vec<ToolCall>),
     ResumeSession,
     RequestProfileSwitch(String),
+    SwitchModel(String),
 }
 
 impl AppState {
@@ -343,6 +373,10 @@ impl AppState {
                 command: "/memorize",
                 description: "Memorize the current conversation history",
             },
+            HelperCommand {
+                command: "/compact",
+                description: "Summarize older messages to free up context",
+            },
             HelperCommand {
                 command: "/list_approved_tools",
                 description: "List all tools that are auto-approved",
@@ -355,6 +389,10 @@ impl AppState {
                 command: "/mouse_capture",
                 description: "Toggle mouse capture on/off",
             },
+            HelperCommand {
+                command: "/model",
+                description: "Show or switch the model e.g. /model fast",
+            },
             HelperCommand {
                 command: "/switch_profile",
                 description: "Switch to a different profile",
@@ -412,6 +450,7 @@ impl AppState {
             show_sessions_dialog: false,
             session_selected: 0,
             account_info: String::new(),
+            session_usage: SessionUsage::default(),
             pending_bash_message_id: None,
             streaming_tool_results: HashMap::new(),
             streaming_tool_result_id: None,
@@ -453,6 +492,7 @@ impl AppState {
             collapsed_message_lines_cache: None,
             processed_lines_cache: None,
             pending_pastes: Vec::new(),
+            pending_attachments: Vec::new(),
             mouse_capture_enabled: crate::services::detect_term::is_unsupported_terminal(
                 &crate::services::detect_term::detect_terminal().emulator,
             ), // Start with mouse capture enabled only for supported terminals
@@ -477,6 +517,8 @@ impl AppState {
             profile_switcher_selected: 0,
             current_profile_name: "default".to_string(),
             profile_switching_in_progress: false,
+            current_model: String::new(),
+            available_models: Vec::new(),
             profile_switch_status_message: None,
             rulebook_config: None,
         }
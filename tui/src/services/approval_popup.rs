@@ -24,7 +24,8 @@
 //!
 //! // Create popup service
 //! let terminal_size = Size { width: 80, height: 24 };
-//! // let mut popup_service = PopupService::new_with_tool_calls(tool_calls, terminal_size);
+//! // let mut popup_service =
+//! //     PopupService::new_with_tool_calls(tool_calls, terminal_size, &secret_manager);
 //!
 //! // Handle events
 //! // popup_service.next_tab();        // Navigate to next tool call
@@ -48,6 +49,8 @@ use ratatui::layout::Size;
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
 use stakpak_shared::models::integrations::openai::ToolCall;
+use stakpak_shared::secret_manager::SecretManager;
+use stakpak_shared::secrets::egress::EgressViolation;
 
 /// Default width percentage for approval popup
 const APPROVAL_POPUP_WIDTH_PERCENT: f32 = 0.8;
@@ -65,6 +68,8 @@ pub enum ApprovalStatus {
 pub struct ToolCallInfo {
     pub tool_call: ToolCall,
     pub status: ApprovalStatus,
+    /// Secrets the tool call would restore against egress policy
+    pub egress_violations: Vec<EgressViolation>,
 }
 
 /// Popup service that manages its own state and event handling
@@ -95,13 +100,30 @@ impl PopupService {
     }
 
     /// Create a new popup service with tool calls
-    pub fn new_with_tool_calls(tool_calls: Vec<ToolCall>, terminal_size: Size) -> Self {
+    pub fn new_with_tool_calls(
+        tool_calls: Vec<ToolCall>,
+        terminal_size: Size,
+        secret_manager: &SecretManager,
+    ) -> Self {
         let term_rect = ratatui::layout::Rect::new(0, 0, terminal_size.width, terminal_size.height);
         let tool_call_infos: Vec<ToolCallInfo> = tool_calls
             .into_iter()
-            .map(|tool_call| ToolCallInfo {
-                tool_call,
-                status: ApprovalStatus::Approved, // All tool calls approved by default
+            .map(|tool_call| {
+                let egress_violations = secret_manager.check_tool_call_egress(
+                    &tool_call.function.name,
+                    &tool_call.function.arguments,
+                );
+                // All tool calls approved by default, unless they would be blocked anyway
+                let status = if egress_violations.is_empty() {
+                    ApprovalStatus::Approved
+                } else {
+                    ApprovalStatus::Rejected
+                };
+                ToolCallInfo {
+                    tool_call,
+                    status,
+                    egress_violations,
+                }
             })
             .collect();
 
@@ -440,10 +462,30 @@ impl PopupService {
     fn create_tool_call_content(
         &self,
         tool_call: &ToolCall,
-        _tool_call_info: &ToolCallInfo,
+        tool_call_info: &ToolCallInfo,
     ) -> StyledLineContent {
         let mut lines = Vec::new();
 
+        if !tool_call_info.egress_violations.is_empty() {
+            lines.push((
+                Line::from(Span::styled(
+                    " Blocked by secret egress policy:",
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                )),
+                Style::default(),
+            ));
+            for violation in &tool_call_info.egress_violations {
+                lines.push((
+                    Line::from(Span::styled(
+                        format!("  • {}", violation),
+                        Style::default().fg(Color::Red),
+                    )),
+                    Style::default(),
+                ));
+            }
+            lines.push((Line::from(""), Style::default()));
+        }
+
         // lines.push((Line::from(""), Style::default()));
         let output = extract_full_command_arguments(tool_call);
         let output = if tool_call.function.name == "run_command" {
@@ -462,6 +504,9 @@ impl PopupService {
                 } else {
                     format_text_content(&output, inner_width)
                 }
+            } else if tool_call.function.name == "sync" {
+                render_sync_plan_lines(tool_call)
+                    .unwrap_or_else(|| format_text_content(&output, inner_width))
             } else if tool_call.function.name == "run_command" {
                 let processed_result = preprocess_terminal_output(&output);
                 let bash_text = format!("```bash\n{processed_result}\n```");
@@ -760,3 +805,79 @@ impl popup_widget::traits::PopupContent for TabContent {
         self
     }
 }
+
+/// Render the changes a `sync` call will apply, colored by action. Returns `None` when the
+/// arguments can't be parsed so the caller falls back to the raw arguments.
+fn render_sync_plan_lines(tool_call: &ToolCall) -> Option<Vec<Line<'static>>> {
+    let args: serde_json::Value = serde_json::from_str(&tool_call.function.arguments).ok()?;
+    let local_path = args.get("local_path")?.as_str()?.to_string();
+    let remote_path = args.get("remote_path")?.as_str()?.to_string();
+    let delete = args
+        .get("delete")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let mut lines = vec![Line::from(vec![
+        Span::styled(local_path, Style::default().fg(Color::Cyan)),
+        Span::styled(" → ", Style::default().fg(Color::Gray)),
+        Span::styled(remote_path, Style::default().fg(Color::Cyan)),
+    ])];
+    if delete {
+        lines.push(Line::from(Span::styled(
+            "Remote files missing locally will be deleted (backed up first)",
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+    lines.push(Line::from(""));
+
+    let Some(plan) = args.get("plan").and_then(|v| v.as_str()) else {
+        lines.push(Line::from(Span::styled(
+            "Compute the sync plan only (read-only, nothing is changed)",
+            Style::default().fg(Color::Gray),
+        )));
+        return Some(lines);
+    };
+
+    let (mut added, mut updated, mut deleted) = (0, 0, 0);
+    for plan_line in plan.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let color = match plan_line.chars().next() {
+            Some('+') => {
+                added += 1;
+                Color::Green
+            }
+            Some('~') => {
+                updated += 1;
+                Color::Yellow
+            }
+            Some('-') => {
+                deleted += 1;
+                Color::Red
+            }
+            _ => Color::Gray,
+        };
+        lines.push(Line::from(Span::styled(
+            plan_line.to_string(),
+            Style::default().fg(color),
+        )));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled(
+            format!("{} to add", added),
+            Style::default().fg(Color::Green),
+        ),
+        Span::styled(", ", Style::default().fg(Color::Gray)),
+        Span::styled(
+            format!("{} to update", updated),
+            Style::default().fg(Color::Yellow),
+        ),
+        Span::styled(", ", Style::default().fg(Color::Gray)),
+        Span::styled(
+            format!("{} to delete", deleted),
+            Style::default().fg(Color::Red),
+        ),
+    ]));
+
+    Some(lines)
+}
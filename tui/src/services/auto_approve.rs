@@ -50,6 +50,11 @@ impl Default for AutoApproveConfig {
         tools.insert("generate_code".to_string(), AutoApprovePolicy::Prompt);
         tools.insert("run_command".to_string(), AutoApprovePolicy::Prompt);
         tools.insert("run_command_task".to_string(), AutoApprovePolicy::Prompt);
+        tools.insert("run_command_fleet".to_string(), AutoApprovePolicy::Prompt);
+        tools.insert("port_forward".to_string(), AutoApprovePolicy::Prompt);
+        tools.insert("copy".to_string(), AutoApprovePolicy::Prompt);
+        tools.insert("sync".to_string(), AutoApprovePolicy::Prompt);
+        tools.insert("tail".to_string(), AutoApprovePolicy::Prompt);
         tools.insert("subagent_task".to_string(), AutoApprovePolicy::Prompt);
         tools.insert("cancel_task".to_string(), AutoApprovePolicy::Prompt);
 
@@ -376,7 +381,7 @@ mod tests {
         assert_eq!(config.tools.get("read"), Some(&AutoApprovePolicy::Auto)); // Profile wins
         assert_eq!(config.tools.get("write"), Some(&AutoApprovePolicy::Auto)); // Profile default
         assert_eq!(config.tools.get("delete"), Some(&AutoApprovePolicy::Auto)); // Session-only
-        assert_eq!(config.enabled, false); // Session override
+        assert!(!config.enabled); // Session override
     }
 
     #[test]
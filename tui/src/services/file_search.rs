@@ -1,5 +1,6 @@
 use std::cmp::Reverse;
 use std::collections::BinaryHeap;
+use std::ops::Range;
 use std::path::Path;
 use std::sync::Arc;
 
@@ -12,6 +13,8 @@ use tokio::sync::mpsc;
 
 use crate::AppState;
 use crate::app::{FileSearchResult, HelperCommand};
+use crate::services::helper_block::push_error_message;
+use stakpak_shared::attachments::{AttachmentError, check_attachment};
 
 /// Maintains the best N matches for a given pattern using parallel processing
 #[derive(Debug)]
@@ -322,15 +325,19 @@ pub fn handle_at_trigger(input: &str, cursor_pos: usize, file_search: &mut FileS
 pub fn handle_file_selection(state: &mut AppState, selected_file: &str) {
     match state.file_search.trigger_char {
         Some('@') => {
-            // Replace from @ to cursor with selected file
+            // Replace from @ to cursor with the attached file, or with its path when it
+            // cannot be attached
             if let Some(at_pos) = find_at_trigger(state.input(), state.cursor_position()) {
-                let before_at = state.input()[..at_pos].to_string();
-                let after_cursor = state.input()[state.cursor_position()..].to_string();
-                let new_text = format!("{}{}{}", before_at, selected_file, after_cursor);
-                state.text_area.set_text(&new_text);
-                state
-                    .text_area
-                    .set_cursor(before_at.len() + selected_file.len());
+                let cursor = state.cursor_position();
+                if !attach_file(state, Path::new(selected_file), at_pos..cursor) {
+                    let before_at = state.input()[..at_pos].to_string();
+                    let after_cursor = state.input()[cursor..].to_string();
+                    let new_text = format!("{}{}{}", before_at, selected_file, after_cursor);
+                    state.text_area.set_text(&new_text);
+                    state
+                        .text_area
+                        .set_cursor(before_at.len() + selected_file.len());
+                }
             }
         }
         None => {
@@ -363,6 +370,29 @@ pub fn handle_file_selection(state: &mut AppState, selected_file: &str) {
     state.helper_selected = 0;
 }
 
+/// Replace `range` of the input with a placeholder for the file at `path`, which is sent
+/// along with the message. Returns false when the file cannot be attached.
+pub fn attach_file(state: &mut AppState, path: &Path, range: Range<usize>) -> bool {
+    match check_attachment(path) {
+        Ok(_) => {
+            let placeholder = format!("[Attached: {}]", path.display());
+            state.text_area.replace_range(range.clone(), "");
+            state.text_area.set_cursor(range.start);
+            state.text_area.insert_element(&placeholder);
+            state
+                .pending_attachments
+                .push((placeholder, path.to_path_buf()));
+            true
+        }
+        Err(e) => {
+            if matches!(e, AttachmentError::TooLarge { .. }) {
+                push_error_message(state, &e.to_string(), None);
+            }
+            false
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DebouncedFilter {
     last_query: String,
@@ -2,6 +2,7 @@ use crate::app::{AppState, LoadingType};
 use crate::services::message::{Message, MessageContent};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
+use stakpak_shared::models::usage::format_tokens;
 use uuid::Uuid;
 
 pub fn get_stakpak_version() -> String {
@@ -87,7 +88,65 @@ pub fn push_status_message(state: &mut AppState) {
         Line::from(format!("  L ID: {}", id)),
         Line::from(format!("  L Name: {}", name)),
         Line::from(""),
+        Line::from(vec![Span::styled(
+            "Model",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )]),
+        Line::from(format!("  L {}", state.current_model)),
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            "Usage",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )]),
+        Line::from(format!("  L Session: {}", state.session_usage.summary())),
+        Line::from(format!(
+            "  L Last turn: {} tokens ({} in, {} out)",
+            format_tokens(state.session_usage.last_turn.total_tokens()),
+            format_tokens(state.session_usage.last_turn.prompt_tokens),
+            format_tokens(state.session_usage.last_turn.completion_tokens)
+        )),
+        Line::from(format!("  L Steps: {}", state.session_usage.steps)),
+        Line::from(""),
+    ];
+    state.messages.push(Message {
+        id: uuid::Uuid::new_v4(),
+        content: MessageContent::StyledBlock(lines),
+        is_collapsed: None,
+    });
+}
+
+pub fn push_model_message(state: &mut AppState) {
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled("Model: ", Style::default().fg(Color::DarkGray)),
+            Span::styled(
+                state.current_model.clone(),
+                Style::default()
+                    .fg(Color::Reset)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(""),
     ];
+    if state.available_models.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "Switch with /model <model>, or name models under [profiles.<name>.models] in your config.",
+            Style::default().fg(Color::DarkGray),
+        )));
+    } else {
+        lines.push(Line::from(Span::styled(
+            "Switch with /model <name> to one of:",
+            Style::default().fg(Color::DarkGray),
+        )));
+        for model in &state.available_models {
+            lines.push(Line::from(format!("  - {}", model)));
+        }
+    }
+    lines.push(Line::from(""));
     state.messages.push(Message {
         id: uuid::Uuid::new_v4(),
         content: MessageContent::StyledBlock(lines),
@@ -292,10 +351,11 @@ pub fn push_error_message(state: &mut AppState, error: &str, remove_flag: Option
 pub fn render_loading_spinner(state: &AppState) -> Line<'_> {
     let spinner_chars = ["▄▀", "▐▌", "▀▄", "▐▌"];
     let spinner = spinner_chars[state.spinner_frame % spinner_chars.len()];
-    let spinner_text = if state.loading_type == LoadingType::Sessions {
-        "Loading sessions..."
-    } else {
-        "Stakpaking..."
+    let spinner_text = match state.loading_type {
+        LoadingType::Sessions => "Loading sessions...",
+        LoadingType::Reconnecting => "Connection lost, reconnecting...",
+        LoadingType::Compacting => "Compacting conversation...",
+        LoadingType::Llm => "Stakpaking...",
     };
 
     if state.loading_type == LoadingType::Sessions {
@@ -8,6 +8,7 @@ use ratatui::{
     text::{Line, Span},
     widgets::Paragraph,
 };
+use stakpak_shared::models::usage::format_tokens;
 
 pub fn render_hint_or_shortcuts(f: &mut Frame, state: &AppState, area: Rect) {
     if state.is_pasting {
@@ -86,7 +87,17 @@ pub fn render_hint_or_shortcuts(f: &mut Frame, state: &AppState, area: Rect) {
                 "? for shortcuts . @ for files . / for commands{}",
                 select_hint
             );
-            let right_text = format!("profile {}", state.current_profile_name);
+            let usage_text = if state.session_usage.steps > 0 {
+                let usage = &state.session_usage.total;
+                let mut usage_text = format!("{} tokens", format_tokens(usage.total_tokens()));
+                if let Some(cost) = usage.cost {
+                    usage_text.push_str(&format!(" . ${:.2}", cost));
+                }
+                format!("{} . ", usage_text)
+            } else {
+                String::new()
+            };
+            let right_text = format!("{}profile {}", usage_text, state.current_profile_name);
 
             // Calculate spacing to align profile info to the right
             let total_width = area.width as usize;
@@ -97,6 +108,7 @@ pub fn render_hint_or_shortcuts(f: &mut Frame, state: &AppState, area: Rect) {
             let spans = vec![
                 Span::styled(left_text, Style::default().fg(Color::Cyan)),
                 Span::styled(" ".repeat(spacing), Style::default()),
+                Span::styled(usage_text, Style::default().fg(Color::DarkGray)),
                 Span::styled("profile ", Style::default().fg(Color::DarkGray)),
                 Span::styled(
                     state.current_profile_name.clone(),
@@ -4,11 +4,11 @@ use crate::services::approval_popup::PopupService;
 use crate::services::auto_approve::AutoApprovePolicy;
 use crate::services::bash_block::{preprocess_terminal_output, render_bash_block_rejected};
 use crate::services::detect_term::AdaptiveColors;
-use crate::services::file_search::{handle_file_selection, handle_tab_trigger};
+use crate::services::file_search::{attach_file, handle_file_selection, handle_tab_trigger};
 use crate::services::helper_block::{
     handle_errors, push_clear_message, push_error_message, push_help_message,
-    push_memorize_message, push_status_message, push_styled_message, render_system_message,
-    welcome_messages,
+    push_memorize_message, push_model_message, push_status_message, push_styled_message,
+    render_system_message, welcome_messages,
 };
 use crate::services::message::{
     Message, MessageContent, get_command_type_name, get_wrapped_collapsed_message_lines_cached,
@@ -18,10 +18,12 @@ use crate::services::shell_mode::SHELL_PROMPT_PREFIX;
 use ratatui::layout::Size;
 use ratatui::style::{Color, Style};
 use serde_json;
+use stakpak_shared::attachments::{AttachmentKind, pasted_file_path};
 use stakpak_shared::helper::truncate_output;
 use stakpak_shared::models::integrations::openai::{
     FunctionCall, ToolCall, ToolCallResult, ToolCallResultProgress, ToolCallResultStatus,
 };
+use stakpak_shared::models::usage::format_tokens;
 use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
@@ -66,7 +68,6 @@ pub fn update(
         }
     }
 
-    state.scroll = state.scroll.max(0);
     match event {
         InputEvent::Up => {
             handle_up_navigation(state);
@@ -76,24 +77,20 @@ pub fn update(
         }
         InputEvent::DropdownUp => handle_dropdown_up(state),
         InputEvent::DropdownDown => handle_dropdown_down(state),
-        InputEvent::DialogUp => {
-            if state.is_dialog_open {
-                if state.dialog_selected > 0 {
-                    state.dialog_selected -= 1;
-                } else {
-                    // Wrap to the last option
-                    state.dialog_selected = 2;
-                }
+        InputEvent::DialogUp if state.is_dialog_open => {
+            if state.dialog_selected > 0 {
+                state.dialog_selected -= 1;
+            } else {
+                // Wrap to the last option
+                state.dialog_selected = 2;
             }
         }
-        InputEvent::DialogDown => {
-            if state.is_dialog_open {
-                if state.dialog_selected < 2 {
-                    state.dialog_selected += 1;
-                } else {
-                    // Wrap to the first option
-                    state.dialog_selected = 0;
-                }
+        InputEvent::DialogDown if state.is_dialog_open => {
+            if state.dialog_selected < 2 {
+                state.dialog_selected += 1;
+            } else {
+                // Wrap to the first option
+                state.dialog_selected = 0;
             }
         }
 
@@ -228,6 +225,15 @@ pub fn update(
                 );
                 return;
             }
+            if err == "EXCEEDED_API_LIMIT" {
+                push_error_message(state, "You have run out of credits.", None);
+                push_error_message(
+                    state,
+                    "Please top up your account at https://stakpak.dev/settings/billing to keep Stakpaking.",
+                    Some(true),
+                );
+                return;
+            }
             if err == "STREAM_CANCELLED" {
                 let rendered_lines =
                     render_bash_block_rejected("Interrupted by user", "System", None, None);
@@ -314,22 +320,20 @@ pub fn update(
         InputEvent::AutoApproveCurrentTool => {
             list_auto_approved_tools(state);
         }
-        InputEvent::ToggleDialogFocus => {
-            if state.is_dialog_open {
-                state.dialog_focused = !state.dialog_focused;
-                let focus_message = if state.dialog_focused {
-                    "Dialog focused"
-                } else {
-                    "Chat view focused"
-                };
-                push_styled_message(
-                    state,
-                    &format!("🎯 {}", focus_message),
-                    Color::DarkGray,
-                    "",
-                    Color::Cyan,
-                );
-            }
+        InputEvent::ToggleDialogFocus if state.is_dialog_open => {
+            state.dialog_focused = !state.dialog_focused;
+            let focus_message = if state.dialog_focused {
+                "Dialog focused"
+            } else {
+                "Chat view focused"
+            };
+            push_styled_message(
+                state,
+                &format!("🎯 {}", focus_message),
+                Color::DarkGray,
+                "",
+                Color::Cyan,
+            );
         }
 
         InputEvent::HandleReject(message, should_stop, color) => {
@@ -473,8 +477,11 @@ pub fn update(
 
             // Tool call is pending - check if we should show popup first
             if !tool_calls.is_empty() && state.toggle_approved_message {
-                state.approval_popup =
-                    PopupService::new_with_tool_calls(tool_calls.clone(), terminal_size);
+                state.approval_popup = PopupService::new_with_tool_calls(
+                    tool_calls.clone(),
+                    terminal_size,
+                    &state.secret_manager,
+                );
                 return;
             }
         }
@@ -557,6 +564,27 @@ pub fn update(
         InputEvent::GetStatus(account_info) => {
             state.account_info = account_info;
         }
+        InputEvent::UsageUpdated(session_usage) => {
+            state.session_usage = session_usage;
+        }
+        InputEvent::ModelsLoaded(models, current_model) => {
+            state.available_models = models;
+            state.current_model = current_model;
+        }
+        InputEvent::ModelSwitched(model) => {
+            render_system_message(state, &format!("Switched to model {}", model));
+            state.current_model = model;
+        }
+        InputEvent::ConversationCompacted(before, after) => {
+            render_system_message(
+                state,
+                &format!(
+                    "Conversation compacted from ~{} to ~{} tokens.",
+                    format_tokens(before),
+                    format_tokens(after)
+                ),
+            );
+        }
         InputEvent::Tab => {
             if state.show_collapsed_messages {
                 handle_collapsed_messages_tab(state, message_area_height, message_area_width);
@@ -1326,6 +1354,18 @@ fn handle_input_submitted(
         return;
     }
 
+    let command = input_text.trim();
+    if command.starts_with("/model ") || (command == "/model" && !state.show_helper_dropdown) {
+        match command.split_whitespace().nth(1) {
+            Some(model) => {
+                let _ = output_tx.try_send(OutputEvent::SwitchModel(model.to_string()));
+            }
+            None => push_model_message(state),
+        }
+        state.text_area.set_text("");
+        return;
+    }
+
     if state.show_sessions_dialog {
         let selected = &state.sessions[state.session_selected];
         let _ = output_tx.try_send(OutputEvent::SwitchToSession(selected.id.to_string()));
@@ -1395,6 +1435,11 @@ fn handle_input_submitted(
                     state.text_area.set_text("");
                     state.show_helper_dropdown = false;
                 }
+                "/compact" => {
+                    let _ = output_tx.try_send(OutputEvent::Compact);
+                    state.text_area.set_text("");
+                    state.show_helper_dropdown = false;
+                }
                 "/help" => {
                     push_help_message(state);
                     state.text_area.set_text("");
@@ -1416,6 +1461,13 @@ fn handle_input_submitted(
                     state.text_area.set_cursor(input.len());
                     state.show_helper_dropdown = false;
                 }
+                "/model" => {
+                    let input = "/model ".to_string();
+                    state.text_area.set_text(&input);
+                    state.text_area.set_cursor(input.len());
+                    state.show_helper_dropdown = false;
+                    push_model_message(state);
+                }
                 "/switch_profile" => {
                     state.show_profile_switcher = true;
                     state.text_area.set_text("");
@@ -1467,9 +1519,20 @@ fn handle_input_submitted(
         }
         state.pasted_long_text = None;
         state.pasted_placeholder = None;
+
+        // Attached files are sent separately and referred to by path in the message
+        let mut attachments = Vec::new();
+        for (placeholder, path) in state.pending_attachments.drain(..) {
+            if final_input.contains(&placeholder) {
+                final_input = final_input.replace(&placeholder, &path.display().to_string());
+                attachments.push(path);
+            }
+        }
+
         let _ = output_tx.try_send(OutputEvent::UserMessage(
             final_input.clone(),
             state.shell_tool_calls.clone(),
+            attachments,
         ));
 
         let _ = input_tx.try_send(InputEvent::AddUserMessage(final_input));
@@ -2019,6 +2082,19 @@ pub fn handle_paste(state: &mut AppState, pasted: String) -> bool {
     // but textarea expects \n. This is the same fix used in Codex.
     let normalized_pasted = pasted.replace("\r\n", "\n").replace('\r', "\n");
 
+    // Dropping a file onto the terminal pastes its path, so screenshots and PDFs are attached
+    if let Some(path) = pasted_file_path(&normalized_pasted)
+        && matches!(
+            AttachmentKind::from_path(&path),
+            AttachmentKind::Image(_) | AttachmentKind::Pdf
+        )
+    {
+        let cursor = state.cursor_position();
+        if attach_file(state, &path, cursor..cursor) {
+            return true;
+        }
+    }
+
     let char_count = normalized_pasted.chars().count();
     if char_count > MAX_PASTE_CHAR_COUNT {
         let placeholder = format!("[Pasted Content {char_count} chars]");